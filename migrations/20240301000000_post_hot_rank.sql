@@ -0,0 +1,24 @@
+-- Stored hot ranking for posts
+--
+-- hot_rank only changes when a post's votes change, so it is maintained on
+-- each vote instead of being recomputed in bulk. The backfill below mirrors
+-- calculate_hot_score in src/domain/services/feed.rs (epoch 2024-01-01).
+
+ALTER TABLE posts ADD COLUMN hot_rank DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+-- The old score worker overwrote score with a truncated hot score
+UPDATE posts SET score = COALESCE(upvotes, 0) - COALESCE(downvotes, 0);
+
+UPDATE posts
+SET hot_rank = SIGN(score) * LOG(GREATEST(ABS(score), 1))
+             + (EXTRACT(EPOCH FROM created_at) - 1704067200) / 45000.0;
+
+DROP INDEX IF EXISTS idx_posts_score;
+DROP INDEX IF EXISTS idx_posts_space_score;
+
+CREATE INDEX idx_posts_hot_rank ON posts(hot_rank DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_space_hot_rank ON posts(space_id, is_pinned DESC, hot_rank DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_score ON posts(score DESC);
+
+-- Bulk score recalculation is no longer scheduled
+DELETE FROM jobs WHERE job_type = 'update_scores' AND status IN ('pending', 'dead');
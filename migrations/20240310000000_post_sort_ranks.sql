@@ -0,0 +1,45 @@
+-- Stored ranks for the controversial and best post sorts
+--
+-- Like hot_rank, these only depend on vote counts and are refreshed on each
+-- vote. The backfill mirrors calculate_controversy_score and
+-- calculate_wilson_score in src/domain/services/feed.rs.
+
+ALTER TABLE posts
+    ADD COLUMN controversy_rank DOUBLE PRECISION NOT NULL DEFAULT 0,
+    ADD COLUMN best_rank DOUBLE PRECISION NOT NULL DEFAULT 0;
+
+UPDATE posts
+SET controversy_rank = CASE
+        WHEN v.n = 0 THEN 0
+        WHEN v.up = v.down THEN v.n
+        ELSE POWER(v.n, LEAST(v.up, v.down) / GREATEST(v.up, v.down))
+    END,
+    best_rank = CASE
+        WHEN v.n = 0 THEN 0
+        ELSE (v.up / v.n + 1.9208 / v.n
+              - 1.96 * SQRT(((v.up / v.n) * (1 - v.up / v.n) + 0.9604 / v.n) / v.n))
+             / (1 + 3.8416 / v.n)
+    END
+FROM (
+    SELECT id,
+           COALESCE(upvotes, 0)::DOUBLE PRECISION AS up,
+           COALESCE(downvotes, 0)::DOUBLE PRECISION AS down,
+           (COALESCE(upvotes, 0) + COALESCE(downvotes, 0))::DOUBLE PRECISION AS n
+    FROM posts
+) v
+WHERE posts.id = v.id;
+
+-- Site-wide feeds
+CREATE INDEX idx_posts_new ON posts(created_at DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_top ON posts(score DESC, created_at DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_controversial ON posts(controversy_rank DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_best ON posts(best_rank DESC) WHERE is_removed = false;
+
+-- Space listings and subscribed feeds
+CREATE INDEX idx_posts_space_new ON posts(space_id, created_at DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_space_top ON posts(space_id, score DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_space_controversial ON posts(space_id, controversy_rank DESC) WHERE is_removed = false;
+CREATE INDEX idx_posts_space_best ON posts(space_id, best_rank DESC) WHERE is_removed = false;
+
+-- Recent vote activity for the rising sort
+CREATE INDEX idx_votes_post_recent ON votes(target_id, created_at DESC) WHERE target_type = 'post';
@@ -0,0 +1,29 @@
+-- Polls attached to posts with content_type = 'poll'
+
+CREATE TABLE polls (
+    post_id UUID PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+    allows_multiple BOOLEAN NOT NULL DEFAULT FALSE,
+    closes_at TIMESTAMPTZ,
+    total_voters INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE poll_options (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    post_id UUID NOT NULL REFERENCES polls(post_id) ON DELETE CASCADE,
+    position SMALLINT NOT NULL,
+    label VARCHAR(200) NOT NULL,
+    vote_count INTEGER NOT NULL DEFAULT 0,
+    UNIQUE(post_id, position)
+);
+
+-- One ballot per identity per poll; a ballot holds every option it chose
+CREATE TABLE poll_ballots (
+    post_id UUID NOT NULL REFERENCES polls(post_id) ON DELETE CASCADE,
+    identity_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    option_ids UUID[] NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (post_id, identity_id)
+);
+
+CREATE INDEX idx_poll_ballots_identity ON poll_ballots(identity_id);
@@ -0,0 +1,29 @@
+-- Edit history for posts and comments
+--
+-- Each edit appends the content it replaced. Revisions are never changed or
+-- deleted once written.
+
+ALTER TABLE posts ADD COLUMN edited_at TIMESTAMPTZ;
+ALTER TABLE comments ADD COLUMN edited_at TIMESTAMPTZ;
+
+CREATE TABLE content_revisions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    target_type VARCHAR(20) NOT NULL,
+    target_id UUID NOT NULL,
+    revision INTEGER NOT NULL,
+    editor_id UUID REFERENCES identities(id) ON DELETE SET NULL,
+    previous_content TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(target_type, target_id, revision),
+    CONSTRAINT valid_revision_target CHECK (target_type IN ('post', 'comment'))
+);
+
+CREATE FUNCTION reject_revision_change() RETURNS TRIGGER AS $$
+BEGIN
+    RAISE EXCEPTION 'content_revisions is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER content_revisions_append_only
+    BEFORE UPDATE OR DELETE ON content_revisions
+    FOR EACH ROW EXECUTE FUNCTION reject_revision_change();
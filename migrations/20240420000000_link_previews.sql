@@ -0,0 +1,15 @@
+-- Link post previews
+--
+-- Filled in by the unfurl_link job after a link post is created. The image,
+-- if any, is stored as media with its metadata stripped.
+
+CREATE TABLE link_previews (
+    post_id UUID PRIMARY KEY REFERENCES posts(id) ON DELETE CASCADE,
+    -- Where the link ended up after redirects
+    url TEXT NOT NULL,
+    title VARCHAR(300),
+    description TEXT,
+    site_name VARCHAR(200),
+    image_id UUID REFERENCES media(id) ON DELETE SET NULL,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
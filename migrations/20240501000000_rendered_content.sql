@@ -0,0 +1,14 @@
+-- Rendered markdown for posts and comments
+--
+-- content_html is the sanitized rendering of content, written alongside it.
+-- A space may narrow the tags its content renders with; NULL allows every
+-- safe tag.
+
+ALTER TABLE posts ADD COLUMN content_html TEXT;
+ALTER TABLE comments ADD COLUMN content_html TEXT;
+ALTER TABLE spaces ADD COLUMN allowed_tags TEXT[];
+
+-- Render everything written before this migration
+INSERT INTO jobs (job_type, payload) VALUES
+    ('render_content', '{"target": "posts"}'),
+    ('render_content', '{"target": "comments"}');
@@ -0,0 +1,11 @@
+-- Coalesced notifications
+--
+-- Notifications sharing a group key (e.g. replies to the same post) collapse
+-- into a single unread row that counts them. Once read, the next one starts a
+-- new row.
+
+ALTER TABLE notifications ADD COLUMN group_key VARCHAR(100);
+
+CREATE UNIQUE INDEX idx_notifications_unread_group
+    ON notifications(recipient_id, group_key)
+    WHERE group_key IS NOT NULL AND is_read = false;
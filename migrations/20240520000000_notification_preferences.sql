@@ -0,0 +1,24 @@
+-- Notification preferences
+--
+-- Identities without a preferences row receive every notification type at
+-- any hour. Mutes drop notifications about a post, anything in a space, or
+-- anything caused by another identity.
+
+CREATE TABLE notification_preferences (
+    identity_id UUID PRIMARY KEY REFERENCES identities(id) ON DELETE CASCADE,
+    disabled_types VARCHAR(50)[] NOT NULL DEFAULT '{}',
+    quiet_hours_start TIME,
+    quiet_hours_end TIME,
+    timezone VARCHAR(64) NOT NULL DEFAULT 'UTC',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT complete_quiet_hours CHECK ((quiet_hours_start IS NULL) = (quiet_hours_end IS NULL))
+);
+
+CREATE TABLE notification_mutes (
+    identity_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    target_type VARCHAR(20) NOT NULL,
+    target_id UUID NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (identity_id, target_type, target_id),
+    CONSTRAINT valid_mute_target CHECK (target_type IN ('post', 'space', 'identity'))
+);
@@ -0,0 +1,13 @@
+-- Identity blocks
+--
+-- A block hides the blocked identity's posts and comments from the blocker,
+-- stops it starting conversations with or notifying the blocker. Blocks are
+-- only ever visible to the blocker.
+
+CREATE TABLE identity_blocks (
+    blocker_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    blocked_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (blocker_id, blocked_id),
+    CONSTRAINT no_self_block CHECK (blocker_id <> blocked_id)
+);
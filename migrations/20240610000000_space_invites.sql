@@ -0,0 +1,27 @@
+-- Space invites
+--
+-- An invite either names the identity it is for, or is a shareable link
+-- (no invitee) that may be redeemed a limited number of times. Link tokens
+-- are signed by the server and only carry the invite id, so revoking or
+-- expiring the row invalidates every copy of the link.
+
+CREATE TABLE space_invites (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    space_id UUID NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    inviter_id UUID REFERENCES identities(id) ON DELETE SET NULL,
+    invitee_id UUID REFERENCES identities(id) ON DELETE CASCADE,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending',
+    max_uses INTEGER,
+    use_count INTEGER NOT NULL DEFAULT 0,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    responded_at TIMESTAMPTZ,
+    CONSTRAINT valid_invite_status CHECK (status IN ('pending', 'accepted', 'declined', 'revoked')),
+    CONSTRAINT link_invite_uses CHECK (invitee_id IS NULL OR max_uses IS NULL),
+    CONSTRAINT positive_max_uses CHECK (max_uses IS NULL OR max_uses > 0)
+);
+
+CREATE INDEX idx_space_invites_space ON space_invites(space_id, created_at DESC) WHERE status = 'pending';
+CREATE UNIQUE INDEX idx_space_invites_pending_invitee ON space_invites(space_id, invitee_id)
+    WHERE status = 'pending' AND invitee_id IS NOT NULL;
+CREATE INDEX idx_space_invites_invitee ON space_invites(invitee_id) WHERE invitee_id IS NOT NULL;
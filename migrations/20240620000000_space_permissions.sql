@@ -0,0 +1,33 @@
+-- Space permissions and platform admins
+--
+-- Moderators hold an explicit set of space permissions; space admins and the
+-- space creator hold all of them. Existing moderators keep what the old
+-- moderator role allowed. Platform admins act across every space and are the
+-- only identities that may suspend accounts.
+
+ALTER TABLE space_members ADD COLUMN permissions VARCHAR(30)[] NOT NULL DEFAULT '{}';
+
+UPDATE space_members
+SET permissions = ARRAY['manage_posts', 'manage_members', 'view_reports']
+WHERE role = 'moderator';
+
+CREATE TABLE platform_admins (
+    identity_id UUID PRIMARY KEY REFERENCES identities(id) ON DELETE CASCADE,
+    granted_by UUID REFERENCES identities(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Space a report falls under; NULL for reports of identities, messages and
+-- deleted content, which only platform admins review
+CREATE VIEW report_spaces AS
+SELECT r.id AS report_id,
+       CASE r.target_type
+           WHEN 'post' THEN p.space_id
+           WHEN 'comment' THEN cp.space_id
+           WHEN 'space' THEN sp.id
+       END AS space_id
+FROM reports r
+LEFT JOIN posts p ON r.target_type = 'post' AND p.id = r.target_id
+LEFT JOIN comments c ON r.target_type = 'comment' AND c.id = r.target_id
+LEFT JOIN posts cp ON cp.id = c.post_id
+LEFT JOIN spaces sp ON r.target_type = 'space' AND sp.id = r.target_id;
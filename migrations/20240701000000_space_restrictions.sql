@@ -0,0 +1,37 @@
+-- Space bans, mutes and approved submitters
+--
+-- A ban removes an identity from a space and keeps it from rejoining,
+-- posting or commenting until it expires (never, when expires_at is NULL).
+-- A mute lets the identity keep reading but not post or comment until it
+-- expires. In a restricted space only approved submitters and moderators may
+-- create posts.
+
+ALTER TABLE spaces ADD COLUMN is_restricted BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE space_bans (
+    space_id UUID NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    identity_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    banned_by UUID REFERENCES identities(id) ON DELETE SET NULL,
+    reason VARCHAR(500) NOT NULL,
+    expires_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (space_id, identity_id)
+);
+
+CREATE TABLE space_mutes (
+    space_id UUID NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    identity_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    muted_by UUID REFERENCES identities(id) ON DELETE SET NULL,
+    reason VARCHAR(500) NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (space_id, identity_id)
+);
+
+CREATE TABLE space_approved_submitters (
+    space_id UUID NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    identity_id UUID NOT NULL REFERENCES identities(id) ON DELETE CASCADE,
+    added_by UUID REFERENCES identities(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (space_id, identity_id)
+);
@@ -0,0 +1,39 @@
+-- Moderation log
+--
+-- Every moderation action is appended here with the state it changed. Rows
+-- are never updated or deleted, so the log holds no foreign keys: entries
+-- outlive the identities, spaces and content they mention. Spaces may opt in
+-- to a public, pseudonymised view of their log.
+
+CREATE TABLE moderation_log (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    space_id UUID,
+    actor_id UUID NOT NULL,
+    action VARCHAR(50) NOT NULL,
+    target_type VARCHAR(20) NOT NULL,
+    target_id UUID NOT NULL,
+    reason TEXT,
+    before_state JSONB,
+    after_state JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_moderation_log_space ON moderation_log(space_id, created_at DESC, id DESC);
+CREATE INDEX idx_moderation_log_created ON moderation_log(created_at DESC, id DESC);
+CREATE INDEX idx_moderation_log_actor ON moderation_log(actor_id, created_at DESC);
+
+CREATE FUNCTION reject_moderation_log_change() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'moderation_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER moderation_log_append_only
+    BEFORE UPDATE OR DELETE ON moderation_log
+    FOR EACH ROW EXECUTE FUNCTION reject_moderation_log_change();
+
+CREATE TRIGGER moderation_log_no_truncate
+    BEFORE TRUNCATE ON moderation_log
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_moderation_log_change();
+
+ALTER TABLE spaces ADD COLUMN public_moderation_log BOOLEAN NOT NULL DEFAULT FALSE;
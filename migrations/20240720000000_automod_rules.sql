@@ -0,0 +1,27 @@
+-- AutoModerator rules
+--
+-- Space moderators define rules that run when posts and comments are created
+-- or edited, and when they are reported. A rule's conditions and actions are
+-- stored as JSON and validated by the API before they are saved.
+--
+-- Content a rule filters is hidden like removed content and marked held until
+-- a moderator reviews it. Actions taken by rules are logged without an actor.
+
+CREATE TABLE automod_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    space_id UUID NOT NULL REFERENCES spaces(id) ON DELETE CASCADE,
+    name VARCHAR(100) NOT NULL,
+    definition JSONB NOT NULL,
+    is_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by UUID REFERENCES identities(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (space_id, name)
+);
+
+CREATE INDEX idx_automod_rules_space ON automod_rules(space_id, created_at) WHERE is_enabled;
+
+ALTER TABLE posts ADD COLUMN held_at TIMESTAMPTZ;
+ALTER TABLE comments ADD COLUMN held_at TIMESTAMPTZ;
+
+ALTER TABLE moderation_log ALTER COLUMN actor_id DROP NOT NULL;
@@ -0,0 +1,8 @@
+-- Moderation queue
+--
+-- Held posts and comments stay hidden like removed content until a moderator
+-- approves or rejects them, which clears `held_at`. The queue lists held
+-- content alongside content with open reports.
+
+CREATE INDEX idx_posts_held ON posts(space_id, held_at) WHERE held_at IS NOT NULL;
+CREATE INDEX idx_comments_held ON comments(held_at) WHERE held_at IS NOT NULL;
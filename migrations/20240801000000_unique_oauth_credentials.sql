@@ -0,0 +1,15 @@
+-- One credential per provider account
+--
+-- Concurrent first logins could each link the same provider account to a new
+-- identity. Keep the earliest link of any duplicates, then enforce uniqueness.
+
+DELETE FROM credentials c
+USING credentials d
+WHERE c.oauth_provider = d.oauth_provider
+      AND c.oauth_subject = d.oauth_subject
+      AND (c.created_at, c.id) > (d.created_at, d.id);
+
+DROP INDEX idx_credentials_oauth;
+
+CREATE UNIQUE INDEX idx_credentials_oauth ON credentials(oauth_provider, oauth_subject)
+    WHERE oauth_provider IS NOT NULL;
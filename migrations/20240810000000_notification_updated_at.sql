@@ -0,0 +1,13 @@
+-- Latest activity on a notification
+--
+-- Grouped notifications keep the time they were started as `created_at`, which
+-- orders the list and counts towards the per-hour group cap. Later joins to the
+-- group move `updated_at` instead.
+
+ALTER TABLE notifications ADD COLUMN updated_at TIMESTAMPTZ;
+
+UPDATE notifications SET updated_at = created_at;
+
+ALTER TABLE notifications
+    ALTER COLUMN updated_at SET NOT NULL,
+    ALTER COLUMN updated_at SET DEFAULT NOW();
@@ -0,0 +1,7 @@
+-- AutoModerator replies
+--
+-- Rules with a reply action answer the content they match with a comment.
+-- Those comments have no author and are flagged so clients can show them as
+-- coming from AutoModerator.
+
+ALTER TABLE comments ADD COLUMN is_automod BOOLEAN NOT NULL DEFAULT FALSE;
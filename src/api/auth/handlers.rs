@@ -15,12 +15,16 @@ use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::config::OAuthProvider;
+use crate::domain::repositories::CredentialRepository;
 use crate::domain::services::auth::{
-    AuthChallenge, ChallengeAuthService, JwtService, OAuthStateManager, PkceService,
+    AuthChallenge, ChallengeAuthService, JwtService, OAuthStateManager, PkceService, TokenPair,
 };
+use crate::domain::services::oauth::{OAuthClient, OAuthUserInfo};
 use crate::errors::{ApiError, ApiResult};
 use crate::infrastructure::crypto::CryptoService;
-use crate::middleware::auth::AuthenticatedUser;
+use crate::infrastructure::database::repositories::{oauth_credential_type, PgCredentialRepository};
+use crate::middleware::auth::{AuthenticatedUser, OptionalUser};
 use crate::AppState;
 
 use super::types::*;
@@ -207,32 +211,7 @@ pub async fn login(
         return Err(ApiError::InvalidCredentials);
     }
 
-    // Generate tokens
-    let jwt_service = JwtService::new(&state.settings.jwt)
-        .map_err(|e| ApiError::CryptoError(e.to_string()))?;
-
-    let (token_pair, refresh_hash, family_id) = jwt_service.generate_token_pair(
-        identity.id,
-        &identity.public_key_fingerprint,
-    )?;
-
-    // Store refresh token
-    let refresh_expires = Utc::now() + Duration::seconds(state.settings.jwt.refresh_token_expiry);
-
-    sqlx::query!(
-        r#"
-        INSERT INTO refresh_tokens (id, identity_id, token_hash, family_id, expires_at, created_at)
-        VALUES ($1, $2, $3, $4, $5, $6)
-        "#,
-        Uuid::new_v4(),
-        identity.id,
-        &refresh_hash,
-        family_id,
-        refresh_expires,
-        Utc::now()
-    )
-    .execute(state.db.pool())
-    .await?;
+    let token_pair = issue_token_pair(&state, identity.id, &identity.public_key_fingerprint).await?;
 
     info!(identity_id = %identity.id, "User logged in successfully");
 
@@ -384,39 +363,89 @@ pub async fn logout_all(
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Generate a token pair and persist its refresh token as a new family
+async fn issue_token_pair(
+    state: &AppState,
+    identity_id: Uuid,
+    fingerprint: &str,
+) -> ApiResult<TokenPair> {
+    let jwt_service = JwtService::new(&state.settings.jwt)
+        .map_err(|e| ApiError::CryptoError(e.to_string()))?;
+
+    let (token_pair, refresh_hash, family_id) = jwt_service.generate_token_pair(
+        identity_id,
+        fingerprint,
+    )?;
+
+    // Store refresh token
+    let refresh_expires = Utc::now() + Duration::seconds(state.settings.jwt.refresh_token_expiry);
+
+    sqlx::query!(
+        r#"
+        INSERT INTO refresh_tokens (id, identity_id, token_hash, family_id, expires_at, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        identity_id,
+        &refresh_hash,
+        family_id,
+        refresh_expires,
+        Utc::now()
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    Ok(token_pair)
+}
+
+/// Look up the configuration for a named OAuth provider
+fn oauth_provider_config<'a>(state: &'a AppState, provider: &str) -> ApiResult<&'a OAuthProvider> {
+    let provider_config = match provider {
+        "github" => state.settings.oauth.github.as_ref(),
+        "discord" => state.settings.oauth.discord.as_ref(),
+        _ => return Err(ApiError::InvalidInput(format!("Unknown OAuth provider: {}", provider))),
+    };
+
+    provider_config
+        .ok_or_else(|| ApiError::InvalidInput(format!("OAuth provider {} not configured", provider)))
+}
+
+/// Redis key suffix derived from an OAuth state value
+fn oauth_state_key(state_b64: &str) -> ApiResult<&str> {
+    state_b64.get(..32).ok_or(ApiError::InvalidOAuthState)
+}
+
 /// Start OAuth authorization flow
 pub async fn oauth_authorize(
     State(state): State<Arc<AppState>>,
     Query(request): Query<OAuthAuthorizeRequest>,
 ) -> ApiResult<Json<OAuthAuthorizeResponse>> {
     // Validate provider
-    let provider_config = match request.provider.as_str() {
-        "github" => state.settings.oauth.github.as_ref(),
-        "discord" => state.settings.oauth.discord.as_ref(),
-        _ => return Err(ApiError::InvalidInput(format!("Unknown OAuth provider: {}", request.provider))),
-    };
+    let provider_config = oauth_provider_config(&state, &request.provider)?;
 
-    let provider_config = provider_config
-        .ok_or_else(|| ApiError::InvalidInput(format!("OAuth provider {} not configured", request.provider)))?;
+    if request.code_challenge_method != "S256" {
+        return Err(ApiError::InvalidInput("Only S256 code challenges are supported".to_string()));
+    }
 
     // Generate state with HMAC protection
     let (state_b64, _) = OAuthStateManager::generate_state(&state.crypto, &request.provider);
 
     // Store PKCE code challenge in Redis
     state.redis.set(
-        &format!("oauth:pkce:{}", &state_b64[..32]),
+        &format!("oauth:pkce:{}", oauth_state_key(&state_b64)?),
         &request.code_challenge,
         Some(std::time::Duration::from_secs(600)),
     ).await?;
 
     // Build authorization URL
     let auth_url = format!(
-        "{}?client_id={}&redirect_uri={}&scope={}&state={}&response_type=code",
+        "{}?client_id={}&redirect_uri={}&scope={}&state={}&response_type=code&code_challenge={}&code_challenge_method=S256",
         provider_config.auth_url,
         urlencoding::encode(&provider_config.client_id),
         urlencoding::encode(&request.redirect_uri.as_deref().unwrap_or(&provider_config.redirect_uri)),
         urlencoding::encode(&provider_config.scopes.join(" ")),
         urlencoding::encode(&state_b64),
+        urlencoding::encode(&request.code_challenge),
     );
 
     Ok(Json(OAuthAuthorizeResponse {
@@ -444,9 +473,9 @@ pub async fn oauth_callback(
 
     // Store the authorization code temporarily
     state.redis.set(
-        &format!("oauth:code:{}", &params.state[..32]),
+        &format!("oauth:code:{}", oauth_state_key(&params.state)?),
         &params.code,
-        Some(std::time::Duration::from_secs(300)),
+        Some(std::time::Duration::from_secs(state.settings.oauth.code_expiry as u64)),
     ).await?;
 
     // Return a page/JSON indicating to exchange the code
@@ -458,24 +487,193 @@ pub async fn oauth_callback(
 }
 
 /// Exchange OAuth authorization code for tokens
+///
+/// Trades the code with the provider, then signs in the identity linked to the
+/// provider account. Unknown accounts are linked to the caller's identity when
+/// authenticated, otherwise a new identity is created for them.
 pub async fn oauth_token_exchange(
     State(state): State<Arc<AppState>>,
+    user: OptionalUser,
     Json(request): Json<OAuthTokenRequest>,
 ) -> ApiResult<Json<OAuthTokenResponse>> {
+    request.validate()?;
+
     if request.grant_type != "authorization_code" {
         return Err(ApiError::InvalidInput("Invalid grant_type".to_string()));
     }
 
-    // This is a simplified implementation
-    // In production, you would:
-    // 1. Exchange the code with the OAuth provider for their tokens
-    // 2. Fetch user info from the provider
-    // 3. Create or link the identity
-    // 4. Generate your own tokens
-
-    Err(ApiError::custom(
-        StatusCode::NOT_IMPLEMENTED,
-        "NOT_IMPLEMENTED",
-        "OAuth token exchange not fully implemented in this example",
-    ))
+    let provider = OAuthStateManager::verify_state(&state.crypto, &request.state, 600)?;
+    let provider_config = oauth_provider_config(&state, &provider)?;
+
+    // The code and challenge are single-use: fetch both, then delete regardless of outcome
+    let state_key = oauth_state_key(&request.state)?;
+    let pkce_key = format!("oauth:pkce:{}", state_key);
+    let code_key = format!("oauth:code:{}", state_key);
+
+    let challenge: Option<String> = state.redis.get(&pkce_key).await?;
+    let stored_code: Option<String> = state.redis.get(&code_key).await?;
+
+    let _ = state.redis.delete(&pkce_key).await;
+    let _ = state.redis.delete(&code_key).await;
+
+    let challenge = challenge.ok_or(ApiError::InvalidOAuthState)?;
+    let stored_code = stored_code.ok_or(ApiError::InvalidOAuthState)?;
+
+    let code_matches: bool = stored_code.as_bytes()
+        .ct_eq(request.code.as_bytes())
+        .into();
+
+    if !code_matches {
+        return Err(ApiError::InvalidOAuthState);
+    }
+
+    if !PkceService::verify(&request.code_verifier, &challenge) {
+        warn!(provider = %provider, "PKCE verification failed during OAuth token exchange");
+        return Err(ApiError::OAuthError("Invalid code verifier".to_string()));
+    }
+
+    // Trade the code with the provider and fetch the account
+    let client = OAuthClient::new()?;
+    let provider_token = client
+        .exchange_code(provider_config, &request.code, &request.code_verifier, &request.redirect_uri)
+        .await?;
+    let user_info = client.fetch_user_info(&provider, provider_config, &provider_token).await?;
+
+    let identity_id = resolve_oauth_identity(&state, &user_info, user.0.as_ref()).await?;
+
+    let identity = sqlx::query!(
+        r#"
+        SELECT id, public_key_fingerprint, display_name, karma, is_suspended
+        FROM identities
+        WHERE id = $1
+        "#,
+        identity_id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or(ApiError::InvalidCredentials)?;
+
+    if identity.is_suspended {
+        return Err(ApiError::AccountSuspended("Account is suspended".to_string()));
+    }
+
+    let token_pair = issue_token_pair(&state, identity.id, &identity.public_key_fingerprint).await?;
+
+    info!(identity_id = %identity.id, provider = %provider, "User logged in via OAuth");
+
+    Ok(Json(LoginResponse {
+        access_token: token_pair.access_token,
+        refresh_token: token_pair.refresh_token,
+        token_type: token_pair.token_type,
+        expires_in: token_pair.expires_in,
+        identity: IdentitySummary {
+            id: identity.id,
+            fingerprint: identity.public_key_fingerprint,
+            display_name: identity.display_name,
+            karma: identity.karma,
+        },
+    }))
+}
+
+/// Find the identity linked to a provider account, linking or creating one if needed
+async fn resolve_oauth_identity(
+    state: &AppState,
+    user_info: &OAuthUserInfo,
+    current_user: Option<&AuthenticatedUser>,
+) -> ApiResult<Uuid> {
+    let credentials = PgCredentialRepository::new(state.db.pool().clone());
+
+    if let Some(credential) = credentials.find_by_oauth(&user_info.provider, &user_info.subject).await? {
+        if let Some(current) = current_user {
+            if current.identity_id != credential.identity_id {
+                return Err(ApiError::Conflict(
+                    "OAuth account is already linked to another identity".to_string(),
+                ));
+            }
+        }
+        return Ok(credential.identity_id);
+    }
+
+    // Link the provider account to the signed-in identity
+    if let Some(current) = current_user {
+        credentials.create_oauth(current.identity_id, &user_info.provider, &user_info.subject).await?;
+        info!(identity_id = %current.identity_id, provider = %user_info.provider, "OAuth account linked");
+        return Ok(current.identity_id);
+    }
+
+    // OAuth identities have no Ed25519 key; derive a stable placeholder so the
+    // key and fingerprint columns stay unique without revealing the subject.
+    let placeholder_key = state.crypto.hmac_sha256(
+        format!("oauth:{}:{}", user_info.provider, user_info.subject).as_bytes(),
+    );
+    let fingerprint = CryptoService::public_key_fingerprint(&placeholder_key);
+    let display_name = user_info
+        .name
+        .as_ref()
+        .map(|name| name.chars().take(50).collect::<String>());
+
+    let id = Uuid::new_v4();
+    let now = Utc::now();
+
+    // The identity and its credential are created together. A concurrent first
+    // login for the same account derives the same placeholder key, so the
+    // loser's insert waits for the winner and then finds nothing to do.
+    let mut tx = state.db.pool().begin().await?;
+
+    let created = sqlx::query_scalar!(
+        r#"
+        INSERT INTO identities (id, public_key, public_key_fingerprint, display_name, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $5)
+        ON CONFLICT (public_key) DO NOTHING
+        RETURNING id
+        "#,
+        id,
+        &placeholder_key,
+        &fingerprint,
+        display_name,
+        now
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    // An identity left without its credential by an earlier failure is
+    // linked rather than duplicated
+    let identity_id = match created {
+        Some(id) => id,
+        None => sqlx::query_scalar!("SELECT id FROM identities WHERE public_key = $1", &placeholder_key)
+            .fetch_one(&mut *tx)
+            .await?,
+    };
+
+    let linked = sqlx::query_scalar!(
+        r#"
+        INSERT INTO credentials (identity_id, credential_type, oauth_provider, oauth_subject)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (oauth_provider, oauth_subject) WHERE oauth_provider IS NOT NULL DO NOTHING
+        RETURNING identity_id
+        "#,
+        identity_id,
+        oauth_credential_type(&user_info.provider),
+        &user_info.provider,
+        &user_info.subject
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if linked.is_none() {
+        tx.rollback().await?;
+
+        // Lost the race: use the identity the other login created
+        let credential = credentials
+            .find_by_oauth(&user_info.provider, &user_info.subject)
+            .await?
+            .ok_or_else(|| ApiError::Conflict("OAuth sign-in already in progress, please retry".to_string()))?;
+        return Ok(credential.identity_id);
+    }
+
+    tx.commit().await?;
+
+    info!(identity_id = %identity_id, provider = %user_info.provider, "New identity registered via OAuth");
+
+    Ok(identity_id)
 }
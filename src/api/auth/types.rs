@@ -127,6 +127,8 @@ pub struct OAuthCallbackParams {
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct OAuthTokenRequest {
     pub grant_type: String,
+    /// State returned by the authorization step
+    pub state: String,
     pub code: String,
     pub code_verifier: String,
     pub redirect_uri: String,
@@ -136,11 +138,4 @@ pub struct OAuthTokenRequest {
 pub type OAuthTokenResponse = LoginResponse;
 
 /// OAuth user info (from provider)
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct OAuthUserInfo {
-    pub provider: String,
-    pub subject: String,
-    pub email: Option<String>,
-    pub name: Option<String>,
-    pub avatar_url: Option<String>,
-}
+pub use crate::domain::services::oauth::OAuthUserInfo;
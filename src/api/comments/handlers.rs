@@ -1,55 +1,288 @@
 //! Comments handlers
 
-use axum::{extract::{Path, State}, http::StatusCode, Json};
+use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde_json::json;
+use sqlx::FromRow;
+use std::collections::HashMap;
 use std::sync::Arc;
 use uuid::Uuid;
 use validator::Validate;
 
+use crate::api::cursor::{Cursor, PageRequest};
 use crate::api::extractors::Pagination;
+use crate::api::feed::context::load_identity_summaries;
+use crate::api::moderation::log::{self, LogEntry};
+use crate::api::notifications::mentions::{notify_mentions, MentionSource};
+use crate::api::notifications::replies::notify_reply;
+use crate::api::revisions::record_revision;
+use crate::api::spaces::require_space_visible;
+use crate::api::spaces::automod::{self, AutomodContent, AutomodTrigger};
+use crate::api::spaces::restrictions::{require_can_contribute, Contribution};
 use crate::domain::entities::*;
+use crate::domain::repositories::VoteRepository;
+use crate::domain::services::comment_tree::{build_comment_tree, sibling_order_sql, Continuation, TreeComment};
+use crate::domain::services::markdown::render_markdown;
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::{AuthenticatedUser, OptionalUser, check_moderator};
+use crate::infrastructure::database::repositories::PgVoteRepository;
+use crate::middleware::auth::{require_space_permission, AuthenticatedUser, OptionalUser};
+use crate::websocket::{publish_channel_event, Channel};
 use crate::AppState;
 
 /// Maximum comment nesting depth to prevent abuse
-const MAX_COMMENT_DEPTH: i32 = 10;
+pub(crate) const MAX_COMMENT_DEPTH: i32 = 10;
+
+/// Upper bound on comments loaded for one tree response. Siblings are ranked
+/// before the bound applies, so a cut branch keeps its best-ranked replies and
+/// continues from there.
+const MAX_TREE_COMMENTS: i64 = 2000;
+
+/// Comments in the requested subtree, with visible reply counts. Removed
+/// comments, and comments by identities the viewer blocked, are only included
+/// while they have a live descendant. Siblings are ranked by the requested
+/// sort and cut to the page at the starting level and to the per-parent limit
+/// below it.
+fn tree_query(sort: CommentSort) -> String {
+    format!(
+        r#"
+    WITH blocked AS (
+        SELECT blocked_id FROM identity_blocks WHERE blocker_id = $6
+    ),
+    visible AS (
+        SELECT c.*, EXISTS (SELECT 1 FROM blocked b WHERE b.blocked_id = c.author_id) AS is_blocked
+        FROM comments c
+        WHERE c.post_id = $1
+          AND ((c.is_removed = false AND NOT EXISTS (SELECT 1 FROM blocked b WHERE b.blocked_id = c.author_id))
+               OR EXISTS (
+                   SELECT 1 FROM comments d
+                   WHERE d.post_id = c.post_id AND d.path LIKE c.path || '.%' AND d.is_removed = false
+                     AND NOT EXISTS (SELECT 1 FROM blocked b WHERE b.blocked_id = d.author_id)
+               ))
+    ),
+    reply_counts AS (
+        SELECT parent_id, COUNT(*) AS reply_count
+        FROM visible
+        WHERE parent_id IS NOT NULL
+        GROUP BY parent_id
+    ),
+    ranked AS (
+        SELECT v.*, COALESCE(r.reply_count, 0) AS reply_count,
+               ROW_NUMBER() OVER (PARTITION BY v.parent_id ORDER BY {order}) AS sibling_rank,
+               COUNT(*) FILTER (WHERE v.depth = $3) OVER () AS level_count
+        FROM visible v
+        LEFT JOIN reply_counts r ON r.parent_id = v.id
+        WHERE ($2::TEXT IS NULL OR v.path LIKE $2 || '.%')
+          AND v.depth >= $3 AND v.depth < $4
+    )
+    SELECT id, post_id, parent_id, author_id, is_automod, content, depth, path,
+           upvotes, downvotes, score, is_removed, removed_reason,
+           created_at, updated_at, edited_at, held_at, content_html, is_blocked,
+           reply_count, level_count
+    FROM ranked
+    WHERE CASE WHEN depth = $3 THEN sibling_rank > $7 AND sibling_rank <= $7 + $8
+               ELSE sibling_rank <= $8 END
+    ORDER BY depth, sibling_rank
+    LIMIT $5
+"#,
+        order = sibling_order_sql(sort)
+    )
+}
 
-/// List comments for a post
+#[derive(FromRow)]
+struct TreeRow {
+    #[sqlx(flatten)]
+    comment: Comment,
+    is_blocked: bool,
+    reply_count: i64,
+    level_count: i64,
+}
+
+/// List comments for a post, leaving out those by identities the viewer blocked
 pub async fn list_by_post(
     State(state): State<Arc<AppState>>,
     Path(post_id): Path<Uuid>,
     Pagination(pagination): Pagination,
+    user: OptionalUser,
 ) -> ApiResult<Json<PaginatedResponse<Comment>>> {
+    let viewer = user.0.map(|u| u.identity_id);
+    require_post_visible(&state, post_id, viewer).await?;
+
+    let page = PageRequest::<String>::new(&pagination, &state.crypto, format!("comments:{}", post_id))?;
+
+    // Paths are unique and sort threads depth-first, so they double as the keyset
     let comments = sqlx::query_as!(
         Comment,
         r#"
-        SELECT id, post_id, parent_id, author_id, content, depth, path,
+        SELECT id, post_id, parent_id, author_id, is_automod, content, depth, path,
                upvotes, downvotes, score, is_removed, removed_reason,
-               created_at, updated_at
-        FROM comments
+               created_at, updated_at, edited_at, held_at, content_html
+        FROM comments c
         WHERE post_id = $1 AND is_removed = false
-        ORDER BY path, created_at
+              AND ($4::TEXT IS NULL OR path > $4)
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $5 AND b.blocked_id = c.author_id
+              )
+        ORDER BY path
         LIMIT $2 OFFSET $3
         "#,
         post_id,
-        pagination.limit,
-        pagination.offset
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        viewer
     )
     .fetch_all(state.db.pool())
     .await?;
 
-    let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM comments WHERE post_id = $1 AND is_removed = false",
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) FROM comments c
+            WHERE post_id = $1 AND is_removed = false
+                  AND NOT EXISTS (
+                      SELECT 1 FROM identity_blocks b
+                      WHERE b.blocker_id = $2 AND b.blocked_id = c.author_id
+                  )
+            "#,
+            post_id,
+            viewer
+        )
+        .fetch_one(state.db.pool())
+        .await?
+        .unwrap_or(0);
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(comments, &state.crypto, |c| Cursor::new(c.path.clone(), c.id), total)
+        .await?;
+
+    Ok(Json(response))
+}
+
+/// Nested comment tree for a post.
+///
+/// Continuation tokens from `more` and `more_replies` placeholders load the
+/// rest of a truncated level or branch.
+pub async fn tree_by_post(
+    State(state): State<Arc<AppState>>,
+    Path(post_id): Path<Uuid>,
+    Query(params): Query<CommentTreeParams>,
+    user: OptionalUser,
+) -> ApiResult<Json<CommentTree>> {
+    params.validate()?;
+
+    let viewer = user.0.as_ref().map(|u| u.identity_id);
+    require_post_visible(&state, post_id, viewer).await?;
+
+    let scope = format!("comment_tree:{}:{:?}", post_id, params.sort);
+    let start = match &params.continuation {
+        Some(token) => Cursor::<Continuation>::decode(token, &state.crypto, &scope)?.key,
+        None => Continuation::root(),
+    };
+
+    let (path_prefix, start_depth) = match start.parent_id {
+        Some(parent_id) => {
+            let parent = sqlx::query!(
+                "SELECT path, depth FROM comments WHERE id = $1 AND post_id = $2",
+                parent_id,
+                post_id
+            )
+            .fetch_optional(state.db.pool())
+            .await?
+            .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
+
+            (Some(parent.path), parent.depth + 1)
+        }
+        None => (None, 0),
+    };
+
+    let rows = sqlx::query_as::<_, TreeRow>(&tree_query(params.sort))
+        .bind(post_id)
+        .bind(path_prefix)
+        .bind(start_depth)
+        .bind(start_depth + params.max_depth)
+        .bind(MAX_TREE_COMMENTS)
+        .bind(viewer)
+        .bind(start.skip as i64)
+        .bind(params.limit)
+        .fetch_all(state.db.pool())
+        .await?;
+
+    let level_count = rows.first().map_or(0, |row| row.level_count);
+    let comments: Vec<TreeComment> = rows
+        .into_iter()
+        .map(|row| TreeComment {
+            comment: row.comment,
+            reply_count: row.reply_count,
+            blocked: row.is_blocked,
+        })
+        .collect();
+
+    let author_ids: Vec<Uuid> = comments
+        .iter()
+        .filter(|c| !c.comment.is_removed && !c.blocked)
+        .filter_map(|c| c.comment.author_id)
+        .collect();
+    let authors = load_identity_summaries(&state, &author_ids).await?;
+
+    let votes: HashMap<Uuid, i16> = match &user.0 {
+        Some(u) => {
+            let comment_ids: Vec<Uuid> = comments.iter().map(|c| c.comment.id).collect();
+            PgVoteRepository::new(state.db.pool().clone())
+                .find_many(u.identity_id, VoteTargetType::Comment, &comment_ids)
+                .await?
+                .into_iter()
+                .map(|vote| (vote.target_id, vote.vote_value))
+                .collect()
+        }
+        None => HashMap::new(),
+    };
+
+    let mut tree = build_comment_tree(
+        comments,
+        start,
+        level_count,
+        params.sort,
+        params.limit as usize,
+        &mut |at, count| MoreComments {
+            count,
+            continuation: Cursor::new(at, post_id).encode(&state.crypto, &scope),
+        },
+    );
+
+    attach_comment_context(&mut tree.comments, &authors, &votes);
+
+    Ok(Json(tree))
+}
+
+/// Check the post exists, is not removed and is in a space the viewer can see
+async fn require_post_visible(state: &AppState, post_id: Uuid, viewer: Option<Uuid>) -> ApiResult<()> {
+    let post = sqlx::query!(
+        r#"SELECT space_id, is_removed as "is_removed!" FROM posts WHERE id = $1"#,
         post_id
     )
-    .fetch_one(state.db.pool())
+    .fetch_optional(state.db.pool())
     .await?
-    .unwrap_or(0);
+    .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
 
-    Ok(Json(PaginatedResponse {
-        data: comments,
-        pagination: PaginationInfo::new(total, pagination.limit, pagination.offset),
-    }))
+    if post.is_removed {
+        return Err(ApiError::Gone);
+    }
+
+    require_space_visible(state, post.space_id, viewer).await
+}
+
+fn attach_comment_context(
+    comments: &mut [CommentWithContext],
+    authors: &HashMap<Uuid, IdentityPublic>,
+    votes: &HashMap<Uuid, i16>,
+) {
+    for node in comments {
+        node.author = node.comment.author_id.and_then(|id| authors.get(&id).cloned());
+        node.user_vote = votes.get(&node.comment.id).copied();
+        attach_comment_context(&mut node.replies, authors, votes);
+    }
 }
 
 /// Create a comment
@@ -62,10 +295,18 @@ pub async fn create(
     request.validate()?;
 
     // Check post exists and is not locked
-    let post = sqlx::query!("SELECT is_locked, is_removed FROM posts WHERE id = $1", post_id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+    let post = sqlx::query!(
+        r#"
+        SELECT p.space_id, p.author_id, p.is_locked, p.is_removed, s.allowed_tags
+        FROM posts p
+        JOIN spaces s ON s.id = p.space_id
+        WHERE p.id = $1
+        "#,
+        post_id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
 
     if post.is_locked {
         return Err(ApiError::OperationNotAllowed("Post is locked".to_string()));
@@ -75,10 +316,12 @@ pub async fn create(
         return Err(ApiError::Gone);
     }
 
-    // Calculate depth and path
-    let (depth, path) = if let Some(parent_id) = request.parent_id {
+    require_can_contribute(&state, post.space_id, user.identity_id, Contribution::Comment).await?;
+
+    // Calculate depth and path, and whose content is being replied to
+    let (depth, path, replied_to_author) = if let Some(parent_id) = request.parent_id {
         let parent = sqlx::query!(
-            "SELECT depth, path, post_id FROM comments WHERE id = $1",
+            "SELECT depth, path, post_id, author_id, is_removed FROM comments WHERE id = $1",
             parent_id
         )
         .fetch_optional(state.db.pool())
@@ -100,22 +343,26 @@ pub async fn create(
             )));
         }
 
-        (new_depth, format!("{}.{}", parent.path, parent_id))
+        let parent_author = parent.author_id.filter(|_| parent.is_removed != Some(true));
+        (new_depth, format!("{}.{}", parent.path, parent_id), parent_author)
     } else {
-        (0, "".to_string())
+        (0, "".to_string(), post.author_id)
     };
 
     let id = Uuid::new_v4();
     let full_path = if path.is_empty() { id.to_string() } else { format!("{}.{}", path, id) };
 
-    let comment = sqlx::query_as!(
+    // The comment is checked by AutoModerator before anyone can see it
+    let mut tx = state.db.pool().begin().await?;
+
+    let mut comment = sqlx::query_as!(
         Comment,
         r#"
-        INSERT INTO comments (id, post_id, parent_id, author_id, content, depth, path, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, NOW(), NOW())
-        RETURNING id, post_id, parent_id, author_id, content, depth, path,
+        INSERT INTO comments (id, post_id, parent_id, author_id, content, content_html, depth, path, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $8, $6, $7, NOW(), NOW())
+        RETURNING id, post_id, parent_id, author_id, is_automod, content, depth, path,
                   upvotes, downvotes, score, is_removed, removed_reason,
-                  created_at, updated_at
+                  created_at, updated_at, edited_at, held_at, content_html
         "#,
         id,
         post_id,
@@ -123,34 +370,72 @@ pub async fn create(
         user.identity_id,
         request.content,
         depth,
-        full_path
+        full_path,
+        render_markdown(&request.content, post.allowed_tags.as_deref())
     )
-    .fetch_one(state.db.pool())
+    .fetch_one(&mut *tx)
     .await?;
 
-    // Increment comment count
-    sqlx::query!("UPDATE posts SET comment_count = comment_count + 1 WHERE id = $1", post_id)
-        .execute(state.db.pool())
-        .await?;
+    // Counts towards the post's comments; held comments stop counting until
+    // they are released
+    let automod_content = AutomodContent::Comment { post_id, comment_id: comment.id };
+    automod::tally(&mut tx, automod_content, 1).await?;
+    let outcome = automod::moderate(&mut tx, automod_content, AutomodTrigger::Write).await?;
+
+    tx.commit().await?;
+
+    outcome.update_comment(&mut comment);
+
+    automod::notify(&state, automod_content, &outcome).await;
+
+    if comment.is_removed {
+        return Ok((StatusCode::CREATED, Json(comment)));
+    }
+
+    notify_reply(&state, &comment, replied_to_author).await;
+    notify_mentions(
+        &state,
+        user.identity_id,
+        MentionSource::Comment { post_id, comment_id: comment.id },
+        None,
+        &comment.content,
+    )
+    .await;
+
+    publish_channel_event(
+        &state,
+        &Channel::Post(post_id),
+        "comment_created",
+        serde_json::to_value(&comment)?,
+    )
+    .await;
 
     Ok((StatusCode::CREATED, Json(comment)))
 }
 
-/// Get comment by ID
+/// Get comment by ID. Comments by identities the viewer blocked are not found.
 pub async fn get_by_id(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
+    user: OptionalUser,
 ) -> ApiResult<Json<Comment>> {
+    let viewer = user.0.map(|u| u.identity_id);
+
     let comment = sqlx::query_as!(
         Comment,
         r#"
-        SELECT id, post_id, parent_id, author_id, content, depth, path,
+        SELECT id, post_id, parent_id, author_id, is_automod, content, depth, path,
                upvotes, downvotes, score, is_removed, removed_reason,
-               created_at, updated_at
-        FROM comments
+               created_at, updated_at, edited_at, held_at, content_html
+        FROM comments c
         WHERE id = $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $2 AND b.blocked_id = c.author_id
+              )
         "#,
-        id
+        id,
+        viewer
     )
     .fetch_optional(state.db.pool())
     .await?
@@ -168,30 +453,75 @@ pub async fn update(
 ) -> ApiResult<Json<Comment>> {
     request.validate()?;
 
-    let comment = sqlx::query!("SELECT author_id FROM comments WHERE id = $1", id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
+    let mut tx = state.db.pool().begin().await?;
+
+    let comment = sqlx::query!(
+        r#"
+        SELECT c.author_id, c.content, s.allowed_tags
+        FROM comments c
+        JOIN posts p ON p.id = c.post_id
+        JOIN spaces s ON s.id = p.space_id
+        WHERE c.id = $1
+        FOR UPDATE OF c
+        "#,
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
 
     if comment.author_id != Some(user.identity_id) {
         return Err(ApiError::Forbidden);
     }
 
-    let updated = sqlx::query_as!(
+    let changed = request.content != comment.content;
+    if changed {
+        record_revision(&mut *tx, RevisionTargetType::Comment, id, user.identity_id, Some(&comment.content))
+            .await?;
+    }
+
+    let mut updated = sqlx::query_as!(
         Comment,
         r#"
-        UPDATE comments SET content = $2, updated_at = NOW()
+        UPDATE comments
+        SET content = $2, content_html = $4, updated_at = NOW(),
+            edited_at = CASE WHEN $3 THEN NOW() ELSE edited_at END
         WHERE id = $1
-        RETURNING id, post_id, parent_id, author_id, content, depth, path,
+        RETURNING id, post_id, parent_id, author_id, is_automod, content, depth, path,
                   upvotes, downvotes, score, is_removed, removed_reason,
-                  created_at, updated_at
+                  created_at, updated_at, edited_at, held_at, content_html
         "#,
         id,
-        request.content
+        request.content,
+        changed,
+        render_markdown(&request.content, comment.allowed_tags.as_deref())
     )
-    .fetch_one(state.db.pool())
+    .fetch_one(&mut *tx)
     .await?;
 
+    let automod_content = AutomodContent::Comment { post_id: updated.post_id, comment_id: id };
+    let outcome = if changed {
+        automod::moderate(&mut tx, automod_content, AutomodTrigger::Write).await?
+    } else {
+        Default::default()
+    };
+
+    tx.commit().await?;
+
+    outcome.update_comment(&mut updated);
+    automod::notify(&state, automod_content, &outcome).await;
+
+    if changed && !updated.is_removed {
+        notify_mentions(
+            &state,
+            user.identity_id,
+            MentionSource::Comment { post_id: updated.post_id, comment_id: id },
+            Some(&comment.content),
+            &updated.content,
+        )
+        .await;
+    }
+
     Ok(Json(updated))
 }
 
@@ -202,7 +532,7 @@ pub async fn delete(
     user: AuthenticatedUser,
 ) -> ApiResult<StatusCode> {
     let comment = sqlx::query!(
-        "SELECT author_id, post_id FROM comments WHERE id = $1",
+        "SELECT c.author_id, p.space_id FROM comments c JOIN posts p ON p.id = c.post_id WHERE c.id = $1",
         id
     )
     .fetch_optional(state.db.pool())
@@ -213,14 +543,7 @@ pub async fn delete(
 
     // If not the author, check if user is a moderator of the post's space
     if !is_author {
-        let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", comment.post_id)
-            .fetch_optional(state.db.pool())
-            .await?
-            .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
-
-        if !check_moderator(&state, user.identity_id, post.space_id).await? {
-            return Err(ApiError::Forbidden);
-        }
+        require_space_permission(&state, user.identity_id, comment.space_id, SpacePermission::ManagePosts).await?;
     }
 
     let reason = if is_author {
@@ -229,51 +552,119 @@ pub async fn delete(
         "Removed by moderator"
     };
 
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = sqlx::query!(
+        r#"SELECT is_removed as "is_removed!", removed_reason FROM comments WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
     sqlx::query!(
-        "UPDATE comments SET is_removed = true, removed_reason = $2 WHERE id = $1",
+        "UPDATE comments SET is_removed = true, removed_reason = $2, held_at = NULL WHERE id = $1",
         id,
         reason
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
     .await?;
 
+    if !is_author {
+        log::record(
+            &mut tx,
+            LogEntry {
+                space_id: Some(comment.space_id),
+                actor_id: Some(user.identity_id),
+                action: ModerationLogAction::RemoveComment,
+                target_type: ModerationTargetType::Comment,
+                target_id: id,
+                reason: None,
+                before: Some(json!({ "is_removed": before.is_removed, "removed_reason": before.removed_reason })),
+                after: Some(json!({ "is_removed": true, "removed_reason": reason })),
+            },
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
-/// List replies to a comment
+/// List replies to a comment, leaving out those by identities the viewer blocked
 pub async fn list_replies(
     State(state): State<Arc<AppState>>,
     Path(parent_id): Path<Uuid>,
     Pagination(pagination): Pagination,
+    user: OptionalUser,
 ) -> ApiResult<Json<PaginatedResponse<Comment>>> {
+    let viewer = user.0.map(|u| u.identity_id);
+
+    let post_id = sqlx::query_scalar!("SELECT post_id FROM comments WHERE id = $1", parent_id)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
+    require_post_visible(&state, post_id, viewer).await?;
+
+    let page = PageRequest::<(i32, chrono::DateTime<chrono::Utc>)>::new(
+        &pagination,
+        &state.crypto,
+        format!("replies:{}", parent_id),
+    )?;
+    let (after_score, after_created_at) = page.after_key().unzip();
+
+    // Highest score first, oldest first among ties
     let comments = sqlx::query_as!(
         Comment,
         r#"
-        SELECT id, post_id, parent_id, author_id, content, depth, path,
+        SELECT id, post_id, parent_id, author_id, is_automod, content, depth, path,
                upvotes, downvotes, score, is_removed, removed_reason,
-               created_at, updated_at
-        FROM comments
+               created_at, updated_at, edited_at, held_at, content_html
+        FROM comments c
         WHERE parent_id = $1 AND is_removed = false
-        ORDER BY score DESC, created_at
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $7 AND b.blocked_id = c.author_id
+              )
+              AND ($4::INTEGER IS NULL
+                   OR score < $4
+                   OR (score = $4 AND (created_at, id) > ($5, $6)))
+        ORDER BY score DESC, created_at, id
         LIMIT $2 OFFSET $3
         "#,
         parent_id,
-        pagination.limit,
-        pagination.offset
+        page.fetch_limit(),
+        page.offset,
+        after_score,
+        after_created_at,
+        page.after_id(),
+        viewer
     )
     .fetch_all(state.db.pool())
     .await?;
 
-    let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM comments WHERE parent_id = $1 AND is_removed = false",
-        parent_id
-    )
-    .fetch_one(state.db.pool())
-    .await?
-    .unwrap_or(0);
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) FROM comments c
+            WHERE parent_id = $1 AND is_removed = false
+                  AND NOT EXISTS (
+                      SELECT 1 FROM identity_blocks b
+                      WHERE b.blocker_id = $2 AND b.blocked_id = c.author_id
+                  )
+            "#,
+            parent_id,
+            viewer
+        )
+        .fetch_one(state.db.pool())
+        .await?
+        .unwrap_or(0);
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(comments, &state.crypto, |c| Cursor::new((c.score, c.created_at), c.id), total)
+        .await?;
 
-    Ok(Json(PaginatedResponse {
-        data: comments,
-        pagination: PaginationInfo::new(total, pagination.limit, pagination.offset),
-    }))
+    Ok(Json(response))
 }
@@ -0,0 +1,186 @@
+//! Keyset pagination cursors
+//!
+//! A cursor records the sort key and id of the last item on a page. It is
+//! signed with the server HMAC key and bound to the listing that issued it, so
+//! clients can pass it back verbatim but cannot forge or transplant one.
+//! Listings accept either `cursor` or the older `offset` parameter.
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::future::Future;
+use uuid::Uuid;
+
+use crate::domain::entities::{PaginatedResponse, PaginationInfo, PaginationParams};
+use crate::errors::{ApiError, ApiResult};
+use crate::infrastructure::crypto::CryptoService;
+
+/// Position after the last item of a page
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor<K> {
+    pub key: K,
+    pub id: Uuid,
+}
+
+impl<K: Serialize + DeserializeOwned> Cursor<K> {
+    /// Create a cursor positioned after the item with this sort key and id
+    pub fn new(key: K, id: Uuid) -> Self {
+        Self { key, id }
+    }
+
+    /// Encode and sign the cursor for the given listing scope
+    pub fn encode(&self, crypto: &CryptoService, scope: &str) -> String {
+        let payload = serde_json::to_vec(self).expect("cursor keys always serialize");
+
+        crypto.sign_token(&signing_context(scope), &payload)
+    }
+
+    /// Verify and decode a cursor issued for the given listing scope
+    pub fn decode(token: &str, crypto: &CryptoService, scope: &str) -> ApiResult<Self> {
+        let invalid = || ApiError::InvalidInput("Invalid cursor".to_string());
+
+        let payload = crypto.verify_token(&signing_context(scope), token).ok_or_else(invalid)?;
+
+        serde_json::from_slice(&payload).map_err(|_| invalid())
+    }
+}
+
+/// Context the cursor MAC covers ahead of the payload
+fn signing_context(scope: &str) -> Vec<u8> {
+    let mut context = Vec::with_capacity(scope.len() + 8);
+    context.extend_from_slice(b"cursor:");
+    context.extend_from_slice(scope.as_bytes());
+    context.push(0);
+    context
+}
+
+/// A page request resolved to either keyset or offset pagination
+#[derive(Debug)]
+pub struct PageRequest<K> {
+    /// Decoded cursor, if the client sent one
+    pub after: Option<Cursor<K>>,
+    pub limit: i64,
+    /// Always 0 when paginating by cursor
+    pub offset: i64,
+    scope: String,
+}
+
+impl<K: Serialize + DeserializeOwned + Clone> PageRequest<K> {
+    /// Resolve pagination parameters for a listing.
+    ///
+    /// `scope` identifies the listing, including anything that changes its
+    /// ordering, so cursors cannot be replayed against a different listing.
+    pub fn new(params: &PaginationParams, crypto: &CryptoService, scope: impl Into<String>) -> ApiResult<Self> {
+        let scope = scope.into();
+        let after = params
+            .cursor
+            .as_deref()
+            .map(|token| Cursor::decode(token, crypto, &scope))
+            .transpose()?;
+        let offset = if after.is_some() { 0 } else { params.offset };
+
+        Ok(Self {
+            after,
+            limit: params.limit,
+            offset,
+            scope,
+        })
+    }
+
+    /// Rows to fetch: one more than the limit to detect a following page
+    pub fn fetch_limit(&self) -> i64 {
+        self.limit + 1
+    }
+
+    /// Sort key of the cursor, if any
+    pub fn after_key(&self) -> Option<K> {
+        self.after.as_ref().map(|cursor| cursor.key.clone())
+    }
+
+    /// Id of the cursor, if any
+    pub fn after_id(&self) -> Option<Uuid> {
+        self.after.as_ref().map(|cursor| cursor.id)
+    }
+
+    /// Build the response from rows fetched with [`fetch_limit`](Self::fetch_limit).
+    ///
+    /// `total` is only awaited for offset pagination; cursor pages skip the count.
+    pub async fn into_response<T>(
+        self,
+        mut rows: Vec<T>,
+        crypto: &CryptoService,
+        cursor_of: impl Fn(&T) -> Cursor<K>,
+        total: impl Future<Output = ApiResult<i64>>,
+    ) -> ApiResult<PaginatedResponse<T>> {
+        let has_more = rows.len() as i64 > self.limit;
+        rows.truncate(self.limit.max(0) as usize);
+
+        let next_cursor = if has_more {
+            rows.last().map(|row| cursor_of(row).encode(crypto, &self.scope))
+        } else {
+            None
+        };
+
+        let pagination = if self.after.is_some() {
+            PaginationInfo::from_cursor(self.limit, has_more, next_cursor)
+        } else {
+            PaginationInfo {
+                next_cursor,
+                ..PaginationInfo::new(total.await?, self.limit, self.offset)
+            }
+        };
+
+        Ok(PaginatedResponse {
+            data: rows,
+            pagination,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::crypto::testing::crypto;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64, Engine};
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let crypto = crypto();
+        let cursor = Cursor::new((42i32, "2024-01-01T00:00:00Z".to_string()), Uuid::new_v4());
+
+        let token = cursor.encode(&crypto, "comments:1");
+        assert_eq!(Cursor::decode(&token, &crypto, "comments:1").unwrap(), cursor);
+    }
+
+    #[test]
+    fn test_float_key_round_trips_exactly() {
+        let crypto = crypto();
+        // Ranks are compared for equality at page boundaries, so no ULP may be lost
+        for rank in [0.1 + 0.2, 1.0 / 3.0, 12_345.678_901_234_567, f64::MIN_POSITIVE] {
+            let cursor = Cursor::new(rank, Uuid::new_v4());
+            let token = cursor.encode(&crypto, "feed:rising");
+            let decoded = Cursor::<f64>::decode(&token, &crypto, "feed:rising").unwrap();
+            assert_eq!(decoded.key.to_bits(), rank.to_bits());
+        }
+    }
+
+    #[test]
+    fn test_cursor_is_bound_to_scope() {
+        let crypto = crypto();
+        let token = Cursor::new(1.5f64, Uuid::new_v4()).encode(&crypto, "feed:hot");
+
+        assert!(Cursor::<f64>::decode(&token, &crypto, "feed:new").is_err());
+    }
+
+    #[test]
+    fn test_tampered_cursor_rejected() {
+        let crypto = crypto();
+        let token = Cursor::new(10i64, Uuid::new_v4()).encode(&crypto, "notifications");
+        let (_, mac) = token.split_once('.').unwrap();
+
+        let forged = Cursor::new(1_000i64, Uuid::new_v4());
+        let forged_payload = BASE64.encode(serde_json::to_vec(&forged).unwrap());
+        let forged_token = format!("{}.{}", forged_payload, mac);
+
+        assert!(Cursor::<i64>::decode(&forged_token, &crypto, "notifications").is_err());
+        assert!(Cursor::<i64>::decode("garbage", &crypto, "notifications").is_err());
+    }
+}
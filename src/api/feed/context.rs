@@ -0,0 +1,305 @@
+//! Batched loading of post context for listings
+//!
+//! Authors, spaces, polls, link previews and the viewer's votes for a page of posts are
+//! loaded with one query each. Author and space summaries are also cached in
+//! Redis for `summary_cache_ttl` seconds, and dropped by the handlers that
+//! change them; cache failures fall back to the database.
+
+use chrono::Utc;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::domain::entities::*;
+use crate::domain::repositories::VoteRepository;
+use crate::domain::services::polls::poll_view;
+use crate::errors::ApiResult;
+use crate::infrastructure::database::repositories::PgVoteRepository;
+use crate::AppState;
+
+/// Cache key for an identity summary
+pub(crate) fn identity_summary_key(identity_id: Uuid) -> String {
+    format!("summary:identity:{}", identity_id)
+}
+
+/// Cache key for a space summary
+pub(crate) fn space_summary_key(space_id: Uuid) -> String {
+    format!("summary:space:{}", space_id)
+}
+
+/// Drop a cached summary after the underlying row changes
+pub(crate) async fn invalidate_summary(state: &AppState, key: &str) {
+    if let Err(e) = state.redis.delete(key).await {
+        warn!(error = %e, key = %key, "Failed to invalidate cached summary");
+    }
+}
+
+/// Attach author, space and the viewer's vote to each post
+pub(crate) async fn load_post_context(
+    state: &AppState,
+    posts: Vec<Post>,
+    viewer: Option<Uuid>,
+) -> ApiResult<Vec<PostWithContext>> {
+    let author_ids: Vec<Uuid> = unique(posts.iter().filter_map(|p| p.author_id));
+    let space_ids: Vec<Uuid> = unique(posts.iter().map(|p| p.space_id));
+    let post_ids: Vec<Uuid> = posts.iter().map(|p| p.id).collect();
+    let poll_ids: Vec<Uuid> = posts
+        .iter()
+        .filter(|p| p.content_type == ContentType::Poll)
+        .map(|p| p.id)
+        .collect();
+    let link_ids: Vec<Uuid> = posts
+        .iter()
+        .filter(|p| p.content_type == ContentType::Link)
+        .map(|p| p.id)
+        .collect();
+
+    let authors = load_identity_summaries(state, &author_ids).await?;
+    let spaces = load_space_summaries(state, &space_ids).await?;
+    let mut polls = load_poll_views(state, &poll_ids, viewer).await?;
+    let mut previews = load_link_previews(state, &link_ids).await?;
+
+    let votes: HashMap<Uuid, i16> = match viewer {
+        Some(identity_id) => PgVoteRepository::new(state.db.pool().clone())
+            .find_many(identity_id, VoteTargetType::Post, &post_ids)
+            .await?
+            .into_iter()
+            .map(|vote| (vote.target_id, vote.vote_value))
+            .collect(),
+        None => HashMap::new(),
+    };
+
+    Ok(posts
+        .into_iter()
+        .map(|post| PostWithContext {
+            author: post.author_id.and_then(|id| authors.get(&id).cloned()),
+            space: spaces.get(&post.space_id).cloned(),
+            user_vote: votes.get(&post.id).copied(),
+            poll: polls.remove(&post.id),
+            preview: previews.remove(&post.id),
+            post,
+        })
+        .collect())
+}
+
+/// Attach context to every post on a page
+pub(crate) async fn load_page_context(
+    state: &AppState,
+    page: PaginatedResponse<Post>,
+    viewer: Option<Uuid>,
+) -> ApiResult<PaginatedResponse<PostWithContext>> {
+    Ok(PaginatedResponse {
+        data: load_post_context(state, page.data, viewer).await?,
+        pagination: page.pagination,
+    })
+}
+
+/// Load public identity summaries by id
+pub(crate) async fn load_identity_summaries(
+    state: &AppState,
+    ids: &[Uuid],
+) -> ApiResult<HashMap<Uuid, IdentityPublic>> {
+    let mut found: HashMap<Uuid, IdentityPublic> =
+        cached(state, ids, identity_summary_key).await;
+
+    let missing: Vec<Uuid> = ids.iter().copied().filter(|id| !found.contains_key(id)).collect();
+    if missing.is_empty() {
+        return Ok(found);
+    }
+
+    let loaded = sqlx::query_as!(
+        IdentityPublic,
+        r#"
+        SELECT id, public_key_fingerprint, display_name, avatar_hash, bio,
+               karma as "karma!", is_verified as "is_verified!", created_at
+        FROM identities
+        WHERE id = ANY($1)
+        "#,
+        &missing
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    store(state, &loaded, |identity| identity_summary_key(identity.id)).await;
+    found.extend(loaded.into_iter().map(|identity| (identity.id, identity)));
+
+    Ok(found)
+}
+
+/// Load space summaries by id
+pub(crate) async fn load_space_summaries(
+    state: &AppState,
+    ids: &[Uuid],
+) -> ApiResult<HashMap<Uuid, SpaceSummary>> {
+    let mut found: HashMap<Uuid, SpaceSummary> = cached(state, ids, space_summary_key).await;
+
+    let missing: Vec<Uuid> = ids.iter().copied().filter(|id| !found.contains_key(id)).collect();
+    if missing.is_empty() {
+        return Ok(found);
+    }
+
+    let loaded = sqlx::query_as!(
+        SpaceSummary,
+        r#"
+        SELECT id, name, slug, icon_url, subscriber_count as "subscriber_count!"
+        FROM spaces
+        WHERE id = ANY($1)
+        "#,
+        &missing
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    store(state, &loaded, |space| space_summary_key(space.id)).await;
+    found.extend(loaded.into_iter().map(|space| (space.id, space)));
+
+    Ok(found)
+}
+
+/// Load the viewer's view of each poll among `post_ids`. Polls in private
+/// spaces the viewer is not a member of are left out.
+pub(crate) async fn load_poll_views(
+    state: &AppState,
+    post_ids: &[Uuid],
+    viewer: Option<Uuid>,
+) -> ApiResult<HashMap<Uuid, PollView>> {
+    if post_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let polls = sqlx::query_as!(
+        Poll,
+        r#"
+        SELECT pl.post_id, pl.allows_multiple, pl.closes_at, pl.total_voters, pl.created_at
+        FROM polls pl
+        JOIN posts p ON p.id = pl.post_id
+        JOIN spaces s ON s.id = p.space_id
+        WHERE pl.post_id = ANY($1)
+          AND (s.is_private IS NOT TRUE
+               OR EXISTS(SELECT 1 FROM space_members sm WHERE sm.space_id = s.id AND sm.identity_id = $2))
+        "#,
+        post_ids,
+        viewer
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    if polls.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let poll_ids: Vec<Uuid> = polls.iter().map(|poll| poll.post_id).collect();
+
+    let mut options: HashMap<Uuid, Vec<PollOption>> = HashMap::new();
+    for option in sqlx::query_as!(
+        PollOption,
+        "SELECT id, post_id, position, label, vote_count FROM poll_options WHERE post_id = ANY($1)",
+        &poll_ids
+    )
+    .fetch_all(state.db.pool())
+    .await?
+    {
+        options.entry(option.post_id).or_default().push(option);
+    }
+
+    let mut ballots: HashMap<Uuid, Vec<Uuid>> = match viewer {
+        Some(identity_id) => sqlx::query!(
+            "SELECT post_id, option_ids FROM poll_ballots WHERE identity_id = $1 AND post_id = ANY($2)",
+            identity_id,
+            &poll_ids
+        )
+        .fetch_all(state.db.pool())
+        .await?
+        .into_iter()
+        .map(|ballot| (ballot.post_id, ballot.option_ids))
+        .collect(),
+        None => HashMap::new(),
+    };
+
+    let now = Utc::now();
+    Ok(polls
+        .into_iter()
+        .map(|poll| {
+            let options = options.remove(&poll.post_id).unwrap_or_default();
+            let choices = ballots.remove(&poll.post_id);
+            (poll.post_id, poll_view(&poll, options, choices, now))
+        })
+        .collect())
+}
+
+/// Load link previews by post id
+pub(crate) async fn load_link_previews(
+    state: &AppState,
+    post_ids: &[Uuid],
+) -> ApiResult<HashMap<Uuid, LinkPreview>> {
+    if post_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let previews = sqlx::query_as!(
+        LinkPreview,
+        r#"
+        SELECT post_id, url, title, description, site_name, image_id, fetched_at
+        FROM link_previews
+        WHERE post_id = ANY($1)
+        "#,
+        post_ids
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(previews.into_iter().map(|preview| (preview.post_id, preview)).collect())
+}
+
+fn summary_ttl(state: &AppState) -> Option<Duration> {
+    match state.settings.redis.summary_cache_ttl {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    }
+}
+
+/// Summaries found in the cache, keyed by id
+async fn cached<T>(state: &AppState, ids: &[Uuid], key: fn(Uuid) -> String) -> HashMap<Uuid, T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    if ids.is_empty() || summary_ttl(state).is_none() {
+        return HashMap::new();
+    }
+
+    let keys: Vec<String> = ids.iter().map(|id| key(*id)).collect();
+
+    match state.redis.get_many::<T>(&keys).await {
+        Ok(values) => ids
+            .iter()
+            .zip(values)
+            .filter_map(|(id, value)| value.map(|v| (*id, v)))
+            .collect(),
+        Err(e) => {
+            warn!(error = %e, "Summary cache unavailable, loading from database");
+            HashMap::new()
+        }
+    }
+}
+
+/// Cache freshly loaded summaries
+async fn store<T>(state: &AppState, items: &[T], key: impl Fn(&T) -> String)
+where
+    T: serde::Serialize + Clone,
+{
+    let Some(ttl) = summary_ttl(state) else {
+        return;
+    };
+
+    let entries: Vec<(String, T)> = items.iter().map(|item| (key(item), item.clone())).collect();
+
+    if let Err(e) = state.redis.set_many(&entries, ttl).await {
+        warn!(error = %e, "Failed to cache summaries");
+    }
+}
+
+fn unique(ids: impl Iterator<Item = Uuid>) -> Vec<Uuid> {
+    let mut seen = HashSet::new();
+    ids.filter(|id| seen.insert(*id)).collect()
+}
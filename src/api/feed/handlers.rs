@@ -1,172 +1,51 @@
 //! Feed handlers with ranking algorithms
+//!
+//! Every feed honours the `sort` and `time_range` query parameters.
 
 use axum::{extract::{Query, State}, Json};
 use std::sync::Arc;
 
-use crate::api::extractors::Pagination;
 use crate::domain::entities::*;
 use crate::errors::ApiResult;
 use crate::middleware::auth::{AuthenticatedUser, OptionalUser};
 use crate::AppState;
 
+use super::context::load_page_context;
+use super::query::{fetch_ranked_posts, FeedScope};
+
 /// Get personalized feed (posts from subscribed spaces)
 pub async fn personalized_feed(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Query(params): Query<FeedParams>,
 ) -> ApiResult<Json<PaginatedResponse<PostWithContext>>> {
-    let posts = sqlx::query_as!(
-        Post,
-        r#"
-        SELECT p.id, p.space_id, p.author_id, p.title, p.content,
-               p.content_type as "content_type: ContentType",
-               p.url, p.media_ids, p.upvotes, p.downvotes, p.score, p.comment_count,
-               p.is_pinned, p.is_locked, p.is_removed, p.removed_reason,
-               p.created_at, p.updated_at
-        FROM posts p
-        JOIN space_members sm ON sm.space_id = p.space_id
-        WHERE sm.identity_id = $1 AND p.is_removed = false
-        ORDER BY p.score DESC, p.created_at DESC
-        LIMIT $2 OFFSET $3
-        "#,
-        user.identity_id,
-        params.pagination.limit,
-        params.pagination.offset
-    )
-    .fetch_all(state.db.pool())
-    .await?;
-
-    let posts_with_context: Vec<PostWithContext> = posts
-        .into_iter()
-        .map(|post| PostWithContext {
-            post,
-            author: None,
-            space: None,
-            user_vote: None,
-        })
-        .collect();
+    let page =
+        fetch_ranked_posts(&state, FeedScope::Subscribed(user.identity_id), &params, Some(user.identity_id)).await?;
 
-    let total: i64 = sqlx::query_scalar!(
-        r#"
-        SELECT COUNT(*)
-        FROM posts p
-        JOIN space_members sm ON sm.space_id = p.space_id
-        WHERE sm.identity_id = $1 AND p.is_removed = false
-        "#,
-        user.identity_id
-    )
-    .fetch_one(state.db.pool())
-    .await?
-    .unwrap_or(0);
-
-    Ok(Json(PaginatedResponse {
-        data: posts_with_context,
-        pagination: PaginationInfo::new(total, params.pagination.limit, params.pagination.offset),
-    }))
+    Ok(Json(load_page_context(&state, page, Some(user.identity_id)).await?))
 }
 
 /// Get all posts across all public spaces
 pub async fn all_feed(
     State(state): State<Arc<AppState>>,
     Query(params): Query<FeedParams>,
-    _user: OptionalUser,
+    user: OptionalUser,
 ) -> ApiResult<Json<PaginatedResponse<PostWithContext>>> {
-    let posts = sqlx::query_as!(
-        Post,
-        r#"
-        SELECT p.id, p.space_id, p.author_id, p.title, p.content,
-               p.content_type as "content_type: ContentType",
-               p.url, p.media_ids, p.upvotes, p.downvotes, p.score, p.comment_count,
-               p.is_pinned, p.is_locked, p.is_removed, p.removed_reason,
-               p.created_at, p.updated_at
-        FROM posts p
-        JOIN spaces s ON s.id = p.space_id
-        WHERE p.is_removed = false AND s.is_private = false
-        ORDER BY p.score DESC, p.created_at DESC
-        LIMIT $1 OFFSET $2
-        "#,
-        params.pagination.limit,
-        params.pagination.offset
-    )
-    .fetch_all(state.db.pool())
-    .await?;
-
-    let posts_with_context: Vec<PostWithContext> = posts
-        .into_iter()
-        .map(|post| PostWithContext {
-            post,
-            author: None,
-            space: None,
-            user_vote: None,
-        })
-        .collect();
+    let viewer = user.0.map(|u| u.identity_id);
+    let page = fetch_ranked_posts(&state, FeedScope::Public, &params, viewer).await?;
 
-    let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM posts p JOIN spaces s ON s.id = p.space_id WHERE p.is_removed = false AND s.is_private = false"
-    )
-    .fetch_one(state.db.pool())
-    .await?
-    .unwrap_or(0);
-
-    Ok(Json(PaginatedResponse {
-        data: posts_with_context,
-        pagination: PaginationInfo::new(total, params.pagination.limit, params.pagination.offset),
-    }))
+    Ok(Json(load_page_context(&state, page, viewer).await?))
 }
 
 /// Get popular feed (trending posts)
 pub async fn popular_feed(
     State(state): State<Arc<AppState>>,
     Query(params): Query<FeedParams>,
-    _user: OptionalUser,
+    user: OptionalUser,
 ) -> ApiResult<Json<PaginatedResponse<PostWithContext>>> {
-    // Popular = high score + recent activity
-    let posts = sqlx::query_as!(
-        Post,
-        r#"
-        SELECT p.id, p.space_id, p.author_id, p.title, p.content,
-               p.content_type as "content_type: ContentType",
-               p.url, p.media_ids, p.upvotes, p.downvotes, p.score, p.comment_count,
-               p.is_pinned, p.is_locked, p.is_removed, p.removed_reason,
-               p.created_at, p.updated_at
-        FROM posts p
-        JOIN spaces s ON s.id = p.space_id
-        WHERE p.is_removed = false AND s.is_private = false
-              AND p.created_at > NOW() - INTERVAL '7 days'
-        ORDER BY (p.upvotes + p.comment_count) DESC, p.created_at DESC
-        LIMIT $1 OFFSET $2
-        "#,
-        params.pagination.limit,
-        params.pagination.offset
-    )
-    .fetch_all(state.db.pool())
-    .await?;
-
-    let posts_with_context: Vec<PostWithContext> = posts
-        .into_iter()
-        .map(|post| PostWithContext {
-            post,
-            author: None,
-            space: None,
-            user_vote: None,
-        })
-        .collect();
-
-    let total: i64 = sqlx::query_scalar!(
-        r#"
-        SELECT COUNT(*)
-        FROM posts p
-        JOIN spaces s ON s.id = p.space_id
-        WHERE p.is_removed = false AND s.is_private = false
-              AND p.created_at > NOW() - INTERVAL '7 days'
-        "#
-    )
-    .fetch_one(state.db.pool())
-    .await?
-    .unwrap_or(0);
+    // Popular = public posts from the last week, ranked by the requested sort
+    let viewer = user.0.map(|u| u.identity_id);
+    let page = fetch_ranked_posts(&state, FeedScope::Popular, &params, viewer).await?;
 
-    Ok(Json(PaginatedResponse {
-        data: posts_with_context,
-        pagination: PaginationInfo::new(total, params.pagination.limit, params.pagination.offset),
-    }))
+    Ok(Json(load_page_context(&state, page, viewer).await?))
 }
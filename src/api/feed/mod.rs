@@ -1,3 +1,5 @@
 //! Feed API module
+pub(crate) mod context;
 pub mod handlers;
+pub(crate) mod query;
 pub use handlers::*;
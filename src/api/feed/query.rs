@@ -0,0 +1,210 @@
+//! Ranked post listings shared by the feeds and space listings
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::api::cursor::{Cursor, PageRequest};
+use crate::domain::entities::*;
+use crate::domain::services::feed::{
+    post_sort_order_by, post_sort_rank_sql, sort_window_start, RISING_ACTIVITY_HOURS,
+};
+use crate::errors::ApiResult;
+use crate::AppState;
+
+/// Post columns selected for listings, over posts aliased as `p`
+pub(crate) const POST_COLUMNS: &str = "p.id, p.space_id, p.author_id, p.title, p.content, p.content_type, \
+    p.url, p.media_ids, p.upvotes, p.downvotes, p.score, p.comment_count, \
+    p.is_pinned, p.is_locked, p.is_removed, p.removed_reason, p.created_at, p.updated_at, p.edited_at, p.held_at, p.content_html";
+
+/// How far back the popular feed looks
+const POPULAR_WINDOW_DAYS: i64 = 7;
+
+/// Which posts a listing draws from
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FeedScope {
+    /// Posts in spaces the identity is a member of
+    Subscribed(Uuid),
+    /// Posts in public spaces
+    Public,
+    /// Posts in public spaces from the last week
+    Popular,
+    /// Posts in a single space, pinned posts first
+    Space(Uuid),
+}
+
+impl FeedScope {
+    fn cursor_scope(&self, params: &FeedParams) -> String {
+        let scope = match self {
+            FeedScope::Subscribed(identity_id) => format!("subscribed:{}", identity_id),
+            FeedScope::Public => "public".to_string(),
+            FeedScope::Popular => "popular".to_string(),
+            FeedScope::Space(space_id) => format!("space:{}", space_id),
+        };
+        format!("posts:{}:{:?}:{:?}", scope, params.sort, params.time_range)
+    }
+}
+
+/// Keyset position in a ranked listing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PostKey {
+    pinned: bool,
+    rank: f64,
+    created_at: DateTime<Utc>,
+    /// Reference time rising ranks were computed at, fixed by the first page
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    as_of: Option<DateTime<Utc>>,
+}
+
+#[derive(FromRow)]
+struct RankedRow {
+    #[sqlx(flatten)]
+    post: Post,
+    sort_rank: f64,
+}
+
+/// Fetch a page of posts ordered by `params.sort` within `params.time_range`.
+/// Posts by identities the viewer has blocked are left out.
+pub(crate) async fn fetch_ranked_posts(
+    state: &AppState,
+    scope: FeedScope,
+    params: &FeedParams,
+    viewer: Option<Uuid>,
+) -> ApiResult<PaginatedResponse<Post>> {
+    let page = PageRequest::<PostKey>::new(&params.pagination, &state.crypto, scope.cursor_scope(params))?;
+
+    let mut since = sort_window_start(params.sort, params.time_range);
+    if let FeedScope::Popular = scope {
+        since = since.max(Utc::now() - Duration::days(POPULAR_WINDOW_DAYS));
+    }
+
+    let rank_sql = post_sort_rank_sql(params.sort);
+    let rising = params.sort == PostSort::Rising;
+    let as_of = page.after.as_ref().and_then(|after| after.key.as_of).unwrap_or_else(Utc::now);
+    let pinned_first = matches!(scope, FeedScope::Space(_));
+
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    query
+        .push(POST_COLUMNS)
+        .push(", ")
+        .push(rank_sql)
+        .push(" AS sort_rank FROM posts p");
+
+    if rising {
+        query
+            .push(" LEFT JOIN LATERAL (SELECT SUM(v.vote_value) AS net, ")
+            .push_bind(as_of)
+            .push(
+                "::TIMESTAMPTZ AS as_of FROM votes v \
+                  WHERE v.target_type = 'post' AND v.target_id = p.id AND v.created_at > ",
+            )
+            .push_bind(as_of - Duration::hours(RISING_ACTIVITY_HOURS))
+            .push(" AND v.created_at <= ")
+            .push_bind(as_of)
+            .push(") recent ON true");
+    }
+
+    push_filters(&mut query, scope, since, viewer);
+
+    if let Some(after) = &page.after {
+        if pinned_first {
+            query
+                .push(" AND (p.is_pinned, ")
+                .push(rank_sql)
+                .push(", p.created_at, p.id) < (")
+                .push_bind(after.key.pinned)
+                .push(", ");
+        } else {
+            query.push(" AND (").push(rank_sql).push(", p.created_at, p.id) < (");
+        }
+        query
+            .push_bind(after.key.rank)
+            .push(", ")
+            .push_bind(after.key.created_at)
+            .push(", ")
+            .push_bind(after.id)
+            .push(")");
+    }
+
+    query.push(" ORDER BY ");
+    if pinned_first {
+        query.push("p.is_pinned DESC, ");
+    }
+    query
+        .push(post_sort_order_by(params.sort))
+        .push(" LIMIT ")
+        .push_bind(page.fetch_limit())
+        .push(" OFFSET ")
+        .push_bind(page.offset);
+
+    let rows = query
+        .build_query_as::<RankedRow>()
+        .fetch_all(state.db.pool())
+        .await?;
+
+    let total = async {
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*) FROM posts p");
+        push_filters(&mut count, scope, since, viewer);
+
+        let total: i64 = count.build_query_scalar().fetch_one(state.db.pool()).await?;
+        Ok(total)
+    };
+
+    let page = page
+        .into_response(
+            rows,
+            &state.crypto,
+            |row| {
+                Cursor::new(
+                    PostKey {
+                        pinned: row.post.is_pinned,
+                        rank: row.sort_rank,
+                        created_at: row.post.created_at,
+                        as_of: rising.then_some(as_of),
+                    },
+                    row.post.id,
+                )
+            },
+            total,
+        )
+        .await?;
+
+    Ok(PaginatedResponse {
+        data: page.data.into_iter().map(|row| row.post).collect(),
+        pagination: page.pagination,
+    })
+}
+
+/// Scope joins and WHERE clause shared by the page and count queries
+fn push_filters(
+    query: &mut QueryBuilder<'_, Postgres>,
+    scope: FeedScope,
+    since: DateTime<Utc>,
+    viewer: Option<Uuid>,
+) {
+    match scope {
+        FeedScope::Subscribed(identity_id) => {
+            query
+                .push(" JOIN space_members sm ON sm.space_id = p.space_id WHERE sm.identity_id = ")
+                .push_bind(identity_id);
+        }
+        FeedScope::Public | FeedScope::Popular => {
+            query.push(" JOIN spaces s ON s.id = p.space_id WHERE s.is_private = false");
+        }
+        FeedScope::Space(space_id) => {
+            query.push(" WHERE p.space_id = ").push_bind(space_id);
+        }
+    }
+
+    query
+        .push(" AND p.is_removed = false AND p.created_at >= ")
+        .push_bind(since);
+
+    if let Some(viewer) = viewer {
+        query
+            .push(" AND NOT EXISTS (SELECT 1 FROM identity_blocks b WHERE b.blocked_id = p.author_id AND b.blocker_id = ")
+            .push_bind(viewer)
+            .push(")");
+    }
+}
@@ -6,6 +6,7 @@ use axum::{extract::State, http::StatusCode, Json};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
+use crate::jobs::LeaseStatus;
 use crate::AppState;
 
 /// Health check response
@@ -98,3 +99,15 @@ pub async fn liveness_check() -> Json<HealthResponse> {
         redis: None,
     })
 }
+
+/// Leader lease check - reports which instance runs periodic jobs
+pub async fn leader_check(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<LeaseStatus>, StatusCode> {
+    state
+        .leader
+        .status()
+        .await
+        .map(Json)
+        .map_err(|_| StatusCode::SERVICE_UNAVAILABLE)
+}
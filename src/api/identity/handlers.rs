@@ -10,9 +10,11 @@ use uuid::Uuid;
 use validator::Validate;
 
 use crate::api::extractors::Pagination;
+use crate::api::feed::context::{identity_summary_key, invalidate_summary};
 use crate::domain::entities::*;
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::AuthenticatedUser;
+use crate::middleware::auth::{AuthenticatedUser, OptionalUser};
+use crate::websocket::recheck_subscriptions;
 use crate::AppState;
 
 /// Get current authenticated identity
@@ -65,6 +67,8 @@ pub async fn update_current(
     .fetch_one(state.db.pool())
     .await?;
 
+    invalidate_summary(&state, &identity_summary_key(identity.id)).await;
+
     Ok(Json(identity))
 }
 
@@ -114,12 +118,15 @@ pub async fn get_by_fingerprint(
     Ok(Json(identity.into()))
 }
 
-/// Get posts by identity
+/// Get posts by identity, none if the viewer blocked them
 pub async fn get_posts(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
     Pagination(pagination): Pagination,
+    user: OptionalUser,
 ) -> ApiResult<Json<PaginatedResponse<Post>>> {
+    let viewer = user.0.map(|u| u.identity_id);
+
     let posts = sqlx::query_as!(
         Post,
         r#"
@@ -127,22 +134,35 @@ pub async fn get_posts(
                content_type as "content_type: ContentType",
                url, media_ids, upvotes, downvotes, score, comment_count,
                is_pinned, is_locked, is_removed, removed_reason,
-               created_at, updated_at
-        FROM posts
+               created_at, updated_at, edited_at, held_at, content_html
+        FROM posts t
         WHERE author_id = $1 AND is_removed = false
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $4 AND b.blocked_id = t.author_id
+              )
         ORDER BY created_at DESC
         LIMIT $2 OFFSET $3
         "#,
         id,
         pagination.limit,
-        pagination.offset
+        pagination.offset,
+        viewer
     )
     .fetch_all(state.db.pool())
     .await?;
 
     let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM posts WHERE author_id = $1 AND is_removed = false",
-        id
+        r#"
+        SELECT COUNT(*) FROM posts t
+        WHERE author_id = $1 AND is_removed = false
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $2 AND b.blocked_id = t.author_id
+              )
+        "#,
+        id,
+        viewer
     )
     .fetch_one(state.db.pool())
     .await?
@@ -154,33 +174,49 @@ pub async fn get_posts(
     }))
 }
 
-/// Get comments by identity
+/// Get comments by identity, none if the viewer blocked them
 pub async fn get_comments(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
     Pagination(pagination): Pagination,
+    user: OptionalUser,
 ) -> ApiResult<Json<PaginatedResponse<Comment>>> {
+    let viewer = user.0.map(|u| u.identity_id);
+
     let comments = sqlx::query_as!(
         Comment,
         r#"
-        SELECT id, post_id, parent_id, author_id, content, depth, path,
+        SELECT id, post_id, parent_id, author_id, is_automod, content, depth, path,
                upvotes, downvotes, score, is_removed, removed_reason,
-               created_at, updated_at
-        FROM comments
+               created_at, updated_at, edited_at, held_at, content_html
+        FROM comments t
         WHERE author_id = $1 AND is_removed = false
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $4 AND b.blocked_id = t.author_id
+              )
         ORDER BY created_at DESC
         LIMIT $2 OFFSET $3
         "#,
         id,
         pagination.limit,
-        pagination.offset
+        pagination.offset,
+        viewer
     )
     .fetch_all(state.db.pool())
     .await?;
 
     let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM comments WHERE author_id = $1 AND is_removed = false",
-        id
+        r#"
+        SELECT COUNT(*) FROM comments t
+        WHERE author_id = $1 AND is_removed = false
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $2 AND b.blocked_id = t.author_id
+              )
+        "#,
+        id,
+        viewer
     )
     .fetch_one(state.db.pool())
     .await?
@@ -234,6 +270,102 @@ pub async fn revoke_session(
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Blocks an identity may hold
+const MAX_BLOCKS: i64 = 1000;
+
+/// List identities the current user has blocked
+pub async fn list_blocks(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> ApiResult<Json<Vec<BlockedIdentity>>> {
+    let blocks = sqlx::query_as!(
+        BlockedIdentity,
+        r#"
+        SELECT i.id, i.public_key_fingerprint, i.display_name, i.avatar_hash, b.created_at as blocked_at
+        FROM identity_blocks b
+        JOIN identities i ON i.id = b.blocked_id
+        WHERE b.blocker_id = $1
+        ORDER BY b.created_at DESC
+        "#,
+        user.identity_id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(Json(blocks))
+}
+
+/// Block an identity
+pub async fn block(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(request): Json<BlockRequest>,
+) -> ApiResult<StatusCode> {
+    if request.identity_id == user.identity_id {
+        return Err(ApiError::InvalidInput("Cannot block yourself".to_string()));
+    }
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM identities WHERE id = $1) as "exists!""#,
+        request.identity_id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if !exists {
+        return Err(ApiError::NotFound("Identity not found".to_string()));
+    }
+
+    let block_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM identity_blocks WHERE blocker_id = $1"#,
+        user.identity_id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if block_count >= MAX_BLOCKS {
+        return Err(ApiError::OperationNotAllowed(format!("At most {} blocks are allowed", MAX_BLOCKS)));
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO identity_blocks (blocker_id, blocked_id)
+        VALUES ($1, $2)
+        ON CONFLICT (blocker_id, blocked_id) DO NOTHING
+        "#,
+        user.identity_id,
+        request.identity_id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    // The blocked identity loses live access to shared conversations
+    recheck_subscriptions(&state, request.identity_id).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Unblock an identity
+pub async fn unblock(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(identity_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let result = sqlx::query!(
+        "DELETE FROM identity_blocks WHERE blocker_id = $1 AND blocked_id = $2",
+        user.identity_id,
+        identity_id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Block not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Session info
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
 pub struct SessionInfo {
@@ -12,6 +12,8 @@ use uuid::Uuid;
 
 use crate::domain::entities::Media;
 use crate::errors::{ApiError, ApiResult};
+use crate::jobs::handlers::{ProcessMediaPayload, PROCESS_MEDIA};
+use crate::jobs::JobQueue;
 use crate::middleware::auth::AuthenticatedUser;
 use crate::AppState;
 
@@ -44,7 +46,7 @@ pub async fn upload(
             Media,
             r#"
             INSERT INTO media (id, uploader_id, file_hash, mime_type, file_size, storage_path, thumbnail_path, is_processed, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, true, NOW())
+            VALUES ($1, $2, $3, $4, $5, $6, $7, false, NOW())
             RETURNING id, uploader_id, file_hash, mime_type, file_size, storage_path, thumbnail_path, is_processed, created_at
             "#,
             stored.id,
@@ -58,6 +60,11 @@ pub async fn upload(
         .fetch_one(state.db.pool())
         .await?;
 
+        // Thumbnails are generated in the background
+        JobQueue::new(state.db.pool().clone())
+            .enqueue(PROCESS_MEDIA, &ProcessMediaPayload { media_id: media.id })
+            .await?;
+
         return Ok((StatusCode::CREATED, Json(media)));
     }
 
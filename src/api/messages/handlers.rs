@@ -2,13 +2,16 @@
 
 use axum::{extract::{Path, State}, http::StatusCode, Json};
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
 use std::sync::Arc;
 use uuid::Uuid;
 
+use crate::api::cursor::{Cursor, PageRequest};
 use crate::api::extractors::Pagination;
 use crate::domain::entities::*;
 use crate::errors::{ApiError, ApiResult};
 use crate::middleware::auth::AuthenticatedUser;
+use crate::websocket::{publish_channel_event, Channel};
 use crate::AppState;
 
 /// List conversations for the current user
@@ -66,10 +69,19 @@ pub async fn create_conversation(
         all_participants.push(user.identity_id);
     }
 
-    // Verify all participants exist
+    // Verify all participants exist. Anyone who has blocked the creator is
+    // reported the same way, so the block stays private.
     let existing_count: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM identities WHERE id = ANY($1)",
-        &all_participants
+        r#"
+        SELECT COUNT(*) FROM identities i
+        WHERE i.id = ANY($1)
+          AND NOT EXISTS (
+              SELECT 1 FROM identity_blocks b
+              WHERE b.blocker_id = i.id AND b.blocked_id = $2
+          )
+        "#,
+        &all_participants,
+        user.identity_id
     )
     .fetch_one(state.db.pool())
     .await?
@@ -211,25 +223,33 @@ pub async fn list_messages(
         return Err(ApiError::Forbidden);
     }
 
+    let page = PageRequest::<DateTime<Utc>>::new(&pagination, &state.crypto, format!("messages:{}", id))?;
+
     let messages = sqlx::query!(
         r#"
         SELECT m.id, m.conversation_id, m.sender_id, m.encrypted_content, m.nonce, m.created_at
         FROM messages m
         WHERE m.conversation_id = $1
-        ORDER BY m.created_at DESC
+              AND ($4::TIMESTAMPTZ IS NULL OR (m.created_at, m.id) < ($4, $5))
+        ORDER BY m.created_at DESC, m.id DESC
         LIMIT $2 OFFSET $3
         "#,
         id,
-        pagination.limit,
-        pagination.offset
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        page.after_id()
     )
     .fetch_all(state.db.pool())
     .await?;
 
-    let total: i64 = sqlx::query_scalar!("SELECT COUNT(*) FROM messages WHERE conversation_id = $1", id)
-        .fetch_one(state.db.pool())
-        .await?
-        .unwrap_or(0);
+    let total = async {
+        let total = sqlx::query_scalar!("SELECT COUNT(*) FROM messages WHERE conversation_id = $1", id)
+            .fetch_one(state.db.pool())
+            .await?
+            .unwrap_or(0);
+        Ok(total)
+    };
 
     let message_responses: Vec<MessageResponse> = messages
         .into_iter()
@@ -244,10 +264,11 @@ pub async fn list_messages(
         })
         .collect();
 
-    Ok(Json(PaginatedResponse {
-        data: message_responses,
-        pagination: PaginationInfo::new(total, pagination.limit, pagination.offset),
-    }))
+    let response = page
+        .into_response(message_responses, &state.crypto, |m| Cursor::new(m.created_at, m.id), total)
+        .await?;
+
+    Ok(Json(response))
 }
 
 /// Send a message
@@ -296,7 +317,7 @@ pub async fn send_message(
         .execute(state.db.pool())
         .await?;
 
-    Ok((StatusCode::CREATED, Json(MessageResponse {
+    let message = MessageResponse {
         id: msg_id,
         conversation_id: id,
         sender_id: Some(user.identity_id),
@@ -304,7 +325,17 @@ pub async fn send_message(
         encrypted_content: request.encrypted_content,
         nonce: request.nonce,
         created_at: now,
-    })))
+    };
+
+    publish_channel_event(
+        &state,
+        &Channel::Conversation(id),
+        "message_created",
+        serde_json::to_value(&message)?,
+    )
+    .await;
+
+    Ok((StatusCode::CREATED, Json(message)))
 }
 
 /// Mark messages as read
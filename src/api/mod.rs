@@ -4,17 +4,21 @@
 //! request/response types, and API-specific logic.
 
 mod routes;
+pub mod cursor;
 pub mod auth;
 pub mod identity;
 pub mod spaces;
 pub mod posts;
+pub mod polls;
 pub mod comments;
+pub mod revisions;
 pub mod votes;
 pub mod messages;
 pub mod media;
 pub mod notifications;
 pub mod moderation;
 pub mod feed;
+pub mod search;
 pub mod health;
 
 pub use routes::create_router;
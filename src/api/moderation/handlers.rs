@@ -1,38 +1,75 @@
 //! Content moderation handlers
+//!
+//! Moderation actions are scoped to the space of the content they target and
+//! require the matching space permission. Reports about identities and
+//! messages, and account suspensions, are reserved for platform admins.
 
 use axum::{extract::{Path, Query, State}, http::StatusCode, Json};
+use serde_json::json;
+use sqlx::PgConnection;
 use std::sync::Arc;
 use uuid::Uuid;
 use validator::Validate;
 
+use super::log::{self, LogEntry};
 use crate::api::extractors::Pagination;
+use crate::api::spaces::automod;
 use crate::domain::entities::*;
+use crate::domain::services::permissions::SpaceAccess;
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::{AuthenticatedUser, check_moderator};
+use crate::middleware::auth::{is_platform_admin, require_platform_admin, require_space_permission, AuthenticatedUser};
 use crate::AppState;
 
-/// Check if user is a global moderator (has moderator role in any space,
-/// or is an admin). For production, this should be replaced with a
-/// dedicated global_roles table or admin flag on the identity.
-async fn require_global_moderator(
-    state: &Arc<AppState>,
-    identity_id: Uuid,
-) -> ApiResult<()> {
-    let is_mod: Option<bool> = sqlx::query_scalar!(
+/// Spaces whose reports the identity may review, or `None` for platform
+/// admins, who review every report
+async fn reviewable_spaces(state: &Arc<AppState>, identity_id: Uuid) -> ApiResult<Option<Vec<Uuid>>> {
+    if is_platform_admin(state, identity_id).await? {
+        return Ok(None);
+    }
+
+    let candidates = sqlx::query!(
         r#"
-        SELECT EXISTS(
-            SELECT 1 FROM space_members
-            WHERE identity_id = $1 AND role IN ('moderator', 'admin')
-        ) as "exists!"
+        SELECT s.id, sm.role as "role?: MemberRole",
+               COALESCE(sm.permissions, '{}') as "permissions!: Vec<SpacePermission>",
+               COALESCE(s.creator_id = $1, FALSE) as "is_creator!"
+        FROM spaces s
+        LEFT JOIN space_members sm ON sm.space_id = s.id AND sm.identity_id = $1
+        WHERE s.creator_id = $1 OR sm.role IN ('moderator', 'admin')
         "#,
         identity_id
     )
-    .fetch_one(state.db.pool())
+    .fetch_all(state.db.pool())
     .await?;
 
-    if !is_mod.unwrap_or(false) {
+    let spaces: Vec<Uuid> = candidates
+        .into_iter()
+        .filter(|row| {
+            SpaceAccess::resolve(row.role, &row.permissions, row.is_creator).has(SpacePermission::ViewReports)
+        })
+        .map(|row| row.id)
+        .collect();
+
+    if spaces.is_empty() {
         return Err(ApiError::Forbidden);
     }
+
+    Ok(Some(spaces))
+}
+
+/// Require permission to review a report in the space it falls under
+async fn require_report_access(state: &Arc<AppState>, identity_id: Uuid, report_id: Uuid) -> ApiResult<()> {
+    let space_id = sqlx::query_scalar!("SELECT space_id FROM report_spaces WHERE report_id = $1", report_id)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Report not found".to_string()))?;
+
+    match space_id {
+        Some(space_id) => {
+            require_space_permission(state, identity_id, space_id, SpacePermission::ViewReports).await?;
+        }
+        None => require_platform_admin(state, identity_id).await?,
+    }
+
     Ok(())
 }
 
@@ -64,41 +101,60 @@ pub async fn create_report(
     .fetch_one(state.db.pool())
     .await?;
 
+    automod::recheck_reported(&state, report.target_type, report.target_id).await;
+
     Ok((StatusCode::CREATED, Json(report)))
 }
 
-/// List reports (moderators only)
+/// List reports in the spaces the user reviews
 pub async fn list_reports(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Query(params): Query<ReportListParams>,
     Pagination(pagination): Pagination,
 ) -> ApiResult<Json<PaginatedResponse<Report>>> {
-    // Verify the user has moderator privileges
-    require_global_moderator(&state, user.identity_id).await?;
+    let spaces = reviewable_spaces(&state, user.identity_id).await?;
+    let status = params.status.map(|s| s.to_string());
 
     let reports = sqlx::query_as!(
         Report,
         r#"
-        SELECT id, reporter_id, target_type as "target_type: ReportTargetType",
-               target_id, reason as "reason: ReportReason", description,
-               status as "status: ReportStatus", reviewed_by, review_notes, created_at, reviewed_at
-        FROM reports
-        WHERE ($1::text IS NULL OR status = $1)
-        ORDER BY created_at DESC
+        SELECT r.id, r.reporter_id, r.target_type as "target_type: ReportTargetType",
+               r.target_id, r.reason as "reason: ReportReason", r.description,
+               r.status as "status: ReportStatus", r.reviewed_by, r.review_notes, r.created_at, r.reviewed_at
+        FROM reports r
+        JOIN report_spaces rs ON rs.report_id = r.id
+        WHERE ($1::text IS NULL OR r.status = $1)
+              AND ($4::UUID[] IS NULL OR rs.space_id = ANY($4))
+              AND ($5::UUID IS NULL OR rs.space_id = $5)
+        ORDER BY r.created_at DESC
         LIMIT $2 OFFSET $3
         "#,
-        params.status.map(|s| s.to_string()),
+        status,
         pagination.limit,
-        pagination.offset
+        pagination.offset,
+        spaces.as_deref(),
+        params.space_id
     )
     .fetch_all(state.db.pool())
     .await?;
 
-    let total: i64 = sqlx::query_scalar!("SELECT COUNT(*) FROM reports")
-        .fetch_one(state.db.pool())
-        .await?
-        .unwrap_or(0);
+    let total: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*)
+        FROM reports r
+        JOIN report_spaces rs ON rs.report_id = r.id
+        WHERE ($1::text IS NULL OR r.status = $1)
+              AND ($2::UUID[] IS NULL OR rs.space_id = ANY($2))
+              AND ($3::UUID IS NULL OR rs.space_id = $3)
+        "#,
+        status,
+        spaces.as_deref(),
+        params.space_id
+    )
+    .fetch_one(state.db.pool())
+    .await?
+    .unwrap_or(0);
 
     Ok(Json(PaginatedResponse {
         data: reports,
@@ -106,13 +162,13 @@ pub async fn list_reports(
     }))
 }
 
-/// Get report by ID (moderators only)
+/// Get report by ID (reviewers of its space only)
 pub async fn get_report(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Path(id): Path<Uuid>,
 ) -> ApiResult<Json<Report>> {
-    require_global_moderator(&state, user.identity_id).await?;
+    require_report_access(&state, user.identity_id, id).await?;
 
     let report = sqlx::query_as!(
         Report,
@@ -132,14 +188,14 @@ pub async fn get_report(
     Ok(Json(report))
 }
 
-/// Update report status (moderators only)
+/// Update report status (reviewers of its space only)
 pub async fn update_report(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Path(id): Path<Uuid>,
     Json(request): Json<UpdateReportRequest>,
 ) -> ApiResult<Json<Report>> {
-    require_global_moderator(&state, user.identity_id).await?;
+    require_report_access(&state, user.identity_id, id).await?;
 
     let report = sqlx::query_as!(
         Report,
@@ -169,24 +225,47 @@ pub async fn remove_post(
     Path(id): Path<Uuid>,
     Json(request): Json<RemoveContentRequest>,
 ) -> ApiResult<StatusCode> {
-    // Look up the post's space and verify moderator privileges
     let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", id)
         .fetch_optional(state.db.pool())
         .await?
         .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
 
-    if !check_moderator(&state, user.identity_id, post.space_id).await? {
-        return Err(ApiError::Forbidden);
-    }
+    require_space_permission(&state, user.identity_id, post.space_id, SpacePermission::ManagePosts).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = sqlx::query!(
+        r#"SELECT is_removed as "is_removed!", removed_reason FROM posts WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
 
     sqlx::query!(
-        "UPDATE posts SET is_removed = true, removed_reason = $2 WHERE id = $1",
+        "UPDATE posts SET is_removed = true, removed_reason = $2, held_at = NULL WHERE id = $1",
         id,
         request.reason
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
+    .await?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(post.space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::RemovePost,
+            target_type: ModerationTargetType::Post,
+            target_id: id,
+            reason: Some(&request.reason),
+            before: Some(json!({ "is_removed": before.is_removed, "removed_reason": before.removed_reason })),
+            after: Some(json!({ "is_removed": true, "removed_reason": request.reason })),
+        },
+    )
     .await?;
 
+    tx.commit().await?;
+
     Ok(StatusCode::OK)
 }
 
@@ -197,40 +276,65 @@ pub async fn remove_comment(
     Path(id): Path<Uuid>,
     Json(request): Json<RemoveContentRequest>,
 ) -> ApiResult<StatusCode> {
-    // Look up the comment's post -> space to verify moderator privileges
-    let comment = sqlx::query!("SELECT post_id FROM comments WHERE id = $1", id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
+    let comment = sqlx::query!(
+        "SELECT p.space_id FROM comments c JOIN posts p ON p.id = c.post_id WHERE c.id = $1",
+        id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
 
-    let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", comment.post_id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+    require_space_permission(&state, user.identity_id, comment.space_id, SpacePermission::ManagePosts).await?;
 
-    if !check_moderator(&state, user.identity_id, post.space_id).await? {
-        return Err(ApiError::Forbidden);
-    }
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = sqlx::query!(
+        r#"SELECT is_removed as "is_removed!", removed_reason FROM comments WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
 
     sqlx::query!(
-        "UPDATE comments SET is_removed = true, removed_reason = $2 WHERE id = $1",
+        "UPDATE comments SET is_removed = true, removed_reason = $2, held_at = NULL WHERE id = $1",
         id,
         request.reason
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
     .await?;
 
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(comment.space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::RemoveComment,
+            target_type: ModerationTargetType::Comment,
+            target_id: id,
+            reason: Some(&request.reason),
+            before: Some(json!({ "is_removed": before.is_removed, "removed_reason": before.removed_reason })),
+            after: Some(json!({ "is_removed": true, "removed_reason": request.reason })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(StatusCode::OK)
 }
 
-/// Suspend an identity (global moderator only)
+/// Suspend an identity (platform admins only)
 pub async fn suspend_identity(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Path(id): Path<Uuid>,
     Json(request): Json<SuspendRequest>,
 ) -> ApiResult<StatusCode> {
-    require_global_moderator(&state, user.identity_id).await?;
+    require_platform_admin(&state, user.identity_id).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = suspension_state(&mut tx, id).await?;
 
     sqlx::query!(
         "UPDATE identities SET is_suspended = true, suspended_reason = $2, suspended_until = $3 WHERE id = $1",
@@ -238,40 +342,171 @@ pub async fn suspend_identity(
         request.reason,
         request.until
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
     .await?;
 
     // Revoke all refresh tokens
     sqlx::query!("UPDATE refresh_tokens SET revoked = true WHERE identity_id = $1", id)
-        .execute(state.db.pool())
+        .execute(&mut *tx)
         .await?;
 
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: None,
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::SuspendIdentity,
+            target_type: ModerationTargetType::Identity,
+            target_id: id,
+            reason: Some(&request.reason),
+            before: Some(before),
+            after: Some(json!({
+                "is_suspended": true,
+                "suspended_reason": request.reason,
+                "suspended_until": request.until,
+            })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(StatusCode::OK)
 }
 
-/// Unsuspend an identity (global moderator only)
+/// Unsuspend an identity (platform admins only)
 pub async fn unsuspend_identity(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Path(id): Path<Uuid>,
 ) -> ApiResult<StatusCode> {
-    require_global_moderator(&state, user.identity_id).await?;
+    require_platform_admin(&state, user.identity_id).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = suspension_state(&mut tx, id).await?;
 
     sqlx::query!(
         "UPDATE identities SET is_suspended = false, suspended_reason = NULL, suspended_until = NULL WHERE id = $1",
         id
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
     .await?;
 
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: None,
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::UnsuspendIdentity,
+            target_type: ModerationTargetType::Identity,
+            target_id: id,
+            reason: None,
+            before: Some(before),
+            after: Some(json!({ "is_suspended": false, "suspended_reason": null, "suspended_until": null })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(StatusCode::OK)
 }
 
+/// Lock an identity's suspension fields and return them for the log
+async fn suspension_state(conn: &mut PgConnection, id: Uuid) -> ApiResult<serde_json::Value> {
+    let identity = sqlx::query!(
+        r#"
+        SELECT is_suspended as "is_suspended!", suspended_reason, suspended_until
+        FROM identities WHERE id = $1 FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Identity not found".to_string()))?;
+
+    Ok(json!({
+        "is_suspended": identity.is_suspended,
+        "suspended_reason": identity.suspended_reason,
+        "suspended_until": identity.suspended_until,
+    }))
+}
+
+/// Grant the platform admin role (platform admins only)
+pub async fn add_platform_admin(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(request): Json<PlatformAdminRequest>,
+) -> ApiResult<StatusCode> {
+    require_platform_admin(&state, user.identity_id).await?;
+
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM identities WHERE id = $1) as "exists!""#,
+        request.identity_id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if !exists {
+        return Err(ApiError::NotFound("Identity not found".to_string()));
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO platform_admins (identity_id, granted_by)
+        VALUES ($1, $2)
+        ON CONFLICT (identity_id) DO NOTHING
+        "#,
+        request.identity_id,
+        user.identity_id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Revoke the platform admin role (platform admins only)
+pub async fn remove_platform_admin(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    require_platform_admin(&state, user.identity_id).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    // Lock the admins so concurrent revocations cannot remove the last one
+    let admins = sqlx::query_scalar!("SELECT identity_id FROM platform_admins FOR UPDATE")
+        .fetch_all(&mut *tx)
+        .await?;
+
+    if !admins.contains(&id) {
+        return Err(ApiError::NotFound("Platform admin not found".to_string()));
+    }
+    if admins.len() == 1 {
+        return Err(ApiError::OperationNotAllowed(
+            "The last platform admin cannot be removed".to_string(),
+        ));
+    }
+
+    sqlx::query!("DELETE FROM platform_admins WHERE identity_id = $1", id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 // Request types
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ReportListParams {
     pub status: Option<ReportStatus>,
+    /// Only reports about content in this space
+    pub space_id: Option<Uuid>,
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -285,6 +520,11 @@ pub struct RemoveContentRequest {
     pub reason: String,
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub struct PlatformAdminRequest {
+    pub identity_id: Uuid,
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct SuspendRequest {
     pub reason: String,
@@ -0,0 +1,232 @@
+//! Moderation log
+//!
+//! Moderation handlers append an entry for every action in the same
+//! transaction as the change itself, recording the state before and after.
+//! Moderators who manage posts read their space's log, platform admins read
+//! everything, and spaces that opt in publish their log with moderators
+//! pseudonymised.
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::cursor::{Cursor, PageRequest};
+use crate::api::extractors::Pagination;
+use crate::api::spaces::space_id;
+use crate::domain::entities::*;
+use crate::errors::{ApiError, ApiResult};
+use crate::infrastructure::crypto::CryptoService;
+use crate::middleware::auth::{require_platform_admin, require_space_permission, AuthenticatedUser};
+use crate::AppState;
+
+/// A moderation action to record
+pub(crate) struct LogEntry<'a> {
+    pub space_id: Option<Uuid>,
+    /// `None` for AutoModerator
+    pub actor_id: Option<Uuid>,
+    pub action: ModerationLogAction,
+    pub target_type: ModerationTargetType,
+    pub target_id: Uuid,
+    pub reason: Option<&'a str>,
+    pub before: Option<serde_json::Value>,
+    pub after: Option<serde_json::Value>,
+}
+
+/// Append an entry to the moderation log
+pub(crate) async fn record(conn: &mut PgConnection, entry: LogEntry<'_>) -> ApiResult<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO moderation_log (space_id, actor_id, action, target_type, target_id, reason, before_state, after_state)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+        entry.space_id,
+        entry.actor_id,
+        entry.action.to_string(),
+        entry.target_type.to_string(),
+        entry.target_id,
+        entry.reason,
+        entry.before,
+        entry.after
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Name shown for actions taken by AutoModerator rules
+pub const AUTOMODERATOR: &str = "automoderator";
+
+/// Pseudonym for a moderator in a space's public log.
+///
+/// Stable within the space, so readers can tell moderators apart, but
+/// unrelated across spaces.
+pub fn moderator_pseudonym(crypto: &CryptoService, space_id: Uuid, actor_id: Uuid) -> String {
+    let mut data = Vec::with_capacity(42);
+    data.extend_from_slice(b"moderator:");
+    data.extend_from_slice(space_id.as_bytes());
+    data.extend_from_slice(actor_id.as_bytes());
+
+    format!("mod-{}", hex::encode(&crypto.hmac_sha256(&data)[..4]))
+}
+
+/// Get a space's moderation log (moderators who manage posts only)
+pub async fn space_log(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Query(params): Query<ModerationLogParams>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<ModerationLogEntry>>> {
+    let space_id = space_id(&state, &slug).await?;
+
+    // Entries name the acting moderators and carry before and after state,
+    // such as AutoModerator rule definitions, that the public log leaves out
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let page = PageRequest::new(&pagination, &state.crypto, format!("modlog:{}", space_id))?;
+    Ok(Json(fetch_log(&state, page, Some(space_id), params.actor_id, &params).await?))
+}
+
+/// Get a space's public moderation log, if it publishes one
+pub async fn public_space_log(
+    State(state): State<Arc<AppState>>,
+    Path(slug): Path<String>,
+    Query(params): Query<ModerationLogParams>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<PublicModerationLogEntry>>> {
+    let space = sqlx::query!("SELECT id, public_moderation_log FROM spaces WHERE slug = $1", slug)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+    if !space.public_moderation_log {
+        return Err(ApiError::NotFound("Moderation log not found".to_string()));
+    }
+
+    // Moderators are pseudonymous here, so they cannot be filtered by id
+    let page = PageRequest::new(&pagination, &state.crypto, format!("modlog:public:{}", space.id))?;
+    let response = fetch_log(&state, page, Some(space.id), None, &params).await?;
+
+    let data = response
+        .data
+        .into_iter()
+        .map(|entry| PublicModerationLogEntry {
+            id: entry.id,
+            action: entry.action,
+            target_type: entry.target_type,
+            target_id: (entry.target_type != ModerationTargetType::Identity).then_some(entry.target_id),
+            reason: entry.reason,
+            moderator: match entry.actor_id {
+                Some(actor_id) => moderator_pseudonym(&state.crypto, space.id, actor_id),
+                None => AUTOMODERATOR.to_string(),
+            },
+            created_at: entry.created_at,
+        })
+        .collect();
+
+    Ok(Json(PaginatedResponse {
+        data,
+        pagination: response.pagination,
+    }))
+}
+
+/// Get the moderation log across all spaces (platform admins only)
+pub async fn platform_log(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Query(params): Query<ModerationLogParams>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<ModerationLogEntry>>> {
+    require_platform_admin(&state, user.identity_id).await?;
+
+    let page = PageRequest::new(&pagination, &state.crypto, "modlog")?;
+    Ok(Json(fetch_log(&state, page, params.space_id, params.actor_id, &params).await?))
+}
+
+async fn fetch_log(
+    state: &Arc<AppState>,
+    page: PageRequest<DateTime<Utc>>,
+    space_id: Option<Uuid>,
+    actor_id: Option<Uuid>,
+    params: &ModerationLogParams,
+) -> ApiResult<PaginatedResponse<ModerationLogEntry>> {
+    let action = params.action.map(|action| action.to_string());
+
+    let entries = sqlx::query_as!(
+        ModerationLogEntry,
+        r#"
+        SELECT id, space_id, actor_id, action as "action: ModerationLogAction",
+               target_type as "target_type: ModerationTargetType", target_id, reason,
+               before_state, after_state, created_at
+        FROM moderation_log
+        WHERE ($1::UUID IS NULL OR space_id = $1)
+              AND ($2::UUID IS NULL OR actor_id = $2)
+              AND ($3::VARCHAR IS NULL OR action = $3)
+              AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+              AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+              AND ($6::TIMESTAMPTZ IS NULL OR (created_at, id) < ($6, $7))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $8 OFFSET $9
+        "#,
+        space_id,
+        actor_id,
+        action,
+        params.since,
+        params.until,
+        page.after_key(),
+        page.after_id(),
+        page.fetch_limit(),
+        page.offset
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!"
+            FROM moderation_log
+            WHERE ($1::UUID IS NULL OR space_id = $1)
+                  AND ($2::UUID IS NULL OR actor_id = $2)
+                  AND ($3::VARCHAR IS NULL OR action = $3)
+                  AND ($4::TIMESTAMPTZ IS NULL OR created_at >= $4)
+                  AND ($5::TIMESTAMPTZ IS NULL OR created_at < $5)
+            "#,
+            space_id,
+            actor_id,
+            action,
+            params.since,
+            params.until
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+        Ok(total)
+    };
+
+    page.into_response(entries, &state.crypto, |e| Cursor::new(e.created_at, e.id), total)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infrastructure::crypto::testing::crypto;
+
+    #[test]
+    fn test_pseudonym_stable_within_space_only() {
+        let crypto = crypto();
+        let (space, other_space, moderator) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let pseudonym = moderator_pseudonym(&crypto, space, moderator);
+        assert_eq!(pseudonym, moderator_pseudonym(&crypto, space, moderator));
+        assert_ne!(pseudonym, moderator_pseudonym(&crypto, other_space, moderator));
+        assert_ne!(pseudonym, moderator_pseudonym(&crypto, space, Uuid::new_v4()));
+        assert!(!pseudonym.contains(&moderator.simple().to_string()[..8]));
+    }
+}
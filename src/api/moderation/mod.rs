@@ -1,3 +1,5 @@
 //! Moderation API module
 pub mod handlers;
+pub mod log;
+pub mod queue;
 pub use handlers::*;
@@ -0,0 +1,565 @@
+//! Moderation queue
+//!
+//! A space's queue lists posts and comments that are held for review, by
+//! AutoModerator rules or the spam check, or that have open reports.
+//! Moderators who manage posts approve or reject each item, or many at once;
+//! a decision settles the content's hold and open reports together, and the
+//! author is told when it changes what others can see. Approving held content
+//! publishes it the way creating it would have: mentions and replies are
+//! notified and the live channel hears of it.
+
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::log::{self, LogEntry};
+use crate::api::cursor::{Cursor, PageRequest};
+use crate::api::extractors::Pagination;
+use crate::api::notifications::mentions::{notify_mentions, MentionSource};
+use crate::api::notifications::replies::notify_reply;
+use crate::api::spaces::automod::{self, AutomodContent};
+use crate::api::spaces::space_id;
+use crate::domain::entities::*;
+use crate::domain::services::moderation::ModerationService;
+use crate::errors::{ApiError, ApiResult};
+use crate::jobs::handlers::NotificationContext;
+use crate::jobs::workers::send_notification_job;
+use crate::middleware::auth::{require_space_permission, AuthenticatedUser};
+use crate::websocket::{publish_channel_event, Channel};
+use crate::AppState;
+
+/// Reason recorded on rejected content when the moderator gives none
+const REJECTED_REASON: &str = "Removed by moderator";
+
+/// Author notification for a decision that changed the content
+struct AuthorNotice {
+    author_id: Uuid,
+    post_id: Uuid,
+    comment_id: Option<Uuid>,
+}
+
+/// List a space's moderation queue, oldest first
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Query(params): Query<ModqueueParams>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<ModqueueItem>>> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let held_only = params.filter == Some(ModqueueFilter::Held);
+    let reported_only = params.filter == Some(ModqueueFilter::Reported);
+
+    let page: PageRequest<DateTime<Utc>> = PageRequest::new(
+        &pagination,
+        &state.crypto,
+        format!("modqueue:{}:{}:{}", space_id, held_only, reported_only),
+    )?;
+
+    let items = sqlx::query_as!(
+        ModqueueItem,
+        r#"
+        WITH open_reports AS (
+            SELECT r.target_type, r.target_id, COUNT(*) AS report_count,
+                   ARRAY_AGG(DISTINCT r.reason) AS reasons, MIN(r.created_at) AS reported_at
+            FROM reports r
+            JOIN report_spaces rs ON rs.report_id = r.id
+            WHERE rs.space_id = $1 AND r.status = 'pending' AND r.target_type IN ('post', 'comment')
+            GROUP BY r.target_type, r.target_id
+        ),
+        queue AS (
+            SELECT 'post'::VARCHAR AS target_type, p.id AS target_id, p.id AS post_id, p.author_id,
+                   p.title, p.content, p.is_removed, p.removed_reason, p.held_at,
+                   COALESCE(o.report_count, 0) AS report_count, COALESCE(o.reasons, '{}') AS reasons,
+                   LEAST(p.held_at, o.reported_at) AS queued_at
+            FROM posts p
+            LEFT JOIN open_reports o ON o.target_type = 'post' AND o.target_id = p.id
+            WHERE p.space_id = $1 AND (p.held_at IS NOT NULL OR o.target_id IS NOT NULL)
+            UNION ALL
+            SELECT 'comment'::VARCHAR, c.id, c.post_id, c.author_id,
+                   NULL, c.content, c.is_removed, c.removed_reason, c.held_at,
+                   COALESCE(o.report_count, 0), COALESCE(o.reasons, '{}'),
+                   LEAST(c.held_at, o.reported_at)
+            FROM comments c
+            JOIN posts p ON p.id = c.post_id
+            LEFT JOIN open_reports o ON o.target_type = 'comment' AND o.target_id = c.id
+            WHERE p.space_id = $1 AND (c.held_at IS NOT NULL OR o.target_id IS NOT NULL)
+        )
+        SELECT target_type as "target_type!: ModerationTargetType", target_id as "target_id!",
+               post_id as "post_id!", author_id, title, content,
+               COALESCE(is_removed, FALSE) as "is_removed!", removed_reason, held_at,
+               report_count as "report_count!", reasons as "report_reasons!: Vec<ReportReason>",
+               queued_at as "queued_at!"
+        FROM queue
+        WHERE (NOT $2 OR held_at IS NOT NULL)
+              AND (NOT $3 OR report_count > 0)
+              AND ($4::TIMESTAMPTZ IS NULL OR (queued_at, target_id) > ($4, $5))
+        ORDER BY queued_at, target_id
+        LIMIT $6 OFFSET $7
+        "#,
+        space_id,
+        held_only,
+        reported_only,
+        page.after_key(),
+        page.after_id(),
+        page.fetch_limit(),
+        page.offset
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"
+            WITH open_reports AS (
+                SELECT r.target_type, r.target_id
+                FROM reports r
+                JOIN report_spaces rs ON rs.report_id = r.id
+                WHERE rs.space_id = $1 AND r.status = 'pending' AND r.target_type IN ('post', 'comment')
+                GROUP BY r.target_type, r.target_id
+            )
+            SELECT
+                (SELECT COUNT(*)
+                 FROM posts p
+                 LEFT JOIN open_reports o ON o.target_type = 'post' AND o.target_id = p.id
+                 WHERE p.space_id = $1
+                       AND (p.held_at IS NOT NULL OR o.target_id IS NOT NULL)
+                       AND (NOT $2 OR p.held_at IS NOT NULL)
+                       AND (NOT $3 OR o.target_id IS NOT NULL))
+              + (SELECT COUNT(*)
+                 FROM comments c
+                 JOIN posts p ON p.id = c.post_id
+                 LEFT JOIN open_reports o ON o.target_type = 'comment' AND o.target_id = c.id
+                 WHERE p.space_id = $1
+                       AND (c.held_at IS NOT NULL OR o.target_id IS NOT NULL)
+                       AND (NOT $2 OR c.held_at IS NOT NULL)
+                       AND (NOT $3 OR o.target_id IS NOT NULL)) as "count!"
+            "#,
+            space_id,
+            held_only,
+            reported_only
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(items, &state.crypto, |item| Cursor::new(item.queued_at, item.target_id), total)
+        .await?;
+
+    Ok(Json(response))
+}
+
+/// Approve a queued post or comment
+pub async fn approve(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, target_type, target_id)): Path<(String, ModerationTargetType, Uuid)>,
+    Json(request): Json<ModqueueDecisionRequest>,
+) -> ApiResult<Json<ModqueueResult>> {
+    let target = ModqueueTarget { target_type, target_id };
+    decide_one(&state, &user, &slug, target, ModqueueDecision::Approve, request).await
+}
+
+/// Reject a queued post or comment
+pub async fn reject(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, target_type, target_id)): Path<(String, ModerationTargetType, Uuid)>,
+    Json(request): Json<ModqueueDecisionRequest>,
+) -> ApiResult<Json<ModqueueResult>> {
+    let target = ModqueueTarget { target_type, target_id };
+    decide_one(&state, &user, &slug, target, ModqueueDecision::Reject, request).await
+}
+
+/// Approve or reject many queued items at once. Either every decision is
+/// applied or, if any item is not in the space, none is.
+pub async fn bulk(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<BulkModqueueRequest>,
+) -> ApiResult<Json<Vec<ModqueueResult>>> {
+    request.validate()?;
+
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    // Rows are locked in id order so overlapping bulk requests cannot deadlock
+    let mut items = request.items.clone();
+    items.sort_by_key(|item| item.target_id);
+    items.dedup();
+
+    let mut tx = state.db.pool().begin().await?;
+    let mut results = Vec::with_capacity(items.len());
+    let mut notices = Vec::new();
+    let mut released = Vec::new();
+
+    for target in items {
+        let (result, notice, release) = decide(
+            &mut tx,
+            space_id,
+            user.identity_id,
+            target,
+            request.decision,
+            request.reason.as_deref(),
+        )
+        .await?;
+        results.push(result);
+        notices.extend(notice);
+        released.extend(release);
+    }
+
+    tx.commit().await?;
+
+    for notice in notices {
+        notify_author(&state, notice, request.decision, request.reason.as_deref()).await;
+    }
+
+    for target in released {
+        publish_released(&state, &slug, target).await;
+    }
+
+    Ok(Json(results))
+}
+
+async fn decide_one(
+    state: &Arc<AppState>,
+    user: &AuthenticatedUser,
+    slug: &str,
+    target: ModqueueTarget,
+    decision: ModqueueDecision,
+    request: ModqueueDecisionRequest,
+) -> ApiResult<Json<ModqueueResult>> {
+    request.validate()?;
+
+    let space_id = space_id(state, slug).await?;
+    require_space_permission(state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+    let (result, notice, release) = decide(
+        &mut tx,
+        space_id,
+        user.identity_id,
+        target,
+        decision,
+        request.reason.as_deref(),
+    )
+    .await?;
+    tx.commit().await?;
+
+    if let Some(notice) = notice {
+        notify_author(state, notice, decision, request.reason.as_deref()).await;
+    }
+
+    if let Some(target) = release {
+        publish_released(state, slug, target).await;
+    }
+
+    Ok(Json(result))
+}
+
+/// Content as loaded for a decision
+struct Queued {
+    post_id: Uuid,
+    author_id: Option<Uuid>,
+    is_removed: bool,
+    removed_reason: Option<String>,
+    held_at: Option<DateTime<Utc>>,
+}
+
+/// Apply a decision to content in the space, closing its open reports.
+/// Returns the target as well when the decision released held content.
+async fn decide(
+    conn: &mut PgConnection,
+    space_id: Uuid,
+    moderator_id: Uuid,
+    target: ModqueueTarget,
+    decision: ModqueueDecision,
+    reason: Option<&str>,
+) -> ApiResult<(ModqueueResult, Option<AuthorNotice>, Option<ModqueueTarget>)> {
+    let current = load(conn, space_id, target).await?;
+    let outcome = ModerationService::review(decision, current.held_at.is_some(), current.is_removed);
+
+    // A changed removal records the moderator's reason; otherwise the
+    // existing one stands
+    let removed_reason = match (outcome.is_removed, outcome.changed) {
+        (false, _) => None,
+        (true, true) => Some(reason.unwrap_or(REJECTED_REASON).to_string()),
+        (true, false) => current.removed_reason.clone(),
+    };
+
+    // Identities were rejected by `load`
+    if target.target_type == ModerationTargetType::Post {
+        sqlx::query!(
+            "UPDATE posts SET is_removed = $2, removed_reason = $3, held_at = NULL WHERE id = $1",
+            target.target_id,
+            outcome.is_removed,
+            removed_reason
+        )
+        .execute(&mut *conn)
+        .await?;
+    } else {
+        sqlx::query!(
+            "UPDATE comments SET is_removed = $2, removed_reason = $3, held_at = NULL WHERE id = $1",
+            target.target_id,
+            outcome.is_removed,
+            removed_reason
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let reports_closed = sqlx::query!(
+        r#"
+        UPDATE reports
+        SET status = $3, reviewed_by = $4, review_notes = $5, reviewed_at = NOW()
+        WHERE target_type = $1 AND target_id = $2 AND status = 'pending'
+        "#,
+        target.target_type.to_string(),
+        target.target_id,
+        outcome.report_status.to_string(),
+        moderator_id,
+        reason
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected();
+
+    if outcome.changed {
+        let action = match (decision, target.target_type) {
+            (ModqueueDecision::Approve, ModerationTargetType::Post) => ModerationLogAction::ApprovePost,
+            (ModqueueDecision::Approve, _) => ModerationLogAction::ApproveComment,
+            (ModqueueDecision::Reject, ModerationTargetType::Post) => ModerationLogAction::RemovePost,
+            (ModqueueDecision::Reject, _) => ModerationLogAction::RemoveComment,
+        };
+
+        log::record(
+            conn,
+            LogEntry {
+                space_id: Some(space_id),
+                actor_id: Some(moderator_id),
+                action,
+                target_type: target.target_type,
+                target_id: target.target_id,
+                reason,
+                before: Some(json!({
+                    "is_removed": current.is_removed,
+                    "removed_reason": current.removed_reason,
+                    "held_at": current.held_at,
+                })),
+                after: Some(json!({
+                    "is_removed": outcome.is_removed,
+                    "removed_reason": removed_reason,
+                    "held_at": null,
+                })),
+            },
+        )
+        .await?;
+    }
+
+    let notice = current.author_id.filter(|_| outcome.changed).map(|author_id| AuthorNotice {
+        author_id,
+        post_id: current.post_id,
+        comment_id: (target.target_type == ModerationTargetType::Comment).then_some(target.target_id),
+    });
+
+    // Held content was hidden from everyone, so releasing it publishes it
+    // and it counts again
+    let released = (!outcome.is_removed && current.held_at.is_some()).then_some(target);
+    if released.is_some() {
+        let content = match target.target_type {
+            ModerationTargetType::Post => AutomodContent::Post { post_id: target.target_id },
+            _ => AutomodContent::Comment { post_id: current.post_id, comment_id: target.target_id },
+        };
+        automod::tally(conn, content, 1).await?;
+    }
+
+    let result = ModqueueResult {
+        target_type: target.target_type,
+        target_id: target.target_id,
+        is_removed: outcome.is_removed,
+        reports_closed,
+    };
+
+    Ok((result, notice, released))
+}
+
+async fn load(conn: &mut PgConnection, space_id: Uuid, target: ModqueueTarget) -> ApiResult<Queued> {
+    let queued = match target.target_type {
+        ModerationTargetType::Post => sqlx::query!(
+            r#"
+            SELECT id, author_id, is_removed as "is_removed!", removed_reason, held_at
+            FROM posts
+            WHERE id = $1 AND space_id = $2
+            FOR UPDATE
+            "#,
+            target.target_id,
+            space_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|post| Queued {
+            post_id: post.id,
+            author_id: post.author_id,
+            is_removed: post.is_removed,
+            removed_reason: post.removed_reason,
+            held_at: post.held_at,
+        }),
+        ModerationTargetType::Comment => sqlx::query!(
+            r#"
+            SELECT c.post_id, c.author_id, c.is_removed as "is_removed!", c.removed_reason, c.held_at
+            FROM comments c
+            JOIN posts p ON p.id = c.post_id
+            WHERE c.id = $1 AND p.space_id = $2
+            FOR UPDATE OF c
+            "#,
+            target.target_id,
+            space_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|comment| Queued {
+            post_id: comment.post_id,
+            author_id: comment.author_id,
+            is_removed: comment.is_removed,
+            removed_reason: comment.removed_reason,
+            held_at: comment.held_at,
+        }),
+        ModerationTargetType::Identity | ModerationTargetType::AutomodRule => {
+            return Err(ApiError::InvalidInput(
+                "Only posts and comments can be reviewed in the moderation queue".to_string(),
+            ))
+        }
+    };
+
+    queued.ok_or_else(|| ApiError::NotFound("Content not found".to_string()))
+}
+
+/// Tell the author what became of their content. The moderator is not named.
+async fn notify_author(state: &Arc<AppState>, notice: AuthorNotice, decision: ModqueueDecision, reason: Option<&str>) {
+    let result = send_notification_job(
+        state,
+        notice.author_id,
+        NotificationType::ModeratorAction,
+        NotificationContext {
+            post_id: Some(notice.post_id),
+            actor_id: None,
+        },
+        json!({
+            "source": "modqueue",
+            "decision": decision,
+            "post_id": notice.post_id,
+            "comment_id": notice.comment_id,
+            "reason": reason,
+        }),
+    )
+    .await;
+
+    if let Err(e) = result {
+        warn!(post_id = %notice.post_id, error = %e, "Failed to notify author of moderation decision");
+    }
+}
+
+/// Announce released content as its creation would have. The decision has
+/// already been committed, so failures are logged rather than returned.
+async fn publish_released(state: &Arc<AppState>, slug: &str, target: ModqueueTarget) {
+    if let Err(e) = announce_released(state, slug, target).await {
+        warn!(target_id = %target.target_id, error = %e, "Failed to publish released content");
+    }
+}
+
+async fn announce_released(state: &Arc<AppState>, slug: &str, target: ModqueueTarget) -> ApiResult<()> {
+    if target.target_type == ModerationTargetType::Post {
+        let post = sqlx::query_as!(
+            Post,
+            r#"
+            SELECT id, space_id, author_id, title, content,
+                   content_type as "content_type!: ContentType",
+                   url, media_ids as "media_ids!", upvotes as "upvotes!", downvotes as "downvotes!",
+                   score as "score!", comment_count as "comment_count!",
+                   is_pinned as "is_pinned!", is_locked as "is_locked!", is_removed as "is_removed!",
+                   removed_reason, created_at, updated_at, edited_at, held_at, content_html
+            FROM posts
+            WHERE id = $1
+            "#,
+            target.target_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+
+        if let (Some(author_id), Some(content)) = (post.author_id, &post.content) {
+            notify_mentions(state, author_id, MentionSource::Post { post_id: post.id }, None, content).await;
+        }
+
+        publish_channel_event(
+            state,
+            &Channel::Space(slug.to_string()),
+            "post_created",
+            serde_json::to_value(&post)?,
+        )
+        .await;
+    } else {
+        let comment = sqlx::query_as!(
+            Comment,
+            r#"
+            SELECT id, post_id, parent_id, author_id, is_automod, content, depth as "depth!", path,
+                   upvotes as "upvotes!", downvotes as "downvotes!", score as "score!",
+                   is_removed as "is_removed!", removed_reason,
+                   created_at, updated_at, edited_at, held_at, content_html
+            FROM comments
+            WHERE id = $1
+            "#,
+            target.target_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+
+        // The author replied to, unless their content has since been removed
+        let replied_to_author = match comment.parent_id {
+            Some(parent_id) => sqlx::query_scalar!(
+                "SELECT author_id FROM comments WHERE id = $1 AND is_removed IS NOT TRUE",
+                parent_id
+            )
+            .fetch_optional(state.db.pool())
+            .await?
+            .flatten(),
+            None => sqlx::query_scalar!("SELECT author_id FROM posts WHERE id = $1", comment.post_id)
+                .fetch_one(state.db.pool())
+                .await?,
+        };
+
+        notify_reply(state, &comment, replied_to_author).await;
+        if let Some(author_id) = comment.author_id {
+            notify_mentions(
+                state,
+                author_id,
+                MentionSource::Comment { post_id: comment.post_id, comment_id: comment.id },
+                None,
+                &comment.content,
+            )
+            .await;
+        }
+
+        publish_channel_event(
+            state,
+            &Channel::Post(comment.post_id),
+            "comment_created",
+            serde_json::to_value(&comment)?,
+        )
+        .await;
+    }
+
+    Ok(())
+}
@@ -9,9 +9,12 @@ use axum::{
 use futures::{sink::SinkExt, stream::StreamExt};
 use std::sync::Arc;
 use uuid::Uuid;
+use validator::Validate;
 
+use crate::api::cursor::{Cursor, PageRequest};
 use crate::api::extractors::Pagination;
 use crate::domain::entities::*;
+use crate::domain::services::notifications::apply_type_toggles;
 use crate::errors::{ApiError, ApiResult};
 use crate::middleware::auth::AuthenticatedUser;
 use crate::AppState;
@@ -22,35 +25,48 @@ pub async fn list(
     user: AuthenticatedUser,
     Pagination(pagination): Pagination,
 ) -> ApiResult<Json<PaginatedResponse<Notification>>> {
+    let page = PageRequest::<chrono::DateTime<chrono::Utc>>::new(
+        &pagination,
+        &state.crypto,
+        format!("notifications:{}", user.identity_id),
+    )?;
+
     let notifications = sqlx::query_as!(
         Notification,
         r#"
         SELECT id, recipient_id, notification_type as "notification_type: NotificationType",
-               payload, is_read, created_at
+               payload, is_read, created_at, updated_at
         FROM notifications
         WHERE recipient_id = $1
-        ORDER BY created_at DESC
+              AND ($4::TIMESTAMPTZ IS NULL OR (created_at, id) < ($4, $5))
+        ORDER BY created_at DESC, id DESC
         LIMIT $2 OFFSET $3
         "#,
         user.identity_id,
-        pagination.limit,
-        pagination.offset
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        page.after_id()
     )
     .fetch_all(state.db.pool())
     .await?;
 
-    let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM notifications WHERE recipient_id = $1",
-        user.identity_id
-    )
-    .fetch_one(state.db.pool())
-    .await?
-    .unwrap_or(0);
+    let total = async {
+        let total = sqlx::query_scalar!(
+            "SELECT COUNT(*) FROM notifications WHERE recipient_id = $1",
+            user.identity_id
+        )
+        .fetch_one(state.db.pool())
+        .await?
+        .unwrap_or(0);
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(notifications, &state.crypto, |n| Cursor::new(n.created_at, n.id), total)
+        .await?;
 
-    Ok(Json(PaginatedResponse {
-        data: notifications,
-        pagination: PaginationInfo::new(total, pagination.limit, pagination.offset),
-    }))
+    Ok(Json(response))
 }
 
 /// Get unread count
@@ -105,6 +121,268 @@ pub async fn mark_all_read(
     Ok(StatusCode::OK)
 }
 
+/// Mutes an identity may hold
+const MAX_MUTES: i64 = 1000;
+
+/// Get notification preferences
+pub async fn get_preferences(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> ApiResult<Json<NotificationPreferences>> {
+    Ok(Json(load_preferences(&state, user.identity_id).await?))
+}
+
+/// Enable or disable notification types
+pub async fn update_preferences(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(request): Json<UpdateNotificationPreferencesRequest>,
+) -> ApiResult<Json<NotificationPreferences>> {
+    let mut tx = state.db.pool().begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO notification_preferences (identity_id) VALUES ($1) ON CONFLICT (identity_id) DO NOTHING",
+        user.identity_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let disabled = sqlx::query_scalar!(
+        r#"
+        SELECT disabled_types as "disabled_types: Vec<NotificationType>"
+        FROM notification_preferences
+        WHERE identity_id = $1
+        FOR UPDATE
+        "#,
+        user.identity_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let disabled: Vec<String> = apply_type_toggles(&disabled, &request.types)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+    sqlx::query!(
+        "UPDATE notification_preferences SET disabled_types = $2, updated_at = NOW() WHERE identity_id = $1",
+        user.identity_id,
+        &disabled
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(load_preferences(&state, user.identity_id).await?))
+}
+
+/// Set quiet hours
+pub async fn set_quiet_hours(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(request): Json<QuietHours>,
+) -> ApiResult<Json<NotificationPreferences>> {
+    request.validate()?;
+
+    if request.start == request.end {
+        return Err(ApiError::InvalidInput("Quiet hours must not start and end at the same time".to_string()));
+    }
+
+    let known_timezone = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM pg_timezone_names WHERE name = $1) as "exists!""#,
+        request.timezone
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if !known_timezone {
+        return Err(ApiError::InvalidInput(format!("Unknown time zone '{}'", request.timezone)));
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO notification_preferences (identity_id, quiet_hours_start, quiet_hours_end, timezone)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (identity_id) DO UPDATE
+        SET quiet_hours_start = EXCLUDED.quiet_hours_start,
+            quiet_hours_end = EXCLUDED.quiet_hours_end,
+            timezone = EXCLUDED.timezone,
+            updated_at = NOW()
+        "#,
+        user.identity_id,
+        request.start,
+        request.end,
+        request.timezone
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    Ok(Json(load_preferences(&state, user.identity_id).await?))
+}
+
+/// Turn quiet hours off
+pub async fn clear_quiet_hours(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> ApiResult<StatusCode> {
+    sqlx::query!(
+        r#"
+        UPDATE notification_preferences
+        SET quiet_hours_start = NULL, quiet_hours_end = NULL, updated_at = NOW()
+        WHERE identity_id = $1
+        "#,
+        user.identity_id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Mute notifications about a post, space or identity
+pub async fn add_mute(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Json(request): Json<MuteRequest>,
+) -> ApiResult<(StatusCode, Json<NotificationMute>)> {
+    let exists = match request.target_type {
+        MuteTargetType::Post => {
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS (SELECT 1 FROM posts WHERE id = $1) as "exists!""#,
+                request.target_id
+            )
+            .fetch_one(state.db.pool())
+            .await?
+        }
+        MuteTargetType::Space => {
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS (SELECT 1 FROM spaces WHERE id = $1) as "exists!""#,
+                request.target_id
+            )
+            .fetch_one(state.db.pool())
+            .await?
+        }
+        MuteTargetType::Identity => {
+            if request.target_id == user.identity_id {
+                return Err(ApiError::InvalidInput("Cannot mute yourself".to_string()));
+            }
+            sqlx::query_scalar!(
+                r#"SELECT EXISTS (SELECT 1 FROM identities WHERE id = $1) as "exists!""#,
+                request.target_id
+            )
+            .fetch_one(state.db.pool())
+            .await?
+        }
+    };
+
+    if !exists {
+        return Err(ApiError::NotFound("Mute target not found".to_string()));
+    }
+
+    let mute_count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM notification_mutes WHERE identity_id = $1"#,
+        user.identity_id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if mute_count >= MAX_MUTES {
+        return Err(ApiError::OperationNotAllowed(format!("At most {} mutes are allowed", MAX_MUTES)));
+    }
+
+    // Muting something already muted returns the existing mute
+    let mute = sqlx::query_as!(
+        NotificationMute,
+        r#"
+        INSERT INTO notification_mutes (identity_id, target_type, target_id)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (identity_id, target_type, target_id)
+        DO UPDATE SET created_at = notification_mutes.created_at
+        RETURNING target_type as "target_type: MuteTargetType", target_id, created_at
+        "#,
+        user.identity_id,
+        request.target_type as MuteTargetType,
+        request.target_id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    Ok((StatusCode::CREATED, Json(mute)))
+}
+
+/// Unmute a post, space or identity
+pub async fn remove_mute(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((target_type, target_id)): Path<(MuteTargetType, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let result = sqlx::query!(
+        "DELETE FROM notification_mutes WHERE identity_id = $1 AND target_type = $2 AND target_id = $3",
+        user.identity_id,
+        target_type as MuteTargetType,
+        target_id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Mute not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn load_preferences(state: &AppState, identity_id: Uuid) -> ApiResult<NotificationPreferences> {
+    let settings = sqlx::query!(
+        r#"
+        SELECT disabled_types as "disabled_types: Vec<NotificationType>",
+               quiet_hours_start, quiet_hours_end, timezone
+        FROM notification_preferences
+        WHERE identity_id = $1
+        "#,
+        identity_id
+    )
+    .fetch_optional(state.db.pool())
+    .await?;
+
+    let mutes = sqlx::query_as!(
+        NotificationMute,
+        r#"
+        SELECT target_type as "target_type: MuteTargetType", target_id, created_at
+        FROM notification_mutes
+        WHERE identity_id = $1
+        ORDER BY created_at DESC
+        "#,
+        identity_id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let Some(settings) = settings else {
+        return Ok(NotificationPreferences {
+            disabled_types: Vec::new(),
+            quiet_hours: None,
+            mutes,
+        });
+    };
+
+    let quiet_hours = match (settings.quiet_hours_start, settings.quiet_hours_end) {
+        (Some(start), Some(end)) => Some(QuietHours {
+            start,
+            end,
+            timezone: settings.timezone,
+        }),
+        _ => None,
+    };
+
+    Ok(NotificationPreferences {
+        disabled_types: settings.disabled_types,
+        quiet_hours,
+        mutes,
+    })
+}
+
 /// WebSocket handler for real-time notifications
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
@@ -141,30 +419,14 @@ async fn handle_websocket(socket: WebSocket, state: Arc<AppState>) {
         Err(_) => return,
     };
 
-    // Send confirmation
-    let _ = sender.send(Message::Text(r#"{"status":"connected"}"#.to_string())).await;
-
-    // Subscribe to notifications via Redis pub/sub (simplified)
-    let channel = format!("notifications:{}", identity_id);
+    // Hand off to the shared connection loop, which registers the socket for
+    // notification delivery
+    let socket = match sender.reunite(receiver) {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
 
-    // In production, you would use Redis pub/sub here
-    // For now, just keep connection alive with heartbeats
-    loop {
-        tokio::select! {
-            _ = tokio::time::sleep(tokio::time::Duration::from_secs(30)) => {
-                if sender.send(Message::Ping(vec![])).await.is_err() {
-                    break;
-                }
-            }
-            msg = receiver.next() => {
-                match msg {
-                    Some(Ok(Message::Pong(_))) => continue,
-                    Some(Ok(Message::Close(_))) | None => break,
-                    _ => continue,
-                }
-            }
-        }
-    }
+    crate::websocket::handle_authenticated_websocket(socket, state, identity_id).await;
 }
 
 #[derive(Debug, serde::Serialize)]
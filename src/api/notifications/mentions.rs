@@ -0,0 +1,153 @@
+//! Mention notifications
+//!
+//! `@name` mentions in post and comment bodies notify the identity they name.
+//! Only mentions added by a write are notified, so editing a post does not
+//! repeat notifications for names it already mentioned. Each author may send
+//! a limited number of mention notifications per hour.
+
+use serde_json::json;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::domain::entities::NotificationType;
+use crate::domain::repositories::IdentityRepository;
+use crate::domain::services::markdown::extract_mentions;
+use crate::errors::ApiResult;
+use crate::infrastructure::cache::RateLimiter;
+use crate::infrastructure::database::repositories::PgIdentityRepository;
+use crate::jobs::handlers::NotificationContext;
+use crate::jobs::workers::send_notification_job;
+use crate::AppState;
+
+/// Names notified from a single post or comment
+const MAX_MENTIONS_PER_WRITE: usize = 10;
+
+/// Mention notifications an author may send per window
+const MENTION_RATE_LIMIT: u32 = 50;
+const MENTION_RATE_WINDOW: Duration = Duration::from_secs(3600);
+
+/// Where a mention was made
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum MentionSource {
+    Post { post_id: Uuid },
+    Comment { post_id: Uuid, comment_id: Uuid },
+}
+
+impl MentionSource {
+    fn payload(self, author_id: Uuid) -> serde_json::Value {
+        match self {
+            MentionSource::Post { post_id } => json!({
+                "source": "post",
+                "post_id": post_id,
+                "mentioned_by": author_id,
+            }),
+            MentionSource::Comment { post_id, comment_id } => json!({
+                "source": "comment",
+                "post_id": post_id,
+                "comment_id": comment_id,
+                "mentioned_by": author_id,
+            }),
+        }
+    }
+
+    fn post_id(self) -> Uuid {
+        match self {
+            MentionSource::Post { post_id } | MentionSource::Comment { post_id, .. } => post_id,
+        }
+    }
+}
+
+/// Notify identities newly mentioned in `content`, which replaced `previous`.
+///
+/// The write has already happened, so failures are logged rather than
+/// returned.
+pub(crate) async fn notify_mentions(
+    state: &Arc<AppState>,
+    author_id: Uuid,
+    source: MentionSource,
+    previous: Option<&str>,
+    content: &str,
+) {
+    if let Err(e) = deliver_mentions(state, author_id, source, previous, content).await {
+        warn!(author_id = %author_id, error = %e, "Failed to send mention notifications");
+    }
+}
+
+async fn deliver_mentions(
+    state: &Arc<AppState>,
+    author_id: Uuid,
+    source: MentionSource,
+    previous: Option<&str>,
+    content: &str,
+) -> ApiResult<()> {
+    let already_mentioned = previous.map(extract_mentions).unwrap_or_default();
+    let names: Vec<String> = extract_mentions(content)
+        .into_iter()
+        .filter(|name| !already_mentioned.contains(name))
+        .take(MAX_MENTIONS_PER_WRITE)
+        .collect();
+
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let identities = PgIdentityRepository::new(state.db.pool().clone())
+        .find_mentioned(&names)
+        .await?;
+
+    // A name and a fingerprint may both resolve to the same identity
+    let mut recipients: Vec<Uuid> = Vec::with_capacity(identities.len());
+    for identity in identities {
+        if identity.id != author_id && !recipients.contains(&identity.id) {
+            recipients.push(identity.id);
+        }
+    }
+
+    // Content in a private space is only visible to its members
+    let recipients: Vec<Uuid> = sqlx::query_scalar!(
+        r#"
+        SELECT r.id as "id!"
+        FROM UNNEST($2::UUID[]) AS r(id)
+        JOIN posts p ON p.id = $1
+        JOIN spaces s ON s.id = p.space_id
+        WHERE s.is_private IS NOT TRUE
+           OR EXISTS (
+               SELECT 1 FROM space_members m
+               WHERE m.space_id = s.id AND m.identity_id = r.id
+           )
+        "#,
+        source.post_id(),
+        &recipients
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let limiter = RateLimiter::new(&state.redis);
+    let rate_key = format!("mentions:{}", author_id);
+
+    for recipient_id in recipients {
+        let (allowed, _, _) = limiter
+            .check_and_increment(&rate_key, MENTION_RATE_LIMIT, MENTION_RATE_WINDOW)
+            .await?;
+        if !allowed {
+            debug!(author_id = %author_id, "Mention notifications rate limited");
+            break;
+        }
+
+        send_notification_job(
+            state,
+            recipient_id,
+            NotificationType::Mention,
+            NotificationContext {
+                post_id: Some(source.post_id()),
+                actor_id: Some(author_id),
+            },
+            source.payload(author_id),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
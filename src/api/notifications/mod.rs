@@ -1,3 +1,5 @@
 //! Notifications API module
 pub mod handlers;
+pub mod mentions;
+pub mod replies;
 pub use handlers::*;
@@ -0,0 +1,61 @@
+//! Reply notifications
+//!
+//! A new comment notifies the author of what it replies to: the parent
+//! comment's author, or the post author for top-level comments. Replies to
+//! the same post or comment coalesce into one unread notification that
+//! counts them, so a busy thread produces one row per reader rather than one
+//! per reply.
+
+use serde_json::json;
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::domain::entities::{Comment, NotificationType};
+use crate::jobs::handlers::NotificationContext;
+use crate::jobs::workers::send_grouped_notification_job;
+use crate::AppState;
+
+/// Notify the author replied to by `comment`.
+///
+/// `replied_to_author` is the parent comment's author, or the post's author
+/// for a top-level comment; `None` when it was deleted or removed. Self-replies
+/// are not notified.
+pub(crate) async fn notify_reply(state: &Arc<AppState>, comment: &Comment, replied_to_author: Option<Uuid>) {
+    let Some(recipient_id) = replied_to_author.filter(|&id| Some(id) != comment.author_id) else {
+        return;
+    };
+
+    let (notification_type, group_key, payload) = match comment.parent_id {
+        Some(parent_id) => (
+            NotificationType::CommentReply,
+            format!("comment_reply:{}", parent_id),
+            json!({
+                "post_id": comment.post_id,
+                "parent_comment_id": parent_id,
+                "comment_id": comment.id,
+                "replied_by": comment.author_id,
+            }),
+        ),
+        None => (
+            NotificationType::PostReply,
+            format!("post_reply:{}", comment.post_id),
+            json!({
+                "post_id": comment.post_id,
+                "comment_id": comment.id,
+                "replied_by": comment.author_id,
+            }),
+        ),
+    };
+
+    let context = NotificationContext {
+        post_id: Some(comment.post_id),
+        actor_id: comment.author_id,
+    };
+
+    if let Err(e) =
+        send_grouped_notification_job(state, recipient_id, notification_type, group_key, context, payload).await
+    {
+        warn!(comment_id = %comment.id, error = %e, "Failed to send reply notification");
+    }
+}
@@ -0,0 +1,154 @@
+//! Poll handlers
+
+use axum::{extract::{Path, State}, Json};
+use chrono::Utc;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::api::feed::context::load_poll_views;
+use crate::api::spaces::require_space_visible;
+use crate::domain::entities::*;
+use crate::domain::services::polls::{poll_tally, poll_view, validate_ballot};
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::{AuthenticatedUser, OptionalUser};
+use crate::websocket::{publish_channel_event, Channel};
+use crate::AppState;
+
+/// Get the poll on a post
+pub async fn get_poll(
+    State(state): State<Arc<AppState>>,
+    Path(post_id): Path<Uuid>,
+    user: OptionalUser,
+) -> ApiResult<Json<PollView>> {
+    let viewer = user.0.map(|u| u.identity_id);
+
+    let space_id = sqlx::query_scalar!("SELECT space_id FROM posts WHERE id = $1", post_id)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+
+    require_space_visible(&state, space_id, viewer).await?;
+
+    load_poll_views(&state, &[post_id], viewer)
+        .await?
+        .remove(&post_id)
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("Poll not found".to_string()))
+}
+
+/// Cast a ballot; each identity votes once per poll
+pub async fn vote(
+    State(state): State<Arc<AppState>>,
+    Path(post_id): Path<Uuid>,
+    user: AuthenticatedUser,
+    Json(request): Json<PollVoteRequest>,
+) -> ApiResult<Json<PollView>> {
+    request.validate()?;
+
+    let post = sqlx::query!(
+        r#"
+        SELECT space_id, is_removed as "is_removed!", is_locked as "is_locked!"
+        FROM posts
+        WHERE id = $1
+        "#,
+        post_id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+
+    if post.is_removed {
+        return Err(ApiError::Gone);
+    }
+
+    if post.is_locked {
+        return Err(ApiError::OperationNotAllowed("Post is locked".to_string()));
+    }
+
+    require_space_visible(&state, post.space_id, Some(user.identity_id)).await?;
+
+    // Lock the poll so concurrent ballots update tallies one at a time
+    let mut tx = state.db.pool().begin().await?;
+
+    let poll = sqlx::query_as!(
+        Poll,
+        r#"
+        SELECT post_id, allows_multiple, closes_at, total_voters, created_at
+        FROM polls
+        WHERE post_id = $1
+        FOR UPDATE
+        "#,
+        post_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Poll not found".to_string()))?;
+
+    let options = sqlx::query_as!(
+        PollOption,
+        "SELECT id, post_id, position, label, vote_count FROM poll_options WHERE post_id = $1",
+        post_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let now = Utc::now();
+    let choices = validate_ballot(&poll, &options, &request.option_ids, now)?;
+
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO poll_ballots (post_id, identity_id, option_ids)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (post_id, identity_id) DO NOTHING
+        "#,
+        post_id,
+        user.identity_id,
+        &choices
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if inserted == 0 {
+        return Err(ApiError::Conflict("You have already voted in this poll".to_string()));
+    }
+
+    let options = sqlx::query_as!(
+        PollOption,
+        r#"
+        UPDATE poll_options
+        SET vote_count = vote_count + CASE WHEN id = ANY($2) THEN 1 ELSE 0 END
+        WHERE post_id = $1
+        RETURNING id, post_id, position, label, vote_count
+        "#,
+        post_id,
+        &choices
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let poll = sqlx::query_as!(
+        Poll,
+        r#"
+        UPDATE polls SET total_voters = total_voters + 1
+        WHERE post_id = $1
+        RETURNING post_id, allows_multiple, closes_at, total_voters, created_at
+        "#,
+        post_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    publish_channel_event(
+        &state,
+        &Channel::Poll(post_id),
+        "poll_tally",
+        serde_json::to_value(poll_tally(&poll, &options))?,
+    )
+    .await;
+
+    Ok(Json(poll_view(&poll, options, Some(choices), now)))
+}
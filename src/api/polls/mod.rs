@@ -0,0 +1,5 @@
+//! Polls API module
+
+pub mod handlers;
+
+pub use handlers::*;
@@ -5,15 +5,29 @@ use axum::{
     http::StatusCode,
     Json,
 };
+use serde_json::json;
 use std::sync::Arc;
 use uuid::Uuid;
 use validator::Validate;
 
 use crate::api::extractors::Pagination;
+use crate::api::feed::context::{load_page_context, load_post_context};
+use crate::api::feed::query::{fetch_ranked_posts, FeedScope};
+use crate::api::moderation::log::{self, LogEntry};
+use crate::api::notifications::mentions::{notify_mentions, MentionSource};
+use crate::api::revisions::record_revision;
+use crate::api::spaces::automod::{self, AutomodContent, AutomodTrigger};
+use crate::api::spaces::require_space_visible;
+use crate::api::spaces::restrictions::{require_can_contribute, Contribution};
 use crate::domain::entities::*;
 use crate::domain::services::feed::calculate_hot_score;
+use crate::domain::services::markdown::render_markdown;
+use crate::domain::services::polls::validate_poll;
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::{AuthenticatedUser, OptionalUser, check_moderator};
+use crate::jobs::handlers::{UnfurlLinkPayload, UNFURL_LINK};
+use crate::jobs::{EnqueueOptions, JobQueue};
+use crate::middleware::auth::{space_access, require_space_permission, AuthenticatedUser, OptionalUser};
+use crate::websocket::{publish_channel_event, Channel};
 use crate::AppState;
 
 /// List posts in a space
@@ -28,59 +42,10 @@ pub async fn list_by_space(
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    let posts = sqlx::query_as!(
-        Post,
-        r#"
-        SELECT id, space_id, author_id, title, content,
-               content_type as "content_type: ContentType",
-               url, media_ids, upvotes, downvotes, score, comment_count,
-               is_pinned, is_locked, is_removed, removed_reason,
-               created_at, updated_at
-        FROM posts
-        WHERE space_id = $1 AND is_removed = false
-        ORDER BY is_pinned DESC, score DESC, created_at DESC
-        LIMIT $2 OFFSET $3
-        "#,
-        space.id,
-        params.pagination.limit,
-        params.pagination.offset
-    )
-    .fetch_all(state.db.pool())
-    .await?;
-
-    // Get user votes if authenticated
-    let user_votes = if let Some(ref u) = user.0 {
-        get_user_votes_for_posts(&state, u.identity_id, &posts).await?
-    } else {
-        std::collections::HashMap::new()
-    };
-
-    // Build response with context
-    let posts_with_context: Vec<PostWithContext> = posts
-        .into_iter()
-        .map(|post| {
-            let user_vote = user_votes.get(&post.id).copied();
-            PostWithContext {
-                post,
-                author: None, // Would load from cache/batch
-                space: None,
-                user_vote,
-            }
-        })
-        .collect();
-
-    let total: i64 = sqlx::query_scalar!(
-        "SELECT COUNT(*) FROM posts WHERE space_id = $1 AND is_removed = false",
-        space.id
-    )
-    .fetch_one(state.db.pool())
-    .await?
-    .unwrap_or(0);
+    let viewer = user.0.map(|u| u.identity_id);
+    let page = fetch_ranked_posts(&state, FeedScope::Space(space.id), &params, viewer).await?;
 
-    Ok(Json(PaginatedResponse {
-        data: posts_with_context,
-        pagination: PaginationInfo::new(total, params.pagination.limit, params.pagination.offset),
-    }))
+    Ok(Json(load_page_context(&state, page, viewer).await?))
 }
 
 /// Create a new post
@@ -92,7 +57,7 @@ pub async fn create(
 ) -> ApiResult<(StatusCode, Json<Post>)> {
     request.validate()?;
 
-    let space = sqlx::query!("SELECT id FROM spaces WHERE slug = $1", slug)
+    let space = sqlx::query!("SELECT id, allowed_tags FROM spaces WHERE slug = $1", slug)
         .fetch_optional(state.db.pool())
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
@@ -111,20 +76,51 @@ pub async fn create(
         return Err(ApiError::Forbidden);
     }
 
+    require_can_contribute(&state, space.id, user.identity_id, Contribution::Post).await?;
+
     let id = Uuid::new_v4();
     let now = chrono::Utc::now();
-    let content_type = request.content_type.unwrap_or_default();
 
-    let post = sqlx::query_as!(
+    let poll_options = request
+        .poll
+        .as_ref()
+        .map(|poll| validate_poll(poll, now))
+        .transpose()?;
+
+    let content_type = request.content_type.unwrap_or(if request.poll.is_some() {
+        ContentType::Poll
+    } else {
+        ContentType::default()
+    });
+
+    match (content_type, &request.poll) {
+        (ContentType::Poll, None) => {
+            return Err(ApiError::InvalidInput("Poll posts need poll options".to_string()));
+        }
+        (ContentType::Poll, Some(_)) | (_, None) => {}
+        (_, Some(_)) => {
+            return Err(ApiError::InvalidInput("Only poll posts can have poll options".to_string()));
+        }
+    }
+
+    let content_html = request
+        .content
+        .as_deref()
+        .map(|content| render_markdown(content, space.allowed_tags.as_deref()));
+
+    // The post and its poll are created together
+    let mut tx = state.db.pool().begin().await?;
+
+    let mut post = sqlx::query_as!(
         Post,
         r#"
-        INSERT INTO posts (id, space_id, author_id, title, content, content_type, url, media_ids, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9)
+        INSERT INTO posts (id, space_id, author_id, title, content, content_html, content_type, url, media_ids, hot_rank, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $11, $6, $7, $8, $10, $9, $9)
         RETURNING id, space_id, author_id, title, content,
                   content_type as "content_type: ContentType",
                   url, media_ids, upvotes, downvotes, score, comment_count,
                   is_pinned, is_locked, is_removed, removed_reason,
-                  created_at, updated_at
+                  created_at, updated_at, edited_at, held_at, content_html
         "#,
         id,
         space.id,
@@ -134,20 +130,75 @@ pub async fn create(
         content_type.to_string(),
         request.url,
         &request.media_ids.unwrap_or_default() as &[Uuid],
-        now
+        now,
+        calculate_hot_score(0, 0, now),
+        content_html
     )
-    .fetch_one(state.db.pool())
+    .fetch_one(&mut *tx)
     .await?;
 
-    // Increment space post count
-    sqlx::query!("UPDATE spaces SET post_count = post_count + 1 WHERE id = $1", space.id)
-        .execute(state.db.pool())
+    if let (Some(poll), Some(labels)) = (&request.poll, poll_options) {
+        sqlx::query!(
+            "INSERT INTO polls (post_id, allows_multiple, closes_at) VALUES ($1, $2, $3)",
+            post.id,
+            poll.allows_multiple,
+            poll.closes_at
+        )
+        .execute(&mut *tx)
         .await?;
 
-    // Award karma for posting
-    sqlx::query!("UPDATE identities SET karma = karma + 1 WHERE id = $1", user.identity_id)
-        .execute(state.db.pool())
+        sqlx::query!(
+            r#"
+            INSERT INTO poll_options (post_id, position, label)
+            SELECT $1, (o.ord - 1)::SMALLINT, o.label
+            FROM UNNEST($2::TEXT[]) WITH ORDINALITY AS o(label, ord)
+            "#,
+            post.id,
+            &labels
+        )
+        .execute(&mut *tx)
         .await?;
+    }
+
+    // Counts towards the space's posts and the author's karma; held posts
+    // stop counting until they are released
+    let automod_content = AutomodContent::Post { post_id: post.id };
+    automod::tally(&mut tx, automod_content, 1).await?;
+    let outcome = automod::moderate(&mut tx, automod_content, AutomodTrigger::Write).await?;
+
+    tx.commit().await?;
+
+    outcome.update_post(&mut post);
+
+    // Link previews are fetched in the background
+    if post.content_type == ContentType::Link && post.url.is_some() {
+        let options = EnqueueOptions {
+            unique_key: Some(format!("{}:{}", UNFURL_LINK, post.id)),
+            ..Default::default()
+        };
+        JobQueue::new(state.db.pool().clone())
+            .enqueue_with(UNFURL_LINK, &UnfurlLinkPayload { post_id: post.id }, options)
+            .await?;
+    }
+
+    automod::notify(&state, automod_content, &outcome).await;
+
+    if post.is_removed {
+        return Ok((StatusCode::CREATED, Json(post)));
+    }
+
+    if let Some(content) = &post.content {
+        notify_mentions(&state, user.identity_id, MentionSource::Post { post_id: post.id }, None, content)
+            .await;
+    }
+
+    publish_channel_event(
+        &state,
+        &Channel::Space(slug),
+        "post_created",
+        serde_json::to_value(&post)?,
+    )
+    .await;
 
     Ok((StatusCode::CREATED, Json(post)))
 }
@@ -165,7 +216,7 @@ pub async fn get_by_id(
                content_type as "content_type: ContentType",
                url, media_ids, upvotes, downvotes, score, comment_count,
                is_pinned, is_locked, is_removed, removed_reason,
-               created_at, updated_at
+               created_at, updated_at, edited_at, held_at, content_html
         FROM posts
         WHERE id = $1
         "#,
@@ -175,29 +226,21 @@ pub async fn get_by_id(
     .await?
     .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
 
-    if post.is_removed {
+    // Authors can still see their posts while they are held for review
+    let viewer = user.0.map(|u| u.identity_id);
+    let own_held = post.held_at.is_some() && viewer.is_some() && post.author_id == viewer;
+    if post.is_removed && !own_held {
         return Err(ApiError::Gone);
     }
 
-    let user_vote = if let Some(ref u) = user.0 {
-        sqlx::query_scalar!(
-            "SELECT vote_value FROM votes WHERE identity_id = $1 AND target_type = 'post' AND target_id = $2",
-            u.identity_id,
-            id
-        )
-        .fetch_optional(state.db.pool())
+    require_space_visible(&state, post.space_id, viewer).await?;
+
+    let post = load_post_context(&state, vec![post], viewer)
         .await?
-        .flatten()
-    } else {
-        None
-    };
+        .pop()
+        .expect("one post in, one post out");
 
-    Ok(Json(PostWithContext {
-        post,
-        author: None,
-        space: None,
-        user_vote,
-    }))
+    Ok(Json(post))
 }
 
 /// Update a post
@@ -209,15 +252,25 @@ pub async fn update(
 ) -> ApiResult<Json<Post>> {
     request.validate()?;
 
+    let mut tx = state.db.pool().begin().await?;
+
     let post = sqlx::query!(
-        "SELECT author_id, is_locked, is_removed FROM posts WHERE id = $1",
+        r#"
+        SELECT p.author_id, p.content, p.is_locked as "is_locked!", p.is_removed as "is_removed!",
+               p.held_at, s.allowed_tags
+        FROM posts p
+        JOIN spaces s ON s.id = p.space_id
+        WHERE p.id = $1
+        FOR UPDATE OF p
+        "#,
         id
     )
-    .fetch_optional(state.db.pool())
+    .fetch_optional(&mut *tx)
     .await?
     .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
 
-    if post.is_removed {
+    // Held posts stay editable while they wait for review
+    if post.is_removed && post.held_at.is_none() {
         return Err(ApiError::Gone);
     }
 
@@ -229,24 +282,61 @@ pub async fn update(
         return Err(ApiError::Forbidden);
     }
 
-    let updated = sqlx::query_as!(
+    let changed = request.content.is_some() && request.content != post.content;
+    if changed {
+        record_revision(&mut *tx, RevisionTargetType::Post, id, user.identity_id, post.content.as_deref())
+            .await?;
+    }
+
+    let content_html = request
+        .content
+        .as_deref()
+        .map(|content| render_markdown(content, post.allowed_tags.as_deref()));
+
+    let mut updated = sqlx::query_as!(
         Post,
         r#"
         UPDATE posts
-        SET content = COALESCE($2, content), updated_at = NOW()
+        SET content = COALESCE($2, content), content_html = COALESCE($4, content_html),
+            updated_at = NOW(), edited_at = CASE WHEN $3 THEN NOW() ELSE edited_at END
         WHERE id = $1
         RETURNING id, space_id, author_id, title, content,
                   content_type as "content_type: ContentType",
                   url, media_ids, upvotes, downvotes, score, comment_count,
                   is_pinned, is_locked, is_removed, removed_reason,
-                  created_at, updated_at
+                  created_at, updated_at, edited_at, held_at, content_html
         "#,
         id,
-        request.content
+        request.content,
+        changed,
+        content_html
     )
-    .fetch_one(state.db.pool())
+    .fetch_one(&mut *tx)
     .await?;
 
+    let automod_content = AutomodContent::Post { post_id: id };
+    let outcome = if changed {
+        automod::moderate(&mut tx, automod_content, AutomodTrigger::Write).await?
+    } else {
+        Default::default()
+    };
+
+    tx.commit().await?;
+
+    outcome.update_post(&mut updated);
+    automod::notify(&state, automod_content, &outcome).await;
+
+    if let (true, false, Some(content)) = (changed, updated.is_removed, &updated.content) {
+        notify_mentions(
+            &state,
+            user.identity_id,
+            MentionSource::Post { post_id: id },
+            post.content.as_deref(),
+            content,
+        )
+        .await;
+    }
+
     Ok(Json(updated))
 }
 
@@ -263,7 +353,9 @@ pub async fn delete(
 
     // Check if author or moderator
     let is_author = post.author_id == Some(user.identity_id);
-    let is_mod = check_moderator(&state, user.identity_id, post.space_id).await?;
+    let is_mod = space_access(&state, user.identity_id, post.space_id)
+        .await?
+        .has(SpacePermission::ManagePosts);
 
     if !is_author && !is_mod {
         return Err(ApiError::Forbidden);
@@ -275,14 +367,43 @@ pub async fn delete(
         "Removed by moderator"
     };
 
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = sqlx::query!(
+        r#"SELECT is_removed as "is_removed!", removed_reason FROM posts WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
     sqlx::query!(
-        "UPDATE posts SET is_removed = true, removed_reason = $2 WHERE id = $1",
+        "UPDATE posts SET is_removed = true, removed_reason = $2, held_at = NULL WHERE id = $1",
         id,
         reason
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
     .await?;
 
+    // Authors deleting their own posts are not moderating
+    if !is_author {
+        log::record(
+            &mut tx,
+            LogEntry {
+                space_id: Some(post.space_id),
+                actor_id: Some(user.identity_id),
+                action: ModerationLogAction::RemovePost,
+                target_type: ModerationTargetType::Post,
+                target_id: id,
+                reason: None,
+                before: Some(json!({ "is_removed": before.is_removed, "removed_reason": before.removed_reason })),
+                after: Some(json!({ "is_removed": true, "removed_reason": reason })),
+            },
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -292,20 +413,7 @@ pub async fn pin(
     Path(id): Path<Uuid>,
     user: AuthenticatedUser,
 ) -> ApiResult<StatusCode> {
-    let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
-
-    if !check_moderator(&state, user.identity_id, post.space_id).await? {
-        return Err(ApiError::Forbidden);
-    }
-
-    sqlx::query!("UPDATE posts SET is_pinned = true WHERE id = $1", id)
-        .execute(state.db.pool())
-        .await?;
-
-    Ok(StatusCode::OK)
+    set_flag(&state, user.identity_id, id, PostFlag::Pinned, true).await
 }
 
 /// Unpin a post
@@ -314,42 +422,16 @@ pub async fn unpin(
     Path(id): Path<Uuid>,
     user: AuthenticatedUser,
 ) -> ApiResult<StatusCode> {
-    let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
-
-    if !check_moderator(&state, user.identity_id, post.space_id).await? {
-        return Err(ApiError::Forbidden);
-    }
-
-    sqlx::query!("UPDATE posts SET is_pinned = false WHERE id = $1", id)
-        .execute(state.db.pool())
-        .await?;
-
-    Ok(StatusCode::OK)
+    set_flag(&state, user.identity_id, id, PostFlag::Pinned, false).await
 }
 
-/// Lock a post (prevent new comments)
+/// Lock a post
 pub async fn lock(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
     user: AuthenticatedUser,
 ) -> ApiResult<StatusCode> {
-    let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", id)
-        .fetch_optional(state.db.pool())
-        .await?
-        .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
-
-    if !check_moderator(&state, user.identity_id, post.space_id).await? {
-        return Err(ApiError::Forbidden);
-    }
-
-    sqlx::query!("UPDATE posts SET is_locked = true WHERE id = $1", id)
-        .execute(state.db.pool())
-        .await?;
-
-    Ok(StatusCode::OK)
+    set_flag(&state, user.identity_id, id, PostFlag::Locked, true).await
 }
 
 /// Unlock a post
@@ -357,41 +439,83 @@ pub async fn unlock(
     State(state): State<Arc<AppState>>,
     Path(id): Path<Uuid>,
     user: AuthenticatedUser,
+) -> ApiResult<StatusCode> {
+    set_flag(&state, user.identity_id, id, PostFlag::Locked, false).await
+}
+
+/// Post flags set by moderators
+#[derive(Debug, Clone, Copy)]
+enum PostFlag {
+    Pinned,
+    Locked,
+}
+
+/// Set a moderator flag on a post, logging the change if there is one
+async fn set_flag(
+    state: &Arc<AppState>,
+    identity_id: Uuid,
+    id: Uuid,
+    flag: PostFlag,
+    value: bool,
 ) -> ApiResult<StatusCode> {
     let post = sqlx::query!("SELECT space_id FROM posts WHERE id = $1", id)
         .fetch_optional(state.db.pool())
         .await?
         .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
 
-    if !check_moderator(&state, user.identity_id, post.space_id).await? {
-        return Err(ApiError::Forbidden);
-    }
+    require_space_permission(state, identity_id, post.space_id, SpacePermission::ManagePosts).await?;
 
-    sqlx::query!("UPDATE posts SET is_locked = false WHERE id = $1", id)
-        .execute(state.db.pool())
-        .await?;
+    let mut tx = state.db.pool().begin().await?;
 
-    Ok(StatusCode::OK)
-}
+    let current = sqlx::query!(
+        r#"SELECT is_pinned as "is_pinned!", is_locked as "is_locked!" FROM posts WHERE id = $1 FOR UPDATE"#,
+        id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
 
-// Helper functions
+    let (field, was, action) = match flag {
+        PostFlag::Pinned => (
+            "is_pinned",
+            current.is_pinned,
+            if value { ModerationLogAction::PinPost } else { ModerationLogAction::UnpinPost },
+        ),
+        PostFlag::Locked => (
+            "is_locked",
+            current.is_locked,
+            if value { ModerationLogAction::LockPost } else { ModerationLogAction::UnlockPost },
+        ),
+    };
 
-async fn get_user_votes_for_posts(
-    state: &Arc<AppState>,
-    identity_id: Uuid,
-    posts: &[Post],
-) -> ApiResult<std::collections::HashMap<Uuid, i16>> {
-    let post_ids: Vec<Uuid> = posts.iter().map(|p| p.id).collect();
-
-    let votes = sqlx::query!(
-        "SELECT target_id, vote_value FROM votes WHERE identity_id = $1 AND target_type = 'post' AND target_id = ANY($2)",
-        identity_id,
-        &post_ids
+    if was == value {
+        return Ok(StatusCode::OK);
+    }
+
+    match flag {
+        PostFlag::Pinned => sqlx::query!("UPDATE posts SET is_pinned = $2 WHERE id = $1", id, value),
+        PostFlag::Locked => sqlx::query!("UPDATE posts SET is_locked = $2 WHERE id = $1", id, value),
+    }
+    .execute(&mut *tx)
+    .await?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(post.space_id),
+            actor_id: Some(identity_id),
+            action,
+            target_type: ModerationTargetType::Post,
+            target_id: id,
+            reason: None,
+            before: Some(json!({ field: was })),
+            after: Some(json!({ field: value })),
+        },
     )
-    .fetch_all(state.db.pool())
     .await?;
 
-    Ok(votes.into_iter().map(|v| (v.target_id, v.vote_value)).collect())
+    tx.commit().await?;
+
+    Ok(StatusCode::OK)
 }
 
 impl std::fmt::Display for ContentType {
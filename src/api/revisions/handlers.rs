@@ -0,0 +1,133 @@
+//! Revision handlers
+//!
+//! Edit history for posts and comments. History is visible to whoever can read
+//! the content; history of removed content is only shown to moderators of the
+//! space it was posted in.
+
+use axum::{extract::{Path, State}, Json};
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use crate::api::spaces::require_space_visible;
+use crate::domain::entities::*;
+use crate::domain::services::revisions::revision_diffs;
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::{require_space_permission, OptionalUser};
+use crate::AppState;
+
+/// Append the content an edit replaced to the target's history
+pub(crate) async fn record_revision(
+    conn: &mut PgConnection,
+    target_type: RevisionTargetType,
+    target_id: Uuid,
+    editor_id: Uuid,
+    previous_content: Option<&str>,
+) -> ApiResult<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO content_revisions (target_type, target_id, revision, editor_id, previous_content)
+        SELECT $1::VARCHAR, $2, COALESCE(MAX(revision), 0) + 1, $3, $4
+        FROM content_revisions
+        WHERE target_type = $1 AND target_id = $2
+        "#,
+        target_type as RevisionTargetType,
+        target_id,
+        editor_id,
+        previous_content
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Edit history of a post
+pub async fn post_revisions(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    user: OptionalUser,
+) -> ApiResult<Json<RevisionHistory>> {
+    let post = sqlx::query!(
+        r#"SELECT space_id, content, is_removed as "is_removed!", edited_at FROM posts WHERE id = $1"#,
+        id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+
+    if post.is_removed {
+        require_moderator(&state, &user, post.space_id).await?;
+    } else {
+        require_space_visible(&state, post.space_id, user.0.as_ref().map(|u| u.identity_id)).await?;
+    }
+
+    history(&state, RevisionTargetType::Post, id, post.content.as_deref(), post.edited_at).await
+}
+
+/// Edit history of a comment
+pub async fn comment_revisions(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    user: OptionalUser,
+) -> ApiResult<Json<RevisionHistory>> {
+    let comment = sqlx::query!(
+        r#"
+        SELECT p.space_id, c.content, c.is_removed as "is_removed!", c.edited_at
+        FROM comments c
+        JOIN posts p ON p.id = c.post_id
+        WHERE c.id = $1
+        "#,
+        id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Comment not found".to_string()))?;
+
+    if comment.is_removed {
+        require_moderator(&state, &user, comment.space_id).await?;
+    } else {
+        require_space_visible(&state, comment.space_id, user.0.as_ref().map(|u| u.identity_id)).await?;
+    }
+
+    history(&state, RevisionTargetType::Comment, id, Some(&comment.content), comment.edited_at).await
+}
+
+async fn require_moderator(state: &Arc<AppState>, user: &OptionalUser, space_id: Uuid) -> ApiResult<()> {
+    let identity_id = user.0.as_ref().ok_or(ApiError::Unauthorized)?.identity_id;
+
+    require_space_permission(state, identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    Ok(())
+}
+
+async fn history(
+    state: &AppState,
+    target_type: RevisionTargetType,
+    target_id: Uuid,
+    current_content: Option<&str>,
+    edited_at: Option<DateTime<Utc>>,
+) -> ApiResult<Json<RevisionHistory>> {
+    let revisions = sqlx::query_as!(
+        ContentRevision,
+        r#"
+        SELECT id, target_type as "target_type: RevisionTargetType", target_id, revision,
+               editor_id, previous_content, created_at
+        FROM content_revisions
+        WHERE target_type = $1 AND target_id = $2
+        ORDER BY revision
+        "#,
+        target_type as RevisionTargetType,
+        target_id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(Json(RevisionHistory {
+        target_type,
+        target_id,
+        edited_at,
+        revisions: revision_diffs(revisions, current_content),
+    }))
+}
@@ -0,0 +1,5 @@
+//! Revisions API module
+
+pub mod handlers;
+
+pub use handlers::*;
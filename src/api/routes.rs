@@ -24,7 +24,7 @@ use crate::middleware::{
 };
 use crate::AppState;
 
-use super::{auth, identity, spaces, posts, comments, votes, messages, media, notifications, moderation, feed, health};
+use super::{auth, identity, spaces, posts, polls, comments, revisions, votes, messages, media, notifications, moderation, feed, search, health};
 
 /// Create the main application router with all routes and middleware
 pub fn create_router(state: Arc<AppState>) -> Router {
@@ -40,6 +40,7 @@ pub fn create_router(state: Arc<AppState>) -> Router {
         .route("/health", get(health::health_check))
         .route("/health/ready", get(health::readiness_check))
         .route("/health/live", get(health::liveness_check))
+        .route("/health/leader", get(health::leader_check))
         // Authentication routes (rate limited, no auth required)
         .nest("/auth", auth_routes())
         // Identity routes — public reads, auth-protected writes
@@ -58,6 +59,8 @@ pub fn create_router(state: Arc<AppState>) -> Router {
         .nest("/notifications", notifications_routes().layer(require_auth.clone()))
         // Feed routes — personalized requires auth, public feeds don't
         .nest("/feed", feed_routes())
+        // Search — public, private spaces only for their members
+        .route("/search", get(search::handlers::search))
         // Moderation routes (all require auth + moderator check inside handlers)
         .nest("/moderation", moderation_routes().layer(require_auth.clone()))
         // Apply rate limiting to all API routes
@@ -186,6 +189,12 @@ fn identity_routes() -> Router<Arc<AppState>> {
         .route("/me", patch(identity::handlers::update_current))
         .route("/me/sessions", get(identity::handlers::list_sessions))
         .route("/me/sessions/:id", delete(identity::handlers::revoke_session))
+        .route("/me/blocks", get(identity::handlers::list_blocks))
+        .route("/me/blocks", post(identity::handlers::block))
+        .route("/me/blocks/:identity_id", delete(identity::handlers::unblock))
+        .route("/me/invites", get(spaces::invites::list_pending))
+        .route("/me/invites/:id/accept", post(spaces::invites::accept))
+        .route("/me/invites/:id/decline", post(spaces::invites::decline))
         // Public identity lookup
         .route("/:id", get(identity::handlers::get_by_id))
         .route("/:id/posts", get(identity::handlers::get_posts))
@@ -207,8 +216,37 @@ fn spaces_routes() -> Router<Arc<AppState>> {
         .route("/:slug/join", post(spaces::handlers::join))
         .route("/:slug/leave", post(spaces::handlers::leave))
         .route("/:slug/members", get(spaces::handlers::list_members))
+        .route("/:slug/permissions", get(spaces::handlers::get_access))
         .route("/:slug/members/:identity_id", patch(spaces::handlers::update_member))
         .route("/:slug/members/:identity_id", delete(spaces::handlers::remove_member))
+        // Invites
+        .route("/:slug/invites", get(spaces::invites::list))
+        .route("/:slug/invites", post(spaces::invites::create))
+        .route("/:slug/invites/:invite_id", delete(spaces::invites::revoke))
+        // Bans, mutes and approved submitters
+        .route("/:slug/bans", get(spaces::restrictions::list_bans))
+        .route("/:slug/bans", post(spaces::restrictions::ban))
+        .route("/:slug/bans/:identity_id", delete(spaces::restrictions::unban))
+        .route("/:slug/mutes", get(spaces::restrictions::list_mutes))
+        .route("/:slug/mutes", post(spaces::restrictions::mute))
+        .route("/:slug/mutes/:identity_id", delete(spaces::restrictions::unmute))
+        .route("/:slug/approved-submitters", get(spaces::restrictions::list_approved_submitters))
+        .route("/:slug/approved-submitters", post(spaces::restrictions::add_approved_submitter))
+        .route("/:slug/approved-submitters/:identity_id", delete(spaces::restrictions::remove_approved_submitter))
+        // AutoModerator rules
+        .route("/:slug/automod", get(spaces::automod::list_rules))
+        .route("/:slug/automod", post(spaces::automod::create_rule))
+        .route("/:slug/automod/test", post(spaces::automod::test_rules))
+        .route("/:slug/automod/:rule_id", patch(spaces::automod::update_rule))
+        .route("/:slug/automod/:rule_id", delete(spaces::automod::delete_rule))
+        // Moderation log
+        .route("/:slug/modlog", get(moderation::log::space_log))
+        .route("/:slug/modlog/public", get(moderation::log::public_space_log))
+        // Moderation queue
+        .route("/:slug/modqueue", get(moderation::queue::list))
+        .route("/:slug/modqueue/bulk", post(moderation::queue::bulk))
+        .route("/:slug/modqueue/:target_type/:target_id/approve", post(moderation::queue::approve))
+        .route("/:slug/modqueue/:target_type/:target_id/reject", post(moderation::queue::reject))
         // Posts in space
         .route("/:slug/posts", get(posts::handlers::list_by_space))
         .route("/:slug/posts", post(posts::handlers::create))
@@ -226,6 +264,12 @@ fn posts_routes() -> Router<Arc<AppState>> {
         // Comments
         .route("/:id/comments", get(comments::handlers::list_by_post))
         .route("/:id/comments", post(comments::handlers::create))
+        .route("/:id/comments/tree", get(comments::handlers::tree_by_post))
+        // Polls
+        .route("/:id/poll", get(polls::handlers::get_poll))
+        .route("/:id/poll/vote", post(polls::handlers::vote))
+        // Edit history
+        .route("/:id/revisions", get(revisions::handlers::post_revisions))
         // Moderation actions
         .route("/:id/pin", post(posts::handlers::pin))
         .route("/:id/unpin", post(posts::handlers::unpin))
@@ -244,6 +288,8 @@ fn comments_routes() -> Router<Arc<AppState>> {
         .route("/:id/vote", delete(votes::handlers::unvote_comment))
         // Replies
         .route("/:id/replies", get(comments::handlers::list_replies))
+        // Edit history
+        .route("/:id/revisions", get(revisions::handlers::comment_revisions))
 }
 
 /// Messages routes (E2E encrypted)
@@ -276,6 +322,13 @@ fn notifications_routes() -> Router<Arc<AppState>> {
         .route("/unread-count", get(notifications::handlers::unread_count))
         .route("/:id/read", post(notifications::handlers::mark_read))
         .route("/read-all", post(notifications::handlers::mark_all_read))
+        // Preferences
+        .route("/preferences", get(notifications::handlers::get_preferences))
+        .route("/preferences", patch(notifications::handlers::update_preferences))
+        .route("/preferences/quiet-hours", put(notifications::handlers::set_quiet_hours))
+        .route("/preferences/quiet-hours", delete(notifications::handlers::clear_quiet_hours))
+        .route("/preferences/mutes", post(notifications::handlers::add_mute))
+        .route("/preferences/mutes/:target_type/:target_id", delete(notifications::handlers::remove_mute))
         // WebSocket for real-time notifications
         .route("/live", get(notifications::handlers::websocket_handler))
 }
@@ -301,4 +354,9 @@ fn moderation_routes() -> Router<Arc<AppState>> {
         .route("/comments/:id/remove", post(moderation::handlers::remove_comment))
         .route("/identities/:id/suspend", post(moderation::handlers::suspend_identity))
         .route("/identities/:id/unsuspend", post(moderation::handlers::unsuspend_identity))
+        // Moderation log
+        .route("/log", get(moderation::log::platform_log))
+        // Platform admins
+        .route("/admins", post(moderation::handlers::add_platform_admin))
+        .route("/admins/:id", delete(moderation::handlers::remove_platform_admin))
 }
@@ -0,0 +1,388 @@
+//! Search handlers
+//!
+//! Full-text search over posts, comments and spaces. Removed content, and
+//! content by identities the viewer blocked, is never returned. Private spaces
+//! are only searched for their members.
+
+use axum::{extract::{Query, State}, Json};
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, Postgres, QueryBuilder};
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::api::cursor::{Cursor, PageRequest};
+use crate::api::feed::context::{load_identity_summaries, load_post_context, load_space_summaries};
+use crate::api::feed::query::POST_COLUMNS;
+use crate::domain::entities::*;
+use crate::domain::services::feed::time_range_start;
+use crate::domain::services::search::{headline_sql, render_snippet, search_rank_sql, SEARCH_CONFIG};
+use crate::errors::ApiResult;
+use crate::middleware::auth::OptionalUser;
+use crate::AppState;
+
+/// The parsed search query, joined into every search as `q`
+const QUERY: &str = "q.query";
+
+/// Keyset position: rank, then creation time
+type SearchKey = (f64, DateTime<Utc>);
+
+/// Search posts, comments or spaces
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SearchParams>,
+    user: OptionalUser,
+) -> ApiResult<Json<PaginatedResponse<SearchHit>>> {
+    params.validate()?;
+
+    let viewer = user.0.map(|u| u.identity_id);
+    let page = PageRequest::<SearchKey>::new(
+        &params.pagination,
+        &state.crypto,
+        format!("search:{:?}:{:?}:{}", params.kind, params.sort, params.q),
+    )?;
+
+    let response = match params.kind {
+        SearchKind::Posts => search_posts(&state, &params, viewer, page).await?,
+        SearchKind::Comments => search_comments(&state, &params, viewer, page).await?,
+        SearchKind::Spaces => search_spaces(&state, &params, viewer, page).await?,
+    };
+
+    Ok(Json(response))
+}
+
+#[derive(FromRow)]
+struct PostRow {
+    #[sqlx(flatten)]
+    post: Post,
+    sort_rank: f64,
+    snippet: String,
+}
+
+async fn search_posts(
+    state: &AppState,
+    params: &SearchParams,
+    viewer: Option<Uuid>,
+    page: PageRequest<SearchKey>,
+) -> ApiResult<PaginatedResponse<SearchHit>> {
+    let rank_sql = search_rank_sql(params.sort, "p.search_vector", QUERY, "p.created_at");
+
+    let mut query = QueryBuilder::<Postgres>::new("SELECT ");
+    query
+        .push(POST_COLUMNS)
+        .push(", ")
+        .push(&rank_sql)
+        .push(" AS sort_rank, ")
+        .push(headline_sql("COALESCE(p.content, p.title)", QUERY))
+        .push(" AS snippet");
+    push_post_filters(&mut query, params, viewer);
+    push_page(&mut query, &page, &rank_sql, "p");
+
+    let rows = query
+        .build_query_as::<PostRow>()
+        .fetch_all(state.db.pool())
+        .await?;
+
+    let total = async {
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*)");
+        push_post_filters(&mut count, params, viewer);
+        let total: i64 = count.build_query_scalar().fetch_one(state.db.pool()).await?;
+        Ok(total)
+    };
+
+    let page = page
+        .into_response(
+            rows,
+            &state.crypto,
+            |row| Cursor::new((row.sort_rank, row.post.created_at), row.post.id),
+            total,
+        )
+        .await?;
+
+    let (posts, snippets): (Vec<Post>, Vec<String>) = page
+        .data
+        .into_iter()
+        .map(|row| (row.post, render_snippet(&row.snippet)))
+        .unzip();
+    let posts = load_post_context(state, posts, viewer).await?;
+
+    Ok(PaginatedResponse {
+        data: posts
+            .into_iter()
+            .zip(snippets)
+            .map(|(post, snippet)| SearchHit::Post(PostSearchHit { post, snippet }))
+            .collect(),
+        pagination: page.pagination,
+    })
+}
+
+#[derive(FromRow)]
+struct CommentRow {
+    #[sqlx(flatten)]
+    comment: Comment,
+    post_title: String,
+    space_id: Uuid,
+    sort_rank: f64,
+    snippet: String,
+}
+
+async fn search_comments(
+    state: &AppState,
+    params: &SearchParams,
+    viewer: Option<Uuid>,
+    page: PageRequest<SearchKey>,
+) -> ApiResult<PaginatedResponse<SearchHit>> {
+    let rank_sql = search_rank_sql(params.sort, "c.search_vector", QUERY, "c.created_at");
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT c.id, c.post_id, c.parent_id, c.author_id, c.is_automod, c.content, c.depth, c.path, \
+         c.upvotes, c.downvotes, c.score, c.is_removed, c.removed_reason, \
+         c.created_at, c.updated_at, c.edited_at, c.held_at, c.content_html, p.title AS post_title, p.space_id, ",
+    );
+    query
+        .push(&rank_sql)
+        .push(" AS sort_rank, ")
+        .push(headline_sql("c.content", QUERY))
+        .push(" AS snippet");
+    push_comment_filters(&mut query, params, viewer);
+    push_page(&mut query, &page, &rank_sql, "c");
+
+    let rows = query
+        .build_query_as::<CommentRow>()
+        .fetch_all(state.db.pool())
+        .await?;
+
+    let total = async {
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*)");
+        push_comment_filters(&mut count, params, viewer);
+        let total: i64 = count.build_query_scalar().fetch_one(state.db.pool()).await?;
+        Ok(total)
+    };
+
+    let page = page
+        .into_response(
+            rows,
+            &state.crypto,
+            |row| Cursor::new((row.sort_rank, row.comment.created_at), row.comment.id),
+            total,
+        )
+        .await?;
+
+    let author_ids: Vec<Uuid> = page.data.iter().filter_map(|row| row.comment.author_id).collect();
+    let space_ids: Vec<Uuid> = page.data.iter().map(|row| row.space_id).collect();
+    let authors = load_identity_summaries(state, &author_ids).await?;
+    let spaces = load_space_summaries(state, &space_ids).await?;
+
+    Ok(PaginatedResponse {
+        data: page
+            .data
+            .into_iter()
+            .map(|row| {
+                SearchHit::Comment(CommentSearchHit {
+                    author: row.comment.author_id.and_then(|id| authors.get(&id).cloned()),
+                    space: spaces.get(&row.space_id).cloned(),
+                    snippet: render_snippet(&row.snippet),
+                    post_title: row.post_title,
+                    comment: row.comment,
+                })
+            })
+            .collect(),
+        pagination: page.pagination,
+    })
+}
+
+#[derive(FromRow)]
+struct SpaceRow {
+    id: Uuid,
+    name: String,
+    slug: String,
+    icon_url: Option<String>,
+    subscriber_count: i32,
+    created_at: DateTime<Utc>,
+    sort_rank: f64,
+    snippet: String,
+}
+
+async fn search_spaces(
+    state: &AppState,
+    params: &SearchParams,
+    viewer: Option<Uuid>,
+    page: PageRequest<SearchKey>,
+) -> ApiResult<PaginatedResponse<SearchHit>> {
+    let rank_sql = search_rank_sql(params.sort, "s.search_vector", QUERY, "s.created_at");
+
+    let mut query = QueryBuilder::<Postgres>::new(
+        "SELECT s.id, s.name, s.slug, s.icon_url, COALESCE(s.subscriber_count, 0) AS subscriber_count, \
+         s.created_at, ",
+    );
+    query
+        .push(&rank_sql)
+        .push(" AS sort_rank, ")
+        .push(headline_sql("COALESCE(s.description, s.name)", QUERY))
+        .push(" AS snippet");
+    push_space_filters(&mut query, params, viewer);
+    push_page(&mut query, &page, &rank_sql, "s");
+
+    let rows = query
+        .build_query_as::<SpaceRow>()
+        .fetch_all(state.db.pool())
+        .await?;
+
+    let total = async {
+        let mut count = QueryBuilder::<Postgres>::new("SELECT COUNT(*)");
+        push_space_filters(&mut count, params, viewer);
+        let total: i64 = count.build_query_scalar().fetch_one(state.db.pool()).await?;
+        Ok(total)
+    };
+
+    let page = page
+        .into_response(
+            rows,
+            &state.crypto,
+            |row| Cursor::new((row.sort_rank, row.created_at), row.id),
+            total,
+        )
+        .await?;
+
+    Ok(PaginatedResponse {
+        data: page
+            .data
+            .into_iter()
+            .map(|row| {
+                SearchHit::Space(SpaceSearchHit {
+                    snippet: render_snippet(&row.snippet),
+                    space: SpaceSummary {
+                        id: row.id,
+                        name: row.name,
+                        slug: row.slug,
+                        icon_url: row.icon_url,
+                        subscriber_count: row.subscriber_count,
+                    },
+                })
+            })
+            .collect(),
+        pagination: page.pagination,
+    })
+}
+
+/// FROM and WHERE for post searches
+fn push_post_filters(query: &mut QueryBuilder<'_, Postgres>, params: &SearchParams, viewer: Option<Uuid>) {
+    query.push(" FROM posts p JOIN spaces s ON s.id = p.space_id");
+    push_tsquery(query, params);
+    query
+        .push(" WHERE p.search_vector @@ q.query AND p.is_removed = false AND p.created_at >= ")
+        .push_bind(time_range_start(params.time_range));
+    push_visibility(query, viewer);
+    push_not_blocked(query, viewer, "p.author_id");
+
+    if let Some(space) = &params.space {
+        query.push(" AND s.slug = ").push_bind(space.clone());
+    }
+    if let Some(author) = params.author {
+        query.push(" AND p.author_id = ").push_bind(author);
+    }
+    if let Some(content_type) = params.content_type {
+        query.push(" AND p.content_type = ").push_bind(content_type);
+    }
+}
+
+/// FROM and WHERE for comment searches
+fn push_comment_filters(query: &mut QueryBuilder<'_, Postgres>, params: &SearchParams, viewer: Option<Uuid>) {
+    query.push(
+        " FROM comments c JOIN posts p ON p.id = c.post_id JOIN spaces s ON s.id = p.space_id",
+    );
+    push_tsquery(query, params);
+    query
+        .push(
+            " WHERE c.search_vector @@ q.query AND c.is_removed = false AND p.is_removed = false \
+              AND c.created_at >= ",
+        )
+        .push_bind(time_range_start(params.time_range));
+    push_visibility(query, viewer);
+    push_not_blocked(query, viewer, "c.author_id");
+
+    if let Some(space) = &params.space {
+        query.push(" AND s.slug = ").push_bind(space.clone());
+    }
+    if let Some(author) = params.author {
+        query.push(" AND c.author_id = ").push_bind(author);
+    }
+}
+
+/// FROM and WHERE for space searches
+fn push_space_filters(query: &mut QueryBuilder<'_, Postgres>, params: &SearchParams, viewer: Option<Uuid>) {
+    query.push(" FROM spaces s");
+    push_tsquery(query, params);
+    query
+        .push(" WHERE s.search_vector @@ q.query AND s.created_at >= ")
+        .push_bind(time_range_start(params.time_range));
+    push_visibility(query, viewer);
+}
+
+fn push_tsquery(query: &mut QueryBuilder<'_, Postgres>, params: &SearchParams) {
+    query
+        .push(" CROSS JOIN websearch_to_tsquery('")
+        .push(SEARCH_CONFIG)
+        .push("', ")
+        .push_bind(params.q.clone())
+        .push(") AS q(query)");
+}
+
+/// Restrict to public spaces and, for a signed-in caller, spaces they belong to
+fn push_visibility(query: &mut QueryBuilder<'_, Postgres>, viewer: Option<Uuid>) {
+    match viewer {
+        Some(identity_id) => {
+            query
+                .push(
+                    " AND (s.is_private = false OR EXISTS (SELECT 1 FROM space_members sm \
+                      WHERE sm.space_id = s.id AND sm.identity_id = ",
+                )
+                .push_bind(identity_id)
+                .push("))");
+        }
+        None => {
+            query.push(" AND s.is_private = false");
+        }
+    }
+}
+
+/// Leave out results by identities the viewer blocked
+fn push_not_blocked(query: &mut QueryBuilder<'_, Postgres>, viewer: Option<Uuid>, author: &str) {
+    if let Some(viewer) = viewer {
+        query
+            .push(" AND NOT EXISTS (SELECT 1 FROM identity_blocks b WHERE b.blocked_id = ")
+            .push(author)
+            .push(" AND b.blocker_id = ")
+            .push_bind(viewer)
+            .push(")");
+    }
+}
+
+/// Keyset condition, ordering and limits over the table aliased as `alias`
+fn push_page(
+    query: &mut QueryBuilder<'_, Postgres>,
+    page: &PageRequest<SearchKey>,
+    rank_sql: &str,
+    alias: &str,
+) {
+    if let Some(after) = &page.after {
+        let (rank, created_at) = after.key;
+        query
+            .push(format!(" AND ({}, {a}.created_at, {a}.id) < (", rank_sql, a = alias))
+            .push_bind(rank)
+            .push(", ")
+            .push_bind(created_at)
+            .push(", ")
+            .push_bind(after.id)
+            .push(")");
+    }
+
+    query
+        .push(format!(
+            " ORDER BY sort_rank DESC, {a}.created_at DESC, {a}.id DESC LIMIT ",
+            a = alias
+        ))
+        .push_bind(page.fetch_limit())
+        .push(" OFFSET ")
+        .push_bind(page.offset);
+}
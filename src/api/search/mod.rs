@@ -0,0 +1,5 @@
+//! Search API module
+
+pub mod handlers;
+
+pub use handlers::*;
@@ -0,0 +1,956 @@
+//! Space AutoModerator
+//!
+//! Moderators who manage a space's posts define rules that run against posts
+//! and comments as they are created and edited, and again when they are
+//! reported. Rules are checked with [`moderate`] inside the transaction that
+//! writes the content, so content a rule hides is never visible. Rule
+//! replies are posted there too, as AutoModerator comments on the content;
+//! they are announced, and moderators notified, with [`notify`] once it
+//! commits. New and edited content that no rule hides also goes through the
+//! spam check, and is held for the moderation queue if it fails. Changes to
+//! rules are recorded in the moderation log.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use sqlx::{types::Json as JsonColumn, PgConnection};
+use std::sync::Arc;
+use tracing::warn;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::api::comments::handlers::MAX_COMMENT_DEPTH;
+use crate::api::moderation::log::{self, LogEntry};
+use crate::api::notifications::replies::notify_reply;
+use crate::api::spaces::space_id;
+use crate::domain::entities::*;
+use crate::domain::services::automod::{
+    evaluate_rule, validate_automod_rule, watches_reports, AutomodSubject, CompiledRule, MAX_AUTOMOD_RULES,
+};
+use crate::domain::services::markdown::render_markdown;
+use crate::domain::services::moderation::{ModerationService, SpamCheckResult};
+use crate::domain::services::permissions::SpaceAccess;
+use crate::errors::{ApiError, ApiResult};
+use crate::jobs::handlers::NotificationContext;
+use crate::jobs::workers::send_notification_job;
+use crate::middleware::auth::{require_space_permission, AuthenticatedUser};
+use crate::websocket::{publish_channel_event, Channel};
+use crate::AppState;
+
+/// Reason recorded on content removed by a rule
+const REMOVED_REASON: &str = "Removed by AutoModerator";
+
+/// Reason recorded on content filtered by a rule
+const FILTERED_REASON: &str = "Held for review by AutoModerator";
+
+/// Reason recorded on content held by the spam check
+const SPAM_REASON: &str = "Held for review as possible spam";
+
+/// Content checked by AutoModerator
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum AutomodContent {
+    Post { post_id: Uuid },
+    Comment { post_id: Uuid, comment_id: Uuid },
+}
+
+impl AutomodContent {
+    fn post_id(self) -> Uuid {
+        match self {
+            AutomodContent::Post { post_id } | AutomodContent::Comment { post_id, .. } => post_id,
+        }
+    }
+}
+
+/// Why rules are being checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AutomodTrigger {
+    /// The content was created or edited
+    Write,
+    /// The content was reported; only rules with report thresholds run
+    Report,
+}
+
+/// What AutoModerator did to some content
+#[derive(Debug, Default)]
+pub(crate) struct AutomodOutcome {
+    pub space_id: Uuid,
+    pub author_id: Option<Uuid>,
+    pub verdict: AutomodVerdict,
+    /// Set when the content was hidden by this check
+    pub removed_reason: Option<&'static str>,
+    /// Set when the content was held for review by this check
+    pub held_at: Option<DateTime<Utc>>,
+    /// Whether the post was locked by this check
+    pub locked: bool,
+    /// AutoModerator comments posted in reply
+    pub replies: Vec<Comment>,
+}
+
+impl AutomodOutcome {
+    /// Reflect the check in a post loaded before it ran
+    pub fn update_post(&self, post: &mut Post) {
+        if let Some(reason) = self.removed_reason {
+            post.is_removed = true;
+            post.removed_reason = Some(reason.to_string());
+        }
+        post.held_at = post.held_at.or(self.held_at);
+        post.is_locked |= self.locked;
+    }
+
+    /// Reflect the check in a comment loaded before it ran
+    pub fn update_comment(&self, comment: &mut Comment) {
+        if let Some(reason) = self.removed_reason {
+            comment.is_removed = true;
+            comment.removed_reason = Some(reason.to_string());
+        }
+        comment.held_at = comment.held_at.or(self.held_at);
+    }
+}
+
+/// Check content against its space's rules and the spam check, and apply
+/// the result.
+///
+/// The content must already be written on `conn`; its row is locked for the
+/// rest of the transaction.
+pub(crate) async fn moderate(
+    conn: &mut PgConnection,
+    content: AutomodContent,
+    trigger: AutomodTrigger,
+) -> ApiResult<AutomodOutcome> {
+    let target = load_target(conn, content).await?;
+
+    let mut outcome = AutomodOutcome {
+        space_id: target.space_id,
+        author_id: target.author_id,
+        ..Default::default()
+    };
+
+    // Content already taken down is left to moderators
+    if target.is_removed {
+        return Ok(outcome);
+    }
+
+    let rules: Vec<_> = load_rules(conn, target.space_id)
+        .await?
+        .into_iter()
+        .filter(|rule| trigger == AutomodTrigger::Write || watches_reports(&rule.compiled.definition))
+        .collect();
+
+    if !rules.is_empty() {
+        evaluate(conn, content, &target, &rules, &mut outcome.verdict).await?;
+    }
+
+    // New and edited content no rule hides is still held if it looks like spam
+    let spam = match trigger {
+        AutomodTrigger::Write if !outcome.verdict.remove && !outcome.verdict.filter => {
+            let text = [target.title.as_deref(), target.body.as_deref()]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join("\n");
+            Some(ModerationService::check_spam(&text)).filter(|result| result.is_spam)
+        }
+        _ => None,
+    };
+
+    apply(conn, content, &target, spam.as_ref(), &mut outcome).await?;
+    post_replies(conn, content, &mut outcome).await?;
+
+    Ok(outcome)
+}
+
+/// Announce the replies and send the moderator notifications of a committed
+/// check
+pub(crate) async fn notify(state: &Arc<AppState>, content: AutomodContent, outcome: &AutomodOutcome) {
+    if let Err(e) = deliver(state, content, outcome).await {
+        warn!(post_id = %content.post_id(), error = %e, "Failed to send AutoModerator notifications");
+    }
+}
+
+/// Adjust what visible content counts towards: for a post, its space's post
+/// count and its author's karma; for a comment, its post's comment count.
+/// Content counts from when it is created, stops counting while it is held
+/// and counts again when it is released.
+pub(crate) async fn tally(conn: &mut PgConnection, content: AutomodContent, delta: i32) -> ApiResult<()> {
+    match content {
+        AutomodContent::Post { post_id } => {
+            sqlx::query!(
+                "UPDATE spaces SET post_count = post_count + $2 WHERE id = (SELECT space_id FROM posts WHERE id = $1)",
+                post_id,
+                delta
+            )
+            .execute(&mut *conn)
+            .await?;
+
+            sqlx::query!(
+                "UPDATE identities SET karma = karma + $2 WHERE id = (SELECT author_id FROM posts WHERE id = $1)",
+                post_id,
+                delta
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+        AutomodContent::Comment { post_id, .. } => {
+            sqlx::query!(
+                "UPDATE posts SET comment_count = comment_count + $2 WHERE id = $1",
+                post_id,
+                delta
+            )
+            .execute(&mut *conn)
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check reported content against the rules with report thresholds
+pub(crate) async fn recheck_reported(state: &Arc<AppState>, target_type: ReportTargetType, target_id: Uuid) {
+    let content = match target_type {
+        ReportTargetType::Post => Some(AutomodContent::Post { post_id: target_id }),
+        ReportTargetType::Comment => sqlx::query_scalar!("SELECT post_id FROM comments WHERE id = $1", target_id)
+            .fetch_optional(state.db.pool())
+            .await
+            .ok()
+            .flatten()
+            .map(|post_id| AutomodContent::Comment {
+                post_id,
+                comment_id: target_id,
+            }),
+        _ => None,
+    };
+
+    let Some(content) = content else {
+        return;
+    };
+
+    let result = async {
+        let mut tx = state.db.pool().begin().await?;
+        let outcome = moderate(&mut tx, content, AutomodTrigger::Report).await?;
+        tx.commit().await?;
+        Ok::<_, ApiError>(outcome)
+    }
+    .await;
+
+    match result {
+        Ok(outcome) => notify(state, content, &outcome).await,
+        Err(e) => warn!(target_id = %target_id, error = %e, "Failed to check reported content"),
+    }
+}
+
+/// List a space's AutoModerator rules
+pub async fn list_rules(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+) -> ApiResult<Json<Vec<AutomodRule>>> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let rules = sqlx::query_as!(
+        AutomodRule,
+        r#"
+        SELECT id, space_id, name, definition as "definition: JsonColumn<AutomodDefinition>",
+               is_enabled, created_by, created_at, updated_at
+        FROM automod_rules
+        WHERE space_id = $1
+        ORDER BY created_at, id
+        "#,
+        space_id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(Json(rules))
+}
+
+/// Add an AutoModerator rule
+pub async fn create_rule(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<CreateAutomodRuleRequest>,
+) -> ApiResult<(StatusCode, Json<AutomodRule>)> {
+    request.validate()?;
+    validate_automod_rule(&request.definition)?;
+
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    // Lock the space so concurrent creates count one at a time
+    sqlx::query!("SELECT id FROM spaces WHERE id = $1 FOR UPDATE", space_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+    let count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM automod_rules WHERE space_id = $1"#,
+        space_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if count >= MAX_AUTOMOD_RULES {
+        return Err(ApiError::OperationNotAllowed(format!(
+            "Spaces can have at most {} AutoModerator rules",
+            MAX_AUTOMOD_RULES
+        )));
+    }
+
+    let rule = sqlx::query_as!(
+        AutomodRule,
+        r#"
+        INSERT INTO automod_rules (space_id, name, definition, is_enabled, created_by)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (space_id, name) DO NOTHING
+        RETURNING id, space_id, name, definition as "definition: JsonColumn<AutomodDefinition>",
+                  is_enabled, created_by, created_at, updated_at
+        "#,
+        space_id,
+        request.name.trim(),
+        JsonColumn(&request.definition) as _,
+        request.is_enabled.unwrap_or(true),
+        user.identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::Conflict("A rule with this name already exists".to_string()))?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::CreateAutomodRule,
+            target_type: ModerationTargetType::AutomodRule,
+            target_id: rule.id,
+            reason: None,
+            before: None,
+            after: Some(rule_state(&rule)),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(rule)))
+}
+
+/// Update an AutoModerator rule
+pub async fn update_rule(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, rule_id)): Path<(String, Uuid)>,
+    Json(request): Json<UpdateAutomodRuleRequest>,
+) -> ApiResult<Json<AutomodRule>> {
+    request.validate()?;
+    if let Some(definition) = &request.definition {
+        validate_automod_rule(definition)?;
+    }
+
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let before = sqlx::query_as!(
+        AutomodRule,
+        r#"
+        SELECT id, space_id, name, definition as "definition: JsonColumn<AutomodDefinition>",
+               is_enabled, created_by, created_at, updated_at
+        FROM automod_rules
+        WHERE id = $1 AND space_id = $2
+        FOR UPDATE
+        "#,
+        rule_id,
+        space_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Rule not found".to_string()))?;
+
+    let taken = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM automod_rules WHERE space_id = $1 AND name = $2 AND id <> $3
+        ) as "taken!"
+        "#,
+        space_id,
+        request.name.as_deref().map(str::trim),
+        rule_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if taken {
+        return Err(ApiError::Conflict("A rule with this name already exists".to_string()));
+    }
+
+    let rule = sqlx::query_as!(
+        AutomodRule,
+        r#"
+        UPDATE automod_rules
+        SET name = COALESCE($3, name),
+            definition = COALESCE($4, definition),
+            is_enabled = COALESCE($5, is_enabled),
+            updated_at = NOW()
+        WHERE id = $1 AND space_id = $2
+        RETURNING id, space_id, name, definition as "definition: JsonColumn<AutomodDefinition>",
+                  is_enabled, created_by, created_at, updated_at
+        "#,
+        rule_id,
+        space_id,
+        request.name.as_deref().map(str::trim),
+        request.definition.as_ref().map(JsonColumn) as _,
+        request.is_enabled
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::UpdateAutomodRule,
+            target_type: ModerationTargetType::AutomodRule,
+            target_id: rule.id,
+            reason: None,
+            before: Some(rule_state(&before)),
+            after: Some(rule_state(&rule)),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(Json(rule))
+}
+
+/// Delete an AutoModerator rule
+pub async fn delete_rule(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, rule_id)): Path<(String, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let rule = sqlx::query_as!(
+        AutomodRule,
+        r#"
+        DELETE FROM automod_rules
+        WHERE id = $1 AND space_id = $2
+        RETURNING id, space_id, name, definition as "definition: JsonColumn<AutomodDefinition>",
+                  is_enabled, created_by, created_at, updated_at
+        "#,
+        rule_id,
+        space_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Rule not found".to_string()))?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::DeleteAutomodRule,
+            target_type: ModerationTargetType::AutomodRule,
+            target_id: rule.id,
+            reason: None,
+            before: Some(rule_state(&rule)),
+            after: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A rule as recorded in the moderation log
+fn rule_state(rule: &AutomodRule) -> serde_json::Value {
+    json!({ "name": rule.name, "definition": rule.definition, "is_enabled": rule.is_enabled })
+}
+
+/// Dry-run a draft rule, or the space's enabled rules, against sample content
+pub async fn test_rules(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<AutomodTestRequest>,
+) -> ApiResult<Json<AutomodVerdict>> {
+    request.validate()?;
+
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManagePosts).await?;
+
+    let sample = &request.sample;
+    let subject = AutomodSubject {
+        kind: sample.kind,
+        title: sample.title.as_deref(),
+        body: sample.body.as_deref(),
+        url: sample.url.as_deref(),
+        author_karma: sample.author_karma,
+        account_age_hours: sample.account_age_hours,
+        report_count: sample.report_count,
+    };
+
+    let mut verdict = AutomodVerdict::default();
+
+    match &request.definition {
+        Some(definition) => {
+            validate_automod_rule(definition)?;
+            evaluate_rule(&mut verdict, "draft", &CompiledRule::new(definition.clone()), &subject);
+        }
+        None => {
+            let mut conn = state.db.pool().acquire().await?;
+            for rule in load_rules(&mut conn, space_id).await? {
+                evaluate_rule(&mut verdict, &rule.name, &rule.compiled, &subject);
+            }
+        }
+    }
+
+    Ok(Json(verdict))
+}
+
+/// Content as loaded for a check
+struct Target {
+    kind: AutomodContentKind,
+    space_id: Uuid,
+    author_id: Option<Uuid>,
+    title: Option<String>,
+    body: Option<String>,
+    url: Option<String>,
+    is_removed: bool,
+    removed_reason: Option<String>,
+    is_locked: bool,
+}
+
+/// An enabled rule, compiled once per check
+struct LoadedRule {
+    name: String,
+    compiled: CompiledRule,
+}
+
+/// Run the rules against the content
+async fn evaluate(
+    conn: &mut PgConnection,
+    content: AutomodContent,
+    target: &Target,
+    rules: &[LoadedRule],
+    verdict: &mut AutomodVerdict,
+) -> ApiResult<()> {
+    let author = sqlx::query!(
+        r#"
+        SELECT COALESCE(karma, 0) as "karma!",
+               EXTRACT(EPOCH FROM NOW() - created_at)::BIGINT / 3600 as "age_hours!"
+        FROM identities WHERE id = $1
+        "#,
+        target.author_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let report_count = if rules.iter().any(|rule| watches_reports(&rule.compiled.definition)) {
+        let (target_type, target_id) = match content {
+            AutomodContent::Post { post_id } => ("post", post_id),
+            AutomodContent::Comment { comment_id, .. } => ("comment", comment_id),
+        };
+        sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM reports WHERE target_type = $1 AND target_id = $2"#,
+            target_type,
+            target_id
+        )
+        .fetch_one(&mut *conn)
+        .await?
+    } else {
+        0
+    };
+
+    let subject = AutomodSubject {
+        kind: target.kind,
+        title: target.title.as_deref(),
+        body: target.body.as_deref(),
+        url: target.url.as_deref(),
+        // Deleted authors are treated as established accounts with no karma
+        author_karma: author.as_ref().map_or(0, |author| author.karma),
+        account_age_hours: author.as_ref().map_or(i64::MAX, |author| author.age_hours),
+        report_count,
+    };
+
+    for rule in rules {
+        evaluate_rule(verdict, &rule.name, &rule.compiled, &subject);
+    }
+
+    Ok(())
+}
+
+async fn load_target(conn: &mut PgConnection, content: AutomodContent) -> ApiResult<Target> {
+    let target = match content {
+        AutomodContent::Post { post_id } => sqlx::query!(
+            r#"
+            SELECT space_id, author_id, title, content, url,
+                   is_removed as "is_removed!", removed_reason, is_locked as "is_locked!"
+            FROM posts WHERE id = $1
+            FOR UPDATE
+            "#,
+            post_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|post| Target {
+            kind: AutomodContentKind::Post,
+            space_id: post.space_id,
+            author_id: post.author_id,
+            title: Some(post.title),
+            body: post.content,
+            url: post.url,
+            is_removed: post.is_removed,
+            removed_reason: post.removed_reason,
+            is_locked: post.is_locked,
+        }),
+        AutomodContent::Comment { comment_id, .. } => sqlx::query!(
+            r#"
+            SELECT p.space_id, c.author_id, c.content,
+                   c.is_removed as "is_removed!", c.removed_reason
+            FROM comments c
+            JOIN posts p ON p.id = c.post_id
+            WHERE c.id = $1
+            FOR UPDATE OF c
+            "#,
+            comment_id
+        )
+        .fetch_optional(&mut *conn)
+        .await?
+        .map(|comment| Target {
+            kind: AutomodContentKind::Comment,
+            space_id: comment.space_id,
+            author_id: comment.author_id,
+            title: None,
+            body: Some(comment.content),
+            url: None,
+            is_removed: comment.is_removed,
+            removed_reason: comment.removed_reason,
+            is_locked: false,
+        }),
+    };
+
+    target.ok_or_else(|| ApiError::NotFound("Content not found".to_string()))
+}
+
+async fn load_rules(conn: &mut PgConnection, space_id: Uuid) -> ApiResult<Vec<LoadedRule>> {
+    let rules = sqlx::query!(
+        r#"
+        SELECT name, definition as "definition: JsonColumn<AutomodDefinition>"
+        FROM automod_rules
+        WHERE space_id = $1 AND is_enabled
+        ORDER BY created_at, id
+        "#,
+        space_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    Ok(rules
+        .into_iter()
+        .map(|rule| LoadedRule {
+            name: rule.name,
+            compiled: CompiledRule::new(rule.definition.0),
+        })
+        .collect())
+}
+
+/// Hide or lock the content as the verdict and spam check say, and log what
+/// changed
+async fn apply(
+    conn: &mut PgConnection,
+    content: AutomodContent,
+    target: &Target,
+    spam: Option<&SpamCheckResult>,
+    outcome: &mut AutomodOutcome,
+) -> ApiResult<()> {
+    let verdict = &outcome.verdict;
+    let removed_reason = match (verdict.remove, verdict.filter, spam) {
+        (true, _, _) => Some(REMOVED_REASON),
+        (false, true, _) => Some(FILTERED_REASON),
+        (false, false, Some(_)) => Some(SPAM_REASON),
+        (false, false, None) => None,
+    };
+    let hold = !verdict.remove && (verdict.filter || spam.is_some());
+    let lock = verdict.lock && !target.is_locked;
+
+    if removed_reason.is_none() && !lock {
+        return Ok(());
+    }
+
+    let (held_at, target_type, target_id, remove_action, filter_action) = match content {
+        AutomodContent::Post { post_id } => {
+            let held_at = sqlx::query_scalar!(
+                r#"
+                UPDATE posts
+                SET is_removed = is_removed OR $2,
+                    removed_reason = COALESCE($3, removed_reason),
+                    held_at = CASE WHEN $4 THEN NOW() ELSE held_at END,
+                    is_locked = is_locked OR $5
+                WHERE id = $1
+                RETURNING held_at
+                "#,
+                post_id,
+                removed_reason.is_some(),
+                removed_reason,
+                hold,
+                lock
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+
+            (
+                held_at,
+                ModerationTargetType::Post,
+                post_id,
+                ModerationLogAction::RemovePost,
+                ModerationLogAction::FilterPost,
+            )
+        }
+        AutomodContent::Comment { comment_id, .. } => {
+            let held_at = sqlx::query_scalar!(
+                r#"
+                UPDATE comments
+                SET is_removed = is_removed OR $2,
+                    removed_reason = COALESCE($3, removed_reason),
+                    held_at = CASE WHEN $4 THEN NOW() ELSE held_at END
+                WHERE id = $1
+                RETURNING held_at
+                "#,
+                comment_id,
+                removed_reason.is_some(),
+                removed_reason,
+                hold
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+
+            (
+                held_at,
+                ModerationTargetType::Comment,
+                comment_id,
+                ModerationLogAction::RemoveComment,
+                ModerationLogAction::FilterComment,
+            )
+        }
+    };
+
+    let rules_reason = format!("AutoModerator: {}", verdict.matched_rules.join(", "));
+
+    if let Some(removed_reason) = removed_reason {
+        let reason = match spam {
+            Some(spam) => format!("Spam check: {}", spam.reasons.join(", ")),
+            None => rules_reason.clone(),
+        };
+
+        log::record(
+            conn,
+            LogEntry {
+                space_id: Some(target.space_id),
+                actor_id: None,
+                action: if hold { filter_action } else { remove_action },
+                target_type,
+                target_id,
+                reason: Some(&reason),
+                before: Some(json!({ "is_removed": target.is_removed, "removed_reason": target.removed_reason })),
+                after: Some(json!({ "is_removed": true, "removed_reason": removed_reason })),
+            },
+        )
+        .await?;
+    }
+
+    if lock {
+        log::record(
+            conn,
+            LogEntry {
+                space_id: Some(target.space_id),
+                actor_id: None,
+                action: ModerationLogAction::LockPost,
+                target_type,
+                target_id,
+                reason: Some(&rules_reason),
+                before: Some(json!({ "is_locked": false })),
+                after: Some(json!({ "is_locked": true })),
+            },
+        )
+        .await?;
+    }
+
+    // Held content stops counting until a moderator releases it
+    if hold {
+        tally(conn, content, -1).await?;
+    }
+
+    outcome.removed_reason = removed_reason;
+    outcome.held_at = held_at.filter(|_| hold);
+    outcome.locked = lock;
+
+    Ok(())
+}
+
+/// Answer the content with the verdict's replies. A rule that matches again,
+/// on an edit or a report, does not repeat a reply it already posted.
+async fn post_replies(conn: &mut PgConnection, content: AutomodContent, outcome: &mut AutomodOutcome) -> ApiResult<()> {
+    if outcome.verdict.replies.is_empty() {
+        return Ok(());
+    }
+
+    let post_id = content.post_id();
+
+    // Replies go below the content, or beside a comment already at the depth
+    // limit. As when comments are created, a reply's path is its id appended
+    // to `prefix`.
+    let (parent_id, depth, prefix) = match content {
+        AutomodContent::Post { .. } => (None, 0, None),
+        AutomodContent::Comment { comment_id, .. } => {
+            let comment = sqlx::query!(
+                r#"SELECT parent_id, depth as "depth!", path FROM comments WHERE id = $1"#,
+                comment_id
+            )
+            .fetch_one(&mut *conn)
+            .await?;
+
+            if comment.depth < MAX_COMMENT_DEPTH {
+                (Some(comment_id), comment.depth + 1, Some(format!("{}.{}", comment.path, comment_id)))
+            } else {
+                let siblings = comment.path.rsplit_once('.').map(|(prefix, _)| prefix.to_string());
+                (comment.parent_id, comment.depth, siblings)
+            }
+        }
+    };
+
+    for message in &outcome.verdict.replies {
+        let id = Uuid::new_v4();
+        let path = match &prefix {
+            Some(prefix) => format!("{}.{}", prefix, id),
+            None => id.to_string(),
+        };
+
+        let reply = sqlx::query_as!(
+            Comment,
+            r#"
+            INSERT INTO comments (id, post_id, parent_id, is_automod, content, content_html, depth, path)
+            SELECT $1, $2, $3, TRUE, $4, $5, $6, $7
+            WHERE NOT EXISTS (
+                SELECT 1 FROM comments
+                WHERE post_id = $2 AND parent_id IS NOT DISTINCT FROM $3 AND is_automod AND content = $4
+            )
+            RETURNING id, post_id, parent_id, author_id, is_automod, content, depth as "depth!", path,
+                      upvotes as "upvotes!", downvotes as "downvotes!", score as "score!",
+                      is_removed as "is_removed!", removed_reason,
+                      created_at, updated_at, edited_at, held_at, content_html
+            "#,
+            id,
+            post_id,
+            parent_id,
+            message,
+            render_markdown(message, None),
+            depth,
+            path
+        )
+        .fetch_optional(&mut *conn)
+        .await?;
+
+        if let Some(reply) = reply {
+            tally(conn, AutomodContent::Comment { post_id, comment_id: reply.id }, 1).await?;
+            outcome.replies.push(reply);
+        }
+    }
+
+    Ok(())
+}
+
+async fn deliver(state: &Arc<AppState>, content: AutomodContent, outcome: &AutomodOutcome) -> ApiResult<()> {
+    let verdict = &outcome.verdict;
+    let comment_id = match content {
+        AutomodContent::Comment { comment_id, .. } => Some(comment_id),
+        AutomodContent::Post { .. } => None,
+    };
+    let context = NotificationContext {
+        post_id: Some(content.post_id()),
+        actor_id: None,
+    };
+
+    for reply in &outcome.replies {
+        notify_reply(state, reply, outcome.author_id).await;
+
+        // Replies to hidden content stay out of sight with it
+        if outcome.removed_reason.is_none() {
+            publish_channel_event(
+                state,
+                &Channel::Post(reply.post_id),
+                "comment_created",
+                serde_json::to_value(reply)?,
+            )
+            .await;
+        }
+    }
+
+    if verdict.notify_moderators {
+        let payload = json!({
+            "source": "automod",
+            "post_id": content.post_id(),
+            "comment_id": comment_id,
+            "space_id": outcome.space_id,
+            "rules": verdict.matched_rules,
+            "removed": outcome.removed_reason.is_some(),
+            "locked": outcome.locked,
+        });
+
+        for moderator_id in post_moderators(state, outcome.space_id).await? {
+            send_notification_job(
+                state,
+                moderator_id,
+                NotificationType::ModeratorAction,
+                context,
+                payload.clone(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Members who hold the manage-posts permission in the space
+async fn post_moderators(state: &Arc<AppState>, space_id: Uuid) -> ApiResult<Vec<Uuid>> {
+    let candidates = sqlx::query!(
+        r#"
+        SELECT sm.identity_id, COALESCE(sm.role, 'member') as "role!: MemberRole",
+               sm.permissions as "permissions: Vec<SpacePermission>",
+               COALESCE(s.creator_id = sm.identity_id, FALSE) as "is_creator!"
+        FROM space_members sm
+        JOIN spaces s ON s.id = sm.space_id
+        WHERE sm.space_id = $1
+              AND (sm.role IN ('moderator', 'admin') OR s.creator_id = sm.identity_id)
+        "#,
+        space_id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(candidates
+        .into_iter()
+        .filter(|row| {
+            SpaceAccess::resolve(Some(row.role), &row.permissions, row.is_creator).has(SpacePermission::ManagePosts)
+        })
+        .map(|row| row.identity_id)
+        .collect())
+}
+
@@ -5,14 +5,25 @@ use axum::{
     http::StatusCode,
     Json,
 };
+use serde_json::json;
+use sqlx::PgConnection;
 use std::sync::Arc;
 use uuid::Uuid;
 use validator::Validate;
 
+use super::invites;
+use crate::api::moderation::log::{self, LogEntry};
+use crate::api::cursor::{Cursor, PageRequest};
+use crate::api::feed::context::{invalidate_summary, space_summary_key};
 use crate::api::extractors::Pagination;
 use crate::domain::entities::*;
+use crate::domain::services::markdown::validate_allowed_tags;
+use crate::domain::services::permissions::{SpaceAccess, DEFAULT_MODERATOR_PERMISSIONS};
 use crate::errors::{ApiError, ApiResult};
-use crate::middleware::auth::{AuthenticatedUser, check_space_admin};
+use crate::jobs::handlers::{RenderContentPayload, RenderTarget, RENDER_CONTENT};
+use crate::jobs::JobQueue;
+use crate::middleware::auth::{is_platform_admin, require_space_permission, space_access, AuthenticatedUser};
+use crate::websocket::recheck_subscriptions;
 use crate::AppState;
 
 /// List spaces with optional search
@@ -27,7 +38,7 @@ pub async fn list(
             r#"
             SELECT id, name, slug, description, rules, icon_url, banner_url,
                    is_private, is_nsfw, creator_id, subscriber_count, post_count,
-                   created_at, updated_at
+                   created_at, updated_at, allowed_tags, is_restricted, public_moderation_log
             FROM spaces
             WHERE (name ILIKE $1 OR description ILIKE $1) AND is_private = false
             ORDER BY subscriber_count DESC
@@ -45,7 +56,7 @@ pub async fn list(
             r#"
             SELECT id, name, slug, description, rules, icon_url, banner_url,
                    is_private, is_nsfw, creator_id, subscriber_count, post_count,
-                   created_at, updated_at
+                   created_at, updated_at, allowed_tags, is_restricted, public_moderation_log
             FROM spaces
             WHERE is_private = false
             ORDER BY subscriber_count DESC
@@ -130,11 +141,11 @@ pub async fn create(
     let space = sqlx::query_as!(
         Space,
         r#"
-        INSERT INTO spaces (id, name, slug, description, is_private, is_nsfw, creator_id, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8)
+        INSERT INTO spaces (id, name, slug, description, is_private, is_nsfw, is_restricted, creator_id, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $9)
         RETURNING id, name, slug, description, rules, icon_url, banner_url,
                   is_private, is_nsfw, creator_id, subscriber_count, post_count,
-                  created_at, updated_at
+                  created_at, updated_at, allowed_tags, is_restricted, public_moderation_log
         "#,
         id,
         request.name,
@@ -142,6 +153,7 @@ pub async fn create(
         request.description,
         request.is_private.unwrap_or(false),
         request.is_nsfw.unwrap_or(false),
+        request.is_restricted.unwrap_or(false),
         user.identity_id,
         now
     )
@@ -172,7 +184,7 @@ pub async fn get_by_slug(
         r#"
         SELECT id, name, slug, description, rules, icon_url, banner_url,
                is_private, is_nsfw, creator_id, subscriber_count, post_count,
-               created_at, updated_at
+               created_at, updated_at, allowed_tags, is_restricted, public_moderation_log
         FROM spaces
         WHERE slug = $1
         "#,
@@ -197,10 +209,14 @@ pub async fn update(
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    // Check admin permission
-    if !check_space_admin(&state, user.identity_id, space.id).await? {
-        return Err(ApiError::Forbidden);
-    }
+    require_space_permission(&state, user.identity_id, space.id, SpacePermission::ManageSettings).await?;
+
+    // Outer `None` leaves the policy alone, inner `None` clears it
+    let allowed_tags = request
+        .allowed_tags
+        .as_ref()
+        .map(|tags| tags.as_deref().map(validate_allowed_tags).transpose())
+        .transpose()?;
 
     let updated = sqlx::query_as!(
         Space,
@@ -209,20 +225,42 @@ pub async fn update(
         SET description = COALESCE($2, description),
             is_private = COALESCE($3, is_private),
             is_nsfw = COALESCE($4, is_nsfw),
+            allowed_tags = CASE WHEN $8 THEN $5 ELSE allowed_tags END,
+            is_restricted = COALESCE($6, is_restricted),
+            public_moderation_log = COALESCE($7, public_moderation_log),
             updated_at = NOW()
         WHERE id = $1
         RETURNING id, name, slug, description, rules, icon_url, banner_url,
                   is_private, is_nsfw, creator_id, subscriber_count, post_count,
-                  created_at, updated_at
+                  created_at, updated_at, allowed_tags, is_restricted, public_moderation_log
         "#,
         space.id,
         request.description,
         request.is_private,
-        request.is_nsfw
+        request.is_nsfw,
+        allowed_tags.as_ref().and_then(|tags| tags.as_deref()),
+        request.is_restricted,
+        request.public_moderation_log,
+        allowed_tags.is_some()
     )
     .fetch_one(state.db.pool())
     .await?;
 
+    invalidate_summary(&state, &space_summary_key(space.id)).await;
+
+    // Existing content is re-rendered under the new policy
+    if allowed_tags.is_some() {
+        let queue = JobQueue::new(state.db.pool().clone());
+        for target in [RenderTarget::Posts, RenderTarget::Comments] {
+            let payload = RenderContentPayload {
+                target,
+                space_id: Some(space.id),
+                after: None,
+            };
+            queue.enqueue(RENDER_CONTENT, &payload).await?;
+        }
+    }
+
     Ok(Json(updated))
 }
 
@@ -237,8 +275,8 @@ pub async fn delete(
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    // Only creator can delete
-    if space.creator_id != Some(user.identity_id) {
+    // Only the creator, or a platform admin, can delete
+    if space.creator_id != Some(user.identity_id) && !is_platform_admin(&state, user.identity_id).await? {
         return Err(ApiError::Forbidden);
     }
 
@@ -246,6 +284,8 @@ pub async fn delete(
         .execute(state.db.pool())
         .await?;
 
+    invalidate_summary(&state, &space_summary_key(space.id)).await;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -254,16 +294,13 @@ pub async fn join(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
     Path(slug): Path<String>,
+    Query(params): Query<JoinParams>,
 ) -> ApiResult<StatusCode> {
     let space = sqlx::query!("SELECT id, is_private FROM spaces WHERE slug = $1", slug)
         .fetch_optional(state.db.pool())
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    if space.is_private {
-        return Err(ApiError::Forbidden);
-    }
-
     // Check if already member
     let existing = sqlx::query!(
         "SELECT id FROM space_members WHERE space_id = $1 AND identity_id = $2",
@@ -277,21 +314,24 @@ pub async fn join(
         return Ok(StatusCode::OK);
     }
 
-    sqlx::query!(
-        "INSERT INTO space_members (id, space_id, identity_id, role, joined_at) VALUES ($1, $2, $3, 'member', NOW())",
-        Uuid::new_v4(),
-        space.id,
-        user.identity_id
-    )
-    .execute(state.db.pool())
-    .await?;
+    let mut tx = state.db.pool().begin().await?;
 
-    // Increment subscriber count
-    sqlx::query!("UPDATE spaces SET subscriber_count = subscriber_count + 1 WHERE id = $1", space.id)
-        .execute(state.db.pool())
-        .await?;
+    // Private spaces are joined by invite only
+    if space.is_private
+        && !invites::redeem(&mut tx, &state.crypto, space.id, user.identity_id, params.invite.as_deref()).await?
+    {
+        return Err(ApiError::Forbidden);
+    }
+
+    let joined = invites::add_member(&mut tx, space.id, user.identity_id).await?;
 
-    Ok(StatusCode::CREATED)
+    tx.commit().await?;
+
+    if joined {
+        invalidate_summary(&state, &space_summary_key(space.id)).await;
+    }
+
+    Ok(if joined { StatusCode::CREATED } else { StatusCode::OK })
 }
 
 /// Leave a space
@@ -319,6 +359,10 @@ pub async fn leave(
             .await?;
     }
 
+    invalidate_summary(&state, &space_summary_key(space.id)).await;
+
+    recheck_subscriptions(&state, user.identity_id).await;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -333,36 +377,64 @@ pub async fn list_members(
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
+    let page = PageRequest::<chrono::DateTime<chrono::Utc>>::new(
+        &pagination,
+        &state.crypto,
+        format!("members:{}", space.id),
+    )?;
+
     let members = sqlx::query_as!(
         SpaceMemberWithIdentity,
         r#"
-        SELECT sm.id, sm.space_id, sm.identity_id, sm.role as "role: MemberRole", sm.joined_at,
+        SELECT sm.id, sm.space_id, sm.identity_id, sm.role as "role: MemberRole",
+               sm.permissions as "permissions: Vec<SpacePermission>", sm.joined_at,
                i.display_name, i.public_key_fingerprint
         FROM space_members sm
         JOIN identities i ON i.id = sm.identity_id
         WHERE sm.space_id = $1
-        ORDER BY sm.joined_at DESC
+              AND ($4::TIMESTAMPTZ IS NULL OR (sm.joined_at, sm.id) < ($4, $5))
+        ORDER BY sm.joined_at DESC, sm.id DESC
         LIMIT $2 OFFSET $3
         "#,
         space.id,
-        pagination.limit,
-        pagination.offset
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        page.after_id()
     )
     .fetch_all(state.db.pool())
     .await?;
 
-    let total: i64 = sqlx::query_scalar!("SELECT COUNT(*) FROM space_members WHERE space_id = $1", space.id)
-        .fetch_one(state.db.pool())
+    let total = async {
+        let total = sqlx::query_scalar!("SELECT COUNT(*) FROM space_members WHERE space_id = $1", space.id)
+            .fetch_one(state.db.pool())
+            .await?
+            .unwrap_or(0);
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(members, &state.crypto, |m| Cursor::new(m.joined_at, m.id), total)
+        .await?;
+
+    Ok(Json(response))
+}
+
+/// Get the current user's permissions in a space
+pub async fn get_access(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+) -> ApiResult<Json<SpaceAccess>> {
+    let space = sqlx::query!("SELECT id FROM spaces WHERE slug = $1", slug)
+        .fetch_optional(state.db.pool())
         .await?
-        .unwrap_or(0);
+        .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    Ok(Json(PaginatedResponse {
-        data: members,
-        pagination: PaginationInfo::new(total, pagination.limit, pagination.offset),
-    }))
+    Ok(Json(space_access(&state, user.identity_id, space.id).await?))
 }
 
-/// Update member role
+/// Update member role and permissions
 pub async fn update_member(
     State(state): State<Arc<AppState>>,
     user: AuthenticatedUser,
@@ -374,19 +446,58 @@ pub async fn update_member(
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    if !check_space_admin(&state, user.identity_id, space.id).await? {
+    let access = space_access(&state, user.identity_id, space.id).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+    let member = lock_member(&mut tx, space.id, identity_id).await?;
+
+    // Only admins assign roles, and only to members they outrank
+    if !access.is_admin() || !access.can_manage(member.role) || !access.can_manage(request.role) {
         return Err(ApiError::Forbidden);
     }
 
+    let permissions: Vec<SpacePermission> = match (request.role, request.permissions) {
+        (MemberRole::Moderator, Some(permissions)) => SpacePermission::ALL
+            .into_iter()
+            .filter(|permission| permissions.contains(permission))
+            .collect(),
+        (MemberRole::Moderator, None) => DEFAULT_MODERATOR_PERMISSIONS.to_vec(),
+        (_, Some(permissions)) if !permissions.is_empty() => {
+            return Err(ApiError::InvalidInput(
+                "Only moderators are granted individual permissions".to_string(),
+            ));
+        }
+        (_, _) => Vec::new(),
+    };
+
+    let stored: Vec<String> = permissions.iter().map(ToString::to_string).collect();
     sqlx::query!(
-        "UPDATE space_members SET role = $1 WHERE space_id = $2 AND identity_id = $3",
+        "UPDATE space_members SET role = $1, permissions = $2 WHERE space_id = $3 AND identity_id = $4",
         request.role.to_string(),
+        &stored,
         space.id,
         identity_id
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
     .await?;
 
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space.id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::UpdateMember,
+            target_type: ModerationTargetType::Identity,
+            target_id: identity_id,
+            reason: None,
+            before: Some(json!({ "role": member.role, "permissions": member.permissions })),
+            after: Some(json!({ "role": request.role, "permissions": permissions })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
     Ok(StatusCode::OK)
 }
 
@@ -401,7 +512,12 @@ pub async fn remove_member(
         .await?
         .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    if !check_space_admin(&state, user.identity_id, space.id).await? {
+    let access = space_access(&state, user.identity_id, space.id).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+    let member = lock_member(&mut tx, space.id, identity_id).await?;
+
+    if !access.can_manage(member.role) {
         return Err(ApiError::Forbidden);
     }
 
@@ -410,12 +526,64 @@ pub async fn remove_member(
         space.id,
         identity_id
     )
-    .execute(state.db.pool())
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("UPDATE spaces SET subscriber_count = subscriber_count - 1 WHERE id = $1", space.id)
+        .execute(&mut *tx)
+        .await?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space.id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::RemoveMember,
+            target_type: ModerationTargetType::Identity,
+            target_id: identity_id,
+            reason: None,
+            before: Some(json!({ "role": member.role, "permissions": member.permissions })),
+            after: None,
+        },
+    )
     .await?;
 
+    tx.commit().await?;
+
+    invalidate_summary(&state, &space_summary_key(space.id)).await;
+
+    recheck_subscriptions(&state, identity_id).await;
+
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// A space member's role and granted permissions
+struct MemberState {
+    role: MemberRole,
+    permissions: Vec<SpacePermission>,
+}
+
+/// Lock a space membership for the rest of the transaction
+async fn lock_member(conn: &mut PgConnection, space_id: Uuid, identity_id: Uuid) -> ApiResult<MemberState> {
+    let member = sqlx::query_as!(
+        MemberState,
+        r#"
+        SELECT COALESCE(role, 'member') as "role!: MemberRole",
+               permissions as "permissions: Vec<SpacePermission>"
+        FROM space_members
+        WHERE space_id = $1 AND identity_id = $2
+        FOR UPDATE
+        "#,
+        space_id,
+        identity_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Member not found".to_string()))?;
+
+    Ok(member)
+}
+
 // Request/Response types
 
 #[derive(Debug, serde::Deserialize)]
@@ -423,12 +591,20 @@ pub struct SpaceListParams {
     pub search: Option<String>,
 }
 
+#[derive(Debug, serde::Deserialize)]
+pub struct JoinParams {
+    /// Invite link token, for private spaces
+    pub invite: Option<String>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, sqlx::FromRow)]
 pub struct SpaceMemberWithIdentity {
     pub id: Uuid,
     pub space_id: Uuid,
     pub identity_id: Uuid,
     pub role: MemberRole,
+    /// Granted permissions; only meaningful for moderators
+    pub permissions: Vec<SpacePermission>,
     pub joined_at: chrono::DateTime<chrono::Utc>,
     pub display_name: Option<String>,
     pub public_key_fingerprint: String,
@@ -437,4 +613,29 @@ pub struct SpaceMemberWithIdentity {
 #[derive(Debug, serde::Deserialize)]
 pub struct UpdateMemberRequest {
     pub role: MemberRole,
+    /// Permissions for a moderator; defaults apply when omitted
+    pub permissions: Option<Vec<SpacePermission>>,
+}
+
+impl std::fmt::Display for MemberRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            MemberRole::Member => "member",
+            MemberRole::Moderator => "moderator",
+            MemberRole::Admin => "admin",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl std::fmt::Display for SpacePermission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            SpacePermission::ManagePosts => "manage_posts",
+            SpacePermission::ManageMembers => "manage_members",
+            SpacePermission::ManageSettings => "manage_settings",
+            SpacePermission::ViewReports => "view_reports",
+        };
+        write!(f, "{}", s)
+    }
 }
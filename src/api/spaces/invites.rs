@@ -0,0 +1,410 @@
+//! Space invite handlers
+//!
+//! Moderators who manage a private space's members invite identities
+//! directly, or create invite links that anyone holding the token can redeem
+//! through `join`. Direct invites notify the invitee, who can accept or
+//! decline them.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde_json::json;
+use sqlx::PgConnection;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::invite_token;
+use crate::api::feed::context::{invalidate_summary, space_summary_key};
+use crate::domain::entities::*;
+use crate::errors::{ApiError, ApiResult};
+use crate::infrastructure::crypto::CryptoService;
+use crate::jobs::handlers::NotificationContext;
+use crate::jobs::workers::send_notification_job;
+use crate::middleware::auth::{require_space_permission, AuthenticatedUser};
+use crate::AppState;
+
+/// Lifetime of an invite created without an explicit expiry
+const DEFAULT_INVITE_HOURS: i64 = 7 * 24;
+
+/// Pending invites a space may have at once
+const MAX_PENDING_INVITES: i64 = 500;
+
+/// Create an invite to a private space
+pub async fn create(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<CreateInviteRequest>,
+) -> ApiResult<(StatusCode, Json<SpaceInviteView>)> {
+    request.validate()?;
+
+    let space = sqlx::query!(r#"SELECT id, name, is_private as "is_private!" FROM spaces WHERE slug = $1"#, slug)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+    require_space_permission(&state, user.identity_id, space.id, SpacePermission::ManageMembers).await?;
+
+    if !space.is_private {
+        return Err(ApiError::OperationNotAllowed(
+            "Invites are only needed for private spaces".to_string(),
+        ));
+    }
+
+    if let Some(invitee_id) = request.invitee_id {
+        if request.max_uses.is_some() {
+            return Err(ApiError::InvalidInput("Only invite links have a use limit".to_string()));
+        }
+
+        let invitee = sqlx::query!(
+            r#"
+            SELECT EXISTS (SELECT 1 FROM identities WHERE id = $2) as "exists!",
+                   EXISTS (
+                       SELECT 1 FROM space_members WHERE space_id = $1 AND identity_id = $2
+                   ) as "is_member!"
+            "#,
+            space.id,
+            invitee_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+
+        if !invitee.exists {
+            return Err(ApiError::NotFound("Identity not found".to_string()));
+        }
+        if invitee.is_member {
+            return Err(ApiError::Conflict("Identity is already a member".to_string()));
+        }
+    }
+
+    let pending = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) as "count!" FROM space_invites WHERE space_id = $1 AND status = 'pending'"#,
+        space.id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if pending >= MAX_PENDING_INVITES {
+        return Err(ApiError::OperationNotAllowed(format!(
+            "At most {} pending invites are allowed",
+            MAX_PENDING_INVITES
+        )));
+    }
+
+    let expires_at = Utc::now() + Duration::hours(request.expires_in_hours.unwrap_or(DEFAULT_INVITE_HOURS));
+
+    let mut tx = state.db.pool().begin().await?;
+
+    // An expired invite would otherwise hold the invitee's pending slot
+    if let Some(invitee_id) = request.invitee_id {
+        sqlx::query!(
+            r#"
+            UPDATE space_invites SET status = 'revoked'
+            WHERE space_id = $1 AND invitee_id = $2 AND status = 'pending' AND expires_at <= NOW()
+            "#,
+            space.id,
+            invitee_id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    let invite = sqlx::query_as!(
+        SpaceInvite,
+        r#"
+        INSERT INTO space_invites (space_id, inviter_id, invitee_id, max_uses, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, space_id, inviter_id, invitee_id, status as "status: InviteStatus",
+                  max_uses, use_count, expires_at, created_at, responded_at
+        "#,
+        space.id,
+        user.identity_id,
+        request.invitee_id,
+        request.max_uses,
+        expires_at
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    if let Some(invitee_id) = invite.invitee_id {
+        send_notification_job(
+            &state,
+            invitee_id,
+            NotificationType::SpaceInvite,
+            NotificationContext {
+                post_id: None,
+                actor_id: Some(user.identity_id),
+            },
+            json!({
+                "invite_id": invite.id,
+                "space_id": space.id,
+                "space_slug": slug,
+                "space_name": space.name,
+                "invited_by": user.identity_id,
+            }),
+        )
+        .await?;
+    }
+
+    Ok((StatusCode::CREATED, Json(view(&state.crypto, invite))))
+}
+
+/// List a space's pending invites
+pub async fn list(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+) -> ApiResult<Json<Vec<SpaceInviteView>>> {
+    let space = sqlx::query!("SELECT id FROM spaces WHERE slug = $1", slug)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+    require_space_permission(&state, user.identity_id, space.id, SpacePermission::ManageMembers).await?;
+
+    let invites = sqlx::query_as!(
+        SpaceInvite,
+        r#"
+        SELECT id, space_id, inviter_id, invitee_id, status as "status: InviteStatus",
+               max_uses, use_count, expires_at, created_at, responded_at
+        FROM space_invites
+        WHERE space_id = $1 AND status = 'pending'
+        ORDER BY created_at DESC
+        "#,
+        space.id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(Json(invites.into_iter().map(|invite| view(&state.crypto, invite)).collect()))
+}
+
+/// Revoke a pending invite
+pub async fn revoke(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, invite_id)): Path<(String, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let space = sqlx::query!("SELECT id FROM spaces WHERE slug = $1", slug)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+    require_space_permission(&state, user.identity_id, space.id, SpacePermission::ManageMembers).await?;
+
+    let result = sqlx::query!(
+        r#"
+        UPDATE space_invites SET status = 'revoked', responded_at = NOW()
+        WHERE id = $1 AND space_id = $2 AND status = 'pending'
+        "#,
+        invite_id,
+        space.id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Invite not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List the current user's pending invites
+pub async fn list_pending(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+) -> ApiResult<Json<Vec<PendingInvite>>> {
+    // Invites from identities the user has blocked are not shown
+    let invites = sqlx::query_as!(
+        PendingInvite,
+        r#"
+        SELECT i.id, i.space_id, s.slug as space_slug, s.name as space_name,
+               i.inviter_id, inviter.display_name as "inviter_display_name?",
+               i.expires_at, i.created_at
+        FROM space_invites i
+        JOIN spaces s ON s.id = i.space_id
+        LEFT JOIN identities inviter ON inviter.id = i.inviter_id
+        WHERE i.invitee_id = $1
+              AND i.status = 'pending'
+              AND (i.expires_at IS NULL OR i.expires_at > NOW())
+              AND NOT EXISTS (
+                  SELECT 1 FROM identity_blocks b
+                  WHERE b.blocker_id = $1 AND b.blocked_id = i.inviter_id
+              )
+        ORDER BY i.created_at DESC
+        "#,
+        user.identity_id
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    Ok(Json(invites))
+}
+
+/// Accept an invite and join its space
+pub async fn accept(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(invite_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.db.pool().begin().await?;
+
+    let space_id = sqlx::query_scalar!(
+        r#"
+        UPDATE space_invites SET status = 'accepted', responded_at = NOW()
+        WHERE id = $1 AND invitee_id = $2 AND status = 'pending'
+              AND (expires_at IS NULL OR expires_at > NOW())
+        RETURNING space_id
+        "#,
+        invite_id,
+        user.identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Invite not found".to_string()))?;
+
+    let joined = add_member(&mut tx, space_id, user.identity_id).await?;
+
+    tx.commit().await?;
+
+    if joined {
+        invalidate_summary(&state, &space_summary_key(space_id)).await;
+    }
+
+    Ok(if joined { StatusCode::CREATED } else { StatusCode::OK })
+}
+
+/// Decline an invite
+pub async fn decline(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(invite_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let result = sqlx::query!(
+        r#"
+        UPDATE space_invites SET status = 'declined', responded_at = NOW()
+        WHERE id = $1 AND invitee_id = $2 AND status = 'pending'
+        "#,
+        invite_id,
+        user.identity_id
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Invite not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Use an invite to join a private space.
+///
+/// A pending direct invite for the identity is accepted first; otherwise
+/// `token` must be a link for this space that has not expired, been revoked
+/// or used up. Returns whether an invite was redeemed.
+pub(crate) async fn redeem(
+    conn: &mut PgConnection,
+    crypto: &CryptoService,
+    space_id: Uuid,
+    identity_id: Uuid,
+    token: Option<&str>,
+) -> ApiResult<bool> {
+    let direct = sqlx::query_scalar!(
+        r#"
+        UPDATE space_invites SET status = 'accepted', responded_at = NOW()
+        WHERE space_id = $1 AND invitee_id = $2 AND status = 'pending'
+              AND (expires_at IS NULL OR expires_at > NOW())
+        RETURNING id
+        "#,
+        space_id,
+        identity_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    if direct.is_some() {
+        return Ok(true);
+    }
+
+    let Some(token) = token else {
+        return Ok(false);
+    };
+    let invite_id = invite_token::decode(crypto, token)?;
+
+    // Checking and counting the use in one statement keeps concurrent
+    // redemptions within the limit
+    let redeemed = sqlx::query_scalar!(
+        r#"
+        UPDATE space_invites SET use_count = use_count + 1
+        WHERE id = $1 AND space_id = $2 AND invitee_id IS NULL AND status = 'pending'
+              AND (expires_at IS NULL OR expires_at > NOW())
+              AND (max_uses IS NULL OR use_count < max_uses)
+        RETURNING id
+        "#,
+        invite_id,
+        space_id
+    )
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(redeemed.is_some())
+}
+
+/// Add an identity to a space as a member.
+///
+/// Returns whether it was added, `false` when it already was a member.
+/// Identities banned from the space cannot be added.
+pub(crate) async fn add_member(conn: &mut PgConnection, space_id: Uuid, identity_id: Uuid) -> ApiResult<bool> {
+    let banned = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM space_bans
+            WHERE space_id = $1 AND identity_id = $2 AND (expires_at IS NULL OR expires_at > NOW())
+        ) as "banned!"
+        "#,
+        space_id,
+        identity_id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    if banned {
+        return Err(ApiError::OperationNotAllowed("You are banned from this space".to_string()));
+    }
+
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO space_members (id, space_id, identity_id, role, joined_at)
+        VALUES ($1, $2, $3, 'member', NOW())
+        ON CONFLICT (space_id, identity_id) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        space_id,
+        identity_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    sqlx::query!("UPDATE spaces SET subscriber_count = subscriber_count + 1 WHERE id = $1", space_id)
+        .execute(&mut *conn)
+        .await?;
+
+    Ok(true)
+}
+
+fn view(crypto: &CryptoService, invite: SpaceInvite) -> SpaceInviteView {
+    let token = invite.invitee_id.is_none().then(|| invite_token::encode(crypto, invite.id));
+    SpaceInviteView { invite, token }
+}
@@ -1,5 +1,47 @@
 //! Spaces (communities) API module
 
+pub mod automod;
 pub mod handlers;
+pub mod invite_token;
+pub mod invites;
+pub mod restrictions;
 
 pub use handlers::*;
+
+use uuid::Uuid;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::AppState;
+
+/// Look up a space's id by slug
+pub(crate) async fn space_id(state: &AppState, slug: &str) -> ApiResult<Uuid> {
+    sqlx::query_scalar!("SELECT id FROM spaces WHERE slug = $1", slug)
+        .fetch_optional(state.db.pool())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))
+}
+
+/// Fail unless the viewer may read content in a space. Private spaces are
+/// readable by their members only.
+pub(crate) async fn require_space_visible(state: &AppState, space_id: Uuid, viewer: Option<Uuid>) -> ApiResult<()> {
+    let visible = sqlx::query_scalar!(
+        r#"
+        SELECT s.is_private IS NOT TRUE
+               OR EXISTS(SELECT 1 FROM space_members WHERE space_id = s.id AND identity_id = $2)
+               AS "visible!"
+        FROM spaces s
+        WHERE s.id = $1
+        "#,
+        space_id,
+        viewer
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+    if !visible {
+        return Err(ApiError::Forbidden);
+    }
+
+    Ok(())
+}
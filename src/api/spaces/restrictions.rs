@@ -0,0 +1,635 @@
+//! Space bans, mutes and approved submitters
+//!
+//! Moderators who manage a space's members can ban identities from it, mute
+//! them for a while, and keep the list of identities allowed to post in a
+//! restricted space. Posts and comments are checked against these with
+//! [`require_can_contribute`].
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::api::cursor::{Cursor, PageRequest};
+use crate::api::extractors::Pagination;
+use crate::api::feed::context::{invalidate_summary, space_summary_key};
+use crate::api::moderation::log::{self, LogEntry};
+use crate::api::spaces::space_id;
+use crate::domain::entities::*;
+use crate::domain::services::permissions::SpaceAccess;
+use crate::errors::{ApiError, ApiResult};
+use crate::middleware::auth::{require_space_permission, space_access, AuthenticatedUser};
+use crate::websocket::recheck_subscriptions;
+use crate::AppState;
+
+/// A write an identity makes in a space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Contribution {
+    Post,
+    Comment,
+}
+
+/// Reject contributions from identities banned or muted in the space, and
+/// posts to a restricted space by identities that are neither approved
+/// submitters nor moderators
+pub(crate) async fn require_can_contribute(
+    state: &Arc<AppState>,
+    space_id: Uuid,
+    identity_id: Uuid,
+    contribution: Contribution,
+) -> ApiResult<()> {
+    let standing = sqlx::query!(
+        r#"
+        SELECT s.is_restricted,
+               EXISTS (
+                   SELECT 1 FROM space_bans b
+                   WHERE b.space_id = s.id AND b.identity_id = $2
+                         AND (b.expires_at IS NULL OR b.expires_at > NOW())
+               ) as "banned!",
+               (
+                   SELECT m.expires_at FROM space_mutes m
+                   WHERE m.space_id = s.id AND m.identity_id = $2 AND m.expires_at > NOW()
+               ) as muted_until,
+               EXISTS (
+                   SELECT 1 FROM space_approved_submitters a
+                   WHERE a.space_id = s.id AND a.identity_id = $2
+               ) as "approved!"
+        FROM spaces s
+        WHERE s.id = $1
+        "#,
+        space_id,
+        identity_id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+    if standing.banned {
+        return Err(ApiError::OperationNotAllowed("You are banned from this space".to_string()));
+    }
+
+    if let Some(until) = standing.muted_until {
+        return Err(ApiError::OperationNotAllowed(format!(
+            "You are muted in this space until {}",
+            until.to_rfc3339()
+        )));
+    }
+
+    if contribution == Contribution::Post
+        && standing.is_restricted
+        && !standing.approved
+        && !space_access(state, identity_id, space_id).await?.has(SpacePermission::ManagePosts)
+    {
+        return Err(ApiError::OperationNotAllowed(
+            "Only approved submitters can post in this space".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Ban an identity from a space
+pub async fn ban(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<BanRequest>,
+) -> ApiResult<StatusCode> {
+    request.validate()?;
+
+    let space_id = space_id(&state, &slug).await?;
+    let access = require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+    require_can_restrict(&state, &access, space_id, user.identity_id, request.identity_id).await?;
+
+    let expires_at = request.duration_hours.map(|hours| Utc::now() + Duration::hours(hours));
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let previous = sqlx::query!(
+        "SELECT reason, expires_at FROM space_bans WHERE space_id = $1 AND identity_id = $2 FOR UPDATE",
+        space_id,
+        request.identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO space_bans (space_id, identity_id, banned_by, reason, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (space_id, identity_id) DO UPDATE
+        SET banned_by = EXCLUDED.banned_by,
+            reason = EXCLUDED.reason,
+            expires_at = EXCLUDED.expires_at,
+            created_at = NOW()
+        "#,
+        space_id,
+        request.identity_id,
+        user.identity_id,
+        request.reason,
+        expires_at
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let removed_role = sqlx::query_scalar!(
+        r#"
+        DELETE FROM space_members WHERE space_id = $1 AND identity_id = $2
+        RETURNING COALESCE(role, 'member') as "role!: MemberRole"
+        "#,
+        space_id,
+        request.identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if removed_role.is_some() {
+        sqlx::query!("UPDATE spaces SET subscriber_count = subscriber_count - 1 WHERE id = $1", space_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    sqlx::query!(
+        r#"
+        UPDATE space_invites SET status = 'revoked', responded_at = NOW()
+        WHERE space_id = $1 AND invitee_id = $2 AND status = 'pending'
+        "#,
+        space_id,
+        request.identity_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::BanMember,
+            target_type: ModerationTargetType::Identity,
+            target_id: request.identity_id,
+            reason: Some(&request.reason),
+            before: Some(json!({
+                "ban": previous.map(|ban| json!({ "reason": ban.reason, "expires_at": ban.expires_at })),
+                "role": removed_role,
+            })),
+            after: Some(json!({
+                "ban": { "reason": request.reason, "expires_at": expires_at },
+                "role": null,
+            })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    if removed_role.is_some() {
+        invalidate_summary(&state, &space_summary_key(space_id)).await;
+    }
+
+    recheck_subscriptions(&state, request.identity_id).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lift a ban
+pub async fn unban(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, identity_id)): Path<(String, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let ban = sqlx::query!(
+        "DELETE FROM space_bans WHERE space_id = $1 AND identity_id = $2 RETURNING reason, expires_at",
+        space_id,
+        identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Ban not found".to_string()))?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::UnbanMember,
+            target_type: ModerationTargetType::Identity,
+            target_id: identity_id,
+            reason: None,
+            before: Some(json!({ "ban": { "reason": ban.reason, "expires_at": ban.expires_at } })),
+            after: Some(json!({ "ban": null })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List a space's active bans
+pub async fn list_bans(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<SpaceRestriction>>> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+
+    let page = PageRequest::<DateTime<Utc>>::new(&pagination, &state.crypto, format!("bans:{}", space_id))?;
+
+    let bans = sqlx::query_as!(
+        SpaceRestriction,
+        r#"
+        SELECT b.identity_id, i.display_name, i.public_key_fingerprint, b.reason,
+               b.banned_by as issued_by, b.expires_at, b.created_at
+        FROM space_bans b
+        JOIN identities i ON i.id = b.identity_id
+        WHERE b.space_id = $1
+              AND (b.expires_at IS NULL OR b.expires_at > NOW())
+              AND ($4::TIMESTAMPTZ IS NULL OR (b.created_at, b.identity_id) < ($4, $5))
+        ORDER BY b.created_at DESC, b.identity_id DESC
+        LIMIT $2 OFFSET $3
+        "#,
+        space_id,
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        page.after_id()
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) as "count!" FROM space_bans
+            WHERE space_id = $1 AND (expires_at IS NULL OR expires_at > NOW())
+            "#,
+            space_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(bans, &state.crypto, |b| Cursor::new(b.created_at, b.identity_id), total)
+        .await?;
+
+    Ok(Json(response))
+}
+
+/// Mute an identity in a space for a while
+pub async fn mute(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<SpaceMuteRequest>,
+) -> ApiResult<StatusCode> {
+    request.validate()?;
+
+    let space_id = space_id(&state, &slug).await?;
+    let access = require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+    require_can_restrict(&state, &access, space_id, user.identity_id, request.identity_id).await?;
+
+    let expires_at = Utc::now() + Duration::hours(request.duration_hours);
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let previous = sqlx::query!(
+        "SELECT reason, expires_at FROM space_mutes WHERE space_id = $1 AND identity_id = $2 FOR UPDATE",
+        space_id,
+        request.identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO space_mutes (space_id, identity_id, muted_by, reason, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (space_id, identity_id) DO UPDATE
+        SET muted_by = EXCLUDED.muted_by,
+            reason = EXCLUDED.reason,
+            expires_at = EXCLUDED.expires_at,
+            created_at = NOW()
+        "#,
+        space_id,
+        request.identity_id,
+        user.identity_id,
+        request.reason,
+        expires_at
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::MuteMember,
+            target_type: ModerationTargetType::Identity,
+            target_id: request.identity_id,
+            reason: Some(&request.reason),
+            before: Some(json!({
+                "mute": previous.map(|mute| json!({ "reason": mute.reason, "expires_at": mute.expires_at })),
+            })),
+            after: Some(json!({ "mute": { "reason": request.reason, "expires_at": expires_at } })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Lift a mute
+pub async fn unmute(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, identity_id)): Path<(String, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let mute = sqlx::query!(
+        "DELETE FROM space_mutes WHERE space_id = $1 AND identity_id = $2 RETURNING reason, expires_at",
+        space_id,
+        identity_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Mute not found".to_string()))?;
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::UnmuteMember,
+            target_type: ModerationTargetType::Identity,
+            target_id: identity_id,
+            reason: None,
+            before: Some(json!({ "mute": { "reason": mute.reason, "expires_at": mute.expires_at } })),
+            after: Some(json!({ "mute": null })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List a space's active mutes
+pub async fn list_mutes(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<SpaceRestriction>>> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+
+    let page = PageRequest::<DateTime<Utc>>::new(&pagination, &state.crypto, format!("mutes:{}", space_id))?;
+
+    let mutes = sqlx::query_as!(
+        SpaceRestriction,
+        r#"
+        SELECT m.identity_id, i.display_name, i.public_key_fingerprint, m.reason,
+               m.muted_by as issued_by, m.expires_at as "expires_at?", m.created_at
+        FROM space_mutes m
+        JOIN identities i ON i.id = m.identity_id
+        WHERE m.space_id = $1
+              AND m.expires_at > NOW()
+              AND ($4::TIMESTAMPTZ IS NULL OR (m.created_at, m.identity_id) < ($4, $5))
+        ORDER BY m.created_at DESC, m.identity_id DESC
+        LIMIT $2 OFFSET $3
+        "#,
+        space_id,
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        page.after_id()
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM space_mutes WHERE space_id = $1 AND expires_at > NOW()"#,
+            space_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(mutes, &state.crypto, |m| Cursor::new(m.created_at, m.identity_id), total)
+        .await?;
+
+    Ok(Json(response))
+}
+
+/// Allow an identity to post in a restricted space
+pub async fn add_approved_submitter(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Json(request): Json<ApproveSubmitterRequest>,
+) -> ApiResult<StatusCode> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+    require_identity_exists(&state, request.identity_id).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let added = sqlx::query!(
+        r#"
+        INSERT INTO space_approved_submitters (space_id, identity_id, added_by)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (space_id, identity_id) DO NOTHING
+        "#,
+        space_id,
+        request.identity_id,
+        user.identity_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // Approving an identity twice changes nothing worth logging
+    if added.rows_affected() > 0 {
+        log::record(
+            &mut tx,
+            LogEntry {
+                space_id: Some(space_id),
+                actor_id: Some(user.identity_id),
+                action: ModerationLogAction::ApproveSubmitter,
+                target_type: ModerationTargetType::Identity,
+                target_id: request.identity_id,
+                reason: None,
+                before: Some(json!({ "approved": false })),
+                after: Some(json!({ "approved": true })),
+            },
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Remove an identity from the approved submitters
+pub async fn remove_approved_submitter(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path((slug, identity_id)): Path<(String, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+
+    let mut tx = state.db.pool().begin().await?;
+
+    let result = sqlx::query!(
+        "DELETE FROM space_approved_submitters WHERE space_id = $1 AND identity_id = $2",
+        space_id,
+        identity_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(ApiError::NotFound("Approved submitter not found".to_string()));
+    }
+
+    log::record(
+        &mut tx,
+        LogEntry {
+            space_id: Some(space_id),
+            actor_id: Some(user.identity_id),
+            action: ModerationLogAction::UnapproveSubmitter,
+            target_type: ModerationTargetType::Identity,
+            target_id: identity_id,
+            reason: None,
+            before: Some(json!({ "approved": true })),
+            after: Some(json!({ "approved": false })),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List a space's approved submitters
+pub async fn list_approved_submitters(
+    State(state): State<Arc<AppState>>,
+    user: AuthenticatedUser,
+    Path(slug): Path<String>,
+    Pagination(pagination): Pagination,
+) -> ApiResult<Json<PaginatedResponse<ApprovedSubmitter>>> {
+    let space_id = space_id(&state, &slug).await?;
+    require_space_permission(&state, user.identity_id, space_id, SpacePermission::ManageMembers).await?;
+
+    let page = PageRequest::<DateTime<Utc>>::new(
+        &pagination,
+        &state.crypto,
+        format!("approved_submitters:{}", space_id),
+    )?;
+
+    let submitters = sqlx::query_as!(
+        ApprovedSubmitter,
+        r#"
+        SELECT a.identity_id, i.display_name, i.public_key_fingerprint, a.added_by, a.created_at
+        FROM space_approved_submitters a
+        JOIN identities i ON i.id = a.identity_id
+        WHERE a.space_id = $1
+              AND ($4::TIMESTAMPTZ IS NULL OR (a.created_at, a.identity_id) < ($4, $5))
+        ORDER BY a.created_at DESC, a.identity_id DESC
+        LIMIT $2 OFFSET $3
+        "#,
+        space_id,
+        page.fetch_limit(),
+        page.offset,
+        page.after_key(),
+        page.after_id()
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let total = async {
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM space_approved_submitters WHERE space_id = $1"#,
+            space_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+        Ok(total)
+    };
+
+    let response = page
+        .into_response(submitters, &state.crypto, |a| Cursor::new(a.created_at, a.identity_id), total)
+        .await?;
+
+    Ok(Json(response))
+}
+
+
+async fn require_identity_exists(state: &Arc<AppState>, identity_id: Uuid) -> ApiResult<()> {
+    let exists = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM identities WHERE id = $1) as "exists!""#,
+        identity_id
+    )
+    .fetch_one(state.db.pool())
+    .await?;
+
+    if !exists {
+        return Err(ApiError::NotFound("Identity not found".to_string()));
+    }
+
+    Ok(())
+}
+
+/// Moderators may only ban or mute identities they outrank. The space creator
+/// and platform admins cannot be restricted; non-members rank as members.
+async fn require_can_restrict(
+    state: &Arc<AppState>,
+    access: &SpaceAccess,
+    space_id: Uuid,
+    moderator_id: Uuid,
+    identity_id: Uuid,
+) -> ApiResult<()> {
+    if identity_id == moderator_id {
+        return Err(ApiError::InvalidInput("Cannot restrict yourself".to_string()));
+    }
+
+    require_identity_exists(state, identity_id).await?;
+
+    let target = space_access(state, identity_id, space_id).await?;
+
+    if !access.can_restrict(&target) {
+        return Err(ApiError::Forbidden);
+    }
+
+    Ok(())
+}
@@ -8,8 +8,10 @@ use std::sync::Arc;
 use uuid::Uuid;
 
 use crate::domain::entities::*;
+use crate::domain::services::feed::ScoreCalculator;
 use crate::errors::{ApiError, ApiResult};
 use crate::middleware::auth::AuthenticatedUser;
+use crate::websocket::{publish_channel_event, Channel};
 use crate::AppState;
 
 /// Allowed vote values: 1 (upvote), -1 (downvote), 0 (remove vote)
@@ -97,18 +99,31 @@ pub async fn vote_post(
     let upvote_delta = compute_delta(old_value, new_value, 1);
     let downvote_delta = compute_delta(old_value, new_value, -1);
 
-    sqlx::query!(
+    let tally = sqlx::query!(
         r#"
         UPDATE posts
         SET upvotes = GREATEST(0, upvotes + $2),
             downvotes = GREATEST(0, downvotes + $3),
             score = (upvotes + $2) - (downvotes + $3)
         WHERE id = $1
+        RETURNING upvotes as "upvotes!", downvotes as "downvotes!", score as "score!", created_at
         "#,
         post_id,
         upvote_delta as i32,
         downvote_delta as i32
     )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Stored ranks only move when votes do, so refresh them alongside the tally
+    let ranks = ScoreCalculator::calculate_post_scores(tally.upvotes, tally.downvotes, tally.created_at);
+    sqlx::query!(
+        "UPDATE posts SET hot_rank = $2, best_rank = $3, controversy_rank = $4 WHERE id = $1",
+        post_id,
+        ranks.hot,
+        ranks.wilson,
+        ranks.controversy
+    )
     .execute(&mut *tx)
     .await?;
 
@@ -127,6 +142,20 @@ pub async fn vote_post(
     }
 
     tx.commit().await?;
+
+    publish_channel_event(
+        &state,
+        &Channel::Post(post_id),
+        "post_vote_updated",
+        serde_json::json!({
+            "post_id": post_id,
+            "upvotes": tally.upvotes,
+            "downvotes": tally.downvotes,
+            "score": tally.score
+        }),
+    )
+    .await;
+
     Ok(StatusCode::OK)
 }
 
@@ -153,7 +182,7 @@ pub async fn vote_comment(
     let mut tx = state.db.pool().begin().await?;
 
     let comment = sqlx::query!(
-        "SELECT id, author_id FROM comments WHERE id = $1 AND is_removed = false FOR UPDATE",
+        "SELECT id, post_id, author_id FROM comments WHERE id = $1 AND is_removed = false FOR UPDATE",
         comment_id
     )
     .fetch_optional(&mut *tx)
@@ -206,19 +235,20 @@ pub async fn vote_comment(
     let upvote_delta = compute_delta(old_value, new_value, 1);
     let downvote_delta = compute_delta(old_value, new_value, -1);
 
-    sqlx::query!(
+    let tally = sqlx::query!(
         r#"
         UPDATE comments
         SET upvotes = GREATEST(0, upvotes + $2),
             downvotes = GREATEST(0, downvotes + $3),
             score = (upvotes + $2) - (downvotes + $3)
         WHERE id = $1
+        RETURNING upvotes, downvotes, score
         "#,
         comment_id,
         upvote_delta as i32,
         downvote_delta as i32
     )
-    .execute(&mut *tx)
+    .fetch_one(&mut *tx)
     .await?;
 
     let karma_delta = (new_value - old_value) as i32;
@@ -235,6 +265,20 @@ pub async fn vote_comment(
     }
 
     tx.commit().await?;
+
+    publish_channel_event(
+        &state,
+        &Channel::Post(comment.post_id),
+        "comment_vote_updated",
+        serde_json::json!({
+            "comment_id": comment_id,
+            "upvotes": tally.upvotes,
+            "downvotes": tally.downvotes,
+            "score": tally.score
+        }),
+    )
+    .await;
+
     Ok(StatusCode::OK)
 }
 
@@ -160,6 +160,8 @@ pub struct RedisSettings {
     pub connect_timeout: u64,
     /// Key prefix for namespacing
     pub key_prefix: String,
+    /// Seconds to cache author and space summaries for listings (0 disables)
+    pub summary_cache_ttl: u64,
 }
 
 impl RedisSettings {
@@ -177,6 +179,10 @@ impl RedisSettings {
                 .map_err(|_| ConfigError::InvalidValue("REDIS_CONNECT_TIMEOUT".to_string()))?,
             key_prefix: env::var("REDIS_KEY_PREFIX")
                 .unwrap_or_else(|_| "silentalliance:".to_string()),
+            summary_cache_ttl: env::var("REDIS_SUMMARY_CACHE_TTL")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .map_err(|_| ConfigError::InvalidValue("REDIS_SUMMARY_CACHE_TTL".to_string()))?,
         })
     }
 }
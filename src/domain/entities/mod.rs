@@ -2,8 +2,9 @@
 //!
 //! These types represent the core business objects in the system.
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use sqlx::FromRow;
 use uuid::Uuid;
 use validator::Validate;
@@ -75,6 +76,22 @@ pub struct UpdateIdentityRequest {
     pub bio: Option<String>,
 }
 
+/// Block request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockRequest {
+    pub identity_id: Uuid,
+}
+
+/// An identity the current user has blocked
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct BlockedIdentity {
+    pub id: Uuid,
+    pub public_key_fingerprint: String,
+    pub display_name: Option<String>,
+    pub avatar_hash: Option<String>,
+    pub blocked_at: DateTime<Utc>,
+}
+
 // ==================== Credentials ====================
 
 /// User credentials for authentication
@@ -122,18 +139,25 @@ pub struct Space {
     pub post_count: i32,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Tags content in this space may render with; all safe tags when unset
+    pub allowed_tags: Option<Vec<String>>,
+    /// Only approved submitters and moderators may post
+    pub is_restricted: bool,
+    /// Moderation log is publicly visible, with moderators pseudonymised
+    pub public_moderation_log: bool,
 }
 
 /// Create space request
 #[derive(Debug, Clone, Serialize, Deserialize, Validate)]
 pub struct CreateSpaceRequest {
     #[validate(length(min = 3, max = 50, message = "Name must be 3-50 characters"))]
-    #[validate(regex(path = "crate::domain::entities::SLUG_REGEX", message = "Name can only contain letters, numbers, and underscores"))]
+    #[validate(regex(path = *SLUG_REGEX, message = "Name can only contain letters, numbers, and underscores"))]
     pub name: String,
     #[validate(length(max = 500, message = "Description must be at most 500 characters"))]
     pub description: Option<String>,
     pub is_private: Option<bool>,
     pub is_nsfw: Option<bool>,
+    pub is_restricted: Option<bool>,
 }
 
 /// Update space request
@@ -144,6 +168,13 @@ pub struct UpdateSpaceRequest {
     pub rules: Option<Vec<String>>,
     pub is_private: Option<bool>,
     pub is_nsfw: Option<bool>,
+    /// Narrow the tags content renders with; `null` restores the default set
+    #[serde(default, with = "serde_with::rust::double_option")]
+    pub allowed_tags: Option<Option<Vec<String>>>,
+    /// Limit posting to approved submitters
+    pub is_restricted: Option<bool>,
+    /// Publish the moderation log
+    pub public_moderation_log: Option<bool>,
 }
 
 /// Space membership
@@ -172,6 +203,146 @@ impl Default for MemberRole {
     }
 }
 
+/// Granular permissions within a space
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum SpacePermission {
+    /// Remove, pin and lock posts and comments
+    ManagePosts,
+    /// Remove members and manage invites
+    ManageMembers,
+    /// Update the space's settings
+    ManageSettings,
+    /// Review reports about the space's content
+    ViewReports,
+}
+
+impl SpacePermission {
+    pub const ALL: [SpacePermission; 4] = [
+        SpacePermission::ManagePosts,
+        SpacePermission::ManageMembers,
+        SpacePermission::ManageSettings,
+        SpacePermission::ViewReports,
+    ];
+}
+
+impl sqlx::postgres::PgHasArrayType for SpacePermission {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_varchar")
+    }
+}
+
+/// Invitation to join a private space
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SpaceInvite {
+    pub id: Uuid,
+    pub space_id: Uuid,
+    pub inviter_id: Option<Uuid>,
+    /// Identity the invite is for; `None` for invite links
+    pub invitee_id: Option<Uuid>,
+    pub status: InviteStatus,
+    /// Redemptions allowed for an invite link; unlimited when unset
+    pub max_uses: Option<i32>,
+    pub use_count: i32,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+}
+
+/// Invite lifecycle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum InviteStatus {
+    Pending,
+    Accepted,
+    Declined,
+    Revoked,
+}
+
+/// Create invite request; omitting `invitee_id` creates an invite link
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateInviteRequest {
+    pub invitee_id: Option<Uuid>,
+    #[validate(range(min = 1, max = 720, message = "Invites expire after 1-720 hours"))]
+    pub expires_in_hours: Option<i64>,
+    #[validate(range(min = 1, max = 1000, message = "Max uses must be 1-1000"))]
+    pub max_uses: Option<i32>,
+}
+
+/// Invite as shown to moderators; links carry the token to share
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceInviteView {
+    #[serde(flatten)]
+    pub invite: SpaceInvite,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// Pending invite as shown to its invitee
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PendingInvite {
+    pub id: Uuid,
+    pub space_id: Uuid,
+    pub space_slug: String,
+    pub space_name: String,
+    pub inviter_id: Option<Uuid>,
+    pub inviter_display_name: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Ban or mute of an identity in a space
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct SpaceRestriction {
+    pub identity_id: Uuid,
+    pub display_name: Option<String>,
+    pub public_key_fingerprint: String,
+    pub reason: String,
+    /// Moderator who issued it
+    pub issued_by: Option<Uuid>,
+    /// `None` for permanent bans
+    pub expires_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Ban request; bans without a duration are permanent
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BanRequest {
+    pub identity_id: Uuid,
+    #[validate(length(min = 1, max = 500, message = "Reason must be 1-500 characters"))]
+    pub reason: String,
+    #[validate(range(min = 1, max = 8760, message = "Bans last 1-8760 hours"))]
+    pub duration_hours: Option<i64>,
+}
+
+/// Space mute request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SpaceMuteRequest {
+    pub identity_id: Uuid,
+    #[validate(length(min = 1, max = 500, message = "Reason must be 1-500 characters"))]
+    pub reason: String,
+    #[validate(range(min = 1, max = 8760, message = "Mutes last 1-8760 hours"))]
+    pub duration_hours: i64,
+}
+
+/// Identity allowed to post in a restricted space
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ApprovedSubmitter {
+    pub identity_id: Uuid,
+    pub display_name: Option<String>,
+    pub public_key_fingerprint: String,
+    pub added_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Approved submitter request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApproveSubmitterRequest {
+    pub identity_id: Uuid,
+}
+
 // ==================== Posts ====================
 
 /// Post in a space
@@ -193,8 +364,15 @@ pub struct Post {
     pub is_locked: bool,
     pub is_removed: bool,
     pub removed_reason: Option<String>,
+    /// Set while the content is held for moderator review. Held content is
+    /// hidden like removed content, but only until a moderator decides on it.
+    pub held_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Set when the content was last edited
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Sanitized HTML rendering of `content`
+    pub content_html: Option<String>,
 }
 
 /// Post with additional context
@@ -205,6 +383,27 @@ pub struct PostWithContext {
     pub author: Option<IdentityPublic>,
     pub space: Option<SpaceSummary>,
     pub user_vote: Option<i16>,
+    /// Present for poll posts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poll: Option<PollView>,
+    /// Present for link posts once the link has been fetched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preview: Option<LinkPreview>,
+}
+
+/// Preview of the page behind a link post
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct LinkPreview {
+    #[serde(skip)]
+    pub post_id: Uuid,
+    /// Final URL after redirects
+    pub url: String,
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    /// Preview image, stored as media
+    pub image_id: Option<Uuid>,
+    pub fetched_at: DateTime<Utc>,
 }
 
 /// Content types for posts
@@ -235,6 +434,9 @@ pub struct CreatePostRequest {
     #[validate(url(message = "Invalid URL"))]
     pub url: Option<String>,
     pub media_ids: Option<Vec<Uuid>>,
+    /// Required for poll posts; implies `content_type: poll`
+    #[validate(nested)]
+    pub poll: Option<CreatePollRequest>,
 }
 
 /// Update post request
@@ -244,6 +446,76 @@ pub struct UpdatePostRequest {
     pub content: Option<String>,
 }
 
+// ==================== Polls ====================
+
+/// Poll attached to a post
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Poll {
+    pub post_id: Uuid,
+    pub allows_multiple: bool,
+    pub closes_at: Option<DateTime<Utc>>,
+    pub total_voters: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Poll option with its running tally
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PollOption {
+    pub id: Uuid,
+    pub post_id: Uuid,
+    pub position: i16,
+    pub label: String,
+    pub vote_count: i32,
+}
+
+/// Poll as shown to a viewer; tallies are hidden until they vote or it closes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollView {
+    pub post_id: Uuid,
+    pub allows_multiple: bool,
+    pub closes_at: Option<DateTime<Utc>>,
+    pub is_closed: bool,
+    pub results_visible: bool,
+    pub total_voters: Option<i32>,
+    pub options: Vec<PollOptionView>,
+    /// Options the viewer chose, if they voted
+    pub user_choices: Option<Vec<Uuid>>,
+}
+
+/// Poll option as shown to a viewer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollOptionView {
+    pub id: Uuid,
+    pub label: String,
+    pub vote_count: Option<i32>,
+}
+
+/// Live tally pushed to poll channel subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PollTally {
+    pub post_id: Uuid,
+    pub total_voters: i32,
+    /// Option id and vote count, in display order
+    pub options: Vec<(Uuid, i32)>,
+}
+
+/// Poll definition when creating a post
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreatePollRequest {
+    #[validate(length(min = 2, max = 10, message = "Polls need 2-10 options"))]
+    pub options: Vec<String>,
+    #[serde(default)]
+    pub allows_multiple: bool,
+    pub closes_at: Option<DateTime<Utc>>,
+}
+
+/// Poll vote request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct PollVoteRequest {
+    #[validate(length(min = 1, max = 10, message = "Choose 1-10 options"))]
+    pub option_ids: Vec<Uuid>,
+}
+
 // ==================== Comments ====================
 
 /// Comment on a post
@@ -253,6 +525,8 @@ pub struct Comment {
     pub post_id: Uuid,
     pub parent_id: Option<Uuid>,
     pub author_id: Option<Uuid>,
+    /// Posted by AutoModerator in answer to a rule; such comments have no author
+    pub is_automod: bool,
     pub content: String,
     pub depth: i32,
     pub path: String,
@@ -261,8 +535,15 @@ pub struct Comment {
     pub score: i32,
     pub is_removed: bool,
     pub removed_reason: Option<String>,
+    /// Set while the content is held for moderator review. Held content is
+    /// hidden like removed content, but only until a moderator decides on it.
+    pub held_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Set when the content was last edited
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Sanitized HTML rendering of `content`
+    pub content_html: Option<String>,
 }
 
 /// Comment with context
@@ -273,6 +554,54 @@ pub struct CommentWithContext {
     pub author: Option<IdentityPublic>,
     pub user_vote: Option<i16>,
     pub replies: Vec<CommentWithContext>,
+    /// Replies left out of `replies`, if this branch was truncated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub more_replies: Option<MoreComments>,
+}
+
+/// Placeholder for comments left out of a tree response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoreComments {
+    /// Number of comments not shown at this level
+    pub count: i64,
+    /// Pass as `continuation` to load them
+    pub continuation: String,
+}
+
+/// Nested comments for a post, or for a branch continued from a placeholder
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentTree {
+    pub comments: Vec<CommentWithContext>,
+    /// Top-level comments left out of `comments`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub more: Option<MoreComments>,
+}
+
+/// Comment tree query parameters
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CommentTreeParams {
+    #[serde(default)]
+    pub sort: CommentSort,
+    /// Levels of replies to include
+    #[validate(range(min = 1, max = 10))]
+    #[serde(default = "default_tree_depth")]
+    pub max_depth: i32,
+    /// Comments to include under each parent
+    #[validate(range(min = 1, max = 100))]
+    #[serde(default = "default_tree_limit")]
+    pub limit: i64,
+    /// Token from a `more` or `more_replies` placeholder
+    #[validate(length(max = 512))]
+    #[serde(default)]
+    pub continuation: Option<String>,
+}
+
+fn default_tree_depth() -> i32 {
+    6
+}
+
+fn default_tree_limit() -> i64 {
+    20
 }
 
 /// Create comment request
@@ -290,6 +619,48 @@ pub struct UpdateCommentRequest {
     pub content: String,
 }
 
+// ==================== Revisions ====================
+
+/// Content types with edit history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum RevisionTargetType {
+    Post,
+    Comment,
+}
+
+/// Content replaced by an edit
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ContentRevision {
+    pub id: Uuid,
+    pub target_type: RevisionTargetType,
+    pub target_id: Uuid,
+    pub revision: i32,
+    pub editor_id: Option<Uuid>,
+    pub previous_content: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A single edit, as a diff from the content it replaced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionDiff {
+    pub revision: i32,
+    pub editor_id: Option<Uuid>,
+    pub edited_at: DateTime<Utc>,
+    /// Unified diff from the content before the edit to the content after it
+    pub diff: String,
+}
+
+/// Edit history of a post or comment, newest edit first
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RevisionHistory {
+    pub target_type: RevisionTargetType,
+    pub target_id: Uuid,
+    pub edited_at: Option<DateTime<Utc>>,
+    pub revisions: Vec<RevisionDiff>,
+}
+
 // ==================== Votes ====================
 
 /// Vote on a post or comment
@@ -440,6 +811,12 @@ pub enum ReportReason {
     Other,
 }
 
+impl sqlx::postgres::PgHasArrayType for ReportReason {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_varchar")
+    }
+}
+
 /// Report statuses
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "varchar", rename_all = "lowercase")]
@@ -467,6 +844,371 @@ pub struct CreateReportRequest {
     pub description: Option<String>,
 }
 
+// ==================== Moderation Log ====================
+
+/// Moderation log entry
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ModerationLogEntry {
+    pub id: Uuid,
+    /// `None` for platform-wide actions such as suspensions
+    pub space_id: Option<Uuid>,
+    /// `None` for actions taken by AutoModerator rules
+    pub actor_id: Option<Uuid>,
+    pub action: ModerationLogAction,
+    pub target_type: ModerationTargetType,
+    pub target_id: Uuid,
+    pub reason: Option<String>,
+    pub before_state: Option<serde_json::Value>,
+    pub after_state: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Moderation log entry as shown publicly
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicModerationLogEntry {
+    pub id: Uuid,
+    pub action: ModerationLogAction,
+    pub target_type: ModerationTargetType,
+    /// Omitted for actions against identities
+    pub target_id: Option<Uuid>,
+    pub reason: Option<String>,
+    /// Pseudonym of the acting moderator, stable within the space, or
+    /// `automoderator`
+    pub moderator: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Recorded moderation actions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ModerationLogAction {
+    RemovePost,
+    RemoveComment,
+    FilterPost,
+    FilterComment,
+    PinPost,
+    UnpinPost,
+    LockPost,
+    UnlockPost,
+    ApprovePost,
+    ApproveComment,
+    UpdateMember,
+    RemoveMember,
+    BanMember,
+    UnbanMember,
+    MuteMember,
+    UnmuteMember,
+    ApproveSubmitter,
+    UnapproveSubmitter,
+    SuspendIdentity,
+    UnsuspendIdentity,
+    CreateAutomodRule,
+    UpdateAutomodRule,
+    DeleteAutomodRule,
+}
+
+impl std::fmt::Display for ModerationLogAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ModerationLogAction::RemovePost => "remove_post",
+            ModerationLogAction::RemoveComment => "remove_comment",
+            ModerationLogAction::FilterPost => "filter_post",
+            ModerationLogAction::FilterComment => "filter_comment",
+            ModerationLogAction::PinPost => "pin_post",
+            ModerationLogAction::UnpinPost => "unpin_post",
+            ModerationLogAction::LockPost => "lock_post",
+            ModerationLogAction::UnlockPost => "unlock_post",
+            ModerationLogAction::ApprovePost => "approve_post",
+            ModerationLogAction::ApproveComment => "approve_comment",
+            ModerationLogAction::UpdateMember => "update_member",
+            ModerationLogAction::RemoveMember => "remove_member",
+            ModerationLogAction::BanMember => "ban_member",
+            ModerationLogAction::UnbanMember => "unban_member",
+            ModerationLogAction::MuteMember => "mute_member",
+            ModerationLogAction::UnmuteMember => "unmute_member",
+            ModerationLogAction::ApproveSubmitter => "approve_submitter",
+            ModerationLogAction::UnapproveSubmitter => "unapprove_submitter",
+            ModerationLogAction::SuspendIdentity => "suspend_identity",
+            ModerationLogAction::UnsuspendIdentity => "unsuspend_identity",
+            ModerationLogAction::CreateAutomodRule => "create_automod_rule",
+            ModerationLogAction::UpdateAutomodRule => "update_automod_rule",
+            ModerationLogAction::DeleteAutomodRule => "delete_automod_rule",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// What a moderation action was taken against
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum ModerationTargetType {
+    Post,
+    Comment,
+    Identity,
+    #[sqlx(rename = "automod_rule")]
+    #[serde(rename = "automod_rule")]
+    AutomodRule,
+}
+
+impl std::fmt::Display for ModerationTargetType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            ModerationTargetType::Post => "post",
+            ModerationTargetType::Comment => "comment",
+            ModerationTargetType::Identity => "identity",
+            ModerationTargetType::AutomodRule => "automod_rule",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Moderation log filters
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModerationLogParams {
+    pub actor_id: Option<Uuid>,
+    pub action: Option<ModerationLogAction>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    /// Platform log only
+    pub space_id: Option<Uuid>,
+}
+
+// ==================== Moderation Queue ====================
+
+/// Content awaiting review in a space's moderation queue: held for review,
+/// reported, or both
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModqueueItem {
+    pub target_type: ModerationTargetType,
+    pub target_id: Uuid,
+    pub post_id: Uuid,
+    pub author_id: Option<Uuid>,
+    /// Posts only
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub is_removed: bool,
+    pub removed_reason: Option<String>,
+    /// Set while the content is held for review
+    pub held_at: Option<DateTime<Utc>>,
+    /// Number of open reports
+    pub report_count: i64,
+    pub report_reasons: Vec<ReportReason>,
+    /// When the content was held or first reported
+    pub queued_at: DateTime<Utc>,
+}
+
+/// Moderation queue filters
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModqueueParams {
+    pub filter: Option<ModqueueFilter>,
+}
+
+/// Part of the moderation queue to list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModqueueFilter {
+    Held,
+    Reported,
+}
+
+/// Moderator decision on queued content
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ModqueueDecision {
+    /// Release held content and dismiss its reports
+    Approve,
+    /// Remove the content and mark its reports actioned
+    Reject,
+}
+
+/// Content a decision applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModqueueTarget {
+    pub target_type: ModerationTargetType,
+    pub target_id: Uuid,
+}
+
+/// Approve or reject request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Validate)]
+pub struct ModqueueDecisionRequest {
+    /// Shown to the author, and recorded as the removal reason on rejection
+    #[validate(length(max = 500, message = "Reason must be at most 500 characters"))]
+    pub reason: Option<String>,
+}
+
+/// Bulk approve or reject request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct BulkModqueueRequest {
+    pub decision: ModqueueDecision,
+    #[validate(length(min = 1, max = 100, message = "Between 1 and 100 items can be reviewed at once"))]
+    pub items: Vec<ModqueueTarget>,
+    #[validate(length(max = 500, message = "Reason must be at most 500 characters"))]
+    pub reason: Option<String>,
+}
+
+/// Content state after a decision
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModqueueResult {
+    pub target_type: ModerationTargetType,
+    pub target_id: Uuid,
+    pub is_removed: bool,
+    /// Open reports closed by the decision
+    pub reports_closed: u64,
+}
+
+// ==================== AutoModerator ====================
+
+/// Space AutoModerator rule
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AutomodRule {
+    pub id: Uuid,
+    pub space_id: Uuid,
+    pub name: String,
+    pub definition: sqlx::types::Json<AutomodDefinition>,
+    pub is_enabled: bool,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// What a rule matches and what it does. A rule matches when all of its
+/// conditions do.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AutomodDefinition {
+    #[serde(default)]
+    pub applies_to: AutomodTarget,
+    pub conditions: Vec<AutomodCondition>,
+    pub actions: Vec<AutomodAction>,
+}
+
+/// Content a rule runs against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomodTarget {
+    #[default]
+    All,
+    Posts,
+    Comments,
+}
+
+/// Text a condition is matched against
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomodField {
+    Title,
+    Body,
+    /// Title and body
+    #[default]
+    Any,
+}
+
+/// Rule condition
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomodCondition {
+    /// Text contains any of the keywords, ignoring case
+    Keywords {
+        #[serde(default)]
+        field: AutomodField,
+        keywords: Vec<String>,
+    },
+    /// Text matches the pattern, ignoring case
+    Regex {
+        #[serde(default)]
+        field: AutomodField,
+        pattern: String,
+    },
+    /// The post's link, or a link in the body, is on one of the domains or
+    /// their subdomains
+    Domain { domains: Vec<String> },
+    /// Author has less karma than this
+    KarmaBelow { karma: i32 },
+    /// Author's account is younger than this
+    AccountAgeBelow { hours: i64 },
+    /// Content has been reported at least this many times
+    ReportsAtLeast { reports: i64 },
+}
+
+/// Rule action
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AutomodAction {
+    /// Hide the content until a moderator reviews it
+    Filter,
+    Remove,
+    /// Lock the post; posts only
+    Lock,
+    /// Answer the content with an AutoModerator comment
+    Reply { message: String },
+    NotifyModerators,
+}
+
+/// Create AutoModerator rule request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct CreateAutomodRuleRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be 1-100 characters"))]
+    pub name: String,
+    pub definition: AutomodDefinition,
+    pub is_enabled: Option<bool>,
+}
+
+/// Update AutoModerator rule request
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct UpdateAutomodRuleRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be 1-100 characters"))]
+    pub name: Option<String>,
+    pub definition: Option<AutomodDefinition>,
+    pub is_enabled: Option<bool>,
+}
+
+/// Kind of content checked by AutoModerator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AutomodContentKind {
+    Post,
+    Comment,
+}
+
+/// Sample content for a dry run
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct AutomodSample {
+    pub kind: AutomodContentKind,
+    #[validate(length(max = 300, message = "Title must be at most 300 characters"))]
+    pub title: Option<String>,
+    #[validate(length(max = 40000, message = "Content must be at most 40000 characters"))]
+    pub body: Option<String>,
+    #[validate(url(message = "Invalid URL"))]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub author_karma: i32,
+    #[serde(default)]
+    pub account_age_hours: i64,
+    #[serde(default)]
+    pub report_count: i64,
+}
+
+/// Dry run request: a draft rule, or the space's enabled rules when omitted
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct AutomodTestRequest {
+    pub definition: Option<AutomodDefinition>,
+    #[validate(nested)]
+    pub sample: AutomodSample,
+}
+
+/// Combined outcome of the rules that matched some content
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutomodVerdict {
+    /// Names of the matching rules
+    pub matched_rules: Vec<String>,
+    pub remove: bool,
+    pub filter: bool,
+    pub lock: bool,
+    pub replies: Vec<String>,
+    pub notify_moderators: bool,
+}
+
 // ==================== Notifications ====================
 
 /// Notification
@@ -478,10 +1220,12 @@ pub struct Notification {
     pub payload: serde_json::Value,
     pub is_read: bool,
     pub created_at: DateTime<Utc>,
+    /// Latest activity; moves when a grouped notification is joined
+    pub updated_at: DateTime<Utc>,
 }
 
 /// Notification types
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "varchar", rename_all = "snake_case")]
 #[serde(rename_all = "snake_case")]
 pub enum NotificationType {
@@ -494,6 +1238,62 @@ pub enum NotificationType {
     SystemAlert,
 }
 
+impl sqlx::postgres::PgHasArrayType for NotificationType {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_varchar")
+    }
+}
+
+/// Notification settings for an identity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    pub disabled_types: Vec<NotificationType>,
+    pub quiet_hours: Option<QuietHours>,
+    pub mutes: Vec<NotificationMute>,
+}
+
+/// Daily window in which notifications are stored but not pushed live
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct QuietHours {
+    /// Local start time; a window may run past midnight
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    /// IANA time zone name, e.g. "Europe/Berlin"
+    #[validate(length(min = 1, max = 64))]
+    pub timezone: String,
+}
+
+/// A post, space or identity an identity no longer wants notifications about
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct NotificationMute {
+    pub target_type: MuteTargetType,
+    pub target_id: Uuid,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Kinds of notification mute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum MuteTargetType {
+    Post,
+    Space,
+    Identity,
+}
+
+/// Update notification preferences request; types not listed keep their setting
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateNotificationPreferencesRequest {
+    pub types: HashMap<NotificationType, bool>,
+}
+
+/// Mute request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MuteRequest {
+    pub target_type: MuteTargetType,
+    pub target_id: Uuid,
+}
+
 // ==================== Pagination ====================
 
 /// Pagination parameters
@@ -505,6 +1305,10 @@ pub struct PaginationParams {
     #[validate(range(min = 0))]
     #[serde(default)]
     pub offset: i64,
+    /// Opaque cursor from a previous page; takes precedence over `offset`
+    #[validate(length(max = 512))]
+    #[serde(default)]
+    pub cursor: Option<String>,
 }
 
 fn default_limit() -> i64 {
@@ -516,6 +1320,7 @@ impl Default for PaginationParams {
         Self {
             limit: 25,
             offset: 0,
+            cursor: None,
         }
     }
 }
@@ -530,19 +1335,36 @@ pub struct PaginatedResponse<T> {
 /// Pagination info
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaginationInfo {
-    pub total: i64,
+    /// Total matching items; omitted for cursor pages, which skip the count
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total: Option<i64>,
     pub limit: i64,
     pub offset: i64,
     pub has_more: bool,
+    /// Cursor for the next page, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
 }
 
 impl PaginationInfo {
     pub fn new(total: i64, limit: i64, offset: i64) -> Self {
         Self {
-            total,
+            total: Some(total),
             limit,
             offset,
             has_more: offset + limit < total,
+            next_cursor: None,
+        }
+    }
+
+    /// Pagination info for a page fetched by cursor
+    pub fn from_cursor(limit: i64, has_more: bool, next_cursor: Option<String>) -> Self {
+        Self {
+            total: None,
+            limit,
+            offset: 0,
+            has_more,
+            next_cursor,
         }
     }
 }
@@ -559,6 +1381,18 @@ pub enum PostSort {
     Top,
     Rising,
     Controversial,
+    Best,
+}
+
+/// Sort options for comments
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentSort {
+    #[default]
+    Best,
+    New,
+    Top,
+    Controversial,
 }
 
 /// Time range for sorting
@@ -585,6 +1419,90 @@ pub struct FeedParams {
     pub pagination: PaginationParams,
 }
 
+// ==================== Search ====================
+
+/// What to search
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    #[default]
+    Posts,
+    Comments,
+    Spaces,
+}
+
+/// Ordering of search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchSort {
+    /// Relevance, boosted by recency
+    #[default]
+    Relevance,
+    New,
+}
+
+/// Search query parameters
+#[derive(Debug, Clone, Serialize, Deserialize, Validate)]
+pub struct SearchParams {
+    /// Search terms; supports quoted phrases, `or` and `-term`
+    #[validate(length(min = 1, max = 256, message = "Query must be 1-256 characters"))]
+    pub q: String,
+    #[serde(default, rename = "type")]
+    pub kind: SearchKind,
+    #[serde(default)]
+    pub sort: SearchSort,
+    /// Space slug; applies to posts and comments
+    pub space: Option<String>,
+    /// Author identity; applies to posts and comments
+    pub author: Option<Uuid>,
+    #[serde(default)]
+    pub time_range: TimeRange,
+    /// Applies to posts only
+    pub content_type: Option<ContentType>,
+    #[validate(nested)]
+    #[serde(flatten)]
+    pub pagination: PaginationParams,
+}
+
+/// A single search result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum SearchHit {
+    Post(PostSearchHit),
+    Comment(CommentSearchHit),
+    Space(SpaceSearchHit),
+}
+
+/// Post matching a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostSearchHit {
+    #[serde(flatten)]
+    pub post: PostWithContext,
+    /// Matching excerpt, HTML-escaped with matches wrapped in `<mark>`
+    pub snippet: String,
+}
+
+/// Comment matching a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommentSearchHit {
+    #[serde(flatten)]
+    pub comment: Comment,
+    pub author: Option<IdentityPublic>,
+    pub post_title: String,
+    pub space: Option<SpaceSummary>,
+    /// Matching excerpt, HTML-escaped with matches wrapped in `<mark>`
+    pub snippet: String,
+}
+
+/// Space matching a search
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpaceSearchHit {
+    #[serde(flatten)]
+    pub space: SpaceSummary,
+    /// Matching excerpt, HTML-escaped with matches wrapped in `<mark>`
+    pub snippet: String,
+}
+
 // ==================== Summary Types ====================
 
 /// Space summary for embedding
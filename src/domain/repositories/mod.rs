@@ -25,6 +25,10 @@ pub trait IdentityRepository: Send + Sync {
     /// Find identity by public key
     async fn find_by_public_key(&self, public_key: &[u8]) -> ApiResult<Option<Identity>>;
 
+    /// Find the identities named by `@mentions`: an exact fingerprint, or a
+    /// display name (case-insensitive) that only one identity uses
+    async fn find_mentioned(&self, names: &[String]) -> ApiResult<Vec<Identity>>;
+
     /// Update identity
     async fn update(&self, id: Uuid, display_name: Option<&str>, bio: Option<&str>) -> ApiResult<Identity>;
 
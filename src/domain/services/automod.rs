@@ -0,0 +1,428 @@
+//! AutoModerator rules
+//!
+//! Validation and evaluation of the rules space moderators define. A rule
+//! matches when all of its conditions do, and the actions of every matching
+//! rule are combined into one verdict. Removal wins over filtering, and locks
+//! only apply to posts.
+
+use once_cell::sync::Lazy;
+use regex::{Regex, RegexBuilder};
+use url::{Host, Url};
+
+use crate::domain::entities::{
+    AutomodAction, AutomodCondition, AutomodContentKind, AutomodDefinition, AutomodField, AutomodTarget,
+    AutomodVerdict,
+};
+use crate::errors::{ApiError, ApiResult};
+
+/// Most rules a space may define
+pub const MAX_AUTOMOD_RULES: i64 = 100;
+
+/// Most conditions in one rule
+pub const MAX_RULE_CONDITIONS: usize = 10;
+
+/// Most keywords or domains in one condition
+pub const MAX_CONDITION_TERMS: usize = 100;
+
+/// Longest keyword or domain
+pub const MAX_TERM_LENGTH: usize = 100;
+
+/// Longest regex pattern
+pub const MAX_PATTERN_LENGTH: usize = 500;
+
+/// Longest reply message
+pub const MAX_REPLY_LENGTH: usize = 2000;
+
+/// Compiled size limit for patterns, which bounds the cost of matching them
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+static LINK_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"https?://[^\s)\]>]+").unwrap());
+
+/// Content and author facts rules are evaluated against
+#[derive(Debug, Clone, Copy)]
+pub struct AutomodSubject<'a> {
+    pub kind: AutomodContentKind,
+    pub title: Option<&'a str>,
+    pub body: Option<&'a str>,
+    /// Link of a link post
+    pub url: Option<&'a str>,
+    pub author_karma: i32,
+    pub account_age_hours: i64,
+    pub report_count: i64,
+}
+
+/// A rule definition with its patterns compiled, ready to evaluate
+#[derive(Debug, Clone)]
+pub struct CompiledRule {
+    pub definition: AutomodDefinition,
+    /// Compiled pattern of each condition; `None` for other conditions and
+    /// for patterns that no longer compile, which never match
+    patterns: Vec<Option<Regex>>,
+}
+
+impl CompiledRule {
+    /// Compile the patterns of a rule once, as it is loaded
+    pub fn new(definition: AutomodDefinition) -> Self {
+        let patterns = definition
+            .conditions
+            .iter()
+            .map(|condition| match condition {
+                AutomodCondition::Regex { pattern, .. } => compile_pattern(pattern).ok(),
+                _ => None,
+            })
+            .collect();
+
+        Self { definition, patterns }
+    }
+}
+
+/// Check a rule definition before it is saved
+pub fn validate_automod_rule(definition: &AutomodDefinition) -> ApiResult<()> {
+    let invalid = |message: String| Err(ApiError::InvalidInput(message));
+
+    if definition.conditions.is_empty() || definition.conditions.len() > MAX_RULE_CONDITIONS {
+        return invalid(format!("Rules need 1-{} conditions", MAX_RULE_CONDITIONS));
+    }
+    if definition.actions.is_empty() {
+        return invalid("Rules need at least one action".to_string());
+    }
+
+    for condition in &definition.conditions {
+        match condition {
+            AutomodCondition::Keywords { keywords, .. } => validate_terms(keywords)?,
+            AutomodCondition::Domain { domains } => {
+                validate_terms(domains)?;
+                if let Some(domain) = domains.iter().find(|domain| normalize_domain(domain).is_none()) {
+                    return invalid(format!("Invalid domain: {}", domain));
+                }
+            }
+            AutomodCondition::Regex { pattern, .. } => {
+                if pattern.is_empty() || pattern.chars().count() > MAX_PATTERN_LENGTH {
+                    return invalid(format!("Patterns must be 1-{} characters", MAX_PATTERN_LENGTH));
+                }
+                compile_pattern(pattern).map_err(|e| ApiError::InvalidInput(format!("Invalid pattern: {}", e)))?;
+            }
+            AutomodCondition::AccountAgeBelow { hours } if *hours < 1 => {
+                return invalid("Account age thresholds must be at least 1 hour".to_string());
+            }
+            AutomodCondition::ReportsAtLeast { reports } if *reports < 1 => {
+                return invalid("Report thresholds must be at least 1".to_string());
+            }
+            AutomodCondition::KarmaBelow { .. }
+            | AutomodCondition::AccountAgeBelow { .. }
+            | AutomodCondition::ReportsAtLeast { .. } => {}
+        }
+    }
+
+    for action in &definition.actions {
+        match action {
+            AutomodAction::Lock if definition.applies_to == AutomodTarget::Comments => {
+                return invalid("Only posts can be locked".to_string());
+            }
+            AutomodAction::Reply { message }
+                if message.trim().is_empty() || message.chars().count() > MAX_REPLY_LENGTH =>
+            {
+                return invalid(format!("Replies must be 1-{} characters", MAX_REPLY_LENGTH));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the keywords or domains of a condition
+fn validate_terms(terms: &[String]) -> ApiResult<()> {
+    if terms.is_empty() || terms.len() > MAX_CONDITION_TERMS {
+        return Err(ApiError::InvalidInput(format!(
+            "Conditions take 1-{} keywords or domains",
+            MAX_CONDITION_TERMS
+        )));
+    }
+    if terms.iter().any(|term| term.trim().is_empty() || term.chars().count() > MAX_TERM_LENGTH) {
+        return Err(ApiError::InvalidInput(format!(
+            "Keywords and domains must be 1-{} characters",
+            MAX_TERM_LENGTH
+        )));
+    }
+
+    Ok(())
+}
+
+/// Whether a rule matches the subject
+pub fn rule_matches(rule: &CompiledRule, subject: &AutomodSubject) -> bool {
+    let applies = matches!(
+        (rule.definition.applies_to, subject.kind),
+        (AutomodTarget::All, _)
+            | (AutomodTarget::Posts, AutomodContentKind::Post)
+            | (AutomodTarget::Comments, AutomodContentKind::Comment)
+    );
+
+    applies
+        && rule
+            .definition
+            .conditions
+            .iter()
+            .zip(&rule.patterns)
+            .all(|(condition, pattern)| condition_matches(condition, pattern.as_ref(), subject))
+}
+
+/// Evaluate a rule and add its actions to the verdict if it matches
+pub fn evaluate_rule(
+    verdict: &mut AutomodVerdict,
+    name: &str,
+    rule: &CompiledRule,
+    subject: &AutomodSubject,
+) -> bool {
+    if !rule_matches(rule, subject) {
+        return false;
+    }
+
+    verdict.matched_rules.push(name.to_string());
+
+    for action in &rule.definition.actions {
+        match action {
+            AutomodAction::Filter => verdict.filter = !verdict.remove,
+            AutomodAction::Remove => {
+                verdict.remove = true;
+                verdict.filter = false;
+            }
+            AutomodAction::Lock => verdict.lock |= subject.kind == AutomodContentKind::Post,
+            AutomodAction::Reply { message } => verdict.replies.push(message.clone()),
+            AutomodAction::NotifyModerators => verdict.notify_moderators = true,
+        }
+    }
+
+    true
+}
+
+/// Whether any condition of the rule depends on reports, so the rule is
+/// worth evaluating again when the content is reported
+pub fn watches_reports(definition: &AutomodDefinition) -> bool {
+    definition
+        .conditions
+        .iter()
+        .any(|condition| matches!(condition, AutomodCondition::ReportsAtLeast { .. }))
+}
+
+/// Check one condition; `pattern` is the compiled pattern of a regex condition
+fn condition_matches(condition: &AutomodCondition, pattern: Option<&Regex>, subject: &AutomodSubject) -> bool {
+    match condition {
+        AutomodCondition::Keywords { field, keywords } => field_text(*field, subject).any(|text| {
+            let text = text.to_lowercase();
+            keywords.iter().any(|keyword| text.contains(&keyword.trim().to_lowercase()))
+        }),
+        AutomodCondition::Regex { field, .. } => {
+            pattern.is_some_and(|regex| field_text(*field, subject).any(|text| regex.is_match(text)))
+        }
+        AutomodCondition::Domain { domains } => {
+            let domains: Vec<String> = domains.iter().filter_map(|domain| normalize_domain(domain)).collect();
+            link_hosts(subject).any(|host| {
+                domains.iter().any(|domain| {
+                    host == *domain || host.strip_suffix(domain.as_str()).is_some_and(|rest| rest.ends_with('.'))
+                })
+            })
+        }
+        AutomodCondition::KarmaBelow { karma } => subject.author_karma < *karma,
+        AutomodCondition::AccountAgeBelow { hours } => subject.account_age_hours < *hours,
+        AutomodCondition::ReportsAtLeast { reports } => subject.report_count >= *reports,
+    }
+}
+
+fn field_text<'a>(field: AutomodField, subject: &AutomodSubject<'a>) -> impl Iterator<Item = &'a str> {
+    let (title, body) = match field {
+        AutomodField::Title => (subject.title, None),
+        AutomodField::Body => (None, subject.body),
+        AutomodField::Any => (subject.title, subject.body),
+    };
+
+    title.into_iter().chain(body)
+}
+
+/// Lowercased hosts of the post link and the links in the body
+fn link_hosts<'a>(subject: &AutomodSubject<'a>) -> impl Iterator<Item = String> + 'a {
+    let body_links = subject
+        .body
+        .into_iter()
+        .flat_map(|body| LINK_REGEX.find_iter(body).map(|link| link.as_str()));
+
+    subject
+        .url
+        .into_iter()
+        .chain(body_links)
+        .filter_map(|link| Url::parse(link).ok()?.host_str().map(str::to_lowercase))
+}
+
+/// Lowercased domain name, or `None` if it is not one
+fn normalize_domain(domain: &str) -> Option<String> {
+    let domain = domain.trim().trim_start_matches('.').to_lowercase();
+
+    match Host::parse(&domain) {
+        Ok(Host::Domain(_)) if domain.contains('.') => Some(domain),
+        _ => None,
+    }
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(PATTERN_SIZE_LIMIT)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post<'a>(title: &'a str, body: &'a str) -> AutomodSubject<'a> {
+        AutomodSubject {
+            kind: AutomodContentKind::Post,
+            title: Some(title),
+            body: Some(body),
+            url: None,
+            author_karma: 50,
+            account_age_hours: 1000,
+            report_count: 0,
+        }
+    }
+
+    fn compiled(conditions: Vec<AutomodCondition>, actions: Vec<AutomodAction>) -> CompiledRule {
+        CompiledRule::new(rule(conditions, actions))
+    }
+
+    fn rule(conditions: Vec<AutomodCondition>, actions: Vec<AutomodAction>) -> AutomodDefinition {
+        AutomodDefinition {
+            applies_to: AutomodTarget::All,
+            conditions,
+            actions,
+        }
+    }
+
+    #[test]
+    fn test_all_conditions_must_match() {
+        let definition = compiled(
+            vec![
+                AutomodCondition::Keywords {
+                    field: AutomodField::Title,
+                    keywords: vec!["Crypto".to_string()],
+                },
+                AutomodCondition::KarmaBelow { karma: 10 },
+            ],
+            vec![AutomodAction::Filter],
+        );
+
+        let mut subject = post("Free CRYPTO giveaway", "");
+        assert!(!rule_matches(&definition, &subject));
+
+        subject.author_karma = 3;
+        assert!(rule_matches(&definition, &subject));
+
+        // The keyword is only looked for in the title
+        assert!(!rule_matches(&definition, &AutomodSubject { author_karma: 3, ..post("Hello", "crypto") }));
+    }
+
+    #[test]
+    fn test_domain_matches_subdomains_and_body_links() {
+        let definition = compiled(
+            vec![AutomodCondition::Domain {
+                domains: vec!["Spam.example".to_string()],
+            }],
+            vec![AutomodAction::Remove],
+        );
+
+        let linked = AutomodSubject {
+            url: Some("https://www.spam.example/offer"),
+            ..post("Look", "")
+        };
+        assert!(rule_matches(&definition, &linked));
+        assert!(rule_matches(&definition, &post("Look", "see (https://spam.example/x) now")));
+        assert!(!rule_matches(&definition, &post("Look", "https://notspam.example/x")));
+    }
+
+    #[test]
+    fn test_verdict_combines_matching_rules() {
+        let filter = compiled(
+            vec![AutomodCondition::AccountAgeBelow { hours: 24 }],
+            vec![AutomodAction::Filter, AutomodAction::Lock, AutomodAction::NotifyModerators],
+        );
+        let remove = compiled(
+            vec![AutomodCondition::Regex {
+                field: AutomodField::Body,
+                pattern: r"buy\s+now".to_string(),
+            }],
+            vec![AutomodAction::Remove],
+        );
+
+        let subject = AutomodSubject {
+            kind: AutomodContentKind::Comment,
+            title: None,
+            account_age_hours: 2,
+            ..post("", "BUY   NOW")
+        };
+
+        let mut verdict = AutomodVerdict::default();
+        assert!(evaluate_rule(&mut verdict, "remove", &remove, &subject));
+        assert!(evaluate_rule(&mut verdict, "new accounts", &filter, &subject));
+
+        assert_eq!(verdict.matched_rules, vec!["remove", "new accounts"]);
+        assert!(verdict.remove && !verdict.filter);
+        assert!(!verdict.lock, "comments cannot be locked");
+        assert!(verdict.notify_moderators);
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let bad_pattern = rule(
+            vec![AutomodCondition::Regex {
+                field: AutomodField::Any,
+                pattern: "(unclosed".to_string(),
+            }],
+            vec![AutomodAction::Remove],
+        );
+        assert!(validate_automod_rule(&bad_pattern).is_err());
+
+        let bad_domain = rule(
+            vec![AutomodCondition::Domain {
+                domains: vec!["https://example.com/path".to_string()],
+            }],
+            vec![AutomodAction::Remove],
+        );
+        assert!(validate_automod_rule(&bad_domain).is_err());
+
+        let mut locks_comments = rule(vec![AutomodCondition::ReportsAtLeast { reports: 3 }], vec![AutomodAction::Lock]);
+        assert!(validate_automod_rule(&locks_comments).is_ok());
+        locks_comments.applies_to = AutomodTarget::Comments;
+        assert!(validate_automod_rule(&locks_comments).is_err());
+
+        assert!(validate_automod_rule(&rule(vec![], vec![AutomodAction::Remove])).is_err());
+    }
+
+    #[test]
+    fn test_patterns_compiled_once_per_rule() {
+        let definition = compiled(
+            vec![
+                AutomodCondition::KarmaBelow { karma: 10 },
+                AutomodCondition::Regex {
+                    field: AutomodField::Title,
+                    pattern: "^free".to_string(),
+                },
+            ],
+            vec![AutomodAction::Filter],
+        );
+        assert!(definition.patterns[0].is_none());
+        assert!(definition.patterns[1].is_some());
+
+        let subject = AutomodSubject { author_karma: 3, ..post("FREE stuff", "") };
+        assert!(rule_matches(&definition, &subject));
+
+        // A stored pattern that no longer compiles never matches
+        let broken = compiled(
+            vec![AutomodCondition::Regex {
+                field: AutomodField::Any,
+                pattern: "(unclosed".to_string(),
+            }],
+            vec![AutomodAction::Remove],
+        );
+        assert!(!rule_matches(&broken, &subject));
+    }
+}
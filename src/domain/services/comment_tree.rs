@@ -0,0 +1,376 @@
+//! Comment tree assembly
+//!
+//! A subtree of comments is loaded flat and nested here. The query ranks
+//! siblings with [`sibling_order_sql`] and loads the requested page of each
+//! level, so the loaded siblings of a parent are always its best-ranked ones.
+//! Truncated levels, and branches that continue below the loaded depth, become
+//! continuation placeholders. Removed comments, and comments by identities the viewer has
+//! blocked, are only loaded when they still have live replies and are
+//! returned as content-less placeholders.
+
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+use crate::domain::entities::{Comment, CommentSort, CommentTree, CommentWithContext, MoreComments};
+use crate::domain::services::feed::{calculate_best_score, calculate_controversy_score};
+
+/// Content shown in place of a removed comment
+pub const REMOVED_COMMENT_CONTENT: &str = "[removed]";
+
+/// Content shown in place of a comment by a blocked identity
+pub const BLOCKED_COMMENT_CONTENT: &str = "[blocked]";
+
+/// A comment loaded for tree assembly
+#[derive(Debug, Clone)]
+pub struct TreeComment {
+    pub comment: Comment,
+    /// Visible direct replies, whether or not they were loaded
+    pub reply_count: i64,
+    /// Written by an identity the viewer has blocked
+    pub blocked: bool,
+}
+
+/// Position to continue a truncated level from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Continuation {
+    /// Parent of the level, `None` for top-level comments
+    pub parent_id: Option<Uuid>,
+    /// Siblings already shown
+    pub skip: usize,
+}
+
+impl Continuation {
+    /// Start of the top level
+    pub fn root() -> Self {
+        Self {
+            parent_id: None,
+            skip: 0,
+        }
+    }
+
+    fn replies_to(parent_id: Uuid) -> Self {
+        Self {
+            parent_id: Some(parent_id),
+            skip: 0,
+        }
+    }
+}
+
+/// SQL ordering of siblings for a sort, over a comment aliased `v`. Matches
+/// [`compare`] so loaded levels nest in the order they were ranked.
+pub fn sibling_order_sql(sort: CommentSort) -> &'static str {
+    match sort {
+        CommentSort::Best => {
+            "CASE WHEN v.upvotes + v.downvotes = 0 THEN 0.0 ELSE \
+             (v.upvotes::DOUBLE PRECISION / (v.upvotes + v.downvotes) + 1.96 * 1.96 / (2.0 * (v.upvotes + v.downvotes)) \
+              - 1.96 * SQRT((v.upvotes::DOUBLE PRECISION / (v.upvotes + v.downvotes) \
+                             * (1.0 - v.upvotes::DOUBLE PRECISION / (v.upvotes + v.downvotes)) \
+                             + 1.96 * 1.96 / (4.0 * (v.upvotes + v.downvotes))) / (v.upvotes + v.downvotes))) \
+             / (1.0 + 1.96 * 1.96 / (v.upvotes + v.downvotes)) END DESC, v.created_at, v.id"
+        }
+        CommentSort::Top => "v.score DESC, v.created_at, v.id",
+        CommentSort::New => "v.created_at DESC, v.id",
+        CommentSort::Controversial => {
+            "CASE WHEN v.upvotes + v.downvotes = 0 THEN 0.0 ELSE \
+             POWER((v.upvotes + v.downvotes)::DOUBLE PRECISION, \
+                   CASE WHEN v.upvotes > v.downvotes THEN v.downvotes::DOUBLE PRECISION / v.upvotes \
+                        WHEN v.downvotes > v.upvotes THEN v.upvotes::DOUBLE PRECISION / v.downvotes \
+                        ELSE 1.0 END) END DESC, v.created_at, v.id"
+        }
+    }
+}
+
+/// Nest loaded comments below `start.parent_id`.
+///
+/// The starting level is loaded from `start.skip` onwards, deeper levels from
+/// their first sibling. `level_count` is the number of visible comments at the
+/// starting level.
+/// `more` turns a continuation and the number of comments it covers into a
+/// placeholder. Author and vote context is left empty for the caller.
+pub fn build_comment_tree(
+    comments: Vec<TreeComment>,
+    start: Continuation,
+    level_count: i64,
+    sort: CommentSort,
+    limit: usize,
+    more: &mut impl FnMut(Continuation, i64) -> MoreComments,
+) -> CommentTree {
+    let mut children: HashMap<Option<Uuid>, Vec<TreeComment>> = HashMap::new();
+    for comment in comments {
+        children.entry(comment.comment.parent_id).or_default().push(comment);
+    }
+
+    let (comments, more) = assemble_level(&mut children, start, level_count, sort, limit, more);
+    CommentTree { comments, more }
+}
+
+fn assemble_level(
+    children: &mut HashMap<Option<Uuid>, Vec<TreeComment>>,
+    at: Continuation,
+    level_count: i64,
+    sort: CommentSort,
+    limit: usize,
+    more: &mut impl FnMut(Continuation, i64) -> MoreComments,
+) -> (Vec<CommentWithContext>, Option<MoreComments>) {
+    let mut level = children.remove(&at.parent_id).unwrap_or_default();
+    level.sort_by(|a, b| compare(&a.comment, &b.comment, sort));
+
+    let mut nodes = Vec::new();
+    for node in level.into_iter().take(limit) {
+        let id = node.comment.id;
+
+        // Loaded replies are the leading ranked siblings, so any that were
+        // cut continue right after them
+        let (replies, more_replies) = if node.reply_count == 0 {
+            (Vec::new(), None)
+        } else if children.contains_key(&Some(id)) {
+            assemble_level(children, Continuation::replies_to(id), node.reply_count, sort, limit, more)
+        } else {
+            (Vec::new(), Some(more(Continuation::replies_to(id), node.reply_count)))
+        };
+
+        nodes.push(CommentWithContext {
+            comment: collapse_hidden(node.comment, node.blocked),
+            author: None,
+            user_vote: None,
+            replies,
+            more_replies,
+        });
+    }
+
+    let shown = at.skip + nodes.len();
+    let remaining = level_count - shown as i64;
+    let level_more = if remaining > 0 {
+        Some(more(
+            Continuation {
+                parent_id: at.parent_id,
+                skip: shown,
+            },
+            remaining,
+        ))
+    } else {
+        None
+    };
+
+    (nodes, level_more)
+}
+
+/// Order siblings by the requested sort, oldest first among ties
+fn compare(a: &Comment, b: &Comment, sort: CommentSort) -> Ordering {
+    let primary = match sort {
+        CommentSort::Best => calculate_best_score(b.upvotes, b.downvotes)
+            .total_cmp(&calculate_best_score(a.upvotes, a.downvotes)),
+        CommentSort::Top => b.score.cmp(&a.score),
+        CommentSort::New => b.created_at.cmp(&a.created_at),
+        CommentSort::Controversial => calculate_controversy_score(b.upvotes, b.downvotes)
+            .total_cmp(&calculate_controversy_score(a.upvotes, a.downvotes)),
+    };
+
+    primary
+        .then_with(|| a.created_at.cmp(&b.created_at))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+/// Strip content and author from a removed or blocked comment kept for its
+/// replies
+fn collapse_hidden(mut comment: Comment, blocked: bool) -> Comment {
+    let placeholder = if comment.is_removed {
+        REMOVED_COMMENT_CONTENT
+    } else if blocked {
+        BLOCKED_COMMENT_CONTENT
+    } else {
+        return comment;
+    };
+
+    comment.content = placeholder.to_string();
+    comment.content_html = None;
+    comment.author_id = None;
+    comment
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, Utc};
+
+    fn comment(parent: Option<&TreeComment>, upvotes: i32, downvotes: i32, age_mins: i64) -> TreeComment {
+        let id = Uuid::new_v4();
+        let (depth, path) = match parent {
+            Some(p) => (p.comment.depth + 1, format!("{}.{}", p.comment.path, id)),
+            None => (0, id.to_string()),
+        };
+
+        TreeComment {
+            comment: Comment {
+                id,
+                post_id: Uuid::nil(),
+                parent_id: parent.map(|p| p.comment.id),
+                author_id: Some(Uuid::new_v4()),
+                is_automod: false,
+                content: "text".to_string(),
+                depth,
+                path,
+                upvotes,
+                downvotes,
+                score: upvotes - downvotes,
+                is_removed: false,
+                removed_reason: None,
+                held_at: None,
+                created_at: Utc::now() - Duration::minutes(age_mins),
+                updated_at: Utc::now(),
+                edited_at: None,
+                content_html: None,
+            },
+            reply_count: 0,
+            blocked: false,
+        }
+    }
+
+    fn placeholder(at: Continuation, count: i64) -> MoreComments {
+        MoreComments {
+            count,
+            continuation: format!("{:?}:{}:{}", at.parent_id, at.skip, count),
+        }
+    }
+
+    #[test]
+    fn test_levels_sorted_and_truncated() {
+        let low = comment(None, 1, 0, 5);
+        let high = comment(None, 50, 2, 10);
+        let mid = comment(None, 10, 1, 1);
+        let (high_id, mid_id) = (high.comment.id, mid.comment.id);
+
+        let tree = build_comment_tree(
+            vec![low, high, mid],
+            Continuation::root(),
+            3,
+            CommentSort::Top,
+            2,
+            &mut placeholder,
+        );
+
+        let ids: Vec<Uuid> = tree.comments.iter().map(|c| c.comment.id).collect();
+        assert_eq!(ids, vec![high_id, mid_id]);
+
+        let more = tree.more.unwrap();
+        assert_eq!(more.count, 1);
+        assert_eq!(more.continuation, "None:2:1");
+    }
+
+    #[test]
+    fn test_continuation_counts_from_skip() {
+        // The first two siblings were shown on an earlier page
+        let oldest = comment(None, 0, 0, 3);
+        let oldest_id = oldest.comment.id;
+
+        let tree = build_comment_tree(
+            vec![oldest],
+            Continuation { parent_id: None, skip: 2 },
+            3,
+            CommentSort::New,
+            2,
+            &mut placeholder,
+        );
+
+        assert_eq!(tree.comments.len(), 1);
+        assert_eq!(tree.comments[0].comment.id, oldest_id);
+        assert!(tree.more.is_none());
+    }
+
+    #[test]
+    fn test_unloaded_replies_become_placeholder() {
+        let mut parent = comment(None, 3, 0, 10);
+        let parent_id = parent.comment.id;
+        parent.reply_count = 2;
+
+        let tree = build_comment_tree(
+            vec![parent],
+            Continuation::root(),
+            1,
+            CommentSort::Best,
+            10,
+            &mut placeholder,
+        );
+
+        let node = &tree.comments[0];
+        assert!(node.replies.is_empty());
+        let more = node.more_replies.as_ref().unwrap();
+        assert_eq!(more.count, 2);
+        assert_eq!(more.continuation, format!("{:?}:0:2", Some(parent_id)));
+    }
+
+    #[test]
+    fn test_partially_loaded_replies_continue_after_loaded() {
+        let mut parent = comment(None, 3, 0, 10);
+        let child = comment(Some(&parent), 1, 0, 5);
+        let (parent_id, child_id) = (parent.comment.id, child.comment.id);
+        // Three visible replies, but the load bound only left the first
+        parent.reply_count = 3;
+
+        let tree = build_comment_tree(
+            vec![parent, child],
+            Continuation::root(),
+            1,
+            CommentSort::Best,
+            10,
+            &mut placeholder,
+        );
+
+        let node = &tree.comments[0];
+        assert_eq!(node.replies.len(), 1);
+        assert_eq!(node.replies[0].comment.id, child_id);
+        let more = node.more_replies.as_ref().unwrap();
+        assert_eq!(more.count, 2);
+        assert_eq!(more.continuation, format!("{:?}:1:2", Some(parent_id)));
+    }
+
+    #[test]
+    fn test_removed_comment_collapsed_with_replies() {
+        let mut parent = comment(None, 0, 0, 10);
+        parent.comment.is_removed = true;
+        parent.comment.removed_reason = Some("Deleted by author".to_string());
+        parent.reply_count = 1;
+        let child = comment(Some(&parent), 1, 0, 5);
+
+        let tree = build_comment_tree(
+            vec![child, parent],
+            Continuation::root(),
+            1,
+            CommentSort::Best,
+            10,
+            &mut placeholder,
+        );
+
+        let node = &tree.comments[0];
+        assert_eq!(node.comment.content, REMOVED_COMMENT_CONTENT);
+        assert_eq!(node.comment.content_html, None);
+        assert_eq!(node.comment.author_id, None);
+        assert_eq!(node.replies.len(), 1);
+        assert_eq!(node.replies[0].comment.content, "text");
+        assert!(node.more_replies.is_none());
+    }
+
+    #[test]
+    fn test_blocked_comment_collapsed() {
+        let mut blocked = comment(None, 5, 0, 10);
+        blocked.blocked = true;
+        blocked.reply_count = 1;
+        let child = comment(Some(&blocked), 1, 0, 5);
+
+        let tree = build_comment_tree(
+            vec![blocked, child],
+            Continuation::root(),
+            1,
+            CommentSort::Best,
+            10,
+            &mut placeholder,
+        );
+
+        let node = &tree.comments[0];
+        assert_eq!(node.comment.content, BLOCKED_COMMENT_CONTENT);
+        assert_eq!(node.comment.author_id, None);
+        assert_eq!(node.replies[0].comment.content, "text");
+    }
+}
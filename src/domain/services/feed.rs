@@ -4,6 +4,11 @@
 
 use chrono::{DateTime, Duration, Utc};
 
+/// Unix timestamp of the hot ranking epoch (2024-01-01T00:00:00Z).
+///
+/// The `hot_rank` backfill migration uses the same epoch; keep them in sync.
+pub const HOT_RANK_EPOCH: i64 = 1_704_067_200;
+
 /// Calculate hot score for a post (Reddit's hot ranking algorithm)
 ///
 /// This algorithm factors in:
@@ -12,6 +17,9 @@ use chrono::{DateTime, Duration, Utc};
 ///
 /// Formula based on Reddit's original hot ranking:
 /// hot = log10(max(|score|, 1)) * sign(score) + (timestamp / 45000)
+///
+/// The result only depends on votes and creation time, so it is stored in
+/// `posts.hot_rank` and refreshed whenever a post's votes change.
 pub fn calculate_hot_score(upvotes: i32, downvotes: i32, created_at: DateTime<Utc>) -> f64 {
     let score = upvotes - downvotes;
     let order = (score.abs().max(1) as f64).log10();
@@ -23,11 +31,7 @@ pub fn calculate_hot_score(upvotes: i32, downvotes: i32, created_at: DateTime<Ut
         0.0
     };
 
-    // Epoch: Jan 1, 2024
-    let epoch = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
-        .unwrap()
-        .with_timezone(&Utc);
-    let seconds = (created_at - epoch).num_seconds() as f64;
+    let seconds = (created_at.timestamp() - HOT_RANK_EPOCH) as f64;
 
     // The divisor determines how quickly posts decay
     // 45000 = about 12.5 hours for decay
@@ -117,14 +121,59 @@ pub fn time_range_start(range: crate::domain::entities::TimeRange) -> DateTime<U
     }
 }
 
-/// Build SQL ORDER BY clause for post sorting
+/// Only posts this many hours old are candidates for the rising sort
+pub const RISING_WINDOW_HOURS: i64 = 24;
+
+/// Votes cast within this many hours count as recent activity for rising
+pub const RISING_ACTIVITY_HOURS: i64 = 1;
+
+/// SQL for [`calculate_rising_score`] over a post `p` joined with its
+/// recent net votes as `recent.net` and the time they were counted up to as
+/// `recent.as_of`. Ages are measured from `recent.as_of` rather than `NOW()`
+/// so every page of a listing ranks posts the same way.
+pub const RISING_SCORE_SQL: &str = "((p.upvotes - p.downvotes) + 2 * COALESCE(recent.net, 0))::DOUBLE PRECISION \
+    / SQRT(GREATEST(FLOOR(EXTRACT(EPOCH FROM recent.as_of - p.created_at) / 3600), 1))";
+
+/// Earliest creation time of posts listed for a sort and time range
+pub fn sort_window_start(
+    sort: crate::domain::entities::PostSort,
+    range: crate::domain::entities::TimeRange,
+) -> DateTime<Utc> {
+    let start = time_range_start(range);
+    match sort {
+        crate::domain::entities::PostSort::Rising => start.max(Utc::now() - Duration::hours(RISING_WINDOW_HOURS)),
+        _ => start,
+    }
+}
+
+/// SQL expression for the primary sort key of a post `p`, as a double.
+///
+/// Rising depends on the `recent` join described in [`RISING_SCORE_SQL`];
+/// new has no primary key and orders purely by creation time.
+pub fn post_sort_rank_sql(sort: crate::domain::entities::PostSort) -> &'static str {
+    match sort {
+        crate::domain::entities::PostSort::Hot => "p.hot_rank",
+        crate::domain::entities::PostSort::New => "0::DOUBLE PRECISION",
+        crate::domain::entities::PostSort::Top => "p.score::DOUBLE PRECISION",
+        crate::domain::entities::PostSort::Rising => RISING_SCORE_SQL,
+        crate::domain::entities::PostSort::Controversial => "p.controversy_rank",
+        crate::domain::entities::PostSort::Best => "p.best_rank",
+    }
+}
+
+/// Build SQL ORDER BY clause for post sorting over posts aliased as `p`.
+///
+/// Hot, controversial and best read the ranks stored on each vote; rising
+/// expects a `sort_rank` column computed with [`post_sort_rank_sql`]. Every
+/// order ends in `(created_at, id)` so it is total and usable as a keyset.
 pub fn post_sort_order_by(sort: crate::domain::entities::PostSort) -> &'static str {
     match sort {
-        crate::domain::entities::PostSort::Hot => "score DESC, created_at DESC",
-        crate::domain::entities::PostSort::New => "created_at DESC",
-        crate::domain::entities::PostSort::Top => "(upvotes - downvotes) DESC, created_at DESC",
-        crate::domain::entities::PostSort::Rising => "score DESC, created_at DESC",
-        crate::domain::entities::PostSort::Controversial => "score DESC, created_at DESC",
+        crate::domain::entities::PostSort::Hot => "p.hot_rank DESC, p.created_at DESC, p.id DESC",
+        crate::domain::entities::PostSort::New => "p.created_at DESC, p.id DESC",
+        crate::domain::entities::PostSort::Top => "p.score DESC, p.created_at DESC, p.id DESC",
+        crate::domain::entities::PostSort::Rising => "sort_rank DESC, p.created_at DESC, p.id DESC",
+        crate::domain::entities::PostSort::Controversial => "p.controversy_rank DESC, p.created_at DESC, p.id DESC",
+        crate::domain::entities::PostSort::Best => "p.best_rank DESC, p.created_at DESC, p.id DESC",
     }
 }
 
@@ -185,6 +234,19 @@ mod tests {
         assert!(score_pos > score_neg);
     }
 
+    #[test]
+    fn test_hot_score_epoch() {
+        let epoch = DateTime::parse_from_rfc3339("2024-01-01T00:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(epoch.timestamp(), HOT_RANK_EPOCH);
+        assert_eq!(calculate_hot_score(0, 0, epoch), 0.0);
+        // Ten net upvotes are worth 12.5 hours of recency
+        let later = epoch + Duration::seconds(45000);
+        assert_eq!(calculate_hot_score(10, 0, epoch), calculate_hot_score(0, 0, later));
+    }
+
     #[test]
     fn test_wilson_score() {
         // 100% upvote rate with few votes should score lower than
@@ -198,6 +260,24 @@ mod tests {
         assert_eq!(score_zero, 0.0);
     }
 
+    #[test]
+    fn test_sort_window_start() {
+        use crate::domain::entities::{PostSort, TimeRange};
+
+        // Rising never looks further back than its window
+        let rising = sort_window_start(PostSort::Rising, TimeRange::All);
+        assert!(rising > Utc::now() - Duration::hours(RISING_WINDOW_HOURS) - Duration::minutes(1));
+
+        // A narrower time range still wins
+        let rising_hour = sort_window_start(PostSort::Rising, TimeRange::Hour);
+        assert!(rising_hour > rising);
+
+        assert_eq!(
+            sort_window_start(PostSort::Top, TimeRange::All),
+            time_range_start(TimeRange::All)
+        );
+    }
+
     #[test]
     fn test_controversy_score() {
         // Even split should be more controversial than lopsided
@@ -0,0 +1,406 @@
+//! Markdown rendering
+//!
+//! Post and comment bodies are rendered when they are written and the
+//! sanitized HTML is stored next to the source. On top of CommonMark with
+//! tables, strikethrough and footnotes:
+//!
+//! - `||text||` is a spoiler, paired within a paragraph, heading, list item or
+//!   table cell
+//! - `@name` links to an identity by display name or key fingerprint
+//! - `s/slug` links to a space
+//!
+//! Autolinks are not made inside code or existing links. A space can narrow
+//! the tags its content may use; disallowed tags are stripped and their text
+//! kept.
+
+use ammonia::Builder;
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag};
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+use crate::errors::{ApiError, ApiResult};
+
+/// Tags rendered content may use; a space's policy picks from these
+pub const SAFE_TAGS: &[&str] = &[
+    "p", "br", "b", "i", "em", "strong", "a", "ul", "ol", "li",
+    "blockquote", "code", "pre", "h1", "h2", "h3", "h4", "h5", "h6",
+    "hr", "table", "thead", "tbody", "tr", "th", "td", "sup", "sub",
+    "del", "s", "span",
+];
+
+/// Tags every policy keeps, so text never loses its line structure
+const REQUIRED_TAGS: &[&str] = &["p", "br"];
+
+const SPOILER_DELIMITER: &str = "||";
+const SPOILER_CLASS: &str = "spoiler";
+const MENTION_CLASS: &str = "mention";
+const SPACE_LINK_CLASS: &str = "space-link";
+
+/// Longest name matched by `@name`; fingerprints are 64 hex characters
+const MAX_MENTION_LENGTH: usize = 64;
+
+/// Space slugs are 3-50 characters
+const SPACE_SLUG_LENGTH: Range<usize> = 3..51;
+
+/// Render markdown to sanitized HTML under a space's tag policy, or the
+/// default safe tags when the space has none
+pub fn render_markdown(content: &str, allowed_tags: Option<&[String]>) -> String {
+    let events = merge_text(Parser::new_ext(content, parser_options()));
+    let events = spoilers(autolinks(events));
+
+    let mut output = String::new();
+    html::push_html(&mut output, events.into_iter());
+
+    sanitize(&output, allowed_tags)
+}
+
+/// Names mentioned with `@name` wherever it would be linked, lowercased and
+/// in order of first mention
+pub fn extract_mentions(content: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+    let (mut in_link, mut in_code) = (0usize, 0usize);
+
+    for event in merge_text(Parser::new_ext(content, parser_options())) {
+        match event {
+            Event::Start(Tag::Link(..) | Tag::Image(..)) => in_link += 1,
+            Event::End(Tag::Link(..) | Tag::Image(..)) => in_link -= 1,
+            Event::Start(Tag::CodeBlock(_)) => in_code += 1,
+            Event::End(Tag::CodeBlock(_)) => in_code -= 1,
+            Event::Text(text) if in_link == 0 && in_code == 0 => {
+                for (_, link) in find_autolinks(&text) {
+                    if let Autolink::Mention(name) = link {
+                        let name = name.to_ascii_lowercase();
+                        if !mentions.contains(&name) {
+                            mentions.push(name);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    mentions
+}
+
+/// Check a space's tag policy, returning it lowercased, deduplicated and sorted
+pub fn validate_allowed_tags(tags: &[String]) -> ApiResult<Vec<String>> {
+    let mut allowed = Vec::with_capacity(tags.len());
+
+    for tag in tags {
+        let tag = tag.trim().to_ascii_lowercase();
+        if !SAFE_TAGS.contains(&tag.as_str()) {
+            return Err(ApiError::InvalidInput(format!("Tag '{}' cannot be allowed", tag)));
+        }
+        if !allowed.contains(&tag) {
+            allowed.push(tag);
+        }
+    }
+
+    allowed.sort();
+    Ok(allowed)
+}
+
+fn parser_options() -> Options {
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_FOOTNOTES);
+    options
+}
+
+fn sanitize(html: &str, allowed_tags: Option<&[String]>) -> String {
+    let tags: HashSet<&str> = match allowed_tags {
+        Some(allowed) => SAFE_TAGS
+            .iter()
+            .copied()
+            .filter(|tag| REQUIRED_TAGS.contains(tag) || allowed.iter().any(|a| a == tag))
+            .collect(),
+        None => SAFE_TAGS.iter().copied().collect(),
+    };
+
+    Builder::default()
+        .tags(tags)
+        .allowed_classes(HashMap::from([
+            ("span", HashSet::from([SPOILER_CLASS])),
+            ("a", HashSet::from([MENTION_CLASS, SPACE_LINK_CLASS])),
+        ]))
+        .url_schemes(HashSet::from(["http", "https", "mailto"]))
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(html)
+        .to_string()
+}
+
+/// The parser splits text at characters that might start markup; join the
+/// pieces back up so delimiters and names are seen whole
+fn merge_text<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut merged: Vec<Event<'a>> = Vec::new();
+
+    for event in events {
+        match (merged.last_mut(), event) {
+            (Some(Event::Text(previous)), Event::Text(text)) => {
+                *previous = CowStr::from(format!("{}{}", previous, text));
+            }
+            (_, event) => merged.push(event),
+        }
+    }
+
+    merged
+}
+
+/// A name or slug recognized in text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Autolink<'a> {
+    Mention(&'a str),
+    Space(&'a str),
+}
+
+impl Autolink<'_> {
+    fn to_html(self) -> String {
+        // Names and slugs are restricted to [A-Za-z0-9_-], so need no escaping
+        match self {
+            Autolink::Mention(name) => {
+                format!(r#"<a href="/u/{0}" class="{1}">@{0}</a>"#, name, MENTION_CLASS)
+            }
+            Autolink::Space(slug) => {
+                format!(r#"<a href="/s/{0}" class="{1}">s/{0}</a>"#, slug, SPACE_LINK_CLASS)
+            }
+        }
+    }
+}
+
+/// Replace `@name` and `s/slug` in text outside code and links with links
+fn autolinks(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    let mut out = Vec::with_capacity(events.len());
+    let (mut in_link, mut in_code) = (0usize, 0usize);
+
+    for event in events {
+        match &event {
+            Event::Start(Tag::Link(..) | Tag::Image(..)) => in_link += 1,
+            Event::End(Tag::Link(..) | Tag::Image(..)) => in_link -= 1,
+            Event::Start(Tag::CodeBlock(_)) => in_code += 1,
+            Event::End(Tag::CodeBlock(_)) => in_code -= 1,
+            Event::Text(text) if in_link == 0 && in_code == 0 => {
+                let links = find_autolinks(text);
+                if !links.is_empty() {
+                    let mut last = 0;
+                    for (range, link) in links {
+                        if range.start > last {
+                            out.push(Event::Text(text[last..range.start].to_string().into()));
+                        }
+                        out.push(Event::Html(link.to_html().into()));
+                        last = range.end;
+                    }
+                    if last < text.len() {
+                        out.push(Event::Text(text[last..].to_string().into()));
+                    }
+                    continue;
+                }
+            }
+            _ => {}
+        }
+
+        out.push(event);
+    }
+
+    out
+}
+
+fn find_autolinks(text: &str) -> Vec<(Range<usize>, Autolink<'_>)> {
+    let mut links = Vec::new();
+    let mut previous = None;
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = previous.map_or(true, is_boundary);
+        previous = Some(c);
+
+        if !at_boundary {
+            continue;
+        }
+
+        let found = match c {
+            '@' => {
+                let name = name_at(&text[i + 1..], 1..MAX_MENTION_LENGTH + 1, true);
+                name.map(|name| (i..i + 1 + name.len(), Autolink::Mention(name)))
+            }
+            's' if text[i + 1..].starts_with('/') => {
+                let slug = name_at(&text[i + 2..], SPACE_SLUG_LENGTH, false);
+                slug.map(|slug| (i..i + 2 + slug.len(), Autolink::Space(slug)))
+            }
+            _ => None,
+        };
+
+        if let Some((range, link)) = found {
+            // Skip past the link; it ends on a name character
+            while chars.peek().is_some_and(|&(j, _)| j < range.end) {
+                previous = chars.next().map(|(_, c)| c);
+            }
+            links.push((range, link));
+        }
+    }
+
+    links
+}
+
+/// The name at the start of `text`, if it has an allowed length and is not
+/// immediately followed by more name-like text
+fn name_at(text: &str, length: Range<usize>, allow_hyphen: bool) -> Option<&str> {
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || (allow_hyphen && c == '-');
+    let end = text.find(|c: char| !is_name_char(c)).unwrap_or(text.len());
+
+    // "s/name/more" or "@name@host" is part of a path or address, and a name
+    // running on into other letters is not the name it starts with
+    match text[end..].chars().next() {
+        Some(c) if c == '/' || c == '@' || c.is_alphanumeric() => None,
+        _ => length.contains(&end).then(|| &text[..end]),
+    }
+}
+
+/// Whether an autolink may start after `c`; keeps emails, URLs and paths intact
+fn is_boundary(c: char) -> bool {
+    !(c.is_alphanumeric() || matches!(c, '_' | '-' | '/' | '@' | '.' | ':' | '&'))
+}
+
+/// Turn paired `||` delimiters into spoiler spans
+fn spoilers(events: Vec<Event<'_>>) -> Vec<Event<'_>> {
+    // Delimiters as (event index, byte offset), grouped by the block holding them
+    let mut delimiters: HashMap<usize, Vec<(usize, usize)>> = HashMap::new();
+    let mut blocks: Vec<usize> = Vec::new();
+    let mut next_block = 0;
+    let mut in_code = 0usize;
+
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            Event::Start(tag) if holds_inline_text(tag) => {
+                blocks.push(next_block);
+                next_block += 1;
+            }
+            Event::End(tag) if holds_inline_text(tag) => {
+                blocks.pop();
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code += 1,
+            Event::End(Tag::CodeBlock(_)) => in_code -= 1,
+            Event::Text(text) if in_code == 0 => {
+                if let Some(&block) = blocks.last() {
+                    let found = text.match_indices(SPOILER_DELIMITER).map(|(offset, _)| (i, offset));
+                    delimiters.entry(block).or_default().extend(found);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Pair delimiters in order within each block; an odd one out stays text
+    let mut splits: HashMap<usize, Vec<(usize, bool)>> = HashMap::new();
+    for found in delimiters.values() {
+        for pair in found.chunks_exact(2) {
+            for (&(event, offset), open) in pair.iter().zip([true, false]) {
+                splits.entry(event).or_default().push((offset, open));
+            }
+        }
+    }
+
+    if splits.is_empty() {
+        return events;
+    }
+
+    let mut out = Vec::with_capacity(events.len() + splits.len() * 2);
+    for (i, event) in events.into_iter().enumerate() {
+        match (splits.remove(&i), event) {
+            (Some(mut marks), Event::Text(text)) => {
+                marks.sort_unstable();
+                let mut last = 0;
+
+                for (offset, open) in marks {
+                    if offset > last {
+                        out.push(Event::Text(text[last..offset].to_string().into()));
+                    }
+                    let tag = if open {
+                        format!(r#"<span class="{}">"#, SPOILER_CLASS)
+                    } else {
+                        "</span>".to_string()
+                    };
+                    out.push(Event::Html(tag.into()));
+                    last = offset + SPOILER_DELIMITER.len();
+                }
+
+                if last < text.len() {
+                    out.push(Event::Text(text[last..].to_string().into()));
+                }
+            }
+            (_, event) => out.push(event),
+        }
+    }
+
+    out
+}
+
+fn holds_inline_text(tag: &Tag<'_>) -> bool {
+    matches!(tag, Tag::Paragraph | Tag::Heading(..) | Tag::TableCell | Tag::Item)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spoilers_pair_within_blocks() {
+        let html = render_markdown("a ||secret **bold**|| b\n\nodd || out", None);
+        assert!(html.contains(r#"a <span class="spoiler">secret <strong>bold</strong></span> b"#));
+        assert!(html.contains("<p>odd || out</p>"));
+
+        // Delimiters in different paragraphs do not pair
+        let html = render_markdown("one ||\n\ntwo ||", None);
+        assert!(!html.contains("spoiler"));
+    }
+
+    #[test]
+    fn test_mention_and_space_autolinks() {
+        let html = render_markdown("hi @alice_1, see s/rust_lang!", None);
+        assert!(html.contains(r#"<a href="/u/alice_1" class="mention" rel="noopener noreferrer nofollow">@alice_1</a>"#));
+        assert!(html.contains(r#"<a href="/s/rust_lang" class="space-link" rel="noopener noreferrer nofollow">s/rust_lang</a>!"#));
+    }
+
+    #[test]
+    fn test_no_autolinks_in_code_links_or_addresses() {
+        let html = render_markdown(
+            "`@alice` and [s/rust](https://example.com) mail bob@example.com or visit \
+             https://example.com/s/rust and s/ab\n\n```\n@carol s/rust\n```",
+            None,
+        );
+        assert!(!html.contains("class=\"mention\""));
+        assert!(!html.contains("class=\"space-link\""));
+    }
+
+    #[test]
+    fn test_extract_mentions_deduplicates_outside_code() {
+        let mentions = extract_mentions("@Alice and @bob, again @alice\n\n`@carol` [@dave](https://example.com) erin@example.com");
+        assert_eq!(mentions, vec!["alice", "bob"]);
+    }
+
+    #[test]
+    fn test_space_policy_strips_disallowed_tags() {
+        let policy = validate_allowed_tags(&["STRONG".to_string(), "strong".to_string()]).unwrap();
+        assert_eq!(policy, vec!["strong"]);
+
+        let html = render_markdown("# Title\n\n**bold** and [link](https://example.com)", Some(&policy));
+        assert!(!html.contains("<h1>"));
+        assert!(!html.contains("<a "));
+        assert!(html.contains("Title"));
+        assert!(html.contains("<strong>bold</strong> and link"));
+
+        assert!(validate_allowed_tags(&["script".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_raw_html_is_sanitized() {
+        let html = render_markdown(
+            "<script>alert(1)</script><span class=\"spoiler evil\" onclick=\"x()\">hi</span>",
+            None,
+        );
+        assert!(!html.contains("script"));
+        assert!(!html.contains("onclick"));
+        assert!(!html.contains("evil"));
+    }
+}
@@ -4,11 +4,31 @@
 //! between repositories and infrastructure services.
 
 pub mod auth;
+pub mod automod;
+pub mod comment_tree;
 pub mod feed;
 pub mod karma;
+pub mod markdown;
 pub mod moderation;
+pub mod notifications;
+pub mod oauth;
+pub mod permissions;
+pub mod polls;
+pub mod revisions;
+pub mod search;
+pub mod unfurl;
 
 pub use auth::*;
+pub use automod::*;
+pub use comment_tree::*;
 pub use feed::*;
 pub use karma::*;
+pub use markdown::*;
 pub use moderation::*;
+pub use notifications::*;
+pub use oauth::*;
+pub use permissions::*;
+pub use polls::*;
+pub use revisions::*;
+pub use search::*;
+pub use unfurl::*;
@@ -7,6 +7,9 @@ use once_cell::sync::Lazy;
 use regex::Regex;
 use ammonia::Builder;
 
+use super::markdown::{render_markdown, SAFE_TAGS};
+use crate::domain::entities::{ModqueueDecision, ReportStatus};
+
 /// Content moderation service
 pub struct ModerationService;
 
@@ -14,12 +17,7 @@ impl ModerationService {
     /// Sanitize HTML content (strip dangerous tags, allow safe formatting)
     pub fn sanitize_html(content: &str) -> String {
         Builder::default()
-            .tags(HashSet::from([
-                "p", "br", "b", "i", "em", "strong", "a", "ul", "ol", "li",
-                "blockquote", "code", "pre", "h1", "h2", "h3", "h4", "h5", "h6",
-                "hr", "table", "thead", "tbody", "tr", "th", "td", "sup", "sub",
-                "del", "s", "span",
-            ]))
+            .tags(SAFE_TAGS.iter().copied().collect())
             .url_schemes(HashSet::from(["http", "https", "mailto"]))
             .link_rel(Some("noopener noreferrer nofollow"))
             .clean(content)
@@ -28,18 +26,7 @@ impl ModerationService {
 
     /// Parse markdown to HTML and sanitize
     pub fn render_markdown(content: &str) -> String {
-        use pulldown_cmark::{Parser, Options, html};
-
-        let mut options = Options::empty();
-        options.insert(Options::ENABLE_STRIKETHROUGH);
-        options.insert(Options::ENABLE_TABLES);
-        options.insert(Options::ENABLE_FOOTNOTES);
-
-        let parser = Parser::new_ext(content, options);
-        let mut html_output = String::new();
-        html::push_html(&mut html_output, parser);
-
-        Self::sanitize_html(&html_output)
+        render_markdown(content, None)
     }
 
     /// Check content for spam indicators
@@ -55,7 +42,7 @@ impl ModerationService {
         }
 
         // Check for repeated characters
-        if REPEATED_CHARS_REGEX.is_match(content) {
+        if Self::has_repeated_chars(content, 5) {
             score += 0.2;
             reasons.push("Repeated characters".to_string());
         }
@@ -100,6 +87,26 @@ impl ModerationService {
         uppercase as f32 / letters.len() as f32
     }
 
+    /// Check for a run of at least `run` identical characters. The regex
+    /// crate has no backreferences, so this is not a pattern.
+    fn has_repeated_chars(content: &str, run: usize) -> bool {
+        let mut chars = content.chars();
+        let Some(mut last) = chars.next() else {
+            return false;
+        };
+        let mut count = 1;
+
+        for c in chars {
+            count = if c == last { count + 1 } else { 1 };
+            if count >= run {
+                return true;
+            }
+            last = c;
+        }
+
+        false
+    }
+
     /// Check if content contains prohibited words
     pub fn check_prohibited_content(content: &str) -> ProhibitedContentResult {
         let lower_content = content.to_lowercase();
@@ -172,6 +179,24 @@ impl ModerationService {
         }
     }
 
+    /// Resolve a moderator's decision on queued content.
+    ///
+    /// Approving releases held content but leaves other removals alone, so
+    /// content deleted by its author is never restored. Rejecting removes the
+    /// content either way.
+    pub fn review(decision: ModqueueDecision, is_held: bool, is_removed: bool) -> ReviewOutcome {
+        let (now_removed, report_status) = match decision {
+            ModqueueDecision::Approve => (is_removed && !is_held, ReportStatus::Dismissed),
+            ModqueueDecision::Reject => (true, ReportStatus::Actioned),
+        };
+
+        ReviewOutcome {
+            is_removed: now_removed,
+            report_status,
+            changed: is_held || now_removed != is_removed,
+        }
+    }
+
     /// Generate a moderation action
     pub fn create_action(
         action_type: ModerationType,
@@ -198,6 +223,17 @@ pub struct SpamCheckResult {
     pub reasons: Vec<String>,
 }
 
+/// Result of a decision on queued content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReviewOutcome {
+    pub is_removed: bool,
+    /// New status of the content's open reports
+    pub report_status: ReportStatus,
+    /// Whether the content's visibility or hold changed, which the author is
+    /// told about
+    pub changed: bool,
+}
+
 /// Prohibited content check result
 #[derive(Debug, Clone)]
 pub struct ProhibitedContentResult {
@@ -270,10 +306,6 @@ static URL_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"https?://[^\s]+").unwrap()
 });
 
-static REPEATED_CHARS_REGEX: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"(.)\1{4,}").unwrap()
-});
-
 static EMAIL_REGEX: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}").unwrap()
 });
@@ -352,6 +384,27 @@ mod tests {
         assert!(matches!(decision, RateLimitDecision::Allowed { .. }));
     }
 
+    #[test]
+    fn test_review_decisions() {
+        // Held content is released or removed for good
+        let approved = ModerationService::review(ModqueueDecision::Approve, true, true);
+        assert!(!approved.is_removed && approved.changed);
+        assert_eq!(approved.report_status, ReportStatus::Dismissed);
+
+        let rejected = ModerationService::review(ModqueueDecision::Reject, true, true);
+        assert!(rejected.is_removed && rejected.changed);
+        assert_eq!(rejected.report_status, ReportStatus::Actioned);
+
+        // Reported content stays up on approval and comes down on rejection
+        assert!(!ModerationService::review(ModqueueDecision::Approve, false, false).changed);
+        let rejected = ModerationService::review(ModqueueDecision::Reject, false, false);
+        assert!(rejected.is_removed && rejected.changed);
+
+        // Removals outside the queue are not undone
+        let approved = ModerationService::review(ModqueueDecision::Approve, false, true);
+        assert!(approved.is_removed && !approved.changed);
+    }
+
     #[test]
     fn test_markdown_rendering() {
         let md = "# Hello\n\n**bold** and *italic*\n\n- list item";
@@ -0,0 +1,81 @@
+//! Notification preferences
+//!
+//! Helpers for applying an identity's notification settings. Storage and
+//! delivery live with the notification handlers and jobs.
+
+use chrono::NaiveTime;
+use std::collections::HashMap;
+
+use crate::domain::entities::NotificationType;
+
+/// Whether `at` falls in the quiet window from `start` to `end`, which wraps
+/// past midnight when `end` is not after `start`
+pub fn in_quiet_hours(start: NaiveTime, end: NaiveTime, at: NaiveTime) -> bool {
+    if start < end {
+        start <= at && at < end
+    } else {
+        at >= start || at < end
+    }
+}
+
+/// Apply per-type toggles to a set of disabled types, keeping the result in
+/// declaration order
+pub fn apply_type_toggles(
+    disabled: &[NotificationType],
+    toggles: &HashMap<NotificationType, bool>,
+) -> Vec<NotificationType> {
+    let mut result: Vec<NotificationType> = disabled
+        .iter()
+        .copied()
+        .filter(|t| toggles.get(t) != Some(&true))
+        .collect();
+
+    for (&notification_type, &enabled) in toggles {
+        if !enabled && !result.contains(&notification_type) {
+            result.push(notification_type);
+        }
+    }
+
+    result.sort_by_key(|&t| t as u8);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_quiet_hours_within_day() {
+        assert!(in_quiet_hours(time(13, 0), time(15, 0), time(13, 0)));
+        assert!(in_quiet_hours(time(13, 0), time(15, 0), time(14, 59)));
+        assert!(!in_quiet_hours(time(13, 0), time(15, 0), time(15, 0)));
+        assert!(!in_quiet_hours(time(13, 0), time(15, 0), time(9, 0)));
+    }
+
+    #[test]
+    fn test_quiet_hours_past_midnight() {
+        assert!(in_quiet_hours(time(22, 0), time(7, 0), time(23, 30)));
+        assert!(in_quiet_hours(time(22, 0), time(7, 0), time(3, 0)));
+        assert!(!in_quiet_hours(time(22, 0), time(7, 0), time(7, 0)));
+        assert!(!in_quiet_hours(time(22, 0), time(7, 0), time(12, 0)));
+    }
+
+    #[test]
+    fn test_type_toggles_merge() {
+        let disabled = vec![NotificationType::Mention, NotificationType::PostReply];
+        let toggles = HashMap::from([
+            (NotificationType::Mention, true),
+            (NotificationType::CommentReply, false),
+            (NotificationType::PostReply, false),
+        ]);
+
+        assert_eq!(
+            apply_type_toggles(&disabled, &toggles),
+            vec![NotificationType::PostReply, NotificationType::CommentReply]
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! OAuth provider client
+//!
+//! Exchanges authorization codes with the configured GitHub/Discord
+//! providers and normalizes their user info responses.
+
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use crate::config::OAuthProvider;
+use crate::errors::ApiError;
+
+/// User agent sent to providers (GitHub rejects requests without one)
+const USER_AGENT: &str = concat!("SilentAlliance/", env!("CARGO_PKG_VERSION"));
+
+/// Timeout for requests to OAuth providers
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// OAuth user info (normalized across providers)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OAuthUserInfo {
+    pub provider: String,
+    pub subject: String,
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+/// Token endpoint response
+///
+/// GitHub answers errors with `200 OK` and an `error` field, so both shapes
+/// are accepted here.
+#[derive(Debug, Deserialize)]
+struct ProviderTokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+/// GitHub `/user` response
+#[derive(Debug, Deserialize)]
+struct GitHubUser {
+    id: i64,
+    login: String,
+    name: Option<String>,
+    email: Option<String>,
+    avatar_url: Option<String>,
+}
+
+/// Discord `/users/@me` response
+#[derive(Debug, Deserialize)]
+struct DiscordUser {
+    id: String,
+    username: String,
+    global_name: Option<String>,
+    email: Option<String>,
+    avatar: Option<String>,
+}
+
+/// Client for talking to OAuth providers
+#[derive(Clone)]
+pub struct OAuthClient {
+    http: reqwest::Client,
+}
+
+impl OAuthClient {
+    /// Create a new OAuth client
+    pub fn new() -> Result<Self, ApiError> {
+        let http = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(PROVIDER_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+
+        Ok(Self { http })
+    }
+
+    /// Exchange an authorization code for a provider access token
+    pub async fn exchange_code(
+        &self,
+        config: &OAuthProvider,
+        code: &str,
+        code_verifier: &str,
+        redirect_uri: &str,
+    ) -> Result<String, ApiError> {
+        let params = [
+            ("grant_type", "authorization_code"),
+            ("client_id", config.client_id.as_str()),
+            ("client_secret", config.client_secret.as_str()),
+            ("code", code),
+            ("code_verifier", code_verifier),
+            ("redirect_uri", redirect_uri),
+        ];
+
+        let response = self
+            .http
+            .post(&config.token_url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .form(&params)
+            .send()
+            .await?;
+
+        let status = response.status();
+        let body: ProviderTokenResponse = response.json().await.map_err(|e| {
+            ApiError::OAuthProviderError(format!("Malformed token response: {}", e))
+        })?;
+
+        if let Some(error) = body.error {
+            warn!(status = %status, error = %error, "OAuth provider rejected code exchange");
+            return Err(ApiError::OAuthProviderError(
+                body.error_description.unwrap_or(error),
+            ));
+        }
+
+        if !status.is_success() {
+            return Err(ApiError::OAuthProviderError(format!(
+                "Token endpoint returned {}",
+                status
+            )));
+        }
+
+        body.access_token
+            .ok_or_else(|| ApiError::OAuthProviderError("Missing access token".to_string()))
+    }
+
+    /// Fetch and normalize the user info for a provider access token
+    pub async fn fetch_user_info(
+        &self,
+        provider: &str,
+        config: &OAuthProvider,
+        access_token: &str,
+    ) -> Result<OAuthUserInfo, ApiError> {
+        let response = self
+            .http
+            .get(&config.userinfo_url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .bearer_auth(access_token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(ApiError::OAuthProviderError(format!(
+                "User info endpoint returned {}",
+                response.status()
+            )));
+        }
+
+        let malformed =
+            |e: reqwest::Error| ApiError::OAuthProviderError(format!("Malformed user info: {}", e));
+
+        let info = match provider {
+            "github" => {
+                let user: GitHubUser = response.json().await.map_err(malformed)?;
+                OAuthUserInfo {
+                    provider: provider.to_string(),
+                    subject: user.id.to_string(),
+                    email: user.email,
+                    name: user.name.or(Some(user.login)),
+                    avatar_url: user.avatar_url,
+                }
+            }
+            "discord" => {
+                let user: DiscordUser = response.json().await.map_err(malformed)?;
+                let avatar_url = user.avatar.as_ref().map(|hash| {
+                    format!("https://cdn.discordapp.com/avatars/{}/{}.png", user.id, hash)
+                });
+                OAuthUserInfo {
+                    provider: provider.to_string(),
+                    subject: user.id,
+                    email: user.email,
+                    name: user.global_name.or(Some(user.username)),
+                    avatar_url,
+                }
+            }
+            _ => {
+                return Err(ApiError::InvalidInput(format!(
+                    "Unknown OAuth provider: {}",
+                    provider
+                )))
+            }
+        };
+
+        debug!(provider = %info.provider, subject = %info.subject, "Fetched OAuth user info");
+
+        Ok(info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_string_contains, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn provider_for(server: &MockServer) -> OAuthProvider {
+        OAuthProvider {
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+            redirect_uri: "http://localhost/callback".to_string(),
+            auth_url: format!("{}/authorize", server.uri()),
+            token_url: format!("{}/token", server.uri()),
+            userinfo_url: format!("{}/user", server.uri()),
+            scopes: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_github_exchange_and_user_info() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .and(body_string_contains("code=abc"))
+            .and(body_string_contains("code_verifier=verifier"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "access_token": "gho_token",
+                "token_type": "bearer"
+            })))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .and(header("authorization", "Bearer gho_token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": 42,
+                "login": "octocat",
+                "name": null,
+                "avatar_url": "https://avatars.example/42"
+            })))
+            .mount(&server)
+            .await;
+
+        let config = provider_for(&server);
+        let client = OAuthClient::new().unwrap();
+
+        let token = client
+            .exchange_code(&config, "abc", "verifier", &config.redirect_uri)
+            .await
+            .unwrap();
+        assert_eq!(token, "gho_token");
+
+        let info = client.fetch_user_info("github", &config, &token).await.unwrap();
+        assert_eq!(info.subject, "42");
+        assert_eq!(info.name.as_deref(), Some("octocat"));
+        assert_eq!(info.avatar_url.as_deref(), Some("https://avatars.example/42"));
+    }
+
+    #[tokio::test]
+    async fn test_discord_user_info() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/user"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "80351110224678912",
+                "username": "nelly",
+                "global_name": "Nelly",
+                "avatar": "8342729096ea3675442027381ff50dfe"
+            })))
+            .mount(&server)
+            .await;
+
+        let config = provider_for(&server);
+        let info = OAuthClient::new()
+            .unwrap()
+            .fetch_user_info("discord", &config, "token")
+            .await
+            .unwrap();
+
+        assert_eq!(info.subject, "80351110224678912");
+        assert_eq!(info.name.as_deref(), Some("Nelly"));
+        assert!(info.avatar_url.unwrap().ends_with("/8342729096ea3675442027381ff50dfe.png"));
+    }
+
+    #[tokio::test]
+    async fn test_exchange_error_is_surfaced() {
+        let server = MockServer::start().await;
+
+        // GitHub reports bad codes with a 200 and an error body
+        Mock::given(method("POST"))
+            .and(path("/token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "error": "bad_verification_code",
+                "error_description": "The code passed is incorrect or expired."
+            })))
+            .mount(&server)
+            .await;
+
+        let config = provider_for(&server);
+        let result = OAuthClient::new()
+            .unwrap()
+            .exchange_code(&config, "bad", "verifier", &config.redirect_uri)
+            .await;
+
+        assert!(matches!(result, Err(ApiError::OAuthProviderError(_))));
+    }
+}
@@ -0,0 +1,127 @@
+//! Space permissions
+//!
+//! Space admins, the space creator and platform admins hold every permission
+//! in a space. Moderators hold only the permissions granted to them, and plain
+//! members hold none. Acting on other members follows the same order:
+//! moderators may manage members, admins may manage moderators, and only
+//! owners may manage admins.
+
+use serde::Serialize;
+
+use crate::domain::entities::{MemberRole, SpacePermission};
+
+/// Permissions given to a moderator appointed without an explicit set
+pub const DEFAULT_MODERATOR_PERMISSIONS: &[SpacePermission] = &[
+    SpacePermission::ManagePosts,
+    SpacePermission::ManageMembers,
+    SpacePermission::ViewReports,
+];
+
+/// What an identity may do in a space
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpaceAccess {
+    /// Membership role, `None` for non-members
+    pub role: Option<MemberRole>,
+    /// Space creator or platform admin
+    pub is_owner: bool,
+    pub permissions: Vec<SpacePermission>,
+}
+
+impl SpaceAccess {
+    /// Resolve the effective permissions of a role and its granted set
+    pub fn resolve(role: Option<MemberRole>, granted: &[SpacePermission], is_owner: bool) -> Self {
+        let permissions = match role {
+            _ if is_owner => SpacePermission::ALL.to_vec(),
+            Some(MemberRole::Admin) => SpacePermission::ALL.to_vec(),
+            Some(MemberRole::Moderator) => SpacePermission::ALL
+                .into_iter()
+                .filter(|permission| granted.contains(permission))
+                .collect(),
+            Some(MemberRole::Member) | None => Vec::new(),
+        };
+
+        Self {
+            role,
+            is_owner,
+            permissions,
+        }
+    }
+
+    /// Whether the permission is held
+    pub fn has(&self, permission: SpacePermission) -> bool {
+        self.permissions.contains(&permission)
+    }
+
+    /// Space admin, creator or platform admin
+    pub fn is_admin(&self) -> bool {
+        self.is_owner || self.role == Some(MemberRole::Admin)
+    }
+
+    /// Whether a member holding `role` may be removed, or given or stripped of
+    /// that role, by this identity
+    pub fn can_manage(&self, role: MemberRole) -> bool {
+        match role {
+            MemberRole::Member => self.has(SpacePermission::ManageMembers),
+            MemberRole::Moderator => self.is_admin(),
+            MemberRole::Admin => self.is_owner,
+        }
+    }
+
+    /// Whether this identity may ban or mute one with `target` access. Owners
+    /// cannot be restricted; everyone else by rank, non-members as members.
+    pub fn can_restrict(&self, target: &SpaceAccess) -> bool {
+        !target.is_owner && self.can_manage(target.role.unwrap_or(MemberRole::Member))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moderator_holds_only_granted_permissions() {
+        let access = SpaceAccess::resolve(Some(MemberRole::Moderator), &[SpacePermission::ViewReports], false);
+
+        assert!(access.has(SpacePermission::ViewReports));
+        assert!(!access.has(SpacePermission::ManagePosts));
+        assert!(!access.can_manage(MemberRole::Member));
+    }
+
+    #[test]
+    fn test_members_hold_nothing() {
+        // Grants left over from a former moderator role do not apply
+        let access = SpaceAccess::resolve(Some(MemberRole::Member), &SpacePermission::ALL, false);
+        assert!(access.permissions.is_empty());
+
+        let outsider = SpaceAccess::resolve(None, &[], false);
+        assert!(outsider.permissions.is_empty());
+        assert!(!outsider.is_admin());
+    }
+
+    #[test]
+    fn test_management_follows_rank() {
+        let admin = SpaceAccess::resolve(Some(MemberRole::Admin), &[], false);
+        assert_eq!(admin.permissions, SpacePermission::ALL.to_vec());
+        assert!(admin.can_manage(MemberRole::Moderator));
+        assert!(!admin.can_manage(MemberRole::Admin));
+
+        let owner = SpaceAccess::resolve(None, &[], true);
+        assert!(owner.has(SpacePermission::ManageSettings));
+        assert!(owner.can_manage(MemberRole::Admin));
+    }
+
+    #[test]
+    fn test_moderator_cannot_restrict_creator() {
+        let moderator = SpaceAccess::resolve(Some(MemberRole::Moderator), &SpacePermission::ALL, false);
+        let outsider = SpaceAccess::resolve(None, &[], false);
+        assert!(moderator.can_restrict(&outsider));
+
+        // The creator need not hold a membership role at all
+        let creator = SpaceAccess::resolve(None, &[], true);
+        assert!(!moderator.can_restrict(&creator));
+
+        let admin = SpaceAccess::resolve(Some(MemberRole::Admin), &[], false);
+        assert!(!admin.can_restrict(&creator));
+        assert!(creator.can_restrict(&admin));
+    }
+}
@@ -0,0 +1,222 @@
+//! Poll rules
+//!
+//! Validation of poll definitions and ballots, and the per-viewer view of a
+//! poll. Tallies are only revealed to identities that have voted, or to
+//! everyone once the poll has closed.
+
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+use crate::domain::entities::{CreatePollRequest, Poll, PollOption, PollOptionView, PollTally, PollView};
+use crate::errors::{ApiError, ApiResult};
+
+/// Longest allowed option label
+pub const MAX_POLL_OPTION_LENGTH: usize = 200;
+
+/// Furthest ahead a poll may close
+pub const MAX_POLL_DURATION_DAYS: i64 = 90;
+
+/// Check a poll definition and return its trimmed option labels
+pub fn validate_poll(request: &CreatePollRequest, now: DateTime<Utc>) -> ApiResult<Vec<String>> {
+    let mut seen = HashSet::new();
+    let mut labels = Vec::with_capacity(request.options.len());
+
+    for option in &request.options {
+        let label = option.trim();
+
+        if label.is_empty() || label.chars().count() > MAX_POLL_OPTION_LENGTH {
+            return Err(ApiError::InvalidInput(format!(
+                "Poll options must be 1-{} characters",
+                MAX_POLL_OPTION_LENGTH
+            )));
+        }
+
+        if !seen.insert(label.to_lowercase()) {
+            return Err(ApiError::InvalidInput("Poll options must be unique".to_string()));
+        }
+
+        labels.push(label.to_string());
+    }
+
+    if let Some(closes_at) = request.closes_at {
+        if closes_at <= now {
+            return Err(ApiError::InvalidInput("Poll close time must be in the future".to_string()));
+        }
+        if closes_at > now + Duration::days(MAX_POLL_DURATION_DAYS) {
+            return Err(ApiError::InvalidInput(format!(
+                "Polls can run for at most {} days",
+                MAX_POLL_DURATION_DAYS
+            )));
+        }
+    }
+
+    Ok(labels)
+}
+
+/// Whether voting has ended
+pub fn is_poll_closed(poll: &Poll, now: DateTime<Utc>) -> bool {
+    poll.closes_at.is_some_and(|closes_at| closes_at <= now)
+}
+
+/// Check a ballot against the poll and return the chosen options, deduplicated
+pub fn validate_ballot(
+    poll: &Poll,
+    options: &[PollOption],
+    choices: &[Uuid],
+    now: DateTime<Utc>,
+) -> ApiResult<Vec<Uuid>> {
+    if is_poll_closed(poll, now) {
+        return Err(ApiError::OperationNotAllowed("Poll is closed".to_string()));
+    }
+
+    let mut chosen = Vec::with_capacity(choices.len());
+    for id in choices {
+        if !options.iter().any(|option| option.id == *id) {
+            return Err(ApiError::InvalidInput("Unknown poll option".to_string()));
+        }
+        if !chosen.contains(id) {
+            chosen.push(*id);
+        }
+    }
+
+    if chosen.is_empty() {
+        return Err(ApiError::InvalidInput("Choose at least one option".to_string()));
+    }
+
+    if chosen.len() > 1 && !poll.allows_multiple {
+        return Err(ApiError::InvalidInput("This poll allows a single choice".to_string()));
+    }
+
+    Ok(chosen)
+}
+
+/// The poll as seen by a viewer who chose `user_choices`, if they voted
+pub fn poll_view(
+    poll: &Poll,
+    mut options: Vec<PollOption>,
+    user_choices: Option<Vec<Uuid>>,
+    now: DateTime<Utc>,
+) -> PollView {
+    let is_closed = is_poll_closed(poll, now);
+    let results_visible = is_closed || user_choices.is_some();
+
+    options.sort_by_key(|option| option.position);
+
+    PollView {
+        post_id: poll.post_id,
+        allows_multiple: poll.allows_multiple,
+        closes_at: poll.closes_at,
+        is_closed,
+        results_visible,
+        total_voters: results_visible.then_some(poll.total_voters),
+        options: options
+            .into_iter()
+            .map(|option| PollOptionView {
+                id: option.id,
+                label: option.label,
+                vote_count: results_visible.then_some(option.vote_count),
+            })
+            .collect(),
+        user_choices,
+    }
+}
+
+/// Current tallies for the live poll channel
+pub fn poll_tally(poll: &Poll, options: &[PollOption]) -> PollTally {
+    let mut options: Vec<&PollOption> = options.iter().collect();
+    options.sort_by_key(|option| option.position);
+
+    PollTally {
+        post_id: poll.post_id,
+        total_voters: poll.total_voters,
+        options: options.iter().map(|option| (option.id, option.vote_count)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poll(allows_multiple: bool, closes_at: Option<DateTime<Utc>>) -> (Poll, Vec<PollOption>) {
+        let post_id = Uuid::new_v4();
+        let poll = Poll {
+            post_id,
+            allows_multiple,
+            closes_at,
+            total_voters: 3,
+            created_at: Utc::now(),
+        };
+        let options = (0..3)
+            .map(|i| PollOption {
+                id: Uuid::new_v4(),
+                post_id,
+                position: 2 - i,
+                label: format!("option {}", i),
+                vote_count: i as i32,
+            })
+            .collect();
+        (poll, options)
+    }
+
+    fn request(options: &[&str], closes_at: Option<DateTime<Utc>>) -> CreatePollRequest {
+        CreatePollRequest {
+            options: options.iter().map(|o| o.to_string()).collect(),
+            allows_multiple: false,
+            closes_at,
+        }
+    }
+
+    #[test]
+    fn test_validate_poll() {
+        let now = Utc::now();
+
+        let labels = validate_poll(&request(&[" Yes ", "No"], None), now).unwrap();
+        assert_eq!(labels, vec!["Yes", "No"]);
+
+        assert!(validate_poll(&request(&["Yes", "yes"], None), now).is_err());
+        assert!(validate_poll(&request(&["Yes", "  "], None), now).is_err());
+        assert!(validate_poll(&request(&["Yes", "No"], Some(now - Duration::minutes(1))), now).is_err());
+        assert!(validate_poll(&request(&["Yes", "No"], Some(now + Duration::days(365))), now).is_err());
+    }
+
+    #[test]
+    fn test_validate_ballot() {
+        let now = Utc::now();
+        let (single, options) = poll(false, None);
+        let (a, b) = (options[0].id, options[1].id);
+
+        assert_eq!(validate_ballot(&single, &options, &[a, a], now).unwrap(), vec![a]);
+        assert!(validate_ballot(&single, &options, &[a, b], now).is_err());
+        assert!(validate_ballot(&single, &options, &[Uuid::new_v4()], now).is_err());
+        assert!(validate_ballot(&single, &options, &[], now).is_err());
+
+        let (multi, options) = poll(true, None);
+        let ids: Vec<Uuid> = options.iter().map(|o| o.id).collect();
+        assert_eq!(validate_ballot(&multi, &options, &ids, now).unwrap(), ids);
+
+        let (closed, options) = poll(false, Some(now - Duration::hours(1)));
+        assert!(validate_ballot(&closed, &options, &[options[0].id], now).is_err());
+    }
+
+    #[test]
+    fn test_results_hidden_until_voted_or_closed() {
+        let now = Utc::now();
+        let (open, options) = poll(false, Some(now + Duration::days(1)));
+
+        let view = poll_view(&open, options.clone(), None, now);
+        assert!(!view.results_visible);
+        assert_eq!(view.total_voters, None);
+        assert!(view.options.iter().all(|o| o.vote_count.is_none()));
+        // Options come back in display order
+        assert_eq!(view.options[0].label, "option 2");
+
+        let view = poll_view(&open, options.clone(), Some(vec![options[0].id]), now);
+        assert!(view.results_visible);
+        assert_eq!(view.total_voters, Some(3));
+
+        let view = poll_view(&open, options, None, now + Duration::days(2));
+        assert!(view.is_closed);
+        assert!(view.results_visible);
+    }
+}
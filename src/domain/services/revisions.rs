@@ -0,0 +1,251 @@
+//! Edit history diffs
+//!
+//! Each edit stores the content it replaced; diffs are computed when the
+//! history is read. Lines between the common prefix and suffix are compared
+//! with a longest-common-subsequence table, and changed regions too large for
+//! that are shown as a wholesale replacement.
+
+use crate::domain::entities::{ContentRevision, RevisionDiff};
+
+/// Unchanged lines shown around each change
+const CONTEXT_LINES: usize = 3;
+
+/// Largest changed region (old lines × new lines) compared line by line
+const MAX_LCS_CELLS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// Unified diff from `old` to `new`; empty when they have the same lines
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, _))| *op != Op::Equal)
+        .map(|(i, _)| i)
+        .collect();
+
+    let Some((&first, rest)) = changes.split_first() else {
+        return String::new();
+    };
+
+    // Changes close enough to share context go in the same hunk
+    let mut hunks = Vec::new();
+    let (mut start, mut last) = (first, first);
+    for &change in rest {
+        if change - last - 1 > 2 * CONTEXT_LINES {
+            hunks.push((start, last));
+            start = change;
+        }
+        last = change;
+    }
+    hunks.push((start, last));
+
+    // Old and new lines consumed before each op
+    let mut old_pos = Vec::with_capacity(ops.len() + 1);
+    let mut new_pos = Vec::with_capacity(ops.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for (op, _) in &ops {
+        old_pos.push(o);
+        new_pos.push(n);
+        match op {
+            Op::Equal => {
+                o += 1;
+                n += 1;
+            }
+            Op::Delete => o += 1,
+            Op::Insert => n += 1,
+        }
+    }
+    old_pos.push(o);
+    new_pos.push(n);
+
+    let mut out = format!("--- {}\n+++ {}\n", old_label, new_label);
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT_LINES);
+        let end = (last + CONTEXT_LINES + 1).min(ops.len());
+        let old_len = old_pos[end] - old_pos[start];
+        let new_len = new_pos[end] - new_pos[start];
+
+        // Empty ranges are numbered by the line they follow
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_pos[start] + usize::from(old_len > 0),
+            old_len,
+            new_pos[start] + usize::from(new_len > 0),
+            new_len
+        ));
+
+        for (op, line) in &ops[start..end] {
+            out.push(match op {
+                Op::Equal => ' ',
+                Op::Delete => '-',
+                Op::Insert => '+',
+            });
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// Diffs for each revision, newest first, given the content as it is now
+pub fn revision_diffs(mut revisions: Vec<ContentRevision>, current: Option<&str>) -> Vec<RevisionDiff> {
+    revisions.sort_by_key(|revision| revision.revision);
+
+    let mut diffs: Vec<RevisionDiff> = revisions
+        .iter()
+        .enumerate()
+        .map(|(i, revision)| {
+            let before = revision.previous_content.as_deref().unwrap_or("");
+            let after = match revisions.get(i + 1) {
+                Some(next) => next.previous_content.as_deref(),
+                None => current,
+            }
+            .unwrap_or("");
+
+            RevisionDiff {
+                revision: revision.revision,
+                editor_id: revision.editor_id,
+                edited_at: revision.created_at,
+                diff: unified_diff(
+                    before,
+                    after,
+                    &format!("revision {}", revision.revision - 1),
+                    &format!("revision {}", revision.revision),
+                ),
+            }
+        })
+        .collect();
+
+    diffs.reverse();
+    diffs
+}
+
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Op, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops = Vec::with_capacity(old.len() + new.len());
+    ops.extend(old[..prefix].iter().map(|line| (Op::Equal, *line)));
+
+    if old_mid.len().saturating_mul(new_mid.len()) <= MAX_LCS_CELLS {
+        lcs_diff(old_mid, new_mid, &mut ops);
+    } else {
+        ops.extend(old_mid.iter().map(|line| (Op::Delete, *line)));
+        ops.extend(new_mid.iter().map(|line| (Op::Insert, *line)));
+    }
+
+    ops.extend(old[old.len() - suffix..].iter().map(|line| (Op::Equal, *line)));
+    ops
+}
+
+fn lcs_diff<'a>(old: &[&'a str], new: &[&'a str], ops: &mut Vec<(Op, &'a str)>) {
+    let (n, m) = (old.len(), new.len());
+    let width = m + 1;
+
+    // lengths[i * width + j] is the LCS length of old[i..] and new[j..]
+    let mut lengths = vec![0u32; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i * width + j] = if old[i] == new[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if old[i] == new[j] {
+            ops.push((Op::Equal, old[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            ops.push((Op::Delete, old[i]));
+            i += 1;
+        } else {
+            ops.push((Op::Insert, new[j]));
+            j += 1;
+        }
+    }
+
+    ops.extend(old[i..].iter().map(|line| (Op::Delete, *line)));
+    ops.extend(new[j..].iter().map(|line| (Op::Insert, *line)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::entities::RevisionTargetType;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_single_line_change() {
+        let diff = unified_diff("a\nb\nc", "a\nB\nc", "old", "new");
+        assert_eq!(diff, "--- old\n+++ new\n@@ -1,3 +1,3 @@\n a\n-b\n+B\n c\n");
+    }
+
+    #[test]
+    fn test_identical_content_has_no_diff() {
+        assert_eq!(unified_diff("same\ntext", "same\ntext", "old", "new"), "");
+    }
+
+    #[test]
+    fn test_insert_into_empty() {
+        let diff = unified_diff("", "hello", "old", "new");
+        assert_eq!(diff, "--- old\n+++ new\n@@ -0,0 +1,1 @@\n+hello\n");
+    }
+
+    #[test]
+    fn test_distant_changes_split_into_hunks() {
+        let old: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "two".to_string();
+        new[18] = "nineteen".to_string();
+
+        let diff = unified_diff(&old.join("\n"), &new.join("\n"), "old", "new");
+        let headers: Vec<&str> = diff.lines().filter(|line| line.starts_with("@@")).collect();
+        assert_eq!(headers, vec!["@@ -1,5 +1,5 @@", "@@ -16,5 +16,5 @@"]);
+    }
+
+    #[test]
+    fn test_revision_diffs_chain_to_current() {
+        let revision = |n: i32, previous: &str| ContentRevision {
+            id: Uuid::new_v4(),
+            target_type: RevisionTargetType::Post,
+            target_id: Uuid::nil(),
+            revision: n,
+            editor_id: None,
+            previous_content: Some(previous.to_string()),
+            created_at: Utc::now(),
+        };
+
+        let diffs = revision_diffs(vec![revision(2, "second"), revision(1, "first")], Some("third"));
+
+        assert_eq!(diffs.len(), 2);
+        assert_eq!(diffs[0].revision, 2);
+        assert!(diffs[0].diff.contains("-second\n+third\n"));
+        assert!(diffs[1].diff.contains("--- revision 0\n+++ revision 1\n"));
+        assert!(diffs[1].diff.contains("-first\n+second\n"));
+    }
+}
@@ -0,0 +1,109 @@
+//! Full-text search ranking and snippets
+//!
+//! Searches run against the generated `search_vector` columns. Relevance is
+//! `ts_rank_cd` on a log scale plus a recency term anchored at a fixed epoch,
+//! so a result's rank does not change between requests and can be used as a
+//! keyset.
+
+use crate::domain::entities::SearchSort;
+use crate::domain::services::feed::HOT_RANK_EPOCH;
+
+/// Text search configuration used by the search vectors
+pub const SEARCH_CONFIG: &str = "english";
+
+/// Marks the start of a match in raw headlines
+const MATCH_START: char = '\u{2}';
+/// Marks the end of a match in raw headlines
+const MATCH_END: char = '\u{3}';
+
+/// Age difference worth one unit of log relevance (180 days)
+const RECENCY_SCALE_SECS: i64 = 180 * 24 * 3600;
+
+/// SQL for a headline over `text`, where `query` is the tsquery expression.
+///
+/// Matches are delimited by the marker characters, which are stripped from
+/// the source first so user content cannot forge highlights.
+pub fn headline_sql(text: &str, query: &str) -> String {
+    format!(
+        "ts_headline('{config}', translate({text}, chr({start}) || chr({end}), ''), {query}, \
+         'StartSel=' || chr({start}) || ', StopSel=' || chr({end}) || ', MaxFragments=2, MaxWords=30, MinWords=10')",
+        config = SEARCH_CONFIG,
+        text = text,
+        query = query,
+        start = MATCH_START as u32,
+        end = MATCH_END as u32,
+    )
+}
+
+/// SQL rank for a result, higher first.
+///
+/// `vector` is the search vector column, `query` the tsquery expression and
+/// `created_at` the timestamp column used for recency. Only inputs that never
+/// change after creation feed the rank, so spaces are not boosted by their
+/// live subscriber count.
+pub fn search_rank_sql(sort: SearchSort, vector: &str, query: &str, created_at: &str) -> String {
+    match sort {
+        SearchSort::Relevance => format!(
+            "(LN(1 + 100 * ts_rank_cd({vector}, {query})) \
+             + (EXTRACT(EPOCH FROM {created_at}) - {epoch}) / {scale})::DOUBLE PRECISION",
+            vector = vector,
+            query = query,
+            created_at = created_at,
+            epoch = HOT_RANK_EPOCH,
+            scale = RECENCY_SCALE_SECS,
+        ),
+        // Ordering falls through to created_at, id
+        SearchSort::New => "0::DOUBLE PRECISION".to_string(),
+    }
+}
+
+/// Turn a raw headline into an HTML-escaped snippet with `<mark>` highlights
+pub fn render_snippet(headline: &str) -> String {
+    let mut snippet = String::with_capacity(headline.len() + 16);
+
+    for c in headline.chars() {
+        match c {
+            MATCH_START => snippet.push_str("<mark>"),
+            MATCH_END => snippet.push_str("</mark>"),
+            '&' => snippet.push_str("&amp;"),
+            '<' => snippet.push_str("&lt;"),
+            '>' => snippet.push_str("&gt;"),
+            '"' => snippet.push_str("&quot;"),
+            '\'' => snippet.push_str("&#39;"),
+            c => snippet.push(c),
+        }
+    }
+
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_snippet_escapes_and_highlights() {
+        let raw = format!("a <b> & {}fox{} said \"hi\"", MATCH_START, MATCH_END);
+
+        assert_eq!(
+            render_snippet(&raw),
+            "a &lt;b&gt; &amp; <mark>fox</mark> said &quot;hi&quot;"
+        );
+    }
+
+    #[test]
+    fn test_new_sort_has_constant_rank() {
+        let rank = search_rank_sql(SearchSort::New, "p.search_vector", "q.query", "p.created_at");
+        assert_eq!(rank, "0::DOUBLE PRECISION");
+
+        let rank = search_rank_sql(SearchSort::Relevance, "p.search_vector", "q.query", "p.created_at");
+        assert!(rank.contains(&HOT_RANK_EPOCH.to_string()));
+    }
+
+    #[test]
+    fn test_headline_uses_match_markers() {
+        let sql = headline_sql("c.content", "q.query");
+        assert!(sql.contains("translate(c.content, chr(2) || chr(3), '')"));
+        assert!(sql.contains("'StartSel=' || chr(2) || ', StopSel=' || chr(3)"));
+    }
+}
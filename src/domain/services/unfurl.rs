@@ -0,0 +1,689 @@
+//! Link previews
+//!
+//! Fetches the page behind a link post and extracts its OpenGraph and Twitter
+//! card metadata. Every request, including each redirect hop and the preview
+//! image, goes through the same guards: http(s) only, public addresses only
+//! (the connection is pinned to the addresses that were checked, so a changed
+//! DNS answer cannot slip through), a bounded number of redirects, and caps on
+//! response size and time.
+//!
+//! Errors are [`ApiError::InvalidInput`] when the link can never be previewed
+//! and [`ApiError::ExternalServiceError`] when a later attempt may succeed.
+
+use reqwest::header::{ACCEPT, CONTENT_TYPE, LOCATION};
+use reqwest::StatusCode;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use url::{Host, Url};
+
+use crate::errors::{ApiError, ApiResult};
+
+/// User agent sent when fetching previews
+const USER_AGENT: &str = concat!("SilentAlliance-LinkPreview/", env!("CARGO_PKG_VERSION"));
+
+/// Timeout for a single request, including reading the body
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout for establishing a connection
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Redirects followed before giving up
+pub const MAX_REDIRECTS: usize = 3;
+
+/// Bytes of a page searched for metadata; it lives in the `<head>`
+const MAX_PAGE_BYTES: usize = 512 * 1024;
+
+/// Largest preview image fetched
+const MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+
+const MAX_TITLE_CHARS: usize = 300;
+const MAX_DESCRIPTION_CHARS: usize = 1000;
+const MAX_SITE_NAME_CHARS: usize = 200;
+
+/// Metadata extracted from a linked page
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LinkMetadata {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub site_name: Option<String>,
+    pub image_url: Option<Url>,
+}
+
+/// A linked page's metadata and where it was found after redirects
+#[derive(Debug, Clone)]
+pub struct UnfurledLink {
+    pub url: Url,
+    pub metadata: LinkMetadata,
+}
+
+/// A fetched preview image
+#[derive(Debug, Clone)]
+pub struct PreviewImage {
+    pub data: Vec<u8>,
+    pub mime_type: String,
+}
+
+struct Fetched {
+    url: Url,
+    content_type: Option<String>,
+    body: Vec<u8>,
+    truncated: bool,
+}
+
+/// Client for fetching link previews
+#[derive(Debug, Clone, Default)]
+pub struct LinkPreviewClient {
+    allow_loopback: bool,
+}
+
+impl LinkPreviewClient {
+    /// Create a new link preview client
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Client that may also reach loopback addresses, for tests against a
+    /// local server
+    #[cfg(test)]
+    fn allowing_loopback() -> Self {
+        Self { allow_loopback: true }
+    }
+
+    /// Fetch a page and extract its preview metadata
+    pub async fn unfurl(&self, url: &Url) -> ApiResult<UnfurledLink> {
+        let page = self.fetch(url, "text/html,application/xhtml+xml", MAX_PAGE_BYTES).await?;
+
+        if !matches!(page.content_type.as_deref(), Some("text/html" | "application/xhtml+xml")) {
+            return Err(ApiError::InvalidInput("Link is not a web page".to_string()));
+        }
+
+        let html = String::from_utf8_lossy(&page.body);
+        let metadata = extract_link_metadata(&html, &page.url);
+
+        Ok(UnfurledLink {
+            url: page.url,
+            metadata,
+        })
+    }
+
+    /// Fetch a preview image
+    pub async fn fetch_image(&self, url: &Url) -> ApiResult<PreviewImage> {
+        let image = self.fetch(url, "image/*", MAX_IMAGE_BYTES).await?;
+
+        if image.truncated {
+            return Err(ApiError::InvalidInput("Preview image is too large".to_string()));
+        }
+
+        let mime_type = image
+            .content_type
+            .filter(|content_type| content_type.starts_with("image/"))
+            .ok_or_else(|| ApiError::InvalidInput("Preview image is not an image".to_string()))?;
+
+        Ok(PreviewImage {
+            data: image.body,
+            mime_type,
+        })
+    }
+
+    /// GET a URL, following redirects by hand so every hop is checked, and
+    /// read at most `max_bytes` of the body
+    async fn fetch(&self, url: &Url, accept: &str, max_bytes: usize) -> ApiResult<Fetched> {
+        let mut url = url.clone();
+
+        for _ in 0..=MAX_REDIRECTS {
+            let client = self.client_for(&url).await?;
+
+            let mut response = client.get(url.clone()).header(ACCEPT, accept).send().await?;
+            let status = response.status();
+
+            if status.is_redirection() {
+                let location = response
+                    .headers()
+                    .get(LOCATION)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| ApiError::InvalidInput("Redirect without a location".to_string()))?;
+
+                url = url
+                    .join(location)
+                    .map_err(|_| ApiError::InvalidInput("Invalid redirect location".to_string()))?;
+                continue;
+            }
+
+            if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+                return Err(ApiError::ExternalServiceError(format!("Link returned {}", status)));
+            }
+
+            if !status.is_success() {
+                return Err(ApiError::InvalidInput(format!("Link returned {}", status)));
+            }
+
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.split(';').next())
+                .map(|value| value.trim().to_ascii_lowercase());
+
+            let mut body = Vec::new();
+            let mut truncated = false;
+            while let Some(chunk) = response.chunk().await? {
+                let room = max_bytes - body.len();
+                if chunk.len() > room {
+                    body.extend_from_slice(&chunk[..room]);
+                    truncated = true;
+                    break;
+                }
+                body.extend_from_slice(&chunk);
+            }
+
+            return Ok(Fetched {
+                url,
+                content_type,
+                body,
+                truncated,
+            });
+        }
+
+        Err(ApiError::InvalidInput("Too many redirects".to_string()))
+    }
+
+    /// HTTP client for a request to `url`, connecting only to the host
+    /// addresses checked here
+    async fn client_for(&self, url: &Url) -> ApiResult<reqwest::Client> {
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(ApiError::InvalidInput(
+                "Only http and https links can be previewed".to_string(),
+            ));
+        }
+
+        if !url.username().is_empty() || url.password().is_some() {
+            return Err(ApiError::InvalidInput(
+                "Links with credentials cannot be previewed".to_string(),
+            ));
+        }
+
+        let (domain, addresses): (Option<&str>, Vec<IpAddr>) = match url.host() {
+            Some(Host::Ipv4(ip)) => (None, vec![IpAddr::V4(ip)]),
+            Some(Host::Ipv6(ip)) => (None, vec![IpAddr::V6(ip)]),
+            Some(Host::Domain(domain)) => {
+                let addresses = tokio::net::lookup_host((domain, 0))
+                    .await
+                    .map_err(|e| {
+                        ApiError::ExternalServiceError(format!("Could not resolve {}: {}", domain, e))
+                    })?
+                    .map(|addr| addr.ip())
+                    .collect();
+                (Some(domain), addresses)
+            }
+            None => return Err(ApiError::InvalidInput("Link has no host".to_string())),
+        };
+
+        let allowed = |ip: &IpAddr| is_public_address(*ip) || (self.allow_loopback && ip.is_loopback());
+        if addresses.is_empty() || !addresses.iter().all(allowed) {
+            return Err(ApiError::InvalidInput(
+                "Link points to a non-public address".to_string(),
+            ));
+        }
+
+        let mut builder = reqwest::Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(FETCH_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy();
+
+        if let Some(domain) = domain {
+            // The port comes from the URL; only the addresses are pinned
+            let pinned: Vec<SocketAddr> = addresses.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect();
+            builder = builder.resolve_to_addrs(domain, &pinned);
+        }
+
+        Ok(builder.build()?)
+    }
+}
+
+/// Whether an address is publicly routable, and so safe to fetch from
+pub fn is_public_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // "This network"
+        || a == 0
+        // Shared address space (carrier-grade NAT)
+        || (a == 100 && (64..128).contains(&b))
+        // IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // Benchmarking
+        || (a == 198 && (b == 18 || b == 19))
+        // Reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    let s = ip.segments();
+    let embedded_v4 = |high: u16, low: u16| {
+        Ipv4Addr::new((high >> 8) as u8, high as u8, (low >> 8) as u8, low as u8)
+    };
+
+    // NAT64 and 6to4 addresses reach the IPv4 address they embed
+    if s[0] == 0x64 && s[1] == 0xff9b && s[2..6] == [0, 0, 0, 0] {
+        return is_public_v4(embedded_v4(s[6], s[7]));
+    }
+    if s[0] == 0x2002 {
+        return is_public_v4(embedded_v4(s[1], s[2]));
+    }
+
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Deprecated IPv4-compatible addresses
+        || s[..6] == [0, 0, 0, 0, 0, 0]
+        // Unique local
+        || (s[0] & 0xfe00) == 0xfc00
+        // Link local
+        || (s[0] & 0xffc0) == 0xfe80
+        // Documentation
+        || (s[0] == 0x2001 && s[1] == 0x0db8))
+}
+
+/// Extract preview metadata from a page's `<head>`.
+///
+/// OpenGraph tags win over Twitter card tags, which win over the plain
+/// `<title>` and description. Relative image URLs are resolved against
+/// `base`, the page's final URL.
+pub fn extract_link_metadata(html: &str, base: &Url) -> LinkMetadata {
+    // ASCII lowercasing keeps byte offsets, so positions found in `lower`
+    // are valid in `html`
+    let lower = html.to_ascii_lowercase();
+    let head_end = lower
+        .find("</head")
+        .or_else(|| lower.find("<body"))
+        .unwrap_or(lower.len());
+    let (html, lower) = (&html[..head_end], &lower[..head_end]);
+
+    let mut meta: HashMap<String, String> = HashMap::new();
+    let mut title_element = None;
+
+    let mut pos = 0;
+    while let Some(offset) = lower[pos..].find('<') {
+        let start = pos + offset + 1;
+        let name_end = lower[start..]
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .map_or(lower.len(), |end| start + end);
+
+        match &lower[start..name_end] {
+            "meta" => {
+                let (attrs, end) = parse_attributes(html, name_end);
+                let key = attrs.get("property").or_else(|| attrs.get("name"));
+                if let (Some(key), Some(content)) = (key, attrs.get("content")) {
+                    meta.entry(key.to_ascii_lowercase()).or_insert_with(|| content.clone());
+                }
+                pos = end;
+            }
+            "title" if title_element.is_none() => {
+                let (_, end) = parse_attributes(html, name_end);
+                let close = lower[end..].find("</title").map_or(lower.len(), |close| end + close);
+                title_element = Some(decode_entities(&html[end..close]));
+                pos = close;
+            }
+            _ => pos = start,
+        }
+    }
+
+    let pick = |keys: &[&str], max_chars: usize| {
+        keys.iter()
+            .find_map(|key| meta.get(*key))
+            .map(|value| clean_text(value, max_chars))
+            .filter(|value| !value.is_empty())
+    };
+
+    let image_url = ["og:image:secure_url", "og:image", "og:image:url", "twitter:image", "twitter:image:src"]
+        .iter()
+        .find_map(|key| meta.get(*key))
+        .and_then(|src| base.join(src.trim()).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"));
+
+    LinkMetadata {
+        title: pick(&["og:title", "twitter:title"], MAX_TITLE_CHARS).or_else(|| {
+            title_element
+                .map(|title| clean_text(&title, MAX_TITLE_CHARS))
+                .filter(|title| !title.is_empty())
+        }),
+        description: pick(
+            &["og:description", "twitter:description", "description"],
+            MAX_DESCRIPTION_CHARS,
+        ),
+        site_name: pick(&["og:site_name"], MAX_SITE_NAME_CHARS),
+        image_url,
+    }
+}
+
+/// Attributes of a tag whose name ends at byte `i`, and the offset just past
+/// the tag. Attribute names are lowercased and values entity-decoded.
+fn parse_attributes(html: &str, mut i: usize) -> (HashMap<String, String>, usize) {
+    let bytes = html.as_bytes();
+    let len = bytes.len();
+    let mut attrs = HashMap::new();
+
+    loop {
+        while i < len && (bytes[i].is_ascii_whitespace() || bytes[i] == b'/') {
+            i += 1;
+        }
+        if i >= len {
+            break;
+        }
+        if bytes[i] == b'>' {
+            i += 1;
+            break;
+        }
+
+        let name_start = i;
+        while i < len && !bytes[i].is_ascii_whitespace() && !matches!(bytes[i], b'=' | b'>' | b'/') {
+            i += 1;
+        }
+        let name = html[name_start..i].to_ascii_lowercase();
+
+        while i < len && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+
+        let mut value = String::new();
+        if i < len && bytes[i] == b'=' {
+            i += 1;
+            while i < len && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+
+            if i < len && matches!(bytes[i], b'"' | b'\'') {
+                let quote = bytes[i];
+                let start = i + 1;
+                i = start;
+                while i < len && bytes[i] != quote {
+                    i += 1;
+                }
+                value = decode_entities(&html[start..i]);
+                i = (i + 1).min(len);
+            } else {
+                let start = i;
+                while i < len && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                    i += 1;
+                }
+                value = decode_entities(&html[start..i]);
+            }
+        }
+
+        if name.is_empty() {
+            // A stray '=' with no name; step over it
+            i += 1;
+            continue;
+        }
+
+        attrs.entry(name).or_insert(value);
+    }
+
+    (attrs, i)
+}
+
+/// Decode the named entities common in metadata and all numeric references
+fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+
+        let decoded = rest[1..]
+            .find(';')
+            .filter(|&end| end <= 10)
+            .and_then(|end| decode_entity(&rest[1..=end]).map(|c| (c, end + 2)));
+
+        match decoded {
+            Some((c, consumed)) => {
+                out.push(c);
+                rest = &rest[consumed..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    let entity = entity.strip_suffix(';').unwrap_or(entity);
+
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        "nbsp" => Some('\u{a0}'),
+        _ => {
+            let code = match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => entity.strip_prefix('#').and_then(|digits| digits.parse().ok()),
+            };
+            code.and_then(char::from_u32)
+        }
+    }
+}
+
+/// Collapse whitespace and cut to `max_chars`
+fn clean_text(text: &str, max_chars: usize) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .take(max_chars)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn html_response(body: &str) -> ResponseTemplate {
+        ResponseTemplate::new(200).set_body_raw(body.to_string(), "text/html; charset=utf-8")
+    }
+
+    #[test]
+    fn test_open_graph_wins_over_fallbacks() {
+        let html = r#"<!doctype html><html><head>
+            <title>Plain title</title>
+            <meta name="twitter:title" content="Card title">
+            <meta property="og:title" content="Tom &amp; Jerry &#8212; the &quot;story&quot;">
+            <meta name="description" content="Plain   description">
+            <META PROPERTY='og:image' CONTENT='/img/cover.png'>
+            <meta property="og:site_name" content=Example>
+            </head><body><meta property="og:description" content="not in head"></body></html>"#;
+
+        let base = Url::parse("https://example.com/articles/1").unwrap();
+        let metadata = extract_link_metadata(html, &base);
+
+        assert_eq!(metadata.title.as_deref(), Some("Tom & Jerry \u{2014} the \"story\""));
+        assert_eq!(metadata.description.as_deref(), Some("Plain description"));
+        assert_eq!(metadata.site_name.as_deref(), Some("Example"));
+        assert_eq!(
+            metadata.image_url.map(String::from).as_deref(),
+            Some("https://example.com/img/cover.png")
+        );
+    }
+
+    #[test]
+    fn test_title_element_fallback_and_unsafe_image() {
+        let html = "<head><title>\n  Just a\n page </title>\
+                    <meta name=\"twitter:image\" content=\"javascript:alert(1)\"></head>";
+
+        let base = Url::parse("https://example.com/").unwrap();
+        let metadata = extract_link_metadata(html, &base);
+
+        assert_eq!(metadata.title.as_deref(), Some("Just a page"));
+        assert_eq!(metadata.description, None);
+        assert_eq!(metadata.image_url, None);
+    }
+
+    #[test]
+    fn test_public_address_rules() {
+        for blocked in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::ffff:127.0.0.1",
+            "fd00::1",
+            "fe80::1",
+            "64:ff9b::a00:1",
+            "2002:c0a8:101::1",
+        ] {
+            assert!(!is_public_address(blocked.parse().unwrap()), "{} should be blocked", blocked);
+        }
+
+        for allowed in ["93.184.216.34", "2606:4700:4700::1111", "64:ff9b::5db8:d822"] {
+            assert!(is_public_address(allowed.parse().unwrap()), "{} should be allowed", allowed);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unfurl_follows_redirects() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/short"))
+            .respond_with(ResponseTemplate::new(301).insert_header("location", "/article"))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/article"))
+            .respond_with(html_response(
+                r#"<head><meta property="og:title" content="Article"><meta property="og:image" content="cover.jpg"></head>"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/short", server.uri())).unwrap();
+        let link = LinkPreviewClient::allowing_loopback()
+            .unfurl(&url)
+            .await
+            .unwrap();
+
+        assert_eq!(link.url.path(), "/article");
+        assert_eq!(link.metadata.title.as_deref(), Some("Article"));
+        assert_eq!(link.metadata.image_url.unwrap().path(), "/cover.jpg");
+    }
+
+    #[tokio::test]
+    async fn test_redirect_loop_is_cut_off() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/loop"))
+            .respond_with(ResponseTemplate::new(302).insert_header("location", "/loop"))
+            .expect(MAX_REDIRECTS as u64 + 1)
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/loop", server.uri())).unwrap();
+        let result = LinkPreviewClient::allowing_loopback().unfurl(&url).await;
+
+        assert!(matches!(result, Err(ApiError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_private_destinations_are_blocked() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .respond_with(html_response("<head><title>internal</title></head>"))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let client = LinkPreviewClient::new();
+        let port = server.address().port();
+
+        for url in [
+            server.uri(),
+            format!("http://localhost:{}/", port),
+            format!("http://[::ffff:127.0.0.1]:{}/", port),
+        ] {
+            let result = client.unfurl(&Url::parse(&url).unwrap()).await;
+            assert!(matches!(result, Err(ApiError::InvalidInput(_))), "{} was not blocked", url);
+        }
+
+        let result = client.unfurl(&Url::parse("file:///etc/passwd").unwrap()).await;
+        assert!(matches!(result, Err(ApiError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_redirect_to_private_address_is_blocked() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/start"))
+            .respond_with(
+                ResponseTemplate::new(302).insert_header("location", "http://169.254.169.254/latest/meta-data"),
+            )
+            .mount(&server)
+            .await;
+
+        let url = Url::parse(&format!("{}/start", server.uri())).unwrap();
+        let result = LinkPreviewClient::allowing_loopback().unfurl(&url).await;
+
+        assert!(matches!(result, Err(ApiError::InvalidInput(_))));
+    }
+
+    #[tokio::test]
+    async fn test_oversized_image_and_non_html_rejected() {
+        let server = MockServer::start().await;
+
+        Mock::given(method("GET"))
+            .and(path("/huge.png"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(vec![0u8; MAX_IMAGE_BYTES + 1], "image/png"))
+            .mount(&server)
+            .await;
+
+        Mock::given(method("GET"))
+            .and(path("/data.json"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("{}", "application/json"))
+            .mount(&server)
+            .await;
+
+        let client = LinkPreviewClient::allowing_loopback();
+
+        let image = Url::parse(&format!("{}/huge.png", server.uri())).unwrap();
+        assert!(matches!(client.fetch_image(&image).await, Err(ApiError::InvalidInput(_))));
+
+        let json = Url::parse(&format!("{}/data.json", server.uri())).unwrap();
+        assert!(matches!(client.unfurl(&json).await, Err(ApiError::InvalidInput(_))));
+    }
+}
@@ -16,6 +16,7 @@ use crate::errors::ApiError;
 #[derive(Clone)]
 pub struct RedisPool {
     pool: Pool,
+    client: redis::Client,
     key_prefix: String,
 }
 
@@ -44,6 +45,12 @@ impl RedisPool {
                 ApiError::CacheError
             })?;
 
+        // Pub/sub needs dedicated connections outside of the pool
+        let client = redis::Client::open(settings.url.as_str()).map_err(|e| {
+            error!(error = %e, "Invalid Redis URL");
+            ApiError::CacheError
+        })?;
+
         info!(
             max_size = settings.max_connections,
             "Redis pool created and connected"
@@ -51,6 +58,7 @@ impl RedisPool {
 
         Ok(Self {
             pool,
+            client,
             key_prefix: settings.key_prefix.clone(),
         })
     }
@@ -117,6 +125,55 @@ impl RedisPool {
         }
     }
 
+    /// Get several values from cache in one round trip.
+    ///
+    /// Results line up with `keys`; entries that are missing or fail to
+    /// deserialize come back as `None`.
+    pub async fn get_many<T: DeserializeOwned>(&self, keys: &[String]) -> Result<Vec<Option<T>>, ApiError> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut conn = self.get_conn().await?;
+        let prefixed_keys: Vec<String> = keys.iter().map(|key| self.prefixed_key(key)).collect();
+
+        let values: Vec<Option<String>> = redis::cmd("MGET")
+            .arg(&prefixed_keys)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                error!(error = %e, count = keys.len(), "Failed to get cache values");
+                ApiError::CacheError
+            })?;
+
+        Ok(values
+            .into_iter()
+            .map(|value| value.and_then(|v| serde_json::from_str(&v).ok()))
+            .collect())
+    }
+
+    /// Set several values with the same expiration in one round trip
+    pub async fn set_many<T: Serialize>(&self, entries: &[(String, T)], ttl: Duration) -> Result<(), ApiError> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = self.get_conn().await?;
+        let mut pipe = redis::pipe();
+
+        for (key, value) in entries {
+            let serialized = serde_json::to_string(value).map_err(|_| ApiError::CacheError)?;
+            pipe.set_ex(self.prefixed_key(key), serialized, ttl.as_secs()).ignore();
+        }
+
+        pipe.query_async::<_, ()>(&mut conn).await.map_err(|e| {
+            error!(error = %e, count = entries.len(), "Failed to set cache values");
+            ApiError::CacheError
+        })?;
+
+        Ok(())
+    }
+
     /// Delete a key from cache
     pub async fn delete(&self, key: &str) -> Result<(), ApiError> {
         let mut conn = self.get_conn().await?;
@@ -305,6 +362,107 @@ impl RedisPool {
         Ok(())
     }
 
+    /// Acquire a lease if nobody holds it (`SET NX PX`)
+    pub async fn try_acquire_lease(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, ApiError> {
+        let mut conn = self.get_conn().await?;
+        let prefixed_key = self.prefixed_key(key);
+
+        let result: Option<String> = redis::cmd("SET")
+            .arg(&prefixed_key)
+            .arg(holder)
+            .arg("NX")
+            .arg("PX")
+            .arg(ttl.as_millis() as u64)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| {
+                error!(error = %e, key = %key, "Failed to acquire lease");
+                ApiError::CacheError
+            })?;
+
+        Ok(result.is_some())
+    }
+
+    /// Extend a lease, but only if it is still held by `holder`
+    pub async fn renew_lease(&self, key: &str, holder: &str, ttl: Duration) -> Result<bool, ApiError> {
+        let mut conn = self.get_conn().await?;
+        let prefixed_key = self.prefixed_key(key);
+
+        let renewed: i64 = redis::Script::new(
+            r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('PEXPIRE', KEYS[1], ARGV[2])
+              else
+                return 0
+              end",
+        )
+        .key(&prefixed_key)
+        .arg(holder)
+        .arg(ttl.as_millis() as u64)
+        .invoke_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!(error = %e, key = %key, "Failed to renew lease");
+            ApiError::CacheError
+        })?;
+
+        Ok(renewed == 1)
+    }
+
+    /// Release a lease, but only if it is still held by `holder`
+    pub async fn release_lease(&self, key: &str, holder: &str) -> Result<bool, ApiError> {
+        let mut conn = self.get_conn().await?;
+        let prefixed_key = self.prefixed_key(key);
+
+        let released: i64 = redis::Script::new(
+            r"if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+              else
+                return 0
+              end",
+        )
+        .key(&prefixed_key)
+        .arg(holder)
+        .invoke_async(&mut conn)
+        .await
+        .map_err(|e| {
+            error!(error = %e, key = %key, "Failed to release lease");
+            ApiError::CacheError
+        })?;
+
+        Ok(released == 1)
+    }
+
+    /// Get the current holder of a lease
+    pub async fn lease_holder(&self, key: &str) -> Result<Option<String>, ApiError> {
+        let mut conn = self.get_conn().await?;
+        let prefixed_key = self.prefixed_key(key);
+
+        let holder: Option<String> = conn.get(&prefixed_key).await.map_err(|e| {
+            error!(error = %e, key = %key, "Failed to get lease holder");
+            ApiError::CacheError
+        })?;
+
+        Ok(holder)
+    }
+
+    /// Open a dedicated pub/sub connection subscribed to a channel
+    pub async fn subscribe(&self, channel: &str) -> Result<redis::aio::PubSub, ApiError> {
+        let prefixed_channel = self.prefixed_key(channel);
+
+        let mut pubsub = self.client.get_async_pubsub().await.map_err(|e| {
+            error!(error = %e, "Failed to open Redis pub/sub connection");
+            ApiError::CacheError
+        })?;
+
+        pubsub.subscribe(&prefixed_channel).await.map_err(|e| {
+            error!(error = %e, channel = %channel, "Failed to subscribe to channel");
+            ApiError::CacheError
+        })?;
+
+        debug!(channel = %channel, "Subscribed to channel");
+        Ok(pubsub)
+    }
+
     /// Health check
     pub async fn health_check(&self) -> Result<(), ApiError> {
         let mut conn = self.get_conn().await?;
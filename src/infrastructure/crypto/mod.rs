@@ -6,14 +6,17 @@
 //! - ChaCha20-Poly1305 symmetric encryption
 //! - Argon2id password hashing
 //! - SHA-256 hashing
-//! - HMAC-SHA256 for message authentication
+//! - HMAC-SHA256 for message authentication and signed tokens
 //! - Secure random generation
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2, Algorithm, Params, Version,
 };
-use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD as BASE64, URL_SAFE_NO_PAD as BASE64_URL},
+    Engine,
+};
 use chacha20poly1305::{
     aead::{Aead, KeyInit, Payload},
     ChaCha20Poly1305, Nonce,
@@ -306,6 +309,27 @@ impl CryptoService {
         mac.verify_slice(expected_mac).is_ok()
     }
 
+    /// Sign a payload into a URL-safe `payload.mac` token.
+    ///
+    /// The MAC covers `context` followed by the payload; `context` is not part
+    /// of the token and must be supplied again to verify it, which binds the
+    /// token to one purpose.
+    pub fn sign_token(&self, context: &[u8], payload: &[u8]) -> String {
+        let mac = self.hmac_sha256(&[context, payload].concat());
+
+        format!("{}.{}", BASE64_URL.encode(payload), BASE64_URL.encode(mac))
+    }
+
+    /// Verify a token from [`sign_token`](Self::sign_token) and return its payload
+    pub fn verify_token(&self, context: &[u8], token: &str) -> Option<Vec<u8>> {
+        let (payload, mac) = token.split_once('.')?;
+        let payload = BASE64_URL.decode(payload).ok()?;
+        let mac = BASE64_URL.decode(mac).ok()?;
+
+        self.verify_hmac_sha256(&[context, payload.as_slice()].concat(), &mac)
+            .then_some(payload)
+    }
+
     /// Calculate HMAC-SHA256 with custom key
     pub fn hmac_sha256_with_key(key: &[u8], data: &[u8]) -> Vec<u8> {
         let mut mac = HmacSha256::new_from_slice(key)
@@ -442,6 +466,23 @@ impl EncryptedMessage {
     }
 }
 
+/// Fixtures for tests in other modules that need a crypto service
+#[cfg(test)]
+pub(crate) mod testing {
+    use super::*;
+
+    /// A crypto service with a fixed master key and cheap hashing parameters
+    pub(crate) fn crypto() -> CryptoService {
+        let settings = CryptoSettings {
+            master_key: BASE64.encode(&[0u8; 32]),
+            argon2_memory_cost: 4096,
+            argon2_time_cost: 1,
+            argon2_parallelism: 1,
+        };
+        CryptoService::new(&settings).unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -541,6 +582,16 @@ mod tests {
         assert!(!crypto.verify_hmac_sha256(b"Wrong data", &mac));
     }
 
+    #[test]
+    fn test_signed_token() {
+        let crypto = test_crypto_service();
+
+        let token = crypto.sign_token(b"scope:a", b"payload");
+        assert_eq!(crypto.verify_token(b"scope:a", &token), Some(b"payload".to_vec()));
+        assert_eq!(crypto.verify_token(b"scope:b", &token), None);
+        assert_eq!(crypto.verify_token(b"scope:a", "garbage"), None);
+    }
+
     #[test]
     fn test_pkce() {
         let verifier = CryptoService::generate_pkce_verifier();
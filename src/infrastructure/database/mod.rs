@@ -3,6 +3,8 @@
 //! Provides PostgreSQL connection pool management, migration execution,
 //! and database access utilities.
 
+pub mod repositories;
+
 use sqlx::{
     postgres::{PgPool, PgPoolOptions},
     migrate::Migrator,
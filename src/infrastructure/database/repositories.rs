@@ -0,0 +1,428 @@
+//! PostgreSQL implementations of the domain repository traits
+
+use async_trait::async_trait;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::entities::{Credential, Identity, Vote, VoteTargetType};
+use crate::domain::repositories::{CredentialRepository, IdentityRepository, VoteRepository};
+use crate::errors::ApiResult;
+use crate::infrastructure::crypto::CryptoService;
+
+/// Credential type used for password credentials
+const PASSWORD_CREDENTIAL: &str = "password";
+
+/// Credential type for an OAuth provider.
+///
+/// Credentials are unique per (identity, type), so each provider gets its own
+/// type to allow one identity to link several providers.
+pub(crate) fn oauth_credential_type(provider: &str) -> String {
+    format!("oauth_{}", provider)
+}
+
+/// PostgreSQL-backed identity repository
+#[derive(Clone)]
+pub struct PgIdentityRepository {
+    pool: PgPool,
+}
+
+impl PgIdentityRepository {
+    /// Create a new repository over the given pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl IdentityRepository for PgIdentityRepository {
+    async fn create(&self, public_key: &[u8], display_name: Option<&str>) -> ApiResult<Identity> {
+        let identity = sqlx::query_as!(
+            Identity,
+            r#"
+            INSERT INTO identities (public_key, public_key_fingerprint, display_name)
+            VALUES ($1, $2, $3)
+            RETURNING id, public_key, public_key_fingerprint, display_name, avatar_hash, bio,
+                      karma as "karma!", is_verified as "is_verified!", is_suspended as "is_suspended!",
+                      suspended_reason, suspended_until, created_at, updated_at
+            "#,
+            public_key,
+            CryptoService::public_key_fingerprint(public_key),
+            display_name
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn find_by_id(&self, id: Uuid) -> ApiResult<Option<Identity>> {
+        let identity = sqlx::query_as!(
+            Identity,
+            r#"
+            SELECT id, public_key, public_key_fingerprint, display_name, avatar_hash, bio,
+                   karma as "karma!", is_verified as "is_verified!", is_suspended as "is_suspended!",
+                   suspended_reason, suspended_until, created_at, updated_at
+            FROM identities
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn find_by_fingerprint(&self, fingerprint: &str) -> ApiResult<Option<Identity>> {
+        let identity = sqlx::query_as!(
+            Identity,
+            r#"
+            SELECT id, public_key, public_key_fingerprint, display_name, avatar_hash, bio,
+                   karma as "karma!", is_verified as "is_verified!", is_suspended as "is_suspended!",
+                   suspended_reason, suspended_until, created_at, updated_at
+            FROM identities
+            WHERE public_key_fingerprint = $1
+            "#,
+            fingerprint
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn find_by_public_key(&self, public_key: &[u8]) -> ApiResult<Option<Identity>> {
+        let identity = sqlx::query_as!(
+            Identity,
+            r#"
+            SELECT id, public_key, public_key_fingerprint, display_name, avatar_hash, bio,
+                   karma as "karma!", is_verified as "is_verified!", is_suspended as "is_suspended!",
+                   suspended_reason, suspended_until, created_at, updated_at
+            FROM identities
+            WHERE public_key = $1
+            "#,
+            public_key
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn find_mentioned(&self, names: &[String]) -> ApiResult<Vec<Identity>> {
+        if names.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let names: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+
+        // A display name shared by several identities names none of them
+        let identities = sqlx::query_as!(
+            Identity,
+            r#"
+            SELECT id, public_key, public_key_fingerprint, display_name, avatar_hash, bio,
+                   karma as "karma!", is_verified as "is_verified!", is_suspended as "is_suspended!",
+                   suspended_reason, suspended_until, created_at, updated_at
+            FROM identities
+            WHERE public_key_fingerprint = ANY($1)
+               OR LOWER(display_name) IN (
+                   SELECT LOWER(display_name)
+                   FROM identities
+                   WHERE LOWER(display_name) = ANY($1)
+                   GROUP BY LOWER(display_name)
+                   HAVING COUNT(*) = 1
+               )
+            "#,
+            &names
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(identities)
+    }
+
+    async fn update(&self, id: Uuid, display_name: Option<&str>, bio: Option<&str>) -> ApiResult<Identity> {
+        let identity = sqlx::query_as!(
+            Identity,
+            r#"
+            UPDATE identities
+            SET display_name = COALESCE($2, display_name),
+                bio = COALESCE($3, bio),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, public_key, public_key_fingerprint, display_name, avatar_hash, bio,
+                      karma as "karma!", is_verified as "is_verified!", is_suspended as "is_suspended!",
+                      suspended_reason, suspended_until, created_at, updated_at
+            "#,
+            id,
+            display_name,
+            bio
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(identity)
+    }
+
+    async fn update_karma(&self, id: Uuid, delta: i32) -> ApiResult<()> {
+        sqlx::query!("UPDATE identities SET karma = karma + $2 WHERE id = $1", id, delta)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn suspend(&self, id: Uuid, reason: &str, until: Option<chrono::DateTime<chrono::Utc>>) -> ApiResult<()> {
+        sqlx::query!(
+            "UPDATE identities SET is_suspended = true, suspended_reason = $2, suspended_until = $3 WHERE id = $1",
+            id,
+            reason,
+            until
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn unsuspend(&self, id: Uuid) -> ApiResult<()> {
+        sqlx::query!(
+            "UPDATE identities SET is_suspended = false, suspended_reason = NULL, suspended_until = NULL WHERE id = $1",
+            id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+/// PostgreSQL-backed credential repository
+#[derive(Clone)]
+pub struct PgCredentialRepository {
+    pool: PgPool,
+}
+
+impl PgCredentialRepository {
+    /// Create a new repository over the given pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CredentialRepository for PgCredentialRepository {
+    async fn create_password(&self, identity_id: Uuid, password_hash: &str) -> ApiResult<Credential> {
+        let credential = sqlx::query_as!(
+            Credential,
+            r#"
+            INSERT INTO credentials (identity_id, credential_type, credential_hash)
+            VALUES ($1, $2, $3)
+            RETURNING id, identity_id, credential_type, credential_hash,
+                      oauth_provider, oauth_subject, created_at
+            "#,
+            identity_id,
+            PASSWORD_CREDENTIAL,
+            password_hash
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn create_oauth(
+        &self,
+        identity_id: Uuid,
+        provider: &str,
+        subject: &str,
+    ) -> ApiResult<Credential> {
+        let credential = sqlx::query_as!(
+            Credential,
+            r#"
+            INSERT INTO credentials (identity_id, credential_type, oauth_provider, oauth_subject)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, identity_id, credential_type, credential_hash,
+                      oauth_provider, oauth_subject, created_at
+            "#,
+            identity_id,
+            oauth_credential_type(provider),
+            provider,
+            subject
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn find_by_identity_and_type(
+        &self,
+        identity_id: Uuid,
+        credential_type: &str,
+    ) -> ApiResult<Option<Credential>> {
+        let credential = sqlx::query_as!(
+            Credential,
+            r#"
+            SELECT id, identity_id, credential_type, credential_hash,
+                   oauth_provider, oauth_subject, created_at
+            FROM credentials
+            WHERE identity_id = $1 AND credential_type = $2
+            "#,
+            identity_id,
+            credential_type
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn find_by_oauth(&self, provider: &str, subject: &str) -> ApiResult<Option<Credential>> {
+        let credential = sqlx::query_as!(
+            Credential,
+            r#"
+            SELECT id, identity_id, credential_type, credential_hash,
+                   oauth_provider, oauth_subject, created_at
+            FROM credentials
+            WHERE oauth_provider = $1 AND oauth_subject = $2
+            "#,
+            provider,
+            subject
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(credential)
+    }
+
+    async fn update_password(&self, identity_id: Uuid, password_hash: &str) -> ApiResult<()> {
+        sqlx::query!(
+            "UPDATE credentials SET credential_hash = $1 WHERE identity_id = $2 AND credential_type = $3",
+            password_hash,
+            identity_id,
+            PASSWORD_CREDENTIAL
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid) -> ApiResult<()> {
+        sqlx::query!("DELETE FROM credentials WHERE id = $1", id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// PostgreSQL-backed vote repository
+#[derive(Clone)]
+pub struct PgVoteRepository {
+    pool: PgPool,
+}
+
+impl PgVoteRepository {
+    /// Create a new repository over the given pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl VoteRepository for PgVoteRepository {
+    async fn upsert(
+        &self,
+        identity_id: Uuid,
+        target_type: VoteTargetType,
+        target_id: Uuid,
+        value: i16,
+    ) -> ApiResult<Vote> {
+        let vote = sqlx::query_as!(
+            Vote,
+            r#"
+            INSERT INTO votes (identity_id, target_type, target_id, vote_value)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (identity_id, target_type, target_id)
+            DO UPDATE SET vote_value = EXCLUDED.vote_value
+            RETURNING id, identity_id, target_type as "target_type: VoteTargetType",
+                      target_id, vote_value, created_at
+            "#,
+            identity_id,
+            target_type as VoteTargetType,
+            target_id,
+            value
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(vote)
+    }
+
+    async fn delete(&self, identity_id: Uuid, target_type: VoteTargetType, target_id: Uuid) -> ApiResult<()> {
+        sqlx::query!(
+            "DELETE FROM votes WHERE identity_id = $1 AND target_type = $2 AND target_id = $3",
+            identity_id,
+            target_type as VoteTargetType,
+            target_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn find(
+        &self,
+        identity_id: Uuid,
+        target_type: VoteTargetType,
+        target_id: Uuid,
+    ) -> ApiResult<Option<Vote>> {
+        let vote = sqlx::query_as!(
+            Vote,
+            r#"
+            SELECT id, identity_id, target_type as "target_type: VoteTargetType",
+                   target_id, vote_value, created_at
+            FROM votes
+            WHERE identity_id = $1 AND target_type = $2 AND target_id = $3
+            "#,
+            identity_id,
+            target_type as VoteTargetType,
+            target_id
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(vote)
+    }
+
+    async fn find_many(
+        &self,
+        identity_id: Uuid,
+        target_type: VoteTargetType,
+        target_ids: &[Uuid],
+    ) -> ApiResult<Vec<Vote>> {
+        if target_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let votes = sqlx::query_as!(
+            Vote,
+            r#"
+            SELECT id, identity_id, target_type as "target_type: VoteTargetType",
+                   target_id, vote_value, created_at
+            FROM votes
+            WHERE identity_id = $1 AND target_type = $2 AND target_id = ANY($3)
+            "#,
+            identity_id,
+            target_type as VoteTargetType,
+            target_ids
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(votes)
+    }
+}
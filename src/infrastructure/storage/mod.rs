@@ -109,9 +109,6 @@ impl StorageService {
             ApiError::StorageError("Failed to sync file".to_string())
         })?;
 
-        // Generate thumbnail
-        let thumbnail_path = self.generate_thumbnail(&processed_data, &file_id, &format).await?;
-
         debug!(
             file_id = %file_id,
             size = processed_data.len(),
@@ -122,13 +119,29 @@ impl StorageService {
         Ok(StoredFile {
             id: file_id,
             path: relative_path,
-            thumbnail_path,
+            thumbnail_path: None,
             content_hash,
             mime_type: Self::mime_from_format(&format),
             size: processed_data.len(),
         })
     }
 
+    /// Generate the thumbnail for a previously stored image.
+    ///
+    /// Thumbnails are produced out of band by the media processing job so
+    /// uploads return as soon as the stripped original is on disk.
+    pub async fn create_thumbnail(
+        &self,
+        file_id: &Uuid,
+        relative_path: &str,
+        mime_type: &str,
+    ) -> Result<Option<String>, ApiError> {
+        let format = Self::format_from_mime(mime_type)?;
+        let data = self.get_file(relative_path).await?;
+
+        self.generate_thumbnail(&data, file_id, &format).await
+    }
+
     /// Process an image: decode, strip metadata, re-encode
     fn process_image(&self, data: &[u8], mime_type: &str) -> Result<(Vec<u8>, ImageFormat), ApiError> {
         // Determine the format from MIME type
@@ -424,7 +437,7 @@ pub struct StoredFile {
     pub id: Uuid,
     /// Relative path to the file
     pub path: String,
-    /// Relative path to thumbnail (if generated)
+    /// Relative path to thumbnail (generated later by the media processing job)
     pub thumbnail_path: Option<String>,
     /// SHA-256 hash of the content
     pub content_hash: String,
@@ -0,0 +1,604 @@
+//! Job handlers
+//!
+//! Each job type stored in the queue maps to a [`JobHandler`] registered in
+//! the [`JobRegistry`] used by the workers.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::time::Duration;
+use tracing::{debug, error, info, warn};
+use url::Url;
+use uuid::Uuid;
+
+use super::queue::JobQueue;
+use crate::domain::entities::NotificationType;
+use crate::domain::services::markdown::render_markdown;
+use crate::domain::services::notifications::in_quiet_hours;
+use crate::domain::services::unfurl::LinkPreviewClient;
+use crate::errors::{ApiError, ApiResult};
+use crate::AppState;
+
+/// Deliver a notification (insert + WebSocket fan-out)
+pub const SEND_NOTIFICATION: &str = "send_notification";
+/// Generate thumbnails for uploaded media
+pub const PROCESS_MEDIA: &str = "process_media";
+/// Fetch the preview for a link post
+pub const UNFURL_LINK: &str = "unfurl_link";
+/// Re-render stored markdown to HTML
+pub const RENDER_CONTENT: &str = "render_content";
+/// Remove expired tokens and stale data
+pub const CLEANUP: &str = "cleanup";
+
+/// Handler for a single job type
+#[async_trait]
+pub trait JobHandler: Send + Sync {
+    /// Run the job. Returning an error schedules a retry.
+    async fn handle(&self, state: &Arc<AppState>, payload: serde_json::Value) -> ApiResult<()>;
+}
+
+/// Maps job types to their handlers
+#[derive(Clone, Default)]
+pub struct JobRegistry {
+    handlers: HashMap<&'static str, Arc<dyn JobHandler>>,
+}
+
+impl JobRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registry with all built-in handlers
+    pub fn with_default_handlers() -> Self {
+        let mut registry = Self::new();
+        registry.register(SEND_NOTIFICATION, SendNotificationHandler);
+        registry.register(PROCESS_MEDIA, ProcessMediaHandler);
+        registry.register(UNFURL_LINK, UnfurlLinkHandler);
+        registry.register(RENDER_CONTENT, RenderContentHandler);
+        registry.register(CLEANUP, CleanupHandler);
+        registry
+    }
+
+    /// Register the handler for a job type
+    pub fn register<H: JobHandler + 'static>(&mut self, job_type: &'static str, handler: H) {
+        self.handlers.insert(job_type, Arc::new(handler));
+    }
+
+    /// Look up the handler for a job type
+    pub fn get(&self, job_type: &str) -> Option<Arc<dyn JobHandler>> {
+        self.handlers.get(job_type).cloned()
+    }
+}
+
+fn parse_payload<T: for<'de> Deserialize<'de>>(payload: serde_json::Value) -> ApiResult<T> {
+    serde_json::from_value(payload)
+        .map_err(|e| ApiError::InvalidInput(format!("Invalid job payload: {}", e)))
+}
+
+// ==================== Notifications ====================
+
+/// Groups a recipient can start per hour; further groups are dropped while
+/// existing unread groups keep counting
+const MAX_NEW_GROUPS_PER_HOUR: i64 = 50;
+
+/// Payload for [`SEND_NOTIFICATION`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SendNotificationPayload {
+    pub recipient_id: Uuid,
+    pub notification_type: NotificationType,
+    pub payload: serde_json::Value,
+    /// Coalesce into the recipient's unread notification with this key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group_key: Option<String>,
+    #[serde(default)]
+    pub context: NotificationContext,
+}
+
+/// What a notification is about, matched against the recipient's mutes
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct NotificationContext {
+    pub post_id: Option<Uuid>,
+    /// Identity whose action caused the notification
+    pub actor_id: Option<Uuid>,
+}
+
+pub struct SendNotificationHandler;
+
+#[async_trait]
+impl JobHandler for SendNotificationHandler {
+    async fn handle(&self, state: &Arc<AppState>, payload: serde_json::Value) -> ApiResult<()> {
+        let job: SendNotificationPayload = parse_payload(payload)?;
+
+        let preferences = sqlx::query!(
+            r#"
+            SELECT
+                COALESCE($2 = ANY(np.disabled_types), false) as "disabled!",
+                EXISTS (
+                    SELECT 1 FROM notification_mutes m
+                    WHERE m.identity_id = r.id
+                      AND ((m.target_type = 'post' AND m.target_id = $3)
+                        OR (m.target_type = 'space' AND m.target_id = (SELECT space_id FROM posts WHERE id = $3))
+                        OR (m.target_type = 'identity' AND m.target_id = $4))
+                ) OR EXISTS (
+                    SELECT 1 FROM identity_blocks b
+                    WHERE b.blocker_id = r.id AND b.blocked_id = $4
+                ) as "muted!",
+                np.quiet_hours_start,
+                np.quiet_hours_end,
+                (NOW() AT TIME ZONE COALESCE(np.timezone, 'UTC'))::TIME as "local_time!"
+            FROM (SELECT $1::UUID AS id) r
+            LEFT JOIN notification_preferences np ON np.identity_id = r.id
+            "#,
+            job.recipient_id,
+            job.notification_type.to_string(),
+            job.context.post_id,
+            job.context.actor_id
+        )
+        .fetch_one(state.db.pool())
+        .await?;
+
+        if preferences.disabled || preferences.muted {
+            debug!(recipient_id = %job.recipient_id, "Notification dropped by recipient preferences or blocks");
+            return Ok(());
+        }
+
+        // Quiet hours hold back the live push; the notification is still stored
+        let quiet = match (preferences.quiet_hours_start, preferences.quiet_hours_end) {
+            (Some(start), Some(end)) => in_quiet_hours(start, end, preferences.local_time),
+            _ => false,
+        };
+
+        let (created_at, payload) = match &job.group_key {
+            None => {
+                let created_at = sqlx::query_scalar!(
+                    r#"
+                    INSERT INTO notifications (id, recipient_id, notification_type, payload, created_at, updated_at)
+                    VALUES ($1, $2, $3, $4, NOW(), NOW())
+                    RETURNING created_at
+                    "#,
+                    Uuid::new_v4(),
+                    job.recipient_id,
+                    job.notification_type.to_string(),
+                    job.payload
+                )
+                .fetch_one(state.db.pool())
+                .await?;
+
+                (created_at, job.payload)
+            }
+            Some(group_key) => {
+                // Joins the unread group, or starts one if under the cap. The
+                // latest payload wins, with `count` tallying the group. A group
+                // keeps its `created_at`, so it counts once towards the cap and
+                // holds its place in the list.
+                let grouped = sqlx::query!(
+                    r#"
+                    INSERT INTO notifications (id, recipient_id, notification_type, payload, group_key, created_at, updated_at)
+                    SELECT $1, $2, $3, $4 || '{"count": 1}'::JSONB, $5::VARCHAR, NOW(), NOW()
+                    WHERE EXISTS (
+                            SELECT 1 FROM notifications
+                            WHERE recipient_id = $2 AND group_key = $5 AND is_read = false
+                        )
+                       OR (
+                            SELECT COUNT(*) FROM notifications
+                            WHERE recipient_id = $2 AND group_key IS NOT NULL
+                              AND created_at > NOW() - INTERVAL '1 hour'
+                        ) < $6
+                    ON CONFLICT (recipient_id, group_key) WHERE group_key IS NOT NULL AND is_read = false
+                    DO UPDATE SET
+                        payload = EXCLUDED.payload || jsonb_build_object(
+                            'count', COALESCE((notifications.payload->>'count')::INT, 1) + 1
+                        ),
+                        updated_at = EXCLUDED.updated_at
+                    RETURNING created_at, payload
+                    "#,
+                    Uuid::new_v4(),
+                    job.recipient_id,
+                    job.notification_type.to_string(),
+                    job.payload,
+                    group_key,
+                    MAX_NEW_GROUPS_PER_HOUR
+                )
+                .fetch_optional(state.db.pool())
+                .await?;
+
+                let Some(grouped) = grouped else {
+                    debug!(recipient_id = %job.recipient_id, group_key = %group_key, "Notification group cap reached");
+                    return Ok(());
+                };
+
+                (grouped.created_at, grouped.payload)
+            }
+        };
+
+        if quiet {
+            return Ok(());
+        }
+
+        // Broadcast to WebSocket if connected
+        crate::websocket::broadcast_notification(
+            state,
+            crate::websocket::NotificationMessage {
+                recipient_id: job.recipient_id,
+                notification_type: job.notification_type.to_string(),
+                payload,
+                created_at,
+            },
+        ).await;
+
+        Ok(())
+    }
+}
+
+// ==================== Media ====================
+
+/// Payload for [`PROCESS_MEDIA`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessMediaPayload {
+    pub media_id: Uuid,
+}
+
+pub struct ProcessMediaHandler;
+
+#[async_trait]
+impl JobHandler for ProcessMediaHandler {
+    async fn handle(&self, state: &Arc<AppState>, payload: serde_json::Value) -> ApiResult<()> {
+        let job: ProcessMediaPayload = parse_payload(payload)?;
+
+        let media = sqlx::query!(
+            r#"SELECT storage_path, mime_type, is_processed as "is_processed!" FROM media WHERE id = $1"#,
+            job.media_id
+        )
+        .fetch_optional(state.db.pool())
+        .await?;
+
+        // Media deleted before processing ran - nothing to do
+        let Some(media) = media else {
+            return Ok(());
+        };
+
+        if media.is_processed {
+            return Ok(());
+        }
+
+        let thumbnail_path = state
+            .storage
+            .create_thumbnail(&job.media_id, &media.storage_path, &media.mime_type)
+            .await?;
+
+        sqlx::query!(
+            "UPDATE media SET thumbnail_path = $2, is_processed = true WHERE id = $1",
+            job.media_id,
+            thumbnail_path
+        )
+        .execute(state.db.pool())
+        .await?;
+
+        debug!(media_id = %job.media_id, "Media processed");
+        Ok(())
+    }
+}
+
+// ==================== Link previews ====================
+
+/// Payload for [`UNFURL_LINK`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnfurlLinkPayload {
+    pub post_id: Uuid,
+}
+
+pub struct UnfurlLinkHandler;
+
+#[async_trait]
+impl JobHandler for UnfurlLinkHandler {
+    async fn handle(&self, state: &Arc<AppState>, payload: serde_json::Value) -> ApiResult<()> {
+        let job: UnfurlLinkPayload = parse_payload(payload)?;
+
+        let post = sqlx::query!(
+            r#"SELECT url, is_removed as "is_removed!" FROM posts WHERE id = $1"#,
+            job.post_id
+        )
+        .fetch_optional(state.db.pool())
+        .await?;
+
+        // Post removed before the job ran - nothing to do
+        let Some(link) = post.filter(|post| !post.is_removed).and_then(|post| post.url) else {
+            return Ok(());
+        };
+
+        let client = LinkPreviewClient::new();
+        let unfurled = match Url::parse(&link) {
+            Ok(url) => client.unfurl(&url).await,
+            Err(_) => Err(ApiError::InvalidInput("Malformed link".to_string())),
+        };
+
+        let unfurled = match unfurled {
+            Ok(unfurled) => unfurled,
+            // Retrying cannot help these
+            Err(ApiError::InvalidInput(reason)) => {
+                info!(post_id = %job.post_id, reason = %reason, "Link cannot be previewed");
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+
+        let metadata = unfurled.metadata;
+
+        // A missing image doesn't hold up the rest of the preview
+        let image_id = match &metadata.image_url {
+            Some(image_url) => match store_preview_image(state, &client, image_url).await {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    warn!(post_id = %job.post_id, error = %e, "Failed to store preview image");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        sqlx::query!(
+            r#"
+            INSERT INTO link_previews (post_id, url, title, description, site_name, image_id, fetched_at)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW())
+            ON CONFLICT (post_id) DO UPDATE
+            SET url = EXCLUDED.url, title = EXCLUDED.title, description = EXCLUDED.description,
+                site_name = EXCLUDED.site_name, image_id = EXCLUDED.image_id,
+                fetched_at = EXCLUDED.fetched_at
+            "#,
+            job.post_id,
+            unfurled.url.as_str(),
+            metadata.title,
+            metadata.description,
+            metadata.site_name,
+            image_id
+        )
+        .execute(state.db.pool())
+        .await?;
+
+        debug!(post_id = %job.post_id, "Link preview stored");
+        Ok(())
+    }
+}
+
+/// Fetch a preview image and store it, stripped of metadata, as media
+async fn store_preview_image(
+    state: &Arc<AppState>,
+    client: &LinkPreviewClient,
+    url: &Url,
+) -> ApiResult<Uuid> {
+    let image = client.fetch_image(url).await?;
+    let stored = state.storage.store_image(&image.data, None, &image.mime_type).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO media (id, file_hash, mime_type, file_size, storage_path, is_processed, created_at)
+        VALUES ($1, $2, $3, $4, $5, false, NOW())
+        "#,
+        stored.id,
+        stored.content_hash,
+        stored.mime_type,
+        stored.size as i32,
+        stored.path
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    JobQueue::new(state.db.pool().clone())
+        .enqueue(PROCESS_MEDIA, &ProcessMediaPayload { media_id: stored.id })
+        .await?;
+
+    Ok(stored.id)
+}
+
+// ==================== Rendered content ====================
+
+/// Rows rendered per batch
+const RENDER_BATCH_SIZE: i64 = 500;
+/// Batches per job run before handing off to a follow-up job
+const RENDER_BATCHES_PER_RUN: usize = 20;
+
+/// Table re-rendered by [`RENDER_CONTENT`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderTarget {
+    Posts,
+    Comments,
+}
+
+/// Payload for [`RENDER_CONTENT`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderContentPayload {
+    pub target: RenderTarget,
+    /// Only render content in this space
+    #[serde(default)]
+    pub space_id: Option<Uuid>,
+    /// Resume after this row id
+    #[serde(default)]
+    pub after: Option<Uuid>,
+}
+
+/// Renders `content_html` in id order, re-enqueueing itself to continue
+pub struct RenderContentHandler;
+
+#[async_trait]
+impl JobHandler for RenderContentHandler {
+    async fn handle(&self, state: &Arc<AppState>, payload: serde_json::Value) -> ApiResult<()> {
+        let mut job: RenderContentPayload = parse_payload(payload)?;
+
+        for _ in 0..RENDER_BATCHES_PER_RUN {
+            let rows = match job.target {
+                RenderTarget::Posts => render_posts_batch(state, job.space_id, job.after).await?,
+                RenderTarget::Comments => render_comments_batch(state, job.space_id, job.after).await?,
+            };
+
+            match rows {
+                Some(last) => job.after = Some(last),
+                None => {
+                    info!(table = ?job.target, space_id = ?job.space_id, "Content re-rendered");
+                    return Ok(());
+                }
+            }
+        }
+
+        JobQueue::new(state.db.pool().clone())
+            .enqueue(RENDER_CONTENT, &job)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// Render one batch of posts, returning the last id seen
+async fn render_posts_batch(
+    state: &Arc<AppState>,
+    space_id: Option<Uuid>,
+    after: Option<Uuid>,
+) -> ApiResult<Option<Uuid>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT p.id, p.content as "content!", s.allowed_tags
+        FROM posts p
+        JOIN spaces s ON s.id = p.space_id
+        WHERE p.content IS NOT NULL
+          AND ($1::UUID IS NULL OR p.space_id = $1)
+          AND ($2::UUID IS NULL OR p.id > $2)
+        ORDER BY p.id
+        LIMIT $3
+        "#,
+        space_id,
+        after,
+        RENDER_BATCH_SIZE
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let Some(last) = rows.last().map(|row| row.id) else {
+        return Ok(None);
+    };
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+    let html: Vec<String> = rows
+        .iter()
+        .map(|row| render_markdown(&row.content, row.allowed_tags.as_deref()))
+        .collect();
+    let contents: Vec<String> = rows.into_iter().map(|row| row.content).collect();
+
+    // Rows edited since they were read already carry fresh HTML
+    sqlx::query!(
+        r#"
+        UPDATE posts p
+        SET content_html = r.html
+        FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[]) AS r(id, html, content)
+        WHERE p.id = r.id AND p.content = r.content
+        "#,
+        &ids,
+        &html,
+        &contents
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    Ok(Some(last))
+}
+
+/// Render one batch of comments, returning the last id seen
+async fn render_comments_batch(
+    state: &Arc<AppState>,
+    space_id: Option<Uuid>,
+    after: Option<Uuid>,
+) -> ApiResult<Option<Uuid>> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT c.id, c.content, s.allowed_tags
+        FROM comments c
+        JOIN posts p ON p.id = c.post_id
+        JOIN spaces s ON s.id = p.space_id
+        WHERE ($1::UUID IS NULL OR p.space_id = $1)
+          AND ($2::UUID IS NULL OR c.id > $2)
+        ORDER BY c.id
+        LIMIT $3
+        "#,
+        space_id,
+        after,
+        RENDER_BATCH_SIZE
+    )
+    .fetch_all(state.db.pool())
+    .await?;
+
+    let Some(last) = rows.last().map(|row| row.id) else {
+        return Ok(None);
+    };
+
+    let ids: Vec<Uuid> = rows.iter().map(|row| row.id).collect();
+    let html: Vec<String> = rows
+        .iter()
+        .map(|row| render_markdown(&row.content, row.allowed_tags.as_deref()))
+        .collect();
+    let contents: Vec<String> = rows.into_iter().map(|row| row.content).collect();
+
+    sqlx::query!(
+        r#"
+        UPDATE comments c
+        SET content_html = r.html
+        FROM UNNEST($1::UUID[], $2::TEXT[], $3::TEXT[]) AS r(id, html, content)
+        WHERE c.id = r.id AND c.content = r.content
+        "#,
+        &ids,
+        &html,
+        &contents
+    )
+    .execute(state.db.pool())
+    .await?;
+
+    Ok(Some(last))
+}
+
+// ==================== Periodic maintenance ====================
+
+/// Removes expired tokens, old notifications, temp files and finished jobs
+pub struct CleanupHandler;
+
+#[async_trait]
+impl JobHandler for CleanupHandler {
+    async fn handle(&self, state: &Arc<AppState>, _payload: serde_json::Value) -> ApiResult<()> {
+        // Clean up expired refresh tokens
+        let result = sqlx::query!(
+            "DELETE FROM refresh_tokens WHERE expires_at < NOW() OR revoked = true"
+        )
+        .execute(state.db.pool())
+        .await?;
+
+        if result.rows_affected() > 0 {
+            info!(count = result.rows_affected(), "Cleaned up expired refresh tokens");
+        }
+
+        // Clean up old notifications (older than 30 days)
+        let result = sqlx::query!(
+            "DELETE FROM notifications WHERE created_at < NOW() - INTERVAL '30 days' AND is_read = true"
+        )
+        .execute(state.db.pool())
+        .await?;
+
+        if result.rows_affected() > 0 {
+            info!(count = result.rows_affected(), "Cleaned up old notifications");
+        }
+
+        // Clean up completed jobs (older than 1 day)
+        let purged = JobQueue::new(state.db.pool().clone())
+            .purge_completed(chrono::Duration::days(1))
+            .await?;
+
+        if purged > 0 {
+            info!(count = purged, "Purged completed jobs");
+        }
+
+        // Clean up temp files
+        if let Err(e) = state.storage.cleanup_temp_files(Duration::from_secs(86400)).await {
+            error!(error = %e, "Failed to clean up temp files");
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,223 @@
+//! Leader election for periodic work
+//!
+//! Instances compete for a Redis lease; the holder renews it well before it
+//! expires and is the only instance that runs periodic tasks. If the leader
+//! dies its lease expires and another instance takes over on its next attempt.
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::time::{interval, Duration, MissedTickBehavior};
+use tracing::{error, info, warn};
+
+use crate::errors::ApiResult;
+use crate::infrastructure::cache::RedisPool;
+
+/// Lease name for the periodic job scheduler
+pub const SCHEDULER_LEASE: &str = "scheduler";
+
+/// How long a lease is valid without renewal
+const LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// How often the holder renews and followers retry acquisition
+const RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Lease-based leader election for a single named role
+#[derive(Clone)]
+pub struct LeaderElection {
+    redis: RedisPool,
+    lease: &'static str,
+    instance_id: String,
+    state: Arc<LeaseState>,
+}
+
+/// Local view of the lease, shared with status reporting
+#[derive(Default)]
+struct LeaseState {
+    is_leader: AtomicBool,
+    /// Unix timestamp (ms) leadership was last acquired, 0 if never
+    acquired_at: AtomicI64,
+    acquisitions: AtomicU64,
+    losses: AtomicU64,
+    renew_failures: AtomicU64,
+}
+
+/// Snapshot of the lease for health reporting
+#[derive(Debug, Clone, Serialize)]
+pub struct LeaseStatus {
+    pub lease: String,
+    pub instance_id: String,
+    pub is_leader: bool,
+    /// Current holder according to Redis
+    pub holder: Option<String>,
+    pub acquired_at: Option<DateTime<Utc>>,
+    pub acquisitions: u64,
+    pub losses: u64,
+    pub renew_failures: u64,
+}
+
+impl LeaderElection {
+    /// Create an election for the given lease; call [`run`](Self::run) to participate
+    pub fn new(redis: RedisPool, lease: &'static str) -> Self {
+        let instance_id = format!(
+            "{}:{}",
+            std::env::var("HOSTNAME").unwrap_or_else(|_| "instance".to_string()),
+            uuid::Uuid::new_v4()
+        );
+
+        Self {
+            redis,
+            lease,
+            instance_id,
+            state: Arc::new(LeaseState::default()),
+        }
+    }
+
+    /// Identifier this instance uses as lease holder
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Whether this instance currently holds the lease
+    pub fn is_leader(&self) -> bool {
+        self.state.is_leader.load(Ordering::Acquire)
+    }
+
+    fn key(&self) -> String {
+        format!("leader:{}", self.lease)
+    }
+
+    /// Participate in the election until the task is dropped
+    pub async fn run(self) {
+        let mut ticker = interval(RENEW_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            self.tick().await;
+        }
+    }
+
+    /// Renew the lease if held, otherwise try to acquire it
+    async fn tick(&self) {
+        let key = self.key();
+
+        if self.is_leader() {
+            match self.redis.renew_lease(&key, &self.instance_id, LEASE_TTL).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    warn!(lease = %self.lease, instance_id = %self.instance_id, "Lost leadership");
+                    self.state.record_lost();
+                }
+                Err(e) => {
+                    // Step down: another instance may take over once the lease
+                    // expires, and we cannot prove we still hold it
+                    error!(error = %e, lease = %self.lease, "Failed to renew lease, stepping down");
+                    self.state.renew_failures.fetch_add(1, Ordering::Relaxed);
+                    self.state.record_lost();
+                }
+            }
+            return;
+        }
+
+        match self.redis.try_acquire_lease(&key, &self.instance_id, LEASE_TTL).await {
+            Ok(true) => {
+                info!(lease = %self.lease, instance_id = %self.instance_id, "Acquired leadership");
+                self.state.record_acquired(Utc::now());
+            }
+            Ok(false) => {}
+            Err(e) => error!(error = %e, lease = %self.lease, "Failed to acquire lease"),
+        }
+    }
+
+    /// Give up the lease so another instance can take over immediately
+    pub async fn resign(&self) {
+        if !self.is_leader() {
+            return;
+        }
+
+        self.state.record_lost();
+
+        match self.redis.release_lease(&self.key(), &self.instance_id).await {
+            Ok(_) => info!(lease = %self.lease, instance_id = %self.instance_id, "Released leadership"),
+            Err(e) => error!(error = %e, lease = %self.lease, "Failed to release lease"),
+        }
+    }
+
+    /// Current lease status, including the holder recorded in Redis
+    pub async fn status(&self) -> ApiResult<LeaseStatus> {
+        let holder = self.redis.lease_holder(&self.key()).await?;
+        Ok(self.state.snapshot(self.lease, &self.instance_id, holder))
+    }
+}
+
+impl LeaseState {
+    fn record_acquired(&self, at: DateTime<Utc>) {
+        self.acquired_at.store(at.timestamp_millis(), Ordering::Relaxed);
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        self.is_leader.store(true, Ordering::Release);
+    }
+
+    fn record_lost(&self) {
+        if self.is_leader.swap(false, Ordering::AcqRel) {
+            self.losses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self, lease: &str, instance_id: &str, holder: Option<String>) -> LeaseStatus {
+        let acquired_at = match self.acquired_at.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Utc.timestamp_millis_opt(ms).single(),
+        };
+
+        LeaseStatus {
+            lease: lease.to_string(),
+            instance_id: instance_id.to_string(),
+            is_leader: self.is_leader.load(Ordering::Acquire),
+            holder,
+            acquired_at,
+            acquisitions: self.acquisitions.load(Ordering::Relaxed),
+            losses: self.losses.load(Ordering::Relaxed),
+            renew_failures: self.renew_failures.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lease_transitions_are_counted() {
+        let state = LeaseState::default();
+        assert!(!state.is_leader.load(Ordering::Acquire));
+
+        state.record_acquired(Utc::now());
+        state.record_lost();
+        // Losing an already-lost lease is not a second transition
+        state.record_lost();
+
+        let status = state.snapshot(SCHEDULER_LEASE, "a", None);
+        assert!(!status.is_leader);
+        assert_eq!(status.acquisitions, 1);
+        assert_eq!(status.losses, 1);
+        assert!(status.acquired_at.is_some());
+    }
+
+    #[test]
+    fn test_snapshot_before_first_acquisition() {
+        let state = LeaseState::default();
+        let status = state.snapshot(SCHEDULER_LEASE, "a", Some("b".to_string()));
+
+        assert!(!status.is_leader);
+        assert_eq!(status.acquired_at, None);
+        assert_eq!(status.holder.as_deref(), Some("b"));
+    }
+
+    #[test]
+    fn test_renewal_outlives_interval() {
+        // The holder must get several renewal attempts before the lease lapses
+        assert!(LEASE_TTL >= RENEW_INTERVAL * 2);
+    }
+}
@@ -1,4 +1,10 @@
 //! Background jobs module
 
+pub mod handlers;
+pub mod leader;
+pub mod queue;
 pub mod workers;
+
+pub use leader::{LeaderElection, LeaseStatus, SCHEDULER_LEASE};
+pub use queue::{EnqueueOptions, Job, JobQueue, JobStatus};
 pub use workers::*;
@@ -0,0 +1,295 @@
+//! Persistent job queue
+//!
+//! Jobs are stored in the `jobs` table and claimed with `FOR UPDATE SKIP LOCKED`,
+//! so any number of instances can poll the queue without running a job twice.
+//! Failed jobs are retried with exponential backoff until they exhaust their
+//! attempts, at which point they are dead-lettered for inspection.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, PgPool};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::errors::ApiResult;
+
+/// Default number of attempts before a job is dead-lettered
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+/// Delay before the first retry
+const BASE_RETRY_DELAY_SECS: i64 = 10;
+
+/// Upper bound for the retry delay
+const MAX_RETRY_DELAY_SECS: i64 = 3600;
+
+/// Job lifecycle states
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[sqlx(type_name = "varchar", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Pending,
+    Running,
+    Completed,
+    Dead,
+}
+
+/// A queued job
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct Job {
+    pub id: Uuid,
+    pub job_type: String,
+    pub payload: serde_json::Value,
+    pub status: JobStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub unique_key: Option<String>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Options for enqueueing a job
+#[derive(Debug, Clone)]
+pub struct EnqueueOptions {
+    /// Earliest time the job may run
+    pub run_at: DateTime<Utc>,
+    /// Attempts before dead-lettering
+    pub max_attempts: i32,
+    /// Deduplication key; a key is only ever enqueued once until purged
+    pub unique_key: Option<String>,
+}
+
+impl Default for EnqueueOptions {
+    fn default() -> Self {
+        Self {
+            run_at: Utc::now(),
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            unique_key: None,
+        }
+    }
+}
+
+/// Postgres-backed job queue
+#[derive(Clone)]
+pub struct JobQueue {
+    pool: PgPool,
+}
+
+impl JobQueue {
+    /// Create a queue over the given pool
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Enqueue a job to run as soon as possible
+    pub async fn enqueue<T: Serialize>(&self, job_type: &str, payload: &T) -> ApiResult<Uuid> {
+        self.enqueue_with(job_type, payload, EnqueueOptions::default())
+            .await
+            .map(|id| id.expect("jobs without a unique key are always inserted"))
+    }
+
+    /// Enqueue a job to run at a specific time
+    pub async fn enqueue_at<T: Serialize>(
+        &self,
+        job_type: &str,
+        payload: &T,
+        run_at: DateTime<Utc>,
+    ) -> ApiResult<Uuid> {
+        let options = EnqueueOptions { run_at, ..Default::default() };
+        self.enqueue_with(job_type, payload, options)
+            .await
+            .map(|id| id.expect("jobs without a unique key are always inserted"))
+    }
+
+    /// Enqueue a job with explicit options.
+    ///
+    /// Returns `None` if a job with the same unique key already exists.
+    pub async fn enqueue_with<T: Serialize>(
+        &self,
+        job_type: &str,
+        payload: &T,
+        options: EnqueueOptions,
+    ) -> ApiResult<Option<Uuid>> {
+        let payload = serde_json::to_value(payload)?;
+
+        let id = sqlx::query_scalar!(
+            r#"
+            INSERT INTO jobs (job_type, payload, run_at, max_attempts, unique_key)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (unique_key) WHERE unique_key IS NOT NULL DO NOTHING
+            RETURNING id
+            "#,
+            job_type,
+            payload,
+            options.run_at,
+            options.max_attempts,
+            options.unique_key
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match id {
+            Some(id) => debug!(job_id = %id, job_type = %job_type, "Job enqueued"),
+            None => debug!(job_type = %job_type, "Job already queued, skipping"),
+        }
+
+        Ok(id)
+    }
+
+    /// Claim up to `limit` due jobs for a worker
+    pub async fn claim(&self, worker_id: &str, limit: i64) -> ApiResult<Vec<Job>> {
+        let jobs = sqlx::query_as!(
+            Job,
+            r#"
+            UPDATE jobs
+            SET status = 'running', attempts = attempts + 1, locked_by = $1, locked_at = NOW()
+            WHERE id IN (
+                SELECT id FROM jobs
+                WHERE status = 'pending' AND run_at <= NOW()
+                ORDER BY run_at
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, job_type, payload, status as "status: JobStatus", attempts,
+                      max_attempts, run_at, unique_key, last_error, created_at
+            "#,
+            worker_id,
+            limit
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(jobs)
+    }
+
+    /// Mark a job as completed
+    pub async fn complete(&self, job_id: Uuid) -> ApiResult<()> {
+        sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET status = 'completed', completed_at = NOW(), locked_by = NULL, locked_at = NULL
+            WHERE id = $1
+            "#,
+            job_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Record a failed attempt, scheduling a retry or dead-lettering the job
+    pub async fn fail(&self, job: &Job, error: &str) -> ApiResult<JobStatus> {
+        if job.attempts >= job.max_attempts {
+            sqlx::query!(
+                r#"
+                UPDATE jobs
+                SET status = 'dead', last_error = $2, locked_by = NULL, locked_at = NULL
+                WHERE id = $1
+                "#,
+                job.id,
+                error
+            )
+            .execute(&self.pool)
+            .await?;
+
+            warn!(job_id = %job.id, job_type = %job.job_type, attempts = job.attempts, error = %error, "Job dead-lettered");
+            return Ok(JobStatus::Dead);
+        }
+
+        let run_at = Utc::now() + retry_delay(job.attempts);
+
+        sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET status = 'pending', run_at = $2, last_error = $3, locked_by = NULL, locked_at = NULL
+            WHERE id = $1
+            "#,
+            job.id,
+            run_at,
+            error
+        )
+        .execute(&self.pool)
+        .await?;
+
+        debug!(job_id = %job.id, job_type = %job.job_type, attempts = job.attempts, retry_at = %run_at, "Job scheduled for retry");
+        Ok(JobStatus::Pending)
+    }
+
+    /// Return jobs whose worker died mid-run to the queue
+    pub async fn recover_stale(&self, older_than: chrono::Duration) -> ApiResult<u64> {
+        let cutoff = Utc::now() - older_than;
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET status = 'pending', locked_by = NULL, locked_at = NULL,
+                last_error = 'Worker lock expired'
+            WHERE status = 'running' AND locked_at < $1
+            "#,
+            cutoff
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Re-queue a dead-lettered job with a fresh set of attempts
+    pub async fn retry_dead(&self, job_id: Uuid) -> ApiResult<bool> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE jobs
+            SET status = 'pending', attempts = 0, run_at = NOW()
+            WHERE id = $1 AND status = 'dead'
+            "#,
+            job_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete completed jobs older than the given age
+    pub async fn purge_completed(&self, older_than: chrono::Duration) -> ApiResult<u64> {
+        let cutoff = Utc::now() - older_than;
+
+        let result = sqlx::query!(
+            "DELETE FROM jobs WHERE status = 'completed' AND completed_at < $1",
+            cutoff
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+}
+
+/// Exponential backoff delay after the given number of attempts
+pub fn retry_delay(attempts: i32) -> chrono::Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 20) as u32;
+    let secs = BASE_RETRY_DELAY_SECS
+        .saturating_mul(2i64.saturating_pow(exponent))
+        .min(MAX_RETRY_DELAY_SECS);
+
+    chrono::Duration::seconds(secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_doubles() {
+        assert_eq!(retry_delay(1).num_seconds(), 10);
+        assert_eq!(retry_delay(2).num_seconds(), 20);
+        assert_eq!(retry_delay(3).num_seconds(), 40);
+    }
+
+    #[test]
+    fn test_retry_delay_is_capped() {
+        assert_eq!(retry_delay(30).num_seconds(), MAX_RETRY_DELAY_SECS);
+        assert_eq!(retry_delay(i32::MAX).num_seconds(), MAX_RETRY_DELAY_SECS);
+    }
+}
@@ -1,143 +1,227 @@
 //! Background job workers
+//!
+//! Workers poll the persistent [`JobQueue`] and dispatch claimed jobs to their
+//! registered handlers. Periodic maintenance is scheduled into the same queue
+//! by whichever instance holds the scheduler lease, keyed by time slot so a
+//! leadership change mid-period cannot duplicate a run.
 
+use futures::future::join_all;
 use std::sync::Arc;
 use tokio::time::{interval, Duration};
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
+use crate::errors::ApiError;
 use crate::AppState;
 
+use super::handlers::{self, JobRegistry, NotificationContext, SendNotificationPayload};
+use super::leader::LeaderElection;
+use super::queue::{EnqueueOptions, Job, JobQueue};
+
+/// How often an idle worker polls for due jobs
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum jobs claimed per poll
+const CLAIM_BATCH_SIZE: i64 = 10;
+
+/// Maximum time a single job may run before it is treated as failed
+const JOB_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How often stale locks from crashed workers are recovered
+const RECOVERY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Running jobs locked for longer than this are returned to the queue
+const STALE_LOCK_AGE: i64 = 900;
+
+/// How often the leader checks whether a periodic job is due
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Periodic jobs: (job type, period in seconds)
+const PERIODIC_JOBS: &[(&str, u64)] = &[
+    (handlers::CLEANUP, 3600), // Every hour
+];
+
 /// Start all background workers
 pub async fn start_background_workers(state: Arc<AppState>) {
-    tokio::spawn(cleanup_worker(state.clone()));
-    tokio::spawn(score_update_worker(state.clone()));
+    let queue = JobQueue::new(state.db.pool().clone());
+    let registry = Arc::new(JobRegistry::with_default_handlers());
+
+    tokio::spawn(job_worker(state.clone(), queue.clone(), registry));
+
+    // Periodic work runs only on the instance holding the scheduler lease
+    tokio::spawn(state.leader.clone().run());
+    tokio::spawn(stale_job_recovery(queue.clone(), state.leader.clone()));
+
+    for &(job_type, period) in PERIODIC_JOBS {
+        tokio::spawn(periodic_scheduler(queue.clone(), state.leader.clone(), job_type, period));
+    }
 
     info!("Background workers started");
 }
 
-/// Cleanup worker - removes expired tokens and old data
-async fn cleanup_worker(state: Arc<AppState>) {
-    let mut ticker = interval(Duration::from_secs(3600)); // Every hour
+/// Worker loop - claims due jobs and runs them
+async fn job_worker(state: Arc<AppState>, queue: JobQueue, registry: Arc<JobRegistry>) {
+    let worker_id = format!(
+        "{}:{}",
+        std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".to_string()),
+        uuid::Uuid::new_v4()
+    );
 
-    loop {
-        ticker.tick().await;
+    info!(worker_id = %worker_id, "Job worker started");
 
-        // Clean up expired refresh tokens
-        match sqlx::query!(
-            "DELETE FROM refresh_tokens WHERE expires_at < NOW() OR revoked = true"
-        )
-        .execute(state.db.pool())
-        .await
-        {
-            Ok(result) => {
-                if result.rows_affected() > 0 {
-                    info!(count = result.rows_affected(), "Cleaned up expired refresh tokens");
-                }
-            }
+    loop {
+        let jobs = match queue.claim(&worker_id, CLAIM_BATCH_SIZE).await {
+            Ok(jobs) => jobs,
             Err(e) => {
-                error!(error = %e, "Failed to clean up refresh tokens");
+                error!(error = %e, "Failed to claim jobs");
+                tokio::time::sleep(POLL_INTERVAL).await;
+                continue;
             }
+        };
+
+        if jobs.is_empty() {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            continue;
         }
 
-        // Clean up old notifications (older than 30 days)
-        match sqlx::query!(
-            "DELETE FROM notifications WHERE created_at < NOW() - INTERVAL '30 days' AND is_read = true"
-        )
-        .execute(state.db.pool())
-        .await
-        {
-            Ok(result) => {
-                if result.rows_affected() > 0 {
-                    info!(count = result.rows_affected(), "Cleaned up old notifications");
-                }
-            }
-            Err(e) => {
-                error!(error = %e, "Failed to clean up notifications");
+        join_all(jobs.into_iter().map(|job| run_job(&state, &queue, &registry, job))).await;
+    }
+}
+
+/// Run a single claimed job and record the outcome
+async fn run_job(state: &Arc<AppState>, queue: &JobQueue, registry: &JobRegistry, job: Job) {
+    let result = match registry.get(&job.job_type) {
+        Some(handler) => {
+            match tokio::time::timeout(JOB_TIMEOUT, handler.handle(state, job.payload.clone())).await {
+                Ok(result) => result.map_err(|e| e.to_string()),
+                Err(_) => Err("Job timed out".to_string()),
             }
         }
+        None => Err(format!("No handler registered for job type '{}'", job.job_type)),
+    };
 
-        // Clean up temp files
-        if let Err(e) = state.storage.cleanup_temp_files(Duration::from_secs(86400)).await {
-            error!(error = %e, "Failed to clean up temp files");
+    let outcome = match result {
+        Ok(()) => {
+            debug!(job_id = %job.id, job_type = %job.job_type, "Job completed");
+            queue.complete(job.id).await
         }
+        Err(e) => {
+            warn!(job_id = %job.id, job_type = %job.job_type, attempt = job.attempts, error = %e, "Job failed");
+            queue.fail(&job, &e).await.map(|_| ())
+        }
+    };
 
-        debug!("Cleanup worker completed cycle");
+    if let Err(e) = outcome {
+        error!(job_id = %job.id, error = %e, "Failed to record job outcome");
     }
 }
 
-/// Score update worker - recalculates hot scores periodically
-async fn score_update_worker(state: Arc<AppState>) {
-    let mut ticker = interval(Duration::from_secs(300)); // Every 5 minutes
+/// Returns jobs held by crashed workers to the queue
+async fn stale_job_recovery(queue: JobQueue, leader: LeaderElection) {
+    let mut ticker = interval(RECOVERY_INTERVAL);
 
     loop {
         ticker.tick().await;
 
-        // Update post scores based on age and votes
-        // Hot score decays over time
-        match sqlx::query!(
-            r#"
-            UPDATE posts
-            SET score = (
-                CASE
-                    WHEN upvotes - downvotes > 0 THEN
-                        LOG(GREATEST(ABS(upvotes - downvotes), 1)) +
-                        EXTRACT(EPOCH FROM created_at) / 45000.0
-                    WHEN upvotes - downvotes < 0 THEN
-                        -LOG(GREATEST(ABS(upvotes - downvotes), 1)) +
-                        EXTRACT(EPOCH FROM created_at) / 45000.0
-                    ELSE
-                        EXTRACT(EPOCH FROM created_at) / 45000.0
-                END
-            )::INTEGER
-            WHERE created_at > NOW() - INTERVAL '7 days'
-            "#
-        )
-        .execute(state.db.pool())
-        .await
-        {
-            Ok(result) => {
-                if result.rows_affected() > 0 {
-                    debug!(count = result.rows_affected(), "Updated post scores");
-                }
-            }
-            Err(e) => {
-                error!(error = %e, "Failed to update post scores");
-            }
+        if !leader.is_leader() {
+            continue;
+        }
+
+        match queue.recover_stale(chrono::Duration::seconds(STALE_LOCK_AGE)).await {
+            Ok(count) if count > 0 => warn!(count = count, "Recovered stale jobs"),
+            Ok(_) => {}
+            Err(e) => error!(error = %e, "Failed to recover stale jobs"),
+        }
+    }
+}
+
+/// Enqueues a periodic job once per time slot while this instance is leader.
+///
+/// Slots are checked frequently rather than once per period so a newly
+/// elected leader picks up any slot its predecessor missed.
+async fn periodic_scheduler(
+    queue: JobQueue,
+    leader: LeaderElection,
+    job_type: &'static str,
+    period: u64,
+) {
+    let mut ticker = interval(SCHEDULE_CHECK_INTERVAL);
+    let mut last_slot = None;
+
+    loop {
+        ticker.tick().await;
+
+        if !leader.is_leader() {
+            continue;
+        }
+
+        let slot = chrono::Utc::now().timestamp() / period as i64;
+        if last_slot == Some(slot) {
+            continue;
         }
 
-        debug!("Score update worker completed cycle");
+        let options = EnqueueOptions {
+            unique_key: Some(format!("{}:{}", job_type, slot)),
+            max_attempts: 3,
+            ..Default::default()
+        };
+
+        match queue.enqueue_with(job_type, &serde_json::json!({}), options).await {
+            Ok(_) => last_slot = Some(slot),
+            Err(e) => error!(error = %e, job_type = %job_type, "Failed to schedule periodic job"),
+        }
     }
 }
 
 /// Job for sending notification
+///
+/// Queues the notification for delivery; the `send_notification` handler
+/// checks the recipient's preferences, then writes the row and pushes it to
+/// connected clients.
 pub async fn send_notification_job(
     state: &Arc<AppState>,
     recipient_id: uuid::Uuid,
     notification_type: crate::domain::entities::NotificationType,
+    context: NotificationContext,
     payload: serde_json::Value,
-) -> Result<(), crate::errors::ApiError> {
-    sqlx::query!(
-        r#"
-        INSERT INTO notifications (id, recipient_id, notification_type, payload, created_at)
-        VALUES ($1, $2, $3, $4, NOW())
-        "#,
-        uuid::Uuid::new_v4(),
-        recipient_id,
-        notification_type.to_string(),
-        payload
-    )
-    .execute(state.db.pool())
-    .await?;
-
-    // Broadcast to WebSocket if connected
-    crate::websocket::broadcast_notification(
-        state,
-        crate::websocket::NotificationMessage {
-            recipient_id,
-            notification_type: notification_type.to_string(),
-            payload,
-            created_at: chrono::Utc::now(),
-        },
-    ).await;
+) -> Result<(), ApiError> {
+    JobQueue::new(state.db.pool().clone())
+        .enqueue(
+            handlers::SEND_NOTIFICATION,
+            &SendNotificationPayload {
+                recipient_id,
+                notification_type,
+                payload,
+                group_key: None,
+                context,
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Job for sending a notification that coalesces with the recipient's unread
+/// notification of the same group
+pub async fn send_grouped_notification_job(
+    state: &Arc<AppState>,
+    recipient_id: uuid::Uuid,
+    notification_type: crate::domain::entities::NotificationType,
+    group_key: String,
+    context: NotificationContext,
+    payload: serde_json::Value,
+) -> Result<(), ApiError> {
+    JobQueue::new(state.db.pool().clone())
+        .enqueue(
+            handlers::SEND_NOTIFICATION,
+            &SendNotificationPayload {
+                recipient_id,
+                notification_type,
+                payload,
+                group_key: Some(group_key),
+                context,
+            },
+        )
+        .await?;
 
     Ok(())
 }
@@ -39,6 +39,8 @@ use infrastructure::{
     crypto::CryptoService,
     storage::StorageService,
 };
+use jobs::LeaderElection;
+use websocket::ConnectionRegistry;
 
 /// Shared application state accessible from all handlers
 #[derive(Clone)]
@@ -53,6 +55,10 @@ pub struct AppState {
     pub storage: StorageService,
     /// Application settings
     pub settings: Settings,
+    /// Live WebSocket connections on this instance
+    pub connections: ConnectionRegistry,
+    /// Scheduler lease held by at most one instance
+    pub leader: LeaderElection,
 }
 
 impl AppState {
@@ -19,6 +19,8 @@ use silent_alliance::{
         crypto::CryptoService,
         storage::StorageService,
     },
+    jobs::{start_background_workers, LeaderElection, SCHEDULER_LEASE},
+    websocket::{start_channel_subscriber, start_notification_subscriber, start_recheck_subscriber, ConnectionRegistry},
     AppState,
 };
 
@@ -92,12 +94,23 @@ async fn main() -> anyhow::Result<()> {
     // Create shared application state
     let app_state = Arc::new(AppState {
         db: db_pool,
+        leader: LeaderElection::new(redis_pool.clone(), SCHEDULER_LEASE),
         redis: redis_pool,
         crypto: crypto_service,
         storage: storage_service,
         settings: settings.clone(),
+        connections: ConnectionRegistry::new(),
     });
 
+    // Relay notifications and channel events published by any instance to
+    // local WebSocket clients
+    start_notification_subscriber(app_state.clone());
+    start_channel_subscriber(app_state.clone());
+    start_recheck_subscriber(app_state.clone());
+
+    // Start the job queue workers and periodic job schedulers
+    start_background_workers(app_state.clone()).await;
+
     // Create the router with all routes and middleware
     let app = create_router(app_state.clone());
 
@@ -118,6 +131,9 @@ async fn main() -> anyhow::Result<()> {
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
+    // Hand the scheduler lease over without waiting for it to expire
+    app_state.leader.resign().await;
+
     info!("Server shutdown complete");
     Ok(())
 }
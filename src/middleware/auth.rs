@@ -13,7 +13,9 @@ use std::sync::Arc;
 use tracing::{debug, warn};
 use uuid::Uuid;
 
+use crate::domain::entities::{MemberRole, SpacePermission};
 use crate::domain::services::auth::JwtService;
+use crate::domain::services::permissions::SpaceAccess;
 use crate::errors::ApiError;
 use crate::AppState;
 
@@ -146,49 +148,65 @@ pub struct ModeratorUser {
     pub space_id: Option<Uuid>,
 }
 
-/// Check if user is a moderator of a space
-pub async fn check_moderator(
+/// Resolve what an identity may do in a space
+pub async fn space_access(
     state: &Arc<AppState>,
     identity_id: Uuid,
     space_id: Uuid,
-) -> Result<bool, ApiError> {
-    let is_mod: Option<bool> = sqlx::query_scalar!(
+) -> Result<SpaceAccess, ApiError> {
+    let row = sqlx::query!(
         r#"
-        SELECT EXISTS(
-            SELECT 1 FROM space_members
-            WHERE space_id = $1 AND identity_id = $2 AND role IN ('moderator', 'admin')
-        ) as "exists!"
+        SELECT sm.role as "role?: MemberRole",
+               COALESCE(sm.permissions, '{}') as "permissions!: Vec<SpacePermission>",
+               COALESCE(s.creator_id = $2, FALSE)
+                   OR EXISTS (SELECT 1 FROM platform_admins WHERE identity_id = $2) as "is_owner!"
+        FROM spaces s
+        LEFT JOIN space_members sm ON sm.space_id = s.id AND sm.identity_id = $2
+        WHERE s.id = $1
         "#,
         space_id,
         identity_id
     )
-    .fetch_one(state.db.pool())
-    .await?;
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
 
-    Ok(is_mod.unwrap_or(false))
+    Ok(SpaceAccess::resolve(row.role, &row.permissions, row.is_owner))
 }
 
-/// Check if user is the creator/admin of a space
-pub async fn check_space_admin(
+/// Require a permission in a space
+pub async fn require_space_permission(
     state: &Arc<AppState>,
     identity_id: Uuid,
     space_id: Uuid,
-) -> Result<bool, ApiError> {
-    let is_admin: Option<bool> = sqlx::query_scalar!(
-        r#"
-        SELECT EXISTS(
-            SELECT 1 FROM space_members
-            WHERE space_id = $1 AND identity_id = $2 AND role = 'admin'
-        ) OR EXISTS(
-            SELECT 1 FROM spaces
-            WHERE id = $1 AND creator_id = $2
-        ) as "exists!"
-        "#,
-        space_id,
+    permission: SpacePermission,
+) -> Result<SpaceAccess, ApiError> {
+    let access = space_access(state, identity_id, space_id).await?;
+
+    if !access.has(permission) {
+        return Err(ApiError::Forbidden);
+    }
+
+    Ok(access)
+}
+
+/// Check if an identity is a platform admin
+pub async fn is_platform_admin(state: &Arc<AppState>, identity_id: Uuid) -> Result<bool, ApiError> {
+    let is_admin = sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM platform_admins WHERE identity_id = $1) as "exists!""#,
         identity_id
     )
     .fetch_one(state.db.pool())
     .await?;
 
-    Ok(is_admin.unwrap_or(false))
+    Ok(is_admin)
+}
+
+/// Require the platform admin role
+pub async fn require_platform_admin(state: &Arc<AppState>, identity_id: Uuid) -> Result<(), ApiError> {
+    if !is_platform_admin(state, identity_id).await? {
+        return Err(ApiError::Forbidden);
+    }
+
+    Ok(())
 }
@@ -0,0 +1,200 @@
+//! Live channels clients can subscribe to over the WebSocket
+//!
+//! Channel names take the form `post:<id>`, `poll:<post id>`, `space:<slug>`
+//! and `conversation:<id>`. Subscribing requires the same access as reading
+//! the underlying entity over the REST API, and subscriptions are checked
+//! again with [`recheck_subscriptions`](super::recheck_subscriptions) when an
+//! identity may have lost that access.
+
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::domain::entities::SLUG_REGEX;
+use crate::errors::{ApiError, ApiResult};
+use crate::AppState;
+
+/// A subscribable live channel
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// New comments and vote tallies on a post
+    Post(Uuid),
+    /// Live tallies for the poll on a post; voters only until it closes
+    Poll(Uuid),
+    /// New posts in a space
+    Space(String),
+    /// New messages and typing indicators in a conversation
+    Conversation(Uuid),
+}
+
+impl Channel {
+    /// Check that an identity may subscribe to this channel
+    pub async fn authorize(&self, state: &AppState, identity_id: Uuid) -> ApiResult<()> {
+        match self {
+            Channel::Post(post_id) => authorize_post(state, *post_id, identity_id).await?,
+            Channel::Poll(post_id) => {
+                authorize_post(state, *post_id, identity_id).await?;
+
+                let poll = sqlx::query!(
+                    r#"
+                    SELECT (closes_at IS NOT NULL AND closes_at <= NOW()) as "is_closed!",
+                           EXISTS(SELECT 1 FROM poll_ballots b
+                                  WHERE b.post_id = polls.post_id AND b.identity_id = $2) as "has_voted!"
+                    FROM polls
+                    WHERE post_id = $1
+                    "#,
+                    post_id,
+                    identity_id
+                )
+                .fetch_optional(state.db.pool())
+                .await?
+                .ok_or_else(|| ApiError::NotFound("Poll not found".to_string()))?;
+
+                // Tallies stay hidden from non-voters until the poll closes
+                if !poll.is_closed && !poll.has_voted {
+                    return Err(ApiError::Forbidden);
+                }
+            }
+            Channel::Space(slug) => {
+                let space = sqlx::query!(
+                    r#"SELECT id, is_private as "is_private!" FROM spaces WHERE slug = $1"#,
+                    slug
+                )
+                .fetch_optional(state.db.pool())
+                .await?
+                .ok_or_else(|| ApiError::NotFound("Space not found".to_string()))?;
+
+                if space.is_private {
+                    require_space_member(state, space.id, identity_id).await?;
+                }
+            }
+            Channel::Conversation(conversation_id) => {
+                // Participants blocked by another participant stop receiving
+                // the conversation live
+                let allowed = sqlx::query_scalar!(
+                    r#"
+                    SELECT EXISTS(
+                        SELECT 1 FROM conversation_participants cp
+                        WHERE cp.conversation_id = $1 AND cp.identity_id = $2
+                    ) AND NOT EXISTS(
+                        SELECT 1 FROM conversation_participants other
+                        JOIN identity_blocks b ON b.blocker_id = other.identity_id AND b.blocked_id = $2
+                        WHERE other.conversation_id = $1
+                    ) as "allowed!"
+                    "#,
+                    conversation_id,
+                    identity_id
+                )
+                .fetch_one(state.db.pool())
+                .await?;
+
+                if !allowed {
+                    return Err(ApiError::Forbidden);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+async fn authorize_post(state: &AppState, post_id: Uuid, identity_id: Uuid) -> ApiResult<()> {
+    let post = sqlx::query!(
+        r#"
+        SELECT p.is_removed as "is_removed!", p.space_id, s.is_private as "is_private!"
+        FROM posts p
+        JOIN spaces s ON s.id = p.space_id
+        WHERE p.id = $1
+        "#,
+        post_id
+    )
+    .fetch_optional(state.db.pool())
+    .await?
+    .ok_or_else(|| ApiError::NotFound("Post not found".to_string()))?;
+
+    if post.is_removed {
+        return Err(ApiError::Gone);
+    }
+
+    if post.is_private {
+        require_space_member(state, post.space_id, identity_id).await?;
+    }
+
+    Ok(())
+}
+
+async fn require_space_member(state: &AppState, space_id: Uuid, identity_id: Uuid) -> ApiResult<()> {
+    let is_member = sqlx::query_scalar!(
+        "SELECT EXISTS(SELECT 1 FROM space_members WHERE space_id = $1 AND identity_id = $2)",
+        space_id,
+        identity_id
+    )
+    .fetch_one(state.db.pool())
+    .await?
+    .unwrap_or(false);
+
+    if !is_member {
+        return Err(ApiError::Forbidden);
+    }
+
+    Ok(())
+}
+
+impl FromStr for Channel {
+    type Err = ApiError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ApiError::InvalidInput(format!("Invalid channel: {}", s));
+
+        let (kind, key) = s.split_once(':').ok_or_else(invalid)?;
+
+        match kind {
+            "post" => key.parse().map(Channel::Post).map_err(|_| invalid()),
+            "poll" => key.parse().map(Channel::Poll).map_err(|_| invalid()),
+            "conversation" => key.parse().map(Channel::Conversation).map_err(|_| invalid()),
+            "space" if key.len() <= 50 && SLUG_REGEX.is_match(key) => {
+                Ok(Channel::Space(key.to_string()))
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Post(id) => write!(f, "post:{}", id),
+            Channel::Poll(id) => write!(f, "poll:{}", id),
+            Channel::Space(slug) => write!(f, "space:{}", slug),
+            Channel::Conversation(id) => write!(f, "conversation:{}", id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_round_trip() {
+        let id = Uuid::new_v4();
+
+        for channel in [
+            Channel::Post(id),
+            Channel::Poll(id),
+            Channel::Space("rust_programming".to_string()),
+            Channel::Conversation(id),
+        ] {
+            assert_eq!(channel.to_string().parse::<Channel>().unwrap(), channel);
+        }
+    }
+
+    #[test]
+    fn test_invalid_channels_rejected() {
+        assert!("post:not-a-uuid".parse::<Channel>().is_err());
+        assert!("space:bad slug".parse::<Channel>().is_err());
+        assert!("space:".parse::<Channel>().is_err());
+        assert!("notifications:abc".parse::<Channel>().is_err());
+        assert!("post".parse::<Channel>().is_err());
+    }
+}
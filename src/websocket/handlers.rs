@@ -5,6 +5,9 @@
 //! - Per-connection message rate limiting
 //! - Maximum message size enforcement
 //! - Graceful disconnection handling
+//! - Live channel subscriptions (`post:<id>`, `space:<slug>`, `conversation:<id>`)
+//! - Cross-instance delivery via Redis pub/sub
+//! - Rechecking subscriptions when an identity may have lost access
 
 use futures::{sink::SinkExt, stream::StreamExt};
 use axum::extract::ws::{Message, WebSocket};
@@ -13,8 +16,12 @@ use std::time::{Duration, Instant};
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+use crate::errors::ApiError;
 use crate::AppState;
 
+use super::channels::Channel;
+use super::registry::ConnectionId;
+
 /// Maximum allowed size for a single WebSocket text message (64 KB)
 const MAX_MESSAGE_SIZE: usize = 64 * 1024;
 
@@ -27,6 +34,21 @@ const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
 /// Heartbeat interval
 const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
 
+/// Redis channel notifications are fanned out on
+const NOTIFICATION_CHANNEL: &str = "ws:notifications";
+
+/// Redis channel live channel events are fanned out on
+const CHANNEL_EVENTS_CHANNEL: &str = "ws:channels";
+
+/// Redis channel subscription rechecks are fanned out on
+const RECHECK_CHANNEL: &str = "ws:rechecks";
+
+/// Maximum live channels a single connection may subscribe to
+const MAX_SUBSCRIPTIONS_PER_CONNECTION: usize = 50;
+
+/// Delay before re-subscribing after the pub/sub connection drops
+const SUBSCRIBER_RETRY_DELAY: Duration = Duration::from_secs(5);
+
 /// Notification message for broadcasting
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct NotificationMessage {
@@ -36,6 +58,14 @@ pub struct NotificationMessage {
     pub created_at: chrono::DateTime<chrono::Utc>,
 }
 
+/// Event published on a live channel
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ChannelEvent {
+    pub channel: String,
+    pub event: String,
+    pub data: serde_json::Value,
+}
+
 /// Simple sliding-window rate limiter for a single connection
 struct ConnectionRateLimiter {
     window_start: Instant,
@@ -71,6 +101,7 @@ pub async fn handle_authenticated_websocket(
 ) {
     let (mut sender, mut receiver) = socket.split();
     let mut rate_limiter = ConnectionRateLimiter::new();
+    let (connection_id, mut outbound) = state.connections.register(identity_id);
 
     info!(identity_id = %identity_id, "WebSocket connection established");
 
@@ -82,10 +113,11 @@ pub async fn handle_authenticated_websocket(
     });
 
     if sender.send(Message::Text(welcome.to_string())).await.is_err() {
+        state.connections.unregister(identity_id, connection_id);
         return;
     }
 
-    // Main loop - handle heartbeats and messages
+    // Main loop - handle heartbeats, server pushes and messages
     loop {
         tokio::select! {
             // Heartbeat every 30 seconds
@@ -95,6 +127,13 @@ pub async fn handle_authenticated_websocket(
                 }
             }
 
+            // Forward messages queued for this connection
+            Some(text) = outbound.recv() => {
+                if sender.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+
             // Handle incoming messages
             msg = receiver.next() => {
                 match msg {
@@ -130,8 +169,16 @@ pub async fn handle_authenticated_websocket(
                         }
 
                         // Handle client messages (e.g., subscribe to channels)
-                        if let Err(e) = handle_client_message(&text, identity_id, &state).await {
-                            warn!(error = %e, "Failed to handle client message");
+                        match handle_client_message(&text, identity_id, connection_id, &state).await {
+                            Ok(Some(reply)) => {
+                                if sender.send(Message::Text(reply.to_string())).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                warn!(error = %e, "Failed to handle client message");
+                            }
                         }
                     }
                     Some(Ok(Message::Binary(data))) => {
@@ -157,49 +204,291 @@ pub async fn handle_authenticated_websocket(
             }
         }
     }
+
+    state.connections.unregister(identity_id, connection_id);
 }
 
+/// Handle a message from the client, returning an optional reply frame
 async fn handle_client_message(
     text: &str,
     identity_id: Uuid,
-    _state: &Arc<AppState>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    connection_id: ConnectionId,
+    state: &Arc<AppState>,
+) -> Result<Option<serde_json::Value>, Box<dyn std::error::Error + Send + Sync>> {
     let message: serde_json::Value = serde_json::from_str(text)?;
+    let channel_name = message.get("channel").and_then(|c| c.as_str());
 
-    match message.get("type").and_then(|t| t.as_str()) {
+    let reply = match message.get("type").and_then(|t| t.as_str()) {
         Some("subscribe") => {
-            // Handle channel subscription
-            if let Some(channel) = message.get("channel").and_then(|c| c.as_str()) {
-                debug!(identity_id = %identity_id, channel = %channel, "Subscribe request");
+            let Some(channel_name) = channel_name else {
+                return Ok(Some(error_frame("Missing channel")));
+            };
+            debug!(identity_id = %identity_id, channel = %channel_name, "Subscribe request");
+
+            let channel = match channel_name.parse::<Channel>() {
+                Ok(c) => c,
+                Err(e) => return Ok(Some(error_frame(&e.to_string()))),
+            };
+
+            if state.connections.subscription_count(identity_id, connection_id)
+                >= MAX_SUBSCRIPTIONS_PER_CONNECTION
+            {
+                return Ok(Some(error_frame("Too many subscriptions")));
+            }
+
+            if let Err(e) = channel.authorize(state, identity_id).await {
+                debug!(identity_id = %identity_id, channel = %channel, error = %e, "Subscription denied");
+                return Ok(Some(serde_json::json!({
+                    "type": "error",
+                    "channel": channel.to_string(),
+                    "message": "Subscription denied"
+                })));
             }
+
+            state.connections.subscribe(identity_id, connection_id, &channel.to_string());
+            Some(serde_json::json!({ "type": "subscribed", "channel": channel.to_string() }))
         }
         Some("unsubscribe") => {
-            // Handle channel unsubscription
-            if let Some(channel) = message.get("channel").and_then(|c| c.as_str()) {
-                debug!(identity_id = %identity_id, channel = %channel, "Unsubscribe request");
+            let Some(channel_name) = channel_name else {
+                return Ok(Some(error_frame("Missing channel")));
+            };
+            debug!(identity_id = %identity_id, channel = %channel_name, "Unsubscribe request");
+
+            state.connections.unsubscribe(identity_id, connection_id, channel_name);
+            Some(serde_json::json!({ "type": "unsubscribed", "channel": channel_name }))
+        }
+        Some("typing") => {
+            // Typing indicators are only relayed to conversations the
+            // connection has already been authorized for
+            let channel = channel_name.and_then(|c| c.parse::<Channel>().ok());
+            match channel {
+                Some(channel @ Channel::Conversation(_))
+                    if state.connections.is_subscribed(identity_id, connection_id, &channel.to_string()) =>
+                {
+                    publish_channel_event(
+                        state,
+                        &channel,
+                        "typing",
+                        serde_json::json!({ "identity_id": identity_id }),
+                    )
+                    .await;
+                    None
+                }
+                _ => Some(error_frame("Not subscribed to this conversation")),
             }
         }
         Some("ping") => {
             debug!(identity_id = %identity_id, "Client ping");
+            Some(serde_json::json!({ "type": "pong" }))
         }
         _ => {
             warn!(identity_id = %identity_id, "Unknown message type");
+            None
+        }
+    };
+
+    Ok(reply)
+}
+
+fn error_frame(message: &str) -> serde_json::Value {
+    serde_json::json!({ "type": "error", "message": message })
+}
+
+/// Publish an event to everyone subscribed to a channel, on every instance
+pub async fn publish_channel_event(
+    state: &AppState,
+    channel: &Channel,
+    event: &str,
+    data: serde_json::Value,
+) {
+    let event = ChannelEvent {
+        channel: channel.to_string(),
+        event: event.to_string(),
+        data,
+    };
+
+    let message = match serde_json::to_string(&event) {
+        Ok(m) => m,
+        Err(e) => {
+            error!(error = %e, "Failed to serialize channel event");
+            return;
         }
+    };
+
+    if let Err(e) = state.redis.publish(CHANNEL_EVENTS_CHANNEL, &message).await {
+        warn!(error = %e, channel = %event.channel, "Channel event publish failed, delivering locally only");
+        deliver_channel_event(state, &event);
     }
+}
 
-    Ok(())
+/// Push a channel event to subscribed sockets on this instance
+fn deliver_channel_event(state: &AppState, event: &ChannelEvent) -> usize {
+    let frame = serde_json::json!({
+        "type": "event",
+        "channel": event.channel,
+        "event": event.event,
+        "data": event.data
+    });
+
+    state.connections.send_to_channel(&event.channel, &frame.to_string())
 }
 
 /// Broadcast a notification to connected clients
+///
+/// Publishes to Redis so every instance delivers it to its local sockets.
+/// Falls back to local delivery if Redis is unavailable.
 pub async fn broadcast_notification(
-    _state: &Arc<AppState>,
+    state: &Arc<AppState>,
     notification: NotificationMessage,
 ) {
-    // In production, this would use Redis pub/sub or a dedicated message broker
-    // to distribute notifications to all server instances
     debug!(
         recipient = %notification.recipient_id,
         notification_type = %notification.notification_type,
         "Broadcasting notification"
     );
+
+    let message = match serde_json::to_string(&notification) {
+        Ok(m) => m,
+        Err(e) => {
+            error!(error = %e, "Failed to serialize notification");
+            return;
+        }
+    };
+
+    if let Err(e) = state.redis.publish(NOTIFICATION_CHANNEL, &message).await {
+        warn!(error = %e, "Notification publish failed, delivering locally only");
+        deliver_notification(state, &notification);
+    }
+}
+
+/// Push a notification to the recipient's sockets on this instance
+fn deliver_notification(state: &AppState, notification: &NotificationMessage) -> usize {
+    let frame = serde_json::json!({
+        "type": "notification",
+        "notification_type": notification.notification_type,
+        "payload": notification.payload,
+        "created_at": notification.created_at.to_rfc3339()
+    });
+
+    state.connections.send(notification.recipient_id, &frame.to_string())
+}
+
+/// Re-authorize an identity's live subscriptions on every instance.
+///
+/// Channels are authorized when subscribed to, so call this whenever the
+/// identity may have lost access since: leaving or being removed or banned
+/// from a space, or being blocked. Channels it can no longer read are dropped.
+pub async fn recheck_subscriptions(state: &Arc<AppState>, identity_id: Uuid) {
+    if let Err(e) = state.redis.publish(RECHECK_CHANNEL, &identity_id.to_string()).await {
+        warn!(error = %e, identity_id = %identity_id, "Subscription recheck publish failed, rechecking locally only");
+        recheck_local(state.clone(), identity_id).await;
+    }
+}
+
+/// Drop the identity's subscriptions on this instance that no longer authorize
+async fn recheck_local(state: Arc<AppState>, identity_id: Uuid) {
+    let subscriptions = state.connections.subscriptions(identity_id);
+
+    let mut channels: Vec<&str> = subscriptions.iter().map(|(_, channel)| channel.as_str()).collect();
+    channels.sort_unstable();
+    channels.dedup();
+
+    for name in channels {
+        let Ok(channel) = name.parse::<Channel>() else {
+            continue;
+        };
+
+        match channel.authorize(&state, identity_id).await {
+            Ok(()) => continue,
+            Err(ApiError::Forbidden | ApiError::NotFound(_) | ApiError::Gone) => {}
+            Err(e) => {
+                // Keep the subscription rather than drop it on a transient failure
+                warn!(error = %e, identity_id = %identity_id, channel = %name, "Subscription recheck failed");
+                continue;
+            }
+        }
+
+        let frame = serde_json::json!({ "type": "unsubscribed", "channel": name, "reason": "access_revoked" });
+        for (connection_id, subscribed) in &subscriptions {
+            if subscribed == name && state.connections.unsubscribe(identity_id, *connection_id, name) {
+                state.connections.send_to_connection(identity_id, *connection_id, &frame.to_string());
+            }
+        }
+
+        debug!(identity_id = %identity_id, channel = %name, "Subscription revoked");
+    }
+}
+
+/// Spawn the task relaying notifications from Redis to local sockets
+pub fn start_notification_subscriber(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    spawn_relay(state, NOTIFICATION_CHANNEL, |state, payload| {
+        match serde_json::from_str::<NotificationMessage>(payload) {
+            Ok(notification) => {
+                let delivered = deliver_notification(state, &notification);
+                debug!(
+                    recipient = %notification.recipient_id,
+                    delivered,
+                    "Notification delivered to local connections"
+                );
+            }
+            Err(e) => warn!(error = %e, "Malformed notification message"),
+        }
+    })
+}
+
+/// Spawn the task relaying channel events from Redis to local subscribers
+pub fn start_channel_subscriber(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    spawn_relay(state, CHANNEL_EVENTS_CHANNEL, |state, payload| {
+        match serde_json::from_str::<ChannelEvent>(payload) {
+            Ok(event) => {
+                let delivered = deliver_channel_event(state, &event);
+                debug!(channel = %event.channel, delivered, "Channel event delivered to local connections");
+            }
+            Err(e) => warn!(error = %e, "Malformed channel event"),
+        }
+    })
+}
+
+/// Spawn the task relaying subscription rechecks from Redis to this instance
+pub fn start_recheck_subscriber(state: Arc<AppState>) -> tokio::task::JoinHandle<()> {
+    spawn_relay(state, RECHECK_CHANNEL, |state, payload| match payload.parse::<Uuid>() {
+        Ok(identity_id) => {
+            tokio::spawn(recheck_local(state.clone(), identity_id));
+        }
+        Err(e) => warn!(error = %e, "Malformed subscription recheck"),
+    })
+}
+
+/// Subscribe to a Redis channel and hand every payload to `handle`,
+/// reconnecting whenever the subscription drops
+fn spawn_relay(
+    state: Arc<AppState>,
+    redis_channel: &'static str,
+    handle: fn(&Arc<AppState>, &str),
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let pubsub = match state.redis.subscribe(redis_channel).await {
+                Ok(p) => p,
+                Err(e) => {
+                    error!(error = %e, channel = %redis_channel, "Pub/sub relay failed to connect");
+                    tokio::time::sleep(SUBSCRIBER_RETRY_DELAY).await;
+                    continue;
+                }
+            };
+
+            info!(channel = %redis_channel, "Pub/sub relay started");
+
+            let mut messages = pubsub.into_on_message();
+            while let Some(msg) = messages.next().await {
+                match msg.get_payload::<String>() {
+                    Ok(payload) => handle(&state, &payload),
+                    Err(e) => warn!(error = %e, channel = %redis_channel, "Invalid pub/sub payload"),
+                }
+            }
+
+            warn!(channel = %redis_channel, "Pub/sub relay disconnected, reconnecting");
+            tokio::time::sleep(SUBSCRIBER_RETRY_DELAY).await;
+        }
+    })
 }
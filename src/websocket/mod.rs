@@ -1,4 +1,9 @@
 //! WebSocket module for real-time features
 
+pub mod channels;
 pub mod handlers;
+pub mod registry;
+
+pub use channels::*;
 pub use handlers::*;
+pub use registry::*;
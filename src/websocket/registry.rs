@@ -0,0 +1,365 @@
+//! Per-process registry of live WebSocket connections
+//!
+//! Each connection registers an outbound queue under its identity and may
+//! subscribe to any number of live channels. Messages delivered through the
+//! registry are pushed onto the matching queues on this instance; cross-instance
+//! fan-out happens over Redis.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Outbound queue depth per connection before messages are dropped
+const CONNECTION_QUEUE_SIZE: usize = 64;
+
+/// Identifier for a single registered connection
+pub type ConnectionId = u64;
+
+/// A registered connection
+struct Connection {
+    tx: mpsc::Sender<String>,
+    channels: HashSet<String>,
+}
+
+/// Registry state guarded by a single lock
+#[derive(Default)]
+struct RegistryInner {
+    connections: HashMap<Uuid, HashMap<ConnectionId, Connection>>,
+    subscribers: HashMap<String, HashSet<(Uuid, ConnectionId)>>,
+}
+
+/// Registry of live connections keyed by identity
+#[derive(Clone, Default)]
+pub struct ConnectionRegistry {
+    inner: Arc<RwLock<RegistryInner>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ConnectionRegistry {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a connection for an identity, returning its id and outbound queue
+    pub fn register(&self, identity_id: Uuid) -> (ConnectionId, mpsc::Receiver<String>) {
+        let (tx, rx) = mpsc::channel(CONNECTION_QUEUE_SIZE);
+        let connection_id = self.next_id.fetch_add(1, Ordering::Relaxed);
+
+        self.inner
+            .write()
+            .expect("connection registry lock poisoned")
+            .connections
+            .entry(identity_id)
+            .or_default()
+            .insert(connection_id, Connection { tx, channels: HashSet::new() });
+
+        debug!(identity_id = %identity_id, connection_id, "Connection registered");
+        (connection_id, rx)
+    }
+
+    /// Remove a connection and all of its channel subscriptions
+    pub fn unregister(&self, identity_id: Uuid, connection_id: ConnectionId) {
+        let mut inner = self.inner.write().expect("connection registry lock poisoned");
+
+        let Some(sockets) = inner.connections.get_mut(&identity_id) else {
+            return;
+        };
+
+        let removed = sockets.remove(&connection_id);
+        if sockets.is_empty() {
+            inner.connections.remove(&identity_id);
+        }
+
+        if let Some(connection) = removed {
+            for channel in connection.channels {
+                inner.remove_subscriber(&channel, identity_id, connection_id);
+            }
+        }
+
+        debug!(identity_id = %identity_id, connection_id, "Connection unregistered");
+    }
+
+    /// Subscribe a connection to a channel.
+    ///
+    /// Returns `false` if the connection is unknown or already subscribed.
+    pub fn subscribe(&self, identity_id: Uuid, connection_id: ConnectionId, channel: &str) -> bool {
+        let mut inner = self.inner.write().expect("connection registry lock poisoned");
+
+        let Some(connection) = inner
+            .connections
+            .get_mut(&identity_id)
+            .and_then(|sockets| sockets.get_mut(&connection_id))
+        else {
+            return false;
+        };
+
+        if !connection.channels.insert(channel.to_string()) {
+            return false;
+        }
+
+        inner
+            .subscribers
+            .entry(channel.to_string())
+            .or_default()
+            .insert((identity_id, connection_id));
+
+        true
+    }
+
+    /// Unsubscribe a connection from a channel
+    pub fn unsubscribe(&self, identity_id: Uuid, connection_id: ConnectionId, channel: &str) -> bool {
+        let mut inner = self.inner.write().expect("connection registry lock poisoned");
+
+        let removed = inner
+            .connections
+            .get_mut(&identity_id)
+            .and_then(|sockets| sockets.get_mut(&connection_id))
+            .map(|connection| connection.channels.remove(channel))
+            .unwrap_or(false);
+
+        if removed {
+            inner.remove_subscriber(channel, identity_id, connection_id);
+        }
+
+        removed
+    }
+
+    /// Whether a connection is subscribed to a channel
+    pub fn is_subscribed(&self, identity_id: Uuid, connection_id: ConnectionId, channel: &str) -> bool {
+        self.inner
+            .read()
+            .expect("connection registry lock poisoned")
+            .subscribers
+            .get(channel)
+            .map(|subscribers| subscribers.contains(&(identity_id, connection_id)))
+            .unwrap_or(false)
+    }
+
+    /// Every channel subscription of an identity's connections
+    pub fn subscriptions(&self, identity_id: Uuid) -> Vec<(ConnectionId, String)> {
+        self.inner
+            .read()
+            .expect("connection registry lock poisoned")
+            .connections
+            .get(&identity_id)
+            .map(|sockets| {
+                sockets
+                    .iter()
+                    .flat_map(|(connection_id, connection)| {
+                        connection.channels.iter().map(|channel| (*connection_id, channel.clone()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Number of channels a connection is subscribed to
+    pub fn subscription_count(&self, identity_id: Uuid, connection_id: ConnectionId) -> usize {
+        self.inner
+            .read()
+            .expect("connection registry lock poisoned")
+            .connections
+            .get(&identity_id)
+            .and_then(|sockets| sockets.get(&connection_id))
+            .map(|connection| connection.channels.len())
+            .unwrap_or(0)
+    }
+
+    /// Deliver a message to every local connection of an identity.
+    ///
+    /// Returns the number of connections the message was queued on. Slow
+    /// consumers with a full queue miss the message rather than block delivery.
+    pub fn send(&self, identity_id: Uuid, message: &str) -> usize {
+        let inner = self.inner.read().expect("connection registry lock poisoned");
+
+        let Some(sockets) = inner.connections.get(&identity_id) else {
+            return 0;
+        };
+
+        sockets
+            .iter()
+            .filter(|(connection_id, connection)| {
+                try_deliver(identity_id, **connection_id, &connection.tx, message)
+            })
+            .count()
+    }
+
+    /// Deliver a message to a single connection
+    pub fn send_to_connection(&self, identity_id: Uuid, connection_id: ConnectionId, message: &str) -> bool {
+        let inner = self.inner.read().expect("connection registry lock poisoned");
+
+        inner
+            .connections
+            .get(&identity_id)
+            .and_then(|sockets| sockets.get(&connection_id))
+            .map(|connection| try_deliver(identity_id, connection_id, &connection.tx, message))
+            .unwrap_or(false)
+    }
+
+    /// Deliver a message to every local connection subscribed to a channel
+    pub fn send_to_channel(&self, channel: &str, message: &str) -> usize {
+        let inner = self.inner.read().expect("connection registry lock poisoned");
+
+        let Some(subscribers) = inner.subscribers.get(channel) else {
+            return 0;
+        };
+
+        subscribers
+            .iter()
+            .filter_map(|(identity_id, connection_id)| {
+                inner
+                    .connections
+                    .get(identity_id)
+                    .and_then(|sockets| sockets.get(connection_id))
+                    .map(|connection| (*identity_id, *connection_id, connection))
+            })
+            .filter(|(identity_id, connection_id, connection)| {
+                try_deliver(*identity_id, *connection_id, &connection.tx, message)
+            })
+            .count()
+    }
+
+    /// Whether an identity has any live connection on this instance
+    pub fn is_connected(&self, identity_id: Uuid) -> bool {
+        self.inner
+            .read()
+            .expect("connection registry lock poisoned")
+            .connections
+            .contains_key(&identity_id)
+    }
+
+    /// Total number of live connections on this instance
+    pub fn connection_count(&self) -> usize {
+        self.inner
+            .read()
+            .expect("connection registry lock poisoned")
+            .connections
+            .values()
+            .map(HashMap::len)
+            .sum()
+    }
+}
+
+impl RegistryInner {
+    fn remove_subscriber(&mut self, channel: &str, identity_id: Uuid, connection_id: ConnectionId) {
+        if let Some(subscribers) = self.subscribers.get_mut(channel) {
+            subscribers.remove(&(identity_id, connection_id));
+            if subscribers.is_empty() {
+                self.subscribers.remove(channel);
+            }
+        }
+    }
+}
+
+/// Queue a message on a connection without blocking
+fn try_deliver(
+    identity_id: Uuid,
+    connection_id: ConnectionId,
+    tx: &mpsc::Sender<String>,
+    message: &str,
+) -> bool {
+    match tx.try_send(message.to_string()) {
+        Ok(()) => true,
+        Err(mpsc::error::TrySendError::Full(_)) => {
+            warn!(identity_id = %identity_id, connection_id, "Connection queue full, dropping message");
+            false
+        }
+        Err(mpsc::error::TrySendError::Closed(_)) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_send_reaches_every_connection() {
+        let registry = ConnectionRegistry::new();
+        let identity = Uuid::new_v4();
+
+        let (_, mut first) = registry.register(identity);
+        let (_, mut second) = registry.register(identity);
+        let (_, mut other) = registry.register(Uuid::new_v4());
+
+        assert_eq!(registry.send(identity, "hello"), 2);
+        assert_eq!(first.recv().await.as_deref(), Some("hello"));
+        assert_eq!(second.recv().await.as_deref(), Some("hello"));
+        assert!(other.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_unregister_removes_identity() {
+        let registry = ConnectionRegistry::new();
+        let identity = Uuid::new_v4();
+
+        let (first, _rx1) = registry.register(identity);
+        let (second, _rx2) = registry.register(identity);
+        assert_eq!(registry.connection_count(), 2);
+
+        registry.unregister(identity, first);
+        assert!(registry.is_connected(identity));
+
+        registry.unregister(identity, second);
+        assert!(!registry.is_connected(identity));
+        assert_eq!(registry.send(identity, "hello"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_channel_delivery_follows_subscriptions() {
+        let registry = ConnectionRegistry::new();
+        let identity = Uuid::new_v4();
+
+        let (subscribed, mut subscribed_rx) = registry.register(identity);
+        let (_, mut idle_rx) = registry.register(identity);
+
+        assert!(registry.subscribe(identity, subscribed, "space:rust"));
+        assert!(!registry.subscribe(identity, subscribed, "space:rust"));
+        assert_eq!(registry.subscription_count(identity, subscribed), 1);
+
+        assert_eq!(registry.send_to_channel("space:rust", "new post"), 1);
+        assert_eq!(subscribed_rx.recv().await.as_deref(), Some("new post"));
+        assert!(idle_rx.try_recv().is_err());
+
+        assert!(registry.unsubscribe(identity, subscribed, "space:rust"));
+        assert_eq!(registry.send_to_channel("space:rust", "another"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_list_every_connection() {
+        let registry = ConnectionRegistry::new();
+        let identity = Uuid::new_v4();
+
+        let (first, mut first_rx) = registry.register(identity);
+        let (second, mut second_rx) = registry.register(identity);
+        registry.subscribe(identity, first, "space:rust");
+        registry.subscribe(identity, second, "post:1");
+
+        let mut subscriptions = registry.subscriptions(identity);
+        subscriptions.sort();
+        let mut expected = vec![(first, "space:rust".to_string()), (second, "post:1".to_string())];
+        expected.sort();
+        assert_eq!(subscriptions, expected);
+        assert!(registry.subscriptions(Uuid::new_v4()).is_empty());
+
+        assert!(registry.send_to_connection(identity, second, "revoked"));
+        assert_eq!(second_rx.recv().await.as_deref(), Some("revoked"));
+        assert!(first_rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_unregister_drops_subscriptions() {
+        let registry = ConnectionRegistry::new();
+        let identity = Uuid::new_v4();
+
+        let (connection, _rx) = registry.register(identity);
+        registry.subscribe(identity, connection, "post:1");
+        registry.unregister(identity, connection);
+
+        assert!(!registry.is_subscribed(identity, connection, "post:1"));
+        assert_eq!(registry.send_to_channel("post:1", "hello"), 0);
+    }
+}